@@ -0,0 +1,53 @@
+// Assembles the context switch of the backends written in assembly (see `src/arch`) into a static library.
+// The C compiler drives the assembler, for the preprocessor to pick the variant of the target and of the features.
+// The `cc` crate finds the compiler and the archiver of the target, e.g. from `CC_<target>` and `AR_<target>`,
+// and passes them the flags of the target.
+
+use std::env;
+use std::io::ErrorKind;
+use std::process::{Command, Stdio};
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    let arch = env::var("CARGO_CFG_TARGET_ARCH").unwrap();
+    // the other backends are written in Rust, over libc, Windows or OS threads.
+    let other_backend = ["UCONTEXT", "FIBERS"]
+        .iter()
+        .any(|feature| env::var_os(format!("CARGO_FEATURE_{feature}")).is_some())
+        || env::var_os("CARGO_CFG_MIRI").is_some();
+    if other_backend || !["x86_64", "aarch64", "riscv64"].contains(&arch.as_str()) {
+        return;
+    }
+
+    let source = format!("src/arch/{arch}.S");
+    println!("cargo:rerun-if-changed={source}");
+    let mut build = cc::Build::new();
+    build.file(&source);
+    if env::var_os("CARGO_FEATURE_SIMD_CONTEXT").is_some() {
+        build.define("UTHREADS_SIMD_CONTEXT", None);
+    }
+    let features = env::var("CARGO_CFG_TARGET_FEATURE").unwrap_or_default();
+    if arch == "riscv64" && features.split(',').any(|feature| feature == "d") {
+        // `cc` passes the float ABI of the target, which the switch saves the float registers for.
+        build.define("UTHREADS_FLOAT_REGS", None);
+    }
+
+    // Without an assembler for the target, the crate can still be checked (e.g. linted or documented),
+    // only linking it fails, for want of the context switch.
+    let compiler = build.get_compiler();
+    let found = Command::new(compiler.path())
+        .arg("--version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map_or_else(|err| err.kind() != ErrorKind::NotFound, |_| true);
+    if !found {
+        println!(
+            "cargo:warning=`{}` was not found, so the context switch isn't assembled and the crate won't link, \
+             set `CC` and `AR` to the tools of the target",
+            compiler.path().display()
+        );
+        return;
+    }
+    build.compile("uthreads_switch");
+}
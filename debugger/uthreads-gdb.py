@@ -0,0 +1,96 @@
+# GDB helper listing the green threads of a stopped uthreads process, and showing their frames.
+# It reads `UTHREADS_REGISTRY`, see `src/debugger.rs`. Load it with `source debugger/uthreads-gdb.py`, then:
+#   uthreads list        lists the threads and their states
+#   uthreads bt ID       shows the backtrace of thread ID
+#   uthreads switch ID   sets the registers to the ones thread ID was switched out with,
+#                        so that the usual commands (frame, info locals...) look at it
+#   uthreads restore     puts the registers of the OS thread back, to be done before resuming the process
+# Only the stack pointer, frame pointer and program counter are set, so values kept in other registers may be off.
+
+import os
+import sys
+
+import gdb
+
+sys.path.insert(0, os.path.dirname(os.path.abspath(__file__)))
+from uthreads import frame, registry, threads  # noqa: E402
+
+# stack pointer, frame pointer and program counter of every architecture with frames in the registry.
+REGISTERS = {
+    "i386:x86-64": ("rsp", "rbp", "rip"),
+    "aarch64": ("sp", "x29", "pc"),
+    "riscv:rv64": ("sp", "s0", "pc"),
+}
+
+
+def read_memory(addr, size):
+    return bytes(gdb.selected_inferior().read_memory(addr, size))
+
+
+class Uthreads(gdb.Command):
+    """Inspects the green threads of a uthreads process: uthreads list|bt ID|switch ID|restore."""
+
+    def __init__(self):
+        super().__init__("uthreads", gdb.COMMAND_STACK)
+        self.saved = None
+
+    def invoke(self, arg, from_tty):
+        try:
+            self.run(arg.split())
+        except ValueError as err:
+            raise gdb.GdbError(str(err))
+
+    def run(self, args):
+        if not args:
+            raise gdb.GdbError("usage: uthreads list|bt ID|switch ID|restore")
+        if args[0] == "list":
+            for id, state, _ in threads(read_memory, self.registry()):
+                print(f"thread {id}: {state}")
+        elif args[0] in ("bt", "switch") and len(args) == 2:
+            self.switch(int(args[1]))
+            if args[0] == "bt":
+                try:
+                    gdb.execute("bt")
+                finally:
+                    self.restore()
+        elif args[0] == "restore":
+            self.restore()
+        else:
+            raise gdb.GdbError("usage: uthreads list|bt ID|switch ID|restore")
+
+    def registry(self):
+        addr = int(gdb.parse_and_eval("(unsigned long)&UTHREADS_REGISTRY"))
+        return registry(read_memory, addr)
+
+    def registers(self):
+        arch = gdb.selected_frame().architecture().name()
+        if arch not in REGISTERS:
+            raise gdb.GdbError(f"unsupported architecture {arch}")
+        return REGISTERS[arch]
+
+    def switch(self, id):
+        reg = self.registry()
+        found = [(state, ctx) for t, state, ctx in threads(read_memory, reg) if t == id]
+        if not found:
+            raise gdb.GdbError(f"no thread {id}")
+        state, ctx = found[0]
+        # the running thread is the one the registers already belong to.
+        if state == "Running":
+            return
+        values = frame(read_memory, reg, ctx)
+        names = self.registers()
+        if self.saved is None:
+            gdb.execute("select-frame 0")
+            self.saved = [int(gdb.parse_and_eval(f"(unsigned long)${r}")) for r in names]
+        for name, value in zip(names, values):
+            gdb.execute(f"set ${name} = {value}")
+
+    def restore(self):
+        if self.saved is None:
+            return
+        for name, value in zip(self.registers(), self.saved):
+            gdb.execute(f"set ${name} = {value}")
+        self.saved = None
+
+
+Uthreads()
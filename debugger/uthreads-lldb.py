@@ -0,0 +1,91 @@
+# LLDB helper listing the green threads of a stopped uthreads process, and showing their frames.
+# It reads `UTHREADS_REGISTRY`, see `src/debugger.rs`. Load it with `command script import debugger/uthreads-lldb.py`.
+# The commands are the same as the ones of the GDB helper, see `uthreads-gdb.py`.
+
+import os
+import sys
+
+import lldb
+
+sys.path.insert(0, os.path.dirname(os.path.abspath(__file__)))
+import uthreads as helper  # noqa: E402
+
+REGISTERS = {
+    "x86_64": ("rsp", "rbp", "rip"),
+    "arm64": ("sp", "fp", "pc"),
+    "aarch64": ("sp", "fp", "pc"),
+    "riscv64": ("sp", "fp", "pc"),
+}
+saved = None
+
+
+def memory_reader(process):
+    def read(addr, size):
+        error = lldb.SBError()
+        data = process.ReadMemory(addr, size, error)
+        if error.Fail():
+            raise ValueError(f"failed to read memory at {addr:#x}: {error}")
+        return data
+
+    return read
+
+
+def registry(target, read):
+    symbols = target.FindSymbols("UTHREADS_REGISTRY")
+    if not symbols.GetSize():
+        raise ValueError("UTHREADS_REGISTRY not found, is this a uthreads process?")
+    addr = symbols[0].symbol.GetStartAddress().GetLoadAddress(target)
+    return helper.registry(read, addr)
+
+
+def registers(target):
+    arch = target.GetTriple().split("-")[0]
+    if arch not in REGISTERS:
+        raise ValueError(f"unsupported architecture {arch}")
+    return REGISTERS[arch]
+
+
+def set_registers(frame, names, values):
+    for name, value in zip(names, values):
+        frame.FindRegister(name).SetValueFromCString(str(value))
+
+
+def uthreads(debugger, command, result, internal_dict):
+    global saved
+    target = debugger.GetSelectedTarget()
+    process = target.GetProcess()
+    frame = process.GetSelectedThread().GetFrameAtIndex(0)
+    read = memory_reader(process)
+    args = command.split()
+    try:
+        if args == ["list"]:
+            for id, state, _ in helper.threads(read, registry(target, read)):
+                result.AppendMessage(f"thread {id}: {state}")
+        elif len(args) == 2 and args[0] in ("bt", "switch"):
+            reg = registry(target, read)
+            found = [(s, c) for t, s, c in helper.threads(read, reg) if t == int(args[1])]
+            if not found:
+                raise ValueError(f"no thread {args[1]}")
+            state, ctx = found[0]
+            names = registers(target)
+            if state != "Running":
+                if saved is None:
+                    saved = [frame.FindRegister(n).GetValueAsUnsigned() for n in names]
+                set_registers(frame, names, helper.frame(read, reg, ctx))
+            if args[0] == "bt":
+                debugger.HandleCommand("bt")
+                if saved is not None:
+                    set_registers(frame, names, saved)
+                    saved = None
+        elif args == ["restore"]:
+            if saved is not None:
+                set_registers(frame, registers(target), saved)
+                saved = None
+        else:
+            result.SetError("usage: uthreads list|bt ID|switch ID|restore")
+    except ValueError as err:
+        result.SetError(str(err))
+
+
+def __lldb_init_module(debugger, internal_dict):
+    debugger.HandleCommand(f"command script add -f {__name__}.uthreads uthreads")
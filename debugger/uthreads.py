@@ -0,0 +1,52 @@
+# Reads the registry of a stopped uthreads process, see `src/debugger.rs`, for the GDB and LLDB helpers.
+# Memory is only read through `read(addr, size)`, which each debugger provides.
+
+import struct
+
+STATES = [
+    "Running",
+    "Ready",
+    "ChannelBlockSend",
+    "ChannelBlockRecv",
+    "SyncBlock",
+    "IoBlocked",
+    "Sleeping",
+    "Parked",
+]
+VERSION = 1
+PC_ON_STACK = 2**64 - 1
+# version, has_frames, threads, len, stride, id, state, ctx, sp, sp_adjust, fp, pc
+REGISTRY = "<IIQQQQQQQQQQ"
+
+
+def u64(read, addr):
+    return struct.unpack("<Q", read(addr, 8))[0]
+
+
+def registry(read, addr):
+    fields = struct.unpack(REGISTRY, read(addr, struct.calcsize(REGISTRY)))
+    names = "version has_frames threads len stride id state ctx sp sp_adjust fp pc".split()
+    reg = dict(zip(names, fields))
+    if reg["version"] != VERSION:
+        raise ValueError(f"unsupported registry version {reg['version']}")
+    return reg
+
+
+# Returns (id, state, address of the context) for every thread.
+def threads(read, reg):
+    for i in range(reg["len"]):
+        thread = reg["threads"] + i * reg["stride"]
+        id = u64(read, thread + reg["id"])
+        state = read(thread + reg["state"], 1)[0]
+        state = STATES[state] if state < len(STATES) else f"unknown ({state})"
+        yield id, state, thread + reg["ctx"]
+
+
+# Returns the (sp, fp, pc) a thread was switched out with.
+def frame(read, reg, ctx):
+    if not reg["has_frames"]:
+        raise ValueError("the context switching backend doesn't keep the registers in memory")
+    sp = u64(read, ctx + reg["sp"])
+    fp = u64(read, ctx + reg["fp"])
+    pc = u64(read, sp) if reg["pc"] == PC_ON_STACK else u64(read, ctx + reg["pc"])
+    return sp + reg["sp_adjust"], fp, pc
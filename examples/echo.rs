@@ -0,0 +1,42 @@
+// A per-connection-thread echo server. Try it with `nc 127.0.0.1 7878`.
+
+use uthreads::io::{Read, Write};
+use uthreads::net::{TcpListener, TcpStream};
+use uthreads::runtime::{create_thread, spawn_with, Runtime};
+
+fn main() {
+    let mut runtime = Runtime::new();
+    unsafe { runtime.init() };
+
+    create_thread(accept_loop);
+    runtime.run();
+}
+
+fn accept_loop() {
+    let listener = TcpListener::bind("127.0.0.1:7878").expect("failed to bind");
+    println!("listening on {}", listener.local_addr().unwrap());
+
+    loop {
+        match listener.accept() {
+            Ok((stream, addr)) => {
+                println!("accepted connection from {}", addr);
+                spawn_with(serve, stream);
+            }
+            Err(err) => println!("failed to accept: {}", err),
+        }
+    }
+}
+
+fn serve(mut stream: TcpStream) {
+    let mut buf = [0; 1024];
+    loop {
+        match stream.read(&mut buf) {
+            Ok(0) | Err(_) => break,
+            Ok(n) => {
+                if stream.write_all(&buf[..n]).is_err() {
+                    break;
+                }
+            }
+        }
+    }
+}
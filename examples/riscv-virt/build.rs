@@ -0,0 +1,10 @@
+// Links the program at the address the virt machine boots from, with the layout in `link.x`.
+
+use std::env;
+use std::path::PathBuf;
+
+fn main() {
+    let dir = PathBuf::from(env::var_os("CARGO_MANIFEST_DIR").unwrap());
+    println!("cargo:rerun-if-changed=link.x");
+    println!("cargo:rustc-link-arg=-T{}", dir.join("link.x").display());
+}
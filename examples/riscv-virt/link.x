@@ -0,0 +1,47 @@
+/* Layout of the program in the RAM of the virt machine, which starts running it at its first byte. */
+
+OUTPUT_ARCH(riscv)
+ENTRY(_start)
+
+MEMORY
+{
+    RAM : ORIGIN = 0x80000000, LENGTH = 128M
+}
+
+SECTIONS
+{
+    .text : {
+        KEEP(*(.text.start))
+        *(.text .text.*)
+    } > RAM
+
+    .rodata : ALIGN(8) {
+        *(.rodata .rodata.*)
+        *(.srodata .srodata.*)
+    } > RAM
+
+    .data : ALIGN(8) {
+        *(.data .data.*)
+        *(.sdata .sdata.*)
+    } > RAM
+
+    /* zeroed by `_start`, as nothing loads it. */
+    .bss (NOLOAD) : ALIGN(8) {
+        _bss_start = .;
+        *(.sbss .sbss.*)
+        *(.bss .bss.*)
+        . = ALIGN(8);
+        _bss_end = .;
+    } > RAM
+
+    /* the stack of the base thread, i.e. of the program until it spawns threads. */
+    .stack (NOLOAD) : ALIGN(16) {
+        . += 64K;
+        _stack_top = .;
+    } > RAM
+
+    /* panics don't unwind. */
+    /DISCARD/ : {
+        *(.eh_frame .eh_frame_hdr)
+    }
+}
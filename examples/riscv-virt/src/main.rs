@@ -0,0 +1,184 @@
+// Runs green threads on bare metal, on the RISC-V virt machine of QEMU, without std nor an OS:
+// the stacks of the threads are carved out of a static array, time goes by the timer of the machine (its CLINT),
+// and the hart sleeps with `wfi` whenever every thread is waiting for a timer.
+// Threads sleep for different durations, then report to another one over a channel, which prints when they did.
+// Run it from this directory with `cargo run`, which needs `qemu-system-riscv64`, and an assembler for the target
+// (`riscv64-unknown-elf-gcc`, or another one given in `CC_riscv64gc_unknown_none_elf`) to build the context switch.
+
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use core::arch::{asm, global_asm};
+use core::fmt::{self, Write};
+use core::panic::PanicInfo;
+use core::time::Duration;
+
+use linked_list_allocator::LockedHeap;
+use uthreads::channel::Channel;
+use uthreads::runtime::{chan_recv, chan_send, Runtime};
+use uthreads::thread::StaticAllocator;
+use uthreads::time::{self, Clock, Instant};
+
+// Entry point, in machine mode, with nothing set up: only hart 0 runs the program, the others sleep for good.
+// The FPU is off at reset, and the context switch saves its registers, so it is turned on (mstatus.FS) first.
+global_asm!(
+    r#"
+    .section .text.start
+    .globl _start
+_start:
+    csrr t0, mhartid
+    bnez t0, 3f
+    li t0, 1 << 13
+    csrs mstatus, t0
+    csrw fcsr, zero
+    la t0, trap
+    csrw mtvec, t0
+    la sp, _stack_top
+    la t0, _bss_start
+    la t1, _bss_end
+1:
+    bgeu t0, t1, 2f
+    sd zero, 0(t0)
+    addi t0, t0, 8
+    j 1b
+2:
+    call start
+3:
+    wfi
+    j 3b
+
+    .p2align 2
+trap:
+    call trap_handler
+"#
+);
+
+// The CLINT of the machine: `mtime` counts from reset at 10 MHz, and the timer interrupt of hart 0 is pending
+// while it is past `mtimecmp`.
+const MTIME: *const u64 = 0x0200_bff8 as *const u64;
+const MTIMECMP: *mut u64 = 0x0200_4000 as *mut u64;
+const NANOS_PER_TICK: u64 = 100;
+
+// The 16550 UART of the machine, whose output QEMU prints.
+const UART: *mut u8 = 0x1000_0000 as *mut u8;
+const UART_LSR: *const u8 = 0x1000_0005 as *const u8;
+const LSR_THR_EMPTY: u8 = 1 << 5;
+
+// The test device of the machine, which powers it off with the status written to it.
+const TEST: *mut u32 = 0x0010_0000 as *mut u32;
+const TEST_PASS: u32 = 0x5555;
+const TEST_FAIL: u32 = 0x3333;
+
+// Threads get the runtime's default size of a stack.
+const STACK_SIZE: usize = 64 * 1024;
+const THREADS: usize = 8;
+const HEAP_SIZE: usize = 1024 * 1024;
+
+#[repr(align(16))]
+struct Stacks([u8; THREADS * STACK_SIZE]);
+
+static mut STACKS: Stacks = Stacks([0; THREADS * STACK_SIZE]);
+static mut HEAP: [u8; HEAP_SIZE] = [0; HEAP_SIZE];
+
+#[global_allocator]
+static ALLOCATOR: LockedHeap = LockedHeap::empty();
+
+// The timer of the machine, as the clock of the runtime.
+struct Clint;
+
+impl Clock for Clint {
+    fn now(&self) -> Instant {
+        let ticks = unsafe { MTIME.read_volatile() };
+        Instant::from_duration(Duration::from_nanos(ticks * NANOS_PER_TICK))
+    }
+
+    // Sleeps until the deadline: the timer interrupt is only enabled in `mie`, not globally (mstatus.MIE),
+    // so that it ends `wfi` without trapping. `wfi` may return early, in which case the runtime comes back here.
+    fn idle(&self, deadline: Instant) -> Duration {
+        let ticks = deadline
+            .as_duration()
+            .as_nanos()
+            .div_ceil(NANOS_PER_TICK as u128);
+        unsafe {
+            MTIMECMP.write_volatile(ticks as u64);
+            asm!("csrs mie, {}", in(reg) 1 << 7);
+            asm!("wfi");
+        }
+        deadline.saturating_duration_since(self.now())
+    }
+}
+
+struct Uart;
+
+impl Write for Uart {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            unsafe {
+                while UART_LSR.read_volatile() & LSR_THR_EMPTY == 0 {}
+                UART.write_volatile(byte);
+            }
+        }
+        Ok(())
+    }
+}
+
+macro_rules! println {
+    ($($arg:tt)*) => {
+        let _ = writeln!(Uart, $($arg)*);
+    };
+}
+
+fn exit(status: u32) -> ! {
+    unsafe { TEST.write_volatile(status) };
+    loop {
+        unsafe { asm!("wfi") };
+    }
+}
+
+#[no_mangle]
+extern "C" fn start() -> ! {
+    unsafe { ALLOCATOR.lock().init((&raw mut HEAP).cast(), HEAP_SIZE) };
+
+    // only borrowed here, as the stacks of the runtime for as long as the program runs.
+    let stacks = unsafe {
+        let stacks = &raw mut STACKS.0;
+        &mut *stacks
+    };
+    let mut runtime = Runtime::with_stack_allocator(StaticAllocator::new(stacks, STACK_SIZE));
+    runtime.set_clock(Clint);
+    unsafe { runtime.init() };
+
+    let chan = Box::into_raw(Box::new(Channel::<u32>::new(1)));
+    for (n, millis) in [(1, 300), (2, 100), (3, 200)] {
+        runtime.spawn(move || {
+            time::sleep(Duration::from_millis(millis));
+            unsafe { chan_send(chan, n) };
+        });
+    }
+    runtime.spawn(move || {
+        for _ in 0..3 {
+            let n = unsafe { chan_recv(chan) };
+            println!("{:?}: thread {n} woke up", time::now().as_duration());
+        }
+    });
+    runtime.run();
+    println!("all threads completed");
+    exit(TEST_PASS)
+}
+
+#[no_mangle]
+extern "C" fn trap_handler() -> ! {
+    let (cause, pc): (usize, usize);
+    unsafe { asm!("csrr {}, mcause", "csrr {}, mepc", out(reg) cause, out(reg) pc) };
+    println!("unexpected trap {cause:#x} at {pc:#x}");
+    exit(1 << 16 | TEST_FAIL)
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    println!("{info}");
+    exit(1 << 16 | TEST_FAIL)
+}
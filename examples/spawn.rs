@@ -0,0 +1,36 @@
+// Measures how long spawning a thread takes, and how long a thread takes from spawning to completing
+// in a server spawning a thread per request which finishes quickly. Run it with `cargo run --release --example spawn`.
+
+use std::time::Instant;
+
+use uthreads::runtime::{self, yield_thread, Runtime};
+
+const THREADS: u32 = 100_000;
+const BATCH: u32 = 1_000;
+
+fn main() {
+    let mut runtime = Runtime::new();
+    unsafe { runtime.init() };
+
+    let start = Instant::now();
+    for _ in 0..BATCH {
+        runtime::spawn(|| {});
+    }
+    let spawned = start.elapsed();
+    runtime.run();
+    println!("spawn: {:?} per thread", spawned / BATCH);
+
+    // the thread accepting requests spawns one thread per request, and yields to them as it waits for the next.
+    let start = Instant::now();
+    runtime::spawn(|| {
+        for _ in 0..THREADS {
+            runtime::spawn(|| {});
+            yield_thread();
+        }
+    });
+    runtime.run();
+    println!(
+        "spawn to completion: {:?} per thread",
+        start.elapsed() / THREADS
+    );
+}
@@ -0,0 +1,96 @@
+#ifndef UTHREADS_H
+#define UTHREADS_H
+
+/* Generated by cbindgen from src/ffi.rs, do not edit. */
+
+#include <stddef.h>
+#include <stdint.h>
+
+
+
+// A channel of byte buffers, see `uthreads_channel_new`.
+typedef struct UthreadsChannel UthreadsChannel;
+
+// A runtime, see `uthreads_runtime_new`.
+typedef struct UthreadsRuntime UthreadsRuntime;
+
+
+
+
+
+
+
+// Creates a runtime and initialises it on the calling OS thread, which runs its threads from then on.
+// Returns NULL if it can't be created.
+//
+// # Safety
+//
+// No other runtime may be initialised on the calling OS thread while this one is in use.
+struct UthreadsRuntime *uthreads_runtime_new(void);
+
+// Runs the threads of `rt` until none is left to run.
+//
+// # Safety
+//
+// `rt` must have been returned by `uthreads_runtime_new` on the calling OS thread, and not freed.
+void uthreads_runtime_run(struct UthreadsRuntime *rt);
+
+// Frees `rt`, along with the threads it didn't run to completion.
+//
+// # Safety
+//
+// `rt` must have been returned by `uthreads_runtime_new`, and not freed already. It must not be running.
+void uthreads_runtime_free(struct UthreadsRuntime *rt);
+
+// Spawns a thread calling `f` with `arg`, on the runtime of the calling OS thread.
+// Returns the id of the thread, or 0 if it can't be spawned as too many threads are alive.
+//
+// # Safety
+//
+// A runtime must be initialised on the calling OS thread, and `arg` valid for whatever `f` does with it.
+size_t uthreads_spawn(void (*f)(void*),
+                      void *arg);
+
+// Gives control to the other threads of the runtime, which runs the calling thread again later.
+//
+// # Safety
+//
+// Must be called from a thread of a runtime.
+void uthreads_yield(void);
+
+// Creates a channel of byte buffers, whose buffer holds `size` of them before senders block.
+struct UthreadsChannel *uthreads_channel_new(size_t size);
+
+// Frees `chan`, along with the buffers sent to it and not received.
+//
+// # Safety
+//
+// `chan` must have been returned by `uthreads_channel_new`, and not freed already. No thread may be blocked on it.
+void uthreads_channel_free(struct UthreadsChannel *chan);
+
+// Sends a copy of the `len` bytes at `data` to `chan`, blocking the calling thread while its buffer is full.
+//
+// # Safety
+//
+// `chan` must be a live channel, used from the threads of a single runtime, and `data` valid for reading `len` bytes.
+void uthreads_channel_send(struct UthreadsChannel *chan,
+                           const uint8_t *data,
+                           size_t len);
+
+// Receives a buffer from `chan`, blocking the calling thread until one is sent.
+// Returns the buffer, of `*len` bytes, which is freed with `uthreads_buffer_free`.
+//
+// # Safety
+//
+// `chan` must be a live channel, used from the threads of a single runtime, and `len` valid for writing.
+uint8_t *uthreads_channel_recv(struct UthreadsChannel *chan,
+                               size_t *len);
+
+// Frees a buffer returned by `uthreads_channel_recv`, of `len` bytes.
+//
+// # Safety
+//
+// `data` and `len` must be what `uthreads_channel_recv` returned, and the buffer not freed already.
+void uthreads_buffer_free(uint8_t *data, size_t len);
+
+#endif  /* UTHREADS_H */
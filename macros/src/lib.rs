@@ -0,0 +1,58 @@
+//! Attribute macros of uthreads, re-exported by it when its `macros` feature is enabled.
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{meta, parse_macro_input, ItemFn, LitInt};
+
+/// How long a test may run by default, in milliseconds.
+const DEFAULT_TIMEOUT_MS: u64 = 60_000;
+
+/// Runs the test in a fresh runtime, on a green thread of its own, see `uthreads::testing::run`.
+/// The test fails if it takes longer than `timeout_ms` milliseconds (60 seconds by default), instead of hanging:
+///
+/// ```ignore
+/// #[uthreads::test(timeout_ms = 500)]
+/// fn ping_pong() {
+///     // spawn threads, use channels...
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn test(args: TokenStream, item: TokenStream) -> TokenStream {
+    let mut timeout_ms = DEFAULT_TIMEOUT_MS;
+    let parser = meta::parser(|meta| {
+        if meta.path.is_ident("timeout_ms") {
+            timeout_ms = meta.value()?.parse::<LitInt>()?.base10_parse()?;
+            Ok(())
+        } else {
+            Err(meta.error("unsupported uthreads::test argument, expected `timeout_ms`"))
+        }
+    });
+    parse_macro_input!(args with parser);
+
+    let ItemFn {
+        attrs,
+        vis,
+        sig,
+        block,
+    } = parse_macro_input!(item as ItemFn);
+    if !sig.inputs.is_empty() || sig.asyncness.is_some() {
+        return syn::Error::new_spanned(
+            sig,
+            "uthreads::test functions take no arguments and aren't async",
+        )
+        .to_compile_error()
+        .into();
+    }
+    let name = &sig.ident;
+    let output = &sig.output;
+
+    quote! {
+        #[::core::prelude::v1::test]
+        #(#attrs)*
+        #vis fn #name() #output {
+            fn body() #output #block
+            ::uthreads::testing::run(body, ::std::time::Duration::from_millis(#timeout_ms))
+        }
+    }
+    .into()
+}
@@ -0,0 +1,197 @@
+// Actors: green threads owning a mailbox, whose messages they handle one at a time.
+// Packages the usual pattern of spawning a thread that loops receiving from a channel:
+// the channel belongs to the actor instead of being a global, and is sent to through addresses.
+
+use std::cell::{Cell, UnsafeCell};
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::Rc;
+
+use crate::channel::Channel;
+use crate::runtime::{self, chan_recv, chan_send};
+use crate::thread::Id;
+
+// How many messages a mailbox holds before senders block, see `spawn`.
+pub(crate) const MAILBOX_SIZE: usize = 16;
+
+/// State handled by a thread of its own, which receives the messages sent to its address one at a time.
+pub trait Actor: Sized + 'static {
+    type Message: 'static;
+
+    /// Called on the thread of the actor before it handles any message.
+    fn started(&mut self, _ctx: &mut Context<Self>) {}
+
+    /// Handles a message sent to the actor.
+    fn handle(&mut self, msg: Self::Message, ctx: &mut Context<Self>);
+
+    /// Called on the thread of the actor once it has stopped, right before the thread completes.
+    fn stopped(&mut self) {}
+}
+
+/// A handle to send messages to an actor, which can be cloned and moved to other threads of the same runtime.
+pub struct Addr<A: Actor> {
+    mailbox: Rc<Mailbox<A::Message>>,
+}
+
+struct Mailbox<M> {
+    chan: UnsafeCell<Channel<Envelope<M>>>,
+    // set once the thread of the actor starts.
+    id: Cell<Option<Id>>,
+    stopped: Cell<bool>,
+}
+
+#[derive(Debug)]
+enum Envelope<M> {
+    Message(M),
+    Stop,
+    // sent by the supervisor of the actor, see `supervisor`.
+    Restart,
+}
+
+// Why the thread of an actor completed, which its supervisor is told about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Exit {
+    Stopped,
+    Panicked,
+    Restarting,
+}
+
+// Called on the thread of a supervised actor once it exits.
+pub(crate) type Monitor = Box<dyn FnOnce(Exit)>;
+
+/// Given to the callbacks of an actor, to get its address and stop it.
+pub struct Context<A: Actor> {
+    addr: Addr<A>,
+    stopping: bool,
+}
+
+/// Spawns a thread running `actor`, with a mailbox of a few messages, and returns its address.
+pub fn spawn<A: Actor>(actor: A) -> Addr<A> {
+    spawn_with_capacity(actor, MAILBOX_SIZE)
+}
+
+/// Spawns a thread running `actor`, whose mailbox holds up to `capacity` messages before senders block,
+/// and returns its address. Senders block until the actor receives their message if `capacity` is 0.
+pub fn spawn_with_capacity<A: Actor>(actor: A, capacity: usize) -> Addr<A> {
+    let addr = Addr::with_capacity(capacity);
+    start(&addr, actor, None);
+    addr
+}
+
+// Spawns a thread running `actor` on the mailbox of `addr`, which a supervisor reuses when restarting it.
+// A panic of a supervised actor is reported to `monitor`, instead of aborting the process.
+pub(crate) fn start<A: Actor>(addr: &Addr<A>, actor: A, monitor: Option<Monitor>) {
+    let ctx = Context {
+        addr: addr.clone(),
+        stopping: false,
+    };
+    let id = runtime::spawn(move || run(actor, ctx, monitor));
+    addr.mailbox.id.set(Some(id));
+}
+
+// The thread of an actor.
+fn run<A: Actor>(mut actor: A, mut ctx: Context<A>, monitor: Option<Monitor>) {
+    let exit = match monitor {
+        None => receive(&mut actor, &mut ctx),
+        Some(_) => panic::catch_unwind(AssertUnwindSafe(|| receive(&mut actor, &mut ctx)))
+            .unwrap_or(Exit::Panicked),
+    };
+    // the mailbox of an actor which panicked or restarts is kept, with its messages, for the next one.
+    if exit == Exit::Stopped {
+        ctx.addr.close();
+    }
+    if exit != Exit::Panicked {
+        actor.stopped();
+    }
+    if let Some(monitor) = monitor {
+        monitor(exit);
+    }
+}
+
+// Handles the messages of the mailbox until the actor stops.
+fn receive<A: Actor>(actor: &mut A, ctx: &mut Context<A>) -> Exit {
+    actor.started(ctx);
+    while !ctx.stopping {
+        match unsafe { chan_recv(ctx.addr.mailbox.chan.get()) } {
+            Envelope::Message(msg) => actor.handle(msg, ctx),
+            Envelope::Stop => break,
+            Envelope::Restart => return Exit::Restarting,
+        }
+    }
+    Exit::Stopped
+}
+
+impl<A: Actor> Addr<A> {
+    // The address of an actor which hasn't started yet, see `start`.
+    pub(crate) fn with_capacity(capacity: usize) -> Self {
+        Addr {
+            mailbox: Rc::new(Mailbox {
+                chan: UnsafeCell::new(Channel::new(capacity)),
+                id: Cell::new(None),
+                stopped: Cell::new(false),
+            }),
+        }
+    }
+
+    /// Sends `msg` to the actor, blocking while its mailbox is full.
+    /// Returns the message if the actor has stopped; messages sent while it is stopping are dropped unhandled.
+    pub fn send(&self, msg: A::Message) -> Result<(), A::Message> {
+        if self.mailbox.stopped.get() {
+            return Err(msg);
+        }
+        unsafe { chan_send(self.mailbox.chan.get(), Envelope::Message(msg)) };
+        Ok(())
+    }
+
+    /// Stops the actor once it has handled the messages sent before, if it hasn't stopped yet.
+    pub fn stop(&self) {
+        if !self.mailbox.stopped.get() {
+            unsafe { chan_send(self.mailbox.chan.get(), Envelope::Stop) };
+        }
+    }
+
+    // Makes the actor exit once it has handled the messages sent before, for its supervisor to start it again.
+    pub(crate) fn restart(&self) {
+        if !self.mailbox.stopped.get() {
+            unsafe { chan_send(self.mailbox.chan.get(), Envelope::Restart) };
+        }
+    }
+
+    // Marks the actor as stopped, and drops the messages left, unblocking the threads blocked sending them.
+    // Done by the thread of the actor as it stops, or by its supervisor if the actor isn't running.
+    pub(crate) fn close(&self) {
+        self.mailbox.stopped.set(true);
+        while !unsafe { (*self.mailbox.chan.get()).is_empty() } {
+            drop(unsafe { chan_recv(self.mailbox.chan.get()) });
+        }
+    }
+
+    /// Returns true once the actor has stopped.
+    pub fn is_stopped(&self) -> bool {
+        self.mailbox.stopped.get()
+    }
+
+    /// Returns the id of the thread of the actor, None until it has been started, e.g. by its supervisor.
+    pub fn id(&self) -> Option<Id> {
+        self.mailbox.id.get()
+    }
+}
+
+impl<A: Actor> Clone for Addr<A> {
+    fn clone(&self) -> Self {
+        Addr {
+            mailbox: self.mailbox.clone(),
+        }
+    }
+}
+
+impl<A: Actor> Context<A> {
+    /// Returns the address of the actor, e.g. for it to hand to others.
+    pub fn address(&self) -> Addr<A> {
+        self.addr.clone()
+    }
+
+    /// Stops the actor once the current callback returns, without handling the messages left in its mailbox.
+    pub fn stop(&mut self) {
+        self.stopping = true;
+    }
+}
@@ -0,0 +1,155 @@
+// Everything architecture specific in the runtime: how the state of a thread is saved and restored,
+// and how a new thread is set up to start running.
+// Each backend lives in its own submodule, selected below as `imp`, and provides a `Context` type implementing `Arch`.
+// Supporting a new CPU means adding a submodule and a line here, the rest of the runtime only uses `Arch`.
+// The assembly backends only save what the ABI requires and are used by default,
+// while the ucontext one (enabled by the `ucontext` feature) relies on libc instead and works anywhere libc supports it,
+// and the fiber one (enabled by the `fibers` feature) relies on the fibers provided by Windows.
+// The latter is untested, as the rest of the runtime doesn't build on Windows yet.
+// Under Miri, which can't run assembly, each thread runs on an OS thread of its own instead.
+
+#[cfg(feature = "std")]
+use core::ffi::c_void;
+
+#[cfg(all(
+    target_arch = "x86_64",
+    not(any(feature = "ucontext", feature = "fibers", miri))
+))]
+#[path = "arch/x86_64.rs"]
+mod imp;
+#[cfg(all(
+    target_arch = "aarch64",
+    not(any(feature = "ucontext", feature = "fibers", miri))
+))]
+#[path = "arch/aarch64.rs"]
+mod imp;
+#[cfg(all(
+    target_arch = "riscv64",
+    not(any(feature = "ucontext", feature = "fibers", miri))
+))]
+#[path = "arch/riscv64.rs"]
+mod imp;
+#[cfg(all(feature = "ucontext", not(miri)))]
+#[path = "arch/ucontext.rs"]
+mod imp;
+#[cfg(all(feature = "fibers", not(miri)))]
+#[path = "arch/fiber.rs"]
+mod imp;
+#[cfg(miri)]
+#[path = "arch/threads.rs"]
+mod imp;
+
+pub use imp::Context;
+#[cfg(all(
+    target_arch = "x86_64",
+    not(any(feature = "ucontext", feature = "fibers", miri)),
+    feature = "simd-context"
+))]
+pub use imp::FxArea;
+
+/// Operations every backend provides on its `Context`.
+pub(crate) trait Arch: Default {
+    /// Sets up the context so that switching to it runs `f` on `stack`.
+    fn bootstrap(&mut self, stack: &mut [u8], f: fn());
+
+    /// Saves the state of the running thread into `old` and restores the one of the thread in `new`,
+    /// jumping to it. Returns once the running thread is switched back to.
+    /// Saving and restoring happen together, as the running thread has to stay untouched in between.
+    ///
+    /// # Safety
+    /// `old` must be the context of the running thread, and `new` the context of a thread
+    /// that was either bootstrapped or switched away from.
+    unsafe fn switch(old: *mut Self, new: *const Self);
+
+    /// Where the context of a switched out thread keeps the registers debuggers need to show its frames,
+    /// None if it doesn't keep them in memory.
+    const FRAME: Option<Frame> = None;
+
+    /// Returns why threads can't be switched on the current OS thread, if they can't, see `RuntimeError::Unsupported`.
+    fn unsupported() -> Option<&'static str> {
+        None
+    }
+}
+
+/// Locates the registers of a switched out thread in its `Context`, as they are when `switch` returns,
+/// i.e. in the frame that called it.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Frame {
+    /// Offset of the stack pointer.
+    pub sp: usize,
+    /// Added to the stack pointer saved, as the stack is adjusted between `switch` being entered and it saving it.
+    pub sp_adjust: usize,
+    /// Offset of the frame pointer.
+    pub fp: usize,
+    /// Offset of the return address, None if it is on top of the saved stack instead.
+    pub pc: Option<usize>,
+}
+
+// Returns the address of the instruction a signal interrupted, from the `ucontext_t` passed to its handler,
+// 0 where it isn't known how to read it.
+#[cfg(all(feature = "std", target_os = "linux", target_arch = "x86_64"))]
+pub(crate) unsafe fn program_counter(context: *mut c_void) -> usize {
+    unsafe {
+        (*context.cast::<libc::ucontext_t>()).uc_mcontext.gregs[libc::REG_RIP as usize] as usize
+    }
+}
+
+#[cfg(all(feature = "std", target_os = "linux", target_arch = "aarch64"))]
+pub(crate) unsafe fn program_counter(context: *mut c_void) -> usize {
+    unsafe { (*context.cast::<libc::ucontext_t>()).uc_mcontext.pc as usize }
+}
+
+#[cfg(all(
+    feature = "std",
+    not(all(
+        target_os = "linux",
+        any(target_arch = "x86_64", target_arch = "aarch64")
+    ))
+))]
+pub(crate) unsafe fn program_counter(_: *mut c_void) -> usize {
+    0
+}
+
+// Returns the stack pointer of the code a signal interrupted, like `program_counter`.
+#[cfg(all(feature = "std", target_os = "linux", target_arch = "x86_64"))]
+pub(crate) unsafe fn stack_pointer(context: *mut c_void) -> usize {
+    unsafe {
+        (*context.cast::<libc::ucontext_t>()).uc_mcontext.gregs[libc::REG_RSP as usize] as usize
+    }
+}
+
+#[cfg(all(feature = "std", target_os = "linux", target_arch = "aarch64"))]
+pub(crate) unsafe fn stack_pointer(context: *mut c_void) -> usize {
+    unsafe { (*context.cast::<libc::ucontext_t>()).uc_mcontext.sp as usize }
+}
+
+#[cfg(all(
+    feature = "std",
+    not(all(
+        target_os = "linux",
+        any(target_arch = "x86_64", target_arch = "aarch64")
+    ))
+))]
+pub(crate) unsafe fn stack_pointer(_: *mut c_void) -> usize {
+    0
+}
+
+#[cfg(not(any(
+    target_arch = "x86_64",
+    target_arch = "aarch64",
+    target_arch = "riscv64",
+    feature = "ucontext",
+    feature = "fibers",
+    miri
+)))]
+compile_error!(
+    "there is no context switching backend for this architecture, enable the `ucontext` feature"
+);
+
+#[cfg(all(feature = "fibers", not(windows)))]
+compile_error!("the `fibers` feature is only available on Windows");
+
+#[cfg(all(feature = "fibers", feature = "ucontext"))]
+compile_error!(
+    "the `fibers` and `ucontext` features select different backends and can't be enabled together"
+);
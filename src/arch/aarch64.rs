@@ -0,0 +1,92 @@
+use core::arch::asm;
+use core::mem::offset_of;
+
+use super::{Arch, Frame};
+use crate::runtime::{done, start};
+
+/// Stores information about a thread that we want preserved between thread switches.
+/// Currently, we only store the callee saved registers: x19-x28, the frame pointer, the link register,
+/// the stack pointer, the floating point control register and the lower halves of v8-v15.
+#[derive(Debug, Default)]
+#[repr(C)]
+pub struct Context {
+    pub x19: u64,
+    pub x20: u64,
+    pub x21: u64,
+    pub x22: u64,
+    pub x23: u64,
+    pub x24: u64,
+    pub x25: u64,
+    pub x26: u64,
+    pub x27: u64,
+    pub x28: u64,
+    pub fp: u64,
+    pub lr: u64,
+    pub sp: u64,
+    pub fpcr: u64,
+    pub d8: u64,
+    pub d9: u64,
+    pub d10: u64,
+    pub d11: u64,
+    pub d12: u64,
+    pub d13: u64,
+    pub d14: u64,
+    pub d15: u64,
+}
+
+impl Arch for Context {
+    fn bootstrap(&mut self, stack: &mut [u8], f: fn()) {
+        let s_ptr = unsafe { stack.as_mut_ptr().add(stack.len()) };
+        // the stack pointer must always be aligned to 16 bytes.
+        self.sp = (s_ptr as usize & !15) as u64;
+        // `switch` returns to the address in the link register, i.e, to `uthreads_thread_entry`,
+        // which runs the user function (passed in x19) between the setup and the cleanup of the thread (in x20 and x21).
+        // Neither `ret` nor `blr` authenticates the addresses, so they mustn't be signed.
+        self.lr = strip_pac(uthreads_thread_entry as *const () as usize) as u64;
+        self.x19 = strip_pac(f as usize) as u64;
+        self.x20 = strip_pac(start as fn() as usize) as u64;
+        self.x21 = strip_pac(done as fn() as usize) as u64;
+    }
+
+    // Unlike `call` on x86_64, `bl` doesn't touch the stack, so nothing below the stack pointer is overwritten.
+    #[inline(always)]
+    unsafe fn switch(old: *mut Context, new: *const Context) {
+        asm!(
+            "bl {switch}",
+            switch = sym uthreads_switch_registers,
+            in("x0") old,
+            in("x1") new,
+            clobber_abi("C")
+        );
+    }
+
+    const FRAME: Option<Frame> = Some(Frame {
+        sp: offset_of!(Context, sp),
+        sp_adjust: 0,
+        fp: offset_of!(Context, fp),
+        pc: Some(offset_of!(Context, lr)),
+    });
+}
+
+// Removes the pointer authentication code from a code pointer, which the arm64e ABI of Apple signs function pointers with.
+// `xpaclri` is in the hint space, so it does nothing on CPUs without pointer authentication, and to unsigned pointers.
+fn strip_pac(ptr: usize) -> usize {
+    let stripped;
+    unsafe {
+        asm!(
+            "hint #7",
+            inout("x30") ptr => stripped,
+            options(nomem, nostack, preserves_flags)
+        )
+    };
+    stripped
+}
+
+// Defined in `aarch64.S`, assembled by the build script.
+extern "C" {
+    // Bottom frame of every thread, switched to when the thread first runs.
+    // Runs the setup (kept in x20), the user function (in x19) and the cleanup (in x21), in order.
+    fn uthreads_thread_entry();
+    // Saves the callee saved registers into the `Context` in x0, and restores those of the `Context` in x1.
+    fn uthreads_switch_registers();
+}
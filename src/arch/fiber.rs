@@ -0,0 +1,88 @@
+use std::ffi::c_void;
+
+use super::Arch;
+use crate::runtime::{done, start};
+
+#[link(name = "kernel32")]
+extern "system" {
+    fn ConvertThreadToFiber(parameter: *mut c_void) -> *mut c_void;
+    fn CreateFiber(
+        stack_size: usize,
+        start_address: unsafe extern "system" fn(*mut c_void),
+        parameter: *mut c_void,
+    ) -> *mut c_void;
+    fn DeleteFiber(fiber: *mut c_void);
+    fn SwitchToFiber(fiber: *mut c_void);
+}
+
+/// Stores information about a thread that we want preserved between thread switches.
+/// Each thread runs on a Windows fiber, which saves the registers (and owns the stack) for us.
+#[derive(Debug)]
+pub struct Context {
+    fiber: *mut c_void,
+    // the fiber of the thread that started the runtime, created by converting it rather than with `CreateFiber`.
+    converted: bool,
+}
+
+impl Default for Context {
+    fn default() -> Self {
+        Context {
+            fiber: std::ptr::null_mut(),
+            converted: false,
+        }
+    }
+}
+
+impl Drop for Context {
+    fn drop(&mut self) {
+        // deleting a converted fiber would terminate the OS thread running it.
+        if !self.fiber.is_null() && !self.converted {
+            unsafe { DeleteFiber(self.fiber) };
+        }
+    }
+}
+
+impl Arch for Context {
+    // Fibers allocate their own stack, so only the size of `stack` is used.
+    fn bootstrap(&mut self, stack: &mut [u8], f: fn()) {
+        let fiber = unsafe { CreateFiber(stack.len(), thread_entry, f as usize as *mut c_void) };
+        if fiber.is_null() {
+            panic!(
+                "failed to create a fiber: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+        self.fiber = fiber;
+    }
+
+    unsafe fn switch(old: *mut Context, new: *const Context) {
+        unsafe {
+            // the thread that started the runtime is not a fiber yet the first time it switches away.
+            if (*old).fiber.is_null() {
+                let fiber = ConvertThreadToFiber(std::ptr::null_mut());
+                if fiber.is_null() {
+                    panic!(
+                        "failed to convert the thread to a fiber: {}",
+                        std::io::Error::last_os_error()
+                    );
+                }
+                (*old).fiber = fiber;
+                (*old).converted = true;
+            }
+            // the state of the running fiber is saved by `SwitchToFiber` itself.
+            SwitchToFiber((*new).fiber);
+        }
+    }
+}
+
+// Bottom frame of every thread, switched to when the thread first runs.
+// Runs the setup, the user function and the cleanup, in order.
+unsafe extern "system" fn thread_entry(f: *mut c_void) {
+    let f: fn() = unsafe { std::mem::transmute(f) };
+
+    start();
+    f();
+    done();
+    // returning from a fiber would terminate the OS thread running it.
+    unreachable!("a completed thread was switched back to");
+}
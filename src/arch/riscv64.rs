@@ -0,0 +1,61 @@
+use core::arch::asm;
+use core::mem::offset_of;
+
+use super::{Arch, Frame};
+use crate::runtime::{done, start};
+
+/// Stores information about a thread that we want preserved between thread switches.
+/// Currently, we only store the callee saved registers: the return address, the stack pointer, s0-s11
+/// and, if the target has double precision floating point registers, fs0-fs11.
+#[derive(Debug, Default)]
+#[repr(C)]
+pub struct Context {
+    pub ra: u64,
+    pub sp: u64,
+    pub s: [u64; 12],
+    #[cfg(target_feature = "d")]
+    pub fs: [u64; 12],
+}
+
+impl Arch for Context {
+    fn bootstrap(&mut self, stack: &mut [u8], f: fn()) {
+        let s_ptr = unsafe { stack.as_mut_ptr().add(stack.len()) };
+        // the stack pointer must always be aligned to 16 bytes.
+        self.sp = (s_ptr as usize & !15) as u64;
+        // `switch` returns to the return address, i.e, to `uthreads_thread_entry`,
+        // which runs the user function (passed in s1) between the setup and the cleanup of the thread (in s2 and s3).
+        self.ra = uthreads_thread_entry as *const () as usize as u64;
+        self.s[1] = f as usize as u64;
+        self.s[2] = start as fn() as usize as u64;
+        self.s[3] = done as fn() as usize as u64;
+    }
+
+    // `call` only writes the return address register, so nothing below the stack pointer is overwritten.
+    #[inline(always)]
+    unsafe fn switch(old: *mut Context, new: *const Context) {
+        asm!(
+            "call {switch}",
+            switch = sym uthreads_switch_registers,
+            in("a0") old,
+            in("a1") new,
+            clobber_abi("C")
+        );
+    }
+
+    // the frame pointer is s0.
+    const FRAME: Option<Frame> = Some(Frame {
+        sp: offset_of!(Context, sp),
+        sp_adjust: 0,
+        fp: offset_of!(Context, s),
+        pc: Some(offset_of!(Context, ra)),
+    });
+}
+
+// Defined in `riscv64.S`, assembled by the build script.
+extern "C" {
+    // Bottom frame of every thread, switched to when the thread first runs.
+    // Runs the setup (kept in s2), the user function (in s1) and the cleanup (in s3), in order.
+    fn uthreads_thread_entry();
+    // Saves the callee saved registers into the `Context` in a0, and restores those of the `Context` in a1.
+    fn uthreads_switch_registers();
+}
@@ -0,0 +1,80 @@
+use std::sync::{Arc, Condvar, Mutex};
+
+use super::Arch;
+use crate::runtime::{done, start, Core};
+use crate::{generator, runtime, set_runtime};
+
+/// Stores information about a thread that we want preserved between thread switches.
+/// Each thread runs on an OS thread of its own, which waits until it is switched to,
+/// so there are no registers to save: only the means to tell the OS thread it may run.
+/// Much slower than the other backends, but free of assembly, so that the runtime can run under Miri.
+/// The OS thread of a completed thread stays blocked for good, so Miri has to be run with `-Zmiri-ignore-leaks`.
+#[derive(Debug, Default)]
+pub struct Context {
+    baton: Arc<Baton>,
+}
+
+// Set when the thread may run, and cleared again once it does.
+// Hands over the runtime too, as every OS thread has a RUNTIME of its own, and the body of the generator starting if any.
+#[derive(Debug, Default)]
+struct Baton {
+    ready: Mutex<Option<Handover>>,
+    cond: Condvar,
+}
+
+#[derive(Debug)]
+struct Handover {
+    core: *mut Core,
+    starting: *mut Option<Box<dyn FnOnce()>>,
+}
+
+// only ever used by the thread holding the baton.
+unsafe impl Send for Handover {}
+
+impl Baton {
+    fn give(&self) {
+        *self.ready.lock().unwrap() = Some(Handover {
+            core: runtime(),
+            starting: generator::starting(),
+        });
+        self.cond.notify_one();
+    }
+
+    fn wait(&self) {
+        let mut ready = self.ready.lock().unwrap();
+        loop {
+            if let Some(handover) = ready.take() {
+                set_runtime(handover.core);
+                generator::set_starting(handover.starting);
+                return;
+            }
+            ready = self.cond.wait(ready).unwrap();
+        }
+    }
+}
+
+impl Arch for Context {
+    // The OS thread gets a stack of its own, so only the size of `stack` is used.
+    fn bootstrap(&mut self, stack: &mut [u8], f: fn()) {
+        let baton = self.baton.clone();
+        std::thread::Builder::new()
+            .stack_size(stack.len())
+            .spawn(move || {
+                baton.wait();
+                start();
+                f();
+                done();
+                unreachable!("a completed thread was switched back to");
+            })
+            .expect("failed to spawn an OS thread");
+    }
+
+    unsafe fn switch(old: *mut Context, new: *const Context) {
+        unsafe {
+            // the baton of `old` may be dropped as soon as `new` runs, if the thread has completed.
+            let old = (*old).baton.clone();
+            (*new).baton.give();
+            old.wait();
+        }
+    }
+}
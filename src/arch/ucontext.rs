@@ -0,0 +1,94 @@
+use std::ffi::c_void;
+use std::mem::MaybeUninit;
+
+use super::Arch;
+use crate::runtime::{done, start};
+
+extern "C" {
+    fn getcontext(ucp: *mut libc::ucontext_t) -> libc::c_int;
+    fn makecontext(ucp: *mut libc::ucontext_t, func: extern "C" fn(), argc: libc::c_int, ...);
+    fn swapcontext(oucp: *mut libc::ucontext_t, ucp: *const libc::ucontext_t) -> libc::c_int;
+    #[cfg(target_os = "linux")]
+    fn fesetenv(envp: *const c_void) -> libc::c_int;
+}
+
+// `FE_DFL_ENV` from fenv.h, the default floating point environment, which is a sentinel with glibc and musl.
+#[cfg(target_os = "linux")]
+const FE_DFL_ENV: *const c_void = usize::MAX as *const c_void;
+
+/// Stores information about a thread that we want preserved between thread switches.
+/// The whole machine context (and signal mask) is saved by libc,
+/// which is slower than only saving the callee saved registers but works on any architecture.
+#[derive(Debug)]
+pub struct Context {
+    // boxed, as the context may point into itself (e.g. to the floating point state in glibc) and must not move.
+    uc: Box<MaybeUninit<libc::ucontext_t>>,
+}
+
+impl Default for Context {
+    fn default() -> Self {
+        Context {
+            uc: Box::new(MaybeUninit::zeroed()),
+        }
+    }
+}
+
+impl Arch for Context {
+    fn bootstrap(&mut self, stack: &mut [u8], f: fn()) {
+        let uc = self.uc.as_mut_ptr();
+        unsafe {
+            if getcontext(uc) < 0 {
+                panic!(
+                    "failed to initialise a thread context: {}",
+                    std::io::Error::last_os_error()
+                );
+            }
+            (*uc).uc_stack.ss_sp = stack.as_mut_ptr().cast();
+            (*uc).uc_stack.ss_size = stack.len();
+            (*uc).uc_link = std::ptr::null_mut();
+
+            // only int arguments can be passed portably, so the function pointer is split in two halves.
+            let f = f as usize as u64;
+            makecontext(
+                uc,
+                std::mem::transmute::<extern "C" fn(libc::c_int, libc::c_int), extern "C" fn()>(
+                    thread_entry,
+                ),
+                2,
+                (f >> 32) as u32 as libc::c_int,
+                f as u32 as libc::c_int,
+            );
+        }
+    }
+
+    unsafe fn switch(old: *mut Context, new: *const Context) {
+        unsafe {
+            let old = (*old).uc.as_mut_ptr();
+            let new = (*new).uc.as_ptr();
+            if swapcontext(old, new) < 0 {
+                panic!(
+                    "failed to switch threads: {}",
+                    std::io::Error::last_os_error()
+                );
+            }
+        }
+    }
+}
+
+// Bottom frame of every thread, switched to when the thread first runs.
+// Runs the setup, the user function and the cleanup, in order.
+extern "C" fn thread_entry(hi: libc::c_int, lo: libc::c_int) {
+    let f = ((hi as u32 as u64) << 32 | lo as u32 as u64) as usize;
+    let f: fn() = unsafe { std::mem::transmute(f) };
+
+    // the context was made from that of the thread setting it up, floating point environment included,
+    // e.g. the rounding mode that thread had set, while threads start with the default one as on the other backends.
+    #[cfg(target_os = "linux")]
+    unsafe {
+        fesetenv(FE_DFL_ENV);
+    }
+    start();
+    f();
+    done();
+    unreachable!("a completed thread was switched back to");
+}
@@ -0,0 +1,211 @@
+use core::arch::asm;
+use core::mem::offset_of;
+
+use super::{Arch, Frame};
+use crate::runtime::{done, start};
+
+/// Stores information about a thread that we want preserved between thread switches.
+/// Currently, we only store the callee saved registers,
+/// including the floating point control state (rounding mode, exception masks) which is callee saved too.
+#[derive(Debug)]
+#[repr(C)]
+pub struct Context {
+    pub rsp: u64,
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub rbx: u64,
+    pub rbp: u64,
+    pub mxcsr: u32,
+    pub fpucw: u16,
+    /// All the x87 and SSE registers, as saved by `fxsave`.
+    #[cfg(feature = "simd-context")]
+    pub fxsave: FxArea,
+}
+
+/// Memory layout expected by the `fxsave` and `fxrstor` instructions.
+#[cfg(feature = "simd-context")]
+#[derive(Debug)]
+#[repr(C, align(16))]
+pub struct FxArea(pub [u8; 512]);
+
+#[cfg(feature = "simd-context")]
+impl Default for FxArea {
+    fn default() -> Self {
+        let mut area = [0; 512];
+        // control words in the same state as the ones stored separately in `Context`,
+        // as `fxrstor` overwrites them.
+        area[0..2].copy_from_slice(&0x037f_u16.to_ne_bytes());
+        area[24..28].copy_from_slice(&0x1f80_u32.to_ne_bytes());
+        FxArea(area)
+    }
+}
+
+impl Default for Context {
+    fn default() -> Self {
+        Context {
+            rsp: 0,
+            r15: 0,
+            r14: 0,
+            r13: 0,
+            r12: 0,
+            rbx: 0,
+            rbp: 0,
+            // the values set up by the OS for a new process:
+            // round to nearest, all floating point exceptions masked, and x87 in extended precision.
+            mxcsr: 0x1f80,
+            fpucw: 0x037f,
+            #[cfg(feature = "simd-context")]
+            fxsave: FxArea::default(),
+        }
+    }
+}
+
+impl Arch for Context {
+    fn bootstrap(&mut self, stack: &mut [u8], f: fn()) {
+        unsafe {
+            let s_ptr = stack.as_mut_ptr().add(stack.len());
+            let s_ptr = s_ptr.map_addr(|addr| addr & !15);
+            // the thread starts in `uthreads_thread_entry`, which runs the user function (passed in r12)
+            // between the setup and the cleanup of the thread (passed in r13 and r14).
+            core::ptr::write(
+                s_ptr.offset(-16) as *mut usize,
+                uthreads_thread_entry as *const () as usize,
+            );
+            // bookkeeping
+            self.rsp = s_ptr.offset(-16) as u64;
+            self.r12 = f as usize as u64;
+            self.r13 = start as fn() as usize as u64;
+            self.r14 = done as fn() as usize as u64;
+        }
+    }
+
+    // Functions that don't call other functions may keep data in the 128 bytes below rsp (the red zone),
+    // which `call` would overwrite with the return address, so the red zone is skipped for the duration of the switch.
+    #[inline(always)]
+    unsafe fn switch(old: *mut Context, new: *const Context) {
+        asm!(
+            "sub rsp, 128",
+            "call {switch}",
+            "add rsp, 128",
+            switch = sym uthreads_switch_registers,
+            in("rdi") old,
+            in("rsi") new,
+            clobber_abi("C")
+        );
+    }
+
+    // `call` pushed the return address and skipped the red zone.
+    const FRAME: Option<Frame> = Some(Frame {
+        sp: offset_of!(Context, rsp),
+        sp_adjust: 8 + 128,
+        fp: offset_of!(Context, rbp),
+        pc: None,
+    });
+
+    // The shadow stack of Intel CET keeps a copy of the return addresses, which `ret` checks against the stack.
+    // The switch returns into another stack without switching shadow stacks, which would fault,
+    // whereas glibc's `swapcontext` does switch them.
+    #[cfg(target_os = "linux")]
+    fn unsupported() -> Option<&'static str> {
+        const ARCH_SHSTK_STATUS: libc::c_ulong = 0x5005;
+        const ARCH_SHSTK_SHSTK: u64 = 1;
+        let mut features = 0_u64;
+        // kernels without shadow stacks don't know the request, and have none enabled.
+        let res = unsafe {
+            libc::syscall(
+                libc::SYS_arch_prctl,
+                ARCH_SHSTK_STATUS,
+                &mut features as *mut u64,
+            )
+        };
+        (res == 0 && features & ARCH_SHSTK_SHSTK != 0).then_some(
+            "the shadow stack of Intel CET is enabled, which the assembly backend doesn't switch, \
+             use the `ucontext` feature or disable it",
+        )
+    }
+}
+
+// Defined in `x86_64.S`, assembled by the build script.
+extern "C" {
+    // Bottom frame of every thread, switched to when the thread first runs.
+    // Runs the setup (kept in r13), the user function (in r12) and the cleanup (in r14), in order.
+    fn uthreads_thread_entry();
+    // Saves the callee saved registers into the `Context` in rdi, and restores those of the `Context` in rsi.
+    fn uthreads_switch_registers();
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use core::arch::asm;
+    use std::ptr;
+
+    use super::Context;
+    use crate::arch::Arch;
+    use crate::thread::{MmapAllocator, StackAllocator};
+
+    // The contexts of the test and of the thread it switches to, reached without going through the stack.
+    static mut CONTEXTS: *mut (Context, Context) = ptr::null_mut();
+
+    const PATTERN: u64 = 0x5a5a_5a5a_5a5a_5a5a;
+
+    // Fills the red zone with a pattern, switches to the thread and back, and returns how many of its words changed.
+    // Nothing is called but `switch`, so nothing else may write below rsp in between.
+    #[inline(never)]
+    unsafe fn leaf() -> u64 {
+        let (main, thread) = unsafe { (&raw mut (*CONTEXTS).0, &raw const (*CONTEXTS).1) };
+        unsafe {
+            asm!(
+                "lea rcx, [rsp - 128]",
+                "2:",
+                "mov [rcx], {pattern}",
+                "add rcx, 8",
+                "cmp rcx, rsp",
+                "jne 2b",
+                pattern = in(reg) PATTERN,
+                out("rcx") _,
+            );
+            Context::switch(main, thread);
+        }
+        let changed: u64;
+        unsafe {
+            asm!(
+                "xor {changed}, {changed}",
+                "lea rcx, [rsp - 128]",
+                "2:",
+                "cmp [rcx], {pattern}",
+                "setne dl",
+                "movzx rdx, dl",
+                "add {changed}, rdx",
+                "add rcx, 8",
+                "cmp rcx, rsp",
+                "jne 2b",
+                pattern = in(reg) PATTERN,
+                changed = out(reg) changed,
+                out("rcx") _,
+                out("rdx") _,
+            );
+        }
+        changed
+    }
+
+    // Switches straight back to the test, which never switches to this thread again.
+    fn thread() {
+        unsafe { Context::switch(&raw mut (*CONTEXTS).1, &raw const (*CONTEXTS).0) };
+        unreachable!("the thread was switched back to");
+    }
+
+    #[test]
+    fn switch_preserves_red_zone() {
+        let mut stack = MmapAllocator.allocate(64 * 1024, true).unwrap();
+        let mut contexts = Box::new((Context::default(), Context::default()));
+        contexts.1.bootstrap(&mut stack, thread);
+        unsafe {
+            CONTEXTS = &mut *contexts;
+            assert_eq!(leaf(), 0, "the switch overwrote the red zone of its caller");
+            CONTEXTS = ptr::null_mut();
+        }
+        MmapAllocator.deallocate(stack);
+    }
+}
@@ -0,0 +1,122 @@
+// A small pool of OS threads that run blocking operations on behalf of green threads.
+// The green thread submitting a job is blocked until the job has run,
+// and is woken up by the worker through the reactor's doorbell, so other green threads keep running meanwhile.
+
+use std::collections::VecDeque;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+use std::thread;
+use std::time::Duration;
+
+use crate::preempt::NoPreempt;
+use crate::runtime::{get_current_thread, notifier, wait_notified};
+
+/// Maximum number of worker threads.
+const MAX_WORKERS: usize = 8;
+/// How long an idle worker waits for a new job before exiting.
+const KEEP_ALIVE: Duration = Duration::from_secs(10);
+
+type Job = Box<dyn FnOnce() + Send>;
+
+struct Pool {
+    state: Mutex<PoolState>,
+    /// Signalled when a job is queued.
+    cond: Condvar,
+}
+
+struct PoolState {
+    jobs: VecDeque<Job>,
+    workers: usize,
+    idle: usize,
+}
+
+// The pool is shared by every runtime in the process, as it doesn't depend on any of them.
+static POOL: OnceLock<Pool> = OnceLock::new();
+
+fn pool() -> &'static Pool {
+    POOL.get_or_init(|| Pool {
+        state: Mutex::new(PoolState {
+            jobs: VecDeque::new(),
+            workers: 0,
+            idle: 0,
+        }),
+        cond: Condvar::new(),
+    })
+}
+
+impl Pool {
+    fn execute(&'static self, job: Job) {
+        let mut state = self.state.lock().unwrap();
+        state.jobs.push_back(job);
+
+        if state.idle > 0 {
+            self.cond.notify_one();
+        } else if state.workers < MAX_WORKERS {
+            state.workers += 1;
+            thread::Builder::new()
+                .name("uthreads-blocking".into())
+                .spawn(move || self.work())
+                .expect("failed to spawn a blocking pool worker");
+        }
+        // otherwise, the job waits for one of the busy workers to finish.
+    }
+
+    fn work(&self) {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(job) = state.jobs.pop_front() {
+                drop(state);
+                job();
+                state = self.state.lock().unwrap();
+                continue;
+            }
+
+            state.idle += 1;
+            let (guard, res) = self.cond.wait_timeout(state, KEEP_ALIVE).unwrap();
+            state = guard;
+            state.idle -= 1;
+            if res.timed_out() && state.jobs.is_empty() {
+                state.workers -= 1;
+                return;
+            }
+        }
+    }
+}
+
+/// Runs `f` on a helper OS thread and blocks the current green thread until it returns,
+/// while the other green threads keep running.
+/// Meant for calls that block the OS thread, e.g. into C libraries or on synchronous IO.
+/// A panic in `f` is propagated to the calling green thread.
+pub fn spawn_blocking<F, T>(f: F) -> T
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let id = get_current_thread();
+    let notifier = notifier();
+    let slot = Arc::new(Mutex::new(None));
+
+    let result = slot.clone();
+    let job: Job = Box::new(move || {
+        let res = panic::catch_unwind(AssertUnwindSafe(f));
+        *result.lock().unwrap() = Some(res);
+        notifier.notify(id);
+    });
+    {
+        // the pool is locked while the job is queued, and other threads must not be switched to meanwhile.
+        let _no_preempt = NoPreempt::new();
+        pool().execute(job);
+    }
+
+    wait_notified();
+
+    let res = slot
+        .lock()
+        .unwrap()
+        .take()
+        .expect("thread woken up before its blocking job completed");
+    match res {
+        Ok(val) => val,
+        Err(payload) => panic::resume_unwind(payload),
+    }
+}
@@ -4,38 +4,357 @@
 // not sync or send - using raw pointers will ensure this.
 // make channel copy
 
-use std::alloc::{alloc_zeroed, Layout};
-use std::cmp::Ordering;
-use std::mem;
+use alloc::alloc::{alloc, dealloc, handle_alloc_error, Layout};
+use alloc::collections::VecDeque;
+use alloc::rc::Rc;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::cell::Cell;
+use core::cmp::Ordering;
+use core::error;
+use core::fmt;
+use core::mem;
+use core::ptr::NonNull;
+use core::task::Waker;
+use core::time::Duration;
 
+use crate::runtime::{
+    register_channel, release_permit, send_permitted, wake_selector, ChannelMetrics,
+};
+use crate::time::{self, Instant};
 use crate::Id;
 
-const BLOCK_QUEUE_SIZE: usize = 10;
+mod bus;
+mod call;
+#[cfg(feature = "std")]
+mod remote;
+#[cfg(feature = "std")]
+mod spsc;
+mod stream;
 
+pub use bus::{Bus, Subscription};
+pub use call::{call_channel, Client, Reply, Server};
+#[cfg(feature = "std")]
+pub use remote::{remote_channel, RemoteReceiver, RemoteSender};
+#[cfg(feature = "std")]
+pub use spsc::{spsc, SpscReceiver, SpscSender};
+pub use stream::{ChannelSink, ChannelStream, RecvFuture, SendFuture};
+
+/// A channel passing values of type `T` between the threads of a runtime, through a buffer of fixed size,
+/// see `chan_send` and `chan_recv`.
+/// Values of different types can go through the same channel as boxed trait objects, e.g. `Channel<Box<dyn Message>>`,
+/// rather than as the variants of an enum: `chan_send(chan, Box::new(msg))` boxes and converts the value in one go.
 // #[derive(Clone, Copy)]
 pub struct Channel<T> {
     pub buffer: CircularBuffer<T>,
-    pub sendq: CircularBuffer<(Id, T)>,
-    pub recvq: CircularBuffer<Id>,
+    // the threads blocked on the channel, which are as many as there are threads.
+    pub sendq: VecDeque<(Id, T)>,
+    pub recvq: VecDeque<Id>,
+    // values handed over to threads blocked on the channel, which take them once they run again:
+    // those sent to blocked receivers, and those given back to blocked senders as they are interrupted.
+    handoff: Vec<(Id, T)>,
+    // threads waiting in a select for a value to receive, see `Select`.
+    selectors: Vec<Id>,
+    // how much of the buffer is kept for the values of permits, and the threads waiting for a permit, see `chan_reserve`.
+    reserved: usize,
+    reservers: Vec<Id>,
+    // what happens to the values sent while the buffer is full, and how many were dropped.
+    overflow: Overflow,
+    dropped: u64,
+    // the activity of the channel, counted once it is named, see `set_name`.
+    stats: Option<Rc<Stats>>,
+    // tasks of async runtimes waiting to receive from and to send to the channel, see `ChannelStream` and `ChannelSink`.
+    receivers: Vec<Waker>,
+    senders: Vec<Waker>,
 }
 
 impl<T> Channel<T> {
+    /// Creates a channel whose buffer holds `size` values before senders block.
     pub fn new(size: usize) -> Self {
+        Channel::with_overflow(size, Overflow::Block)
+    }
+
+    /// Creates a channel whose buffer holds `size` values, handling those sent while it is full according to `overflow`,
+    /// e.g. dropping old events rather than blocking the thread producing them.
+    pub fn with_overflow(size: usize, overflow: Overflow) -> Self {
         let buffer = CircularBuffer::<T>::new(size);
-        let sendq = CircularBuffer::<(Id, T)>::new(BLOCK_QUEUE_SIZE);
-        let recvq = CircularBuffer::<Id>::new(BLOCK_QUEUE_SIZE);
 
         Channel {
             buffer,
-            sendq,
-            recvq,
+            sendq: VecDeque::new(),
+            recvq: VecDeque::new(),
+            handoff: Vec::new(),
+            selectors: Vec::new(),
+            reserved: 0,
+            reservers: Vec::new(),
+            overflow,
+            dropped: 0,
+            stats: None,
+            receivers: Vec::new(),
+            senders: Vec::new(),
+        }
+    }
+
+    pub fn overflow(&self) -> Overflow {
+        self.overflow
+    }
+
+    /// Returns how many values have been dropped as they were sent while the buffer was full, see `Overflow`.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    pub(crate) fn count_dropped(&mut self) {
+        self.dropped += 1;
+    }
+
+    /// Names the channel, e.g. after the stage of a pipeline it feeds, and starts counting its activity:
+    /// the values sent and received, and how long threads blocked on it, which `Runtime::metrics` reports per channel,
+    /// so that the busy or stalled channels stand out. The events traced about the channel mention its name as well.
+    /// The runtime of the OS thread it is named on reports it, until it is dropped.
+    pub fn set_name(&mut self, name: impl Into<String>) {
+        let stats = Rc::new(Stats {
+            name: name.into(),
+            sent: Cell::new(0),
+            received: Cell::new(0),
+            depth: Cell::new(self.buffer.len()),
+            blocked: Cell::new(Duration::ZERO),
+            buffer_bytes: self.buffer.bytes(),
+        });
+        register_channel(Rc::downgrade(&stats));
+        self.stats = Some(stats);
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.stats.as_deref().map(|stats| stats.name.as_str())
+    }
+
+    // Describes the channel in trace events, by its name if it has one.
+    pub(crate) fn label(&self) -> Label<'_> {
+        Label(self.name())
+    }
+
+    pub(crate) fn count_sent(&self) {
+        if let Some(stats) = &self.stats {
+            stats.sent.set(stats.sent.get() + 1);
+            stats.depth.set(self.buffer.len());
+        }
+    }
+
+    pub(crate) fn count_received(&self) {
+        if let Some(stats) = &self.stats {
+            stats.received.set(stats.received.get() + 1);
+            stats.depth.set(self.buffer.len());
+        }
+    }
+
+    // Returns when a thread blocking on the channel started to, if how long threads block on it is counted.
+    pub(crate) fn block_started(&self) -> Option<Instant> {
+        self.stats.as_ref().map(|_| time::instant())
+    }
+
+    pub(crate) fn count_blocked(&self, since: Option<Instant>) {
+        if let (Some(stats), Some(since)) = (&self.stats, since) {
+            stats.blocked.set(stats.blocked.get() + since.elapsed());
         }
     }
+
+    // Hands `val` over to thread `id`, blocked on the channel, which takes it with `take_handoff` once it runs again.
+    pub(crate) fn hand_off(&mut self, id: Id, val: T) {
+        assert!(
+            self.handoff.iter().all(|(to, _)| *to != id),
+            "a value was handed over twice to the same thread"
+        );
+        self.handoff.push((id, val));
+    }
+
+    pub(crate) fn take_handoff(&mut self, id: Id) -> Option<T> {
+        let pos = self.handoff.iter().position(|(to, _)| *to == id)?;
+        Some(self.handoff.swap_remove(pos).1)
+    }
+
+    pub(crate) fn add_receiver(&mut self, waker: &Waker) {
+        add_waker(&mut self.receivers, waker);
+    }
+
+    pub(crate) fn add_sender(&mut self, waker: &Waker) {
+        add_waker(&mut self.senders, waker);
+    }
+
+    // True if receiving would block, as there is neither a value in the buffer nor a thread blocked sending one.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.buffer.is_empty() && self.sendq.is_empty()
+    }
+
+    // How many threads are blocked receiving from the channel.
+    #[cfg(feature = "std")]
+    pub(crate) fn receivers_blocked(&self) -> usize {
+        self.recvq.len()
+    }
+
+    // True if a value can go into the buffer without taking the room kept for permits.
+    pub(crate) fn has_room(&self) -> bool {
+        self.buffer.len() + self.reserved < self.buffer.size
+    }
+
+    // Keeps room in the buffer for the value of a permit, if there is any.
+    pub(crate) fn reserve(&mut self) -> bool {
+        assert!(
+            self.buffer.size > 0,
+            "reserving room in a channel without a buffer"
+        );
+        let room = self.has_room();
+        self.reserved += room as usize;
+        room
+    }
+
+    // Gives back the room kept for the value of a permit, as it is sent or dropped.
+    pub(crate) fn unreserve(&mut self) {
+        self.reserved -= 1;
+    }
+
+    pub(crate) fn add_reserver(&mut self, id: Id) {
+        self.reservers.push(id);
+    }
+
+    #[cfg(feature = "std")]
+    pub(crate) fn add_selector(&mut self, id: Id) {
+        self.selectors.push(id);
+    }
+
+    #[cfg(feature = "std")]
+    pub(crate) fn remove_selector(&mut self, id: Id) {
+        self.selectors.retain(|&selector| selector != id);
+    }
+
+    // Wakes up the tasks and the selecting threads waiting to receive, as a value may be there for them.
+    pub(crate) fn wake_receivers(&mut self) {
+        self.receivers.drain(..).for_each(Waker::wake);
+        self.selectors.drain(..).for_each(wake_selector);
+    }
+
+    // Wakes up the tasks waiting to send and the threads waiting for a permit, as there may be room for their values.
+    pub(crate) fn wake_senders(&mut self) {
+        self.senders.drain(..).for_each(Waker::wake);
+        self.reservers.drain(..).for_each(wake_selector);
+    }
+}
+
+// The activity of a named channel, shared with the runtime which reports it.
+pub(crate) struct Stats {
+    name: String,
+    sent: Cell<u64>,
+    received: Cell<u64>,
+    // how many values were in the buffer as of the last one sent or received.
+    depth: Cell<usize>,
+    blocked: Cell<Duration>,
+    // size of the memory allocated for the buffer, which never changes.
+    buffer_bytes: usize,
+}
+
+impl Stats {
+    pub(crate) fn metrics(&self) -> ChannelMetrics {
+        ChannelMetrics {
+            name: self.name.clone(),
+            sent: self.sent.get(),
+            received: self.received.get(),
+            depth: self.depth.get(),
+            blocked: self.blocked.get(),
+        }
+    }
+
+    pub(crate) fn buffer_bytes(&self) -> usize {
+        self.buffer_bytes
+    }
+}
+
+pub(crate) struct Label<'a>(Option<&'a str>);
+
+impl fmt::Display for Label<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(name) => write!(f, "channel {name:?}"),
+            None => write!(f, "a channel"),
+        }
+    }
+}
+
+/// Room kept in the buffer of a channel for one value, see `chan_reserve`.
+/// Sending with it neither blocks nor fails. Dropping it unused gives the room back.
+pub struct Permit<T> {
+    chan: NonNull<Channel<T>>,
+}
+
+impl<T> Permit<T> {
+    pub(crate) fn new(chan: NonNull<Channel<T>>) -> Self {
+        Permit { chan }
+    }
+
+    /// Sends `val` into the room kept for it, or directly to a thread blocked receiving.
+    pub fn send(self, val: T) {
+        let chan = self.chan;
+        mem::forget(self);
+        send_permitted(unsafe { &mut *chan.as_ptr() }, val);
+    }
+}
+
+impl<T> Drop for Permit<T> {
+    fn drop(&mut self) {
+        release_permit(unsafe { self.chan.as_mut() });
+    }
+}
+
+impl<T> fmt::Debug for Permit<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Permit").finish_non_exhaustive()
+    }
+}
+
+/// What happens when a value is sent to a channel whose buffer is full, and no thread is blocked receiving,
+/// see `Channel::with_overflow`. `chan_try_send` fails with `ChannelError::Full` then whatever the policy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+    /// The sender blocks until there is room.
+    Block,
+    /// The value sent is dropped.
+    DropNewest,
+    /// The oldest value of the buffer is dropped to make room, or the value sent if the buffer is empty,
+    /// i.e. if it has no room or all of it is kept for permits.
+    DropOldest,
+    /// Sending panics rather than blocking, as it has no way to report the error:
+    /// senders are expected to use `chan_try_send`, which tells them.
+    Error,
+}
+
+/// Why a channel operation which doesn't block failed, see `chan_try_send` and `chan_try_recv`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelError {
+    /// The buffer is full and no thread is blocked receiving, so sending would block.
+    Full,
+    /// The buffer is empty and no thread is blocked sending, so receiving would block.
+    Empty,
+}
+
+impl fmt::Display for ChannelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChannelError::Full => write!(f, "the channel is full"),
+            ChannelError::Empty => write!(f, "the channel is empty"),
+        }
+    }
+}
+
+impl error::Error for ChannelError {}
+
+fn add_waker(wakers: &mut Vec<Waker>, waker: &Waker) {
+    if !wakers.iter().any(|w| w.will_wake(waker)) {
+        wakers.push(waker.clone());
+    }
 }
 
 // #[derive(Clone, Copy)]
 pub struct CircularBuffer<T> {
-    inner: *mut T,
+    inner: NonNull<T>,
     write: usize,
     read: usize,
     size: usize,
@@ -44,15 +363,17 @@ pub struct CircularBuffer<T> {
 
 impl<T> CircularBuffer<T> {
     fn new(size: usize) -> Self {
-        let align = mem::align_of::<T>();
-        let ts = mem::size_of::<T>();
-        let buf_size = ts.checked_mul(size).unwrap();
-
-        let layout = Layout::from_size_align(buf_size, align).unwrap();
-        let ptr = unsafe { alloc_zeroed(layout) };
+        let layout = Layout::array::<T>(size).unwrap();
+        // allocating zero bytes is not allowed, but no memory is needed then anyway.
+        let ptr = if layout.size() == 0 {
+            NonNull::dangling()
+        } else {
+            let ptr = unsafe { alloc(layout) };
+            NonNull::new(ptr.cast()).unwrap_or_else(|| handle_alloc_error(layout))
+        };
 
         CircularBuffer {
-            inner: ptr.cast(),
+            inner: ptr,
             write: 0,
             read: 0,
             size,
@@ -60,6 +381,10 @@ impl<T> CircularBuffer<T> {
         }
     }
 
+    fn bytes(&self) -> usize {
+        self.size * mem::size_of::<T>()
+    }
+
     fn len(&self) -> usize {
         if self.full {
             return self.size;
@@ -92,6 +417,8 @@ impl<T> CircularBuffer<T> {
         self.full
     }
 
+    // a public method since the first version, whose signature is kept for the callers of it.
+    #[allow(clippy::result_unit_err)]
     pub fn read(&mut self) -> Result<T, ()> {
         if self.is_empty() {
             return Err(());
@@ -124,6 +451,11 @@ impl<T> CircularBuffer<T> {
 
 impl<T> Drop for CircularBuffer<T> {
     fn drop(&mut self) {
-        let _ = unsafe { Vec::from_raw_parts(self.inner, 0, self.size) };
+        while self.read().is_ok() {}
+
+        let layout = Layout::array::<T>(self.size).unwrap();
+        if layout.size() != 0 {
+            unsafe { dealloc(self.inner.as_ptr().cast(), layout) };
+        }
     }
 }
@@ -0,0 +1,125 @@
+use alloc::borrow::ToOwned;
+#[cfg(not(feature = "std"))]
+use alloc::collections::BTreeMap as HashMap;
+use alloc::rc::{Rc, Weak};
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::cell::{RefCell, UnsafeCell};
+#[cfg(feature = "std")]
+use std::collections::HashMap;
+
+use super::Channel;
+use crate::runtime::{chan_recv, chan_send};
+
+// How many messages a subscription holds before publishers block, see `Bus::new`.
+const SUBSCRIPTION_SIZE: usize = 16;
+
+type Inbox<T> = Rc<UnsafeCell<Channel<T>>>;
+// The subscriptions of every topic, which are dropped along the way once unsubscribed.
+type Topics<T> = HashMap<String, Vec<Weak<UnsafeCell<Channel<T>>>>>;
+
+/// Delivers the messages published on a topic to every thread subscribed to it, for components
+/// which don't know about one another. Every subscription has a channel of its own, which gets a clone of each message.
+/// Can be cloned and moved to other threads of the same runtime, the clones sharing the topics.
+pub struct Bus<T> {
+    topics: Rc<RefCell<Topics<T>>>,
+    capacity: usize,
+}
+
+/// Receives the messages published on a topic from the moment it was subscribed to.
+/// Dropping it unsubscribes.
+pub struct Subscription<T> {
+    chan: Inbox<T>,
+}
+
+impl<T: Clone> Bus<T> {
+    /// Creates a bus whose subscriptions hold a few messages before publishers block.
+    pub fn new() -> Self {
+        Bus::with_capacity(SUBSCRIPTION_SIZE)
+    }
+
+    /// Creates a bus whose subscriptions hold up to `capacity` messages before publishers block.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Bus {
+            topics: Rc::new(RefCell::new(HashMap::new())),
+            capacity,
+        }
+    }
+
+    /// Subscribes to `topic`.
+    pub fn subscribe(&self, topic: &str) -> Subscription<T> {
+        let chan = Rc::new(UnsafeCell::new(Channel::new(self.capacity)));
+        self.topics
+            .borrow_mut()
+            .entry(topic.to_owned())
+            .or_default()
+            .push(Rc::downgrade(&chan));
+        Subscription { chan }
+    }
+
+    /// Sends `msg` to every subscription of `topic`, blocking while one of them is full,
+    /// and returns how many it was sent to.
+    pub fn publish(&self, topic: &str, msg: T) -> usize {
+        // the subscriptions may change while blocked sending, so they are collected first.
+        let inboxes: Vec<Inbox<T>> = {
+            let mut topics = self.topics.borrow_mut();
+            let Some(subscriptions) = topics.get_mut(topic) else {
+                return 0;
+            };
+            subscriptions.retain(|chan| chan.strong_count() > 0);
+            let inboxes = subscriptions.iter().filter_map(Weak::upgrade).collect();
+            if subscriptions.is_empty() {
+                topics.remove(topic);
+            }
+            inboxes
+        };
+        if let Some((last, others)) = inboxes.split_last() {
+            for chan in others {
+                unsafe { chan_send(chan.get(), msg.clone()) };
+            }
+            unsafe { chan_send(last.get(), msg) };
+        }
+        inboxes.len()
+    }
+
+    /// Returns how many subscriptions `topic` has.
+    pub fn subscribers(&self, topic: &str) -> usize {
+        self.topics.borrow().get(topic).map_or(0, |subscriptions| {
+            subscriptions
+                .iter()
+                .filter(|chan| chan.strong_count() > 0)
+                .count()
+        })
+    }
+}
+
+impl<T: Clone> Default for Bus<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Clone for Bus<T> {
+    fn clone(&self) -> Self {
+        Bus {
+            topics: self.topics.clone(),
+            capacity: self.capacity,
+        }
+    }
+}
+
+impl<T> Subscription<T> {
+    /// Receives the next message published, blocking until there is one.
+    pub fn recv(&self) -> T {
+        unsafe { chan_recv(self.chan.get()) }
+    }
+}
+
+impl<T> Drop for Subscription<T> {
+    fn drop(&mut self) {
+        // publishers blocked on the subscription would otherwise never be unblocked.
+        while !unsafe { (*self.chan.get()).is_empty() } {
+            drop(unsafe { chan_recv(self.chan.get()) });
+        }
+    }
+}
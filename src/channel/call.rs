@@ -0,0 +1,130 @@
+use alloc::rc::Rc;
+use core::cell::{Cell, UnsafeCell};
+use core::fmt::{self, Debug};
+
+use super::Channel;
+use crate::preempt::NoPreempt;
+use crate::runtime::{chan_recv, chan_send, change_thread_state, get_current_thread, yield_thread};
+use crate::thread::{Id, State};
+use crate::trace::event;
+
+/// Creates a channel of requests, each carrying the slot its reply goes to, whose buffer holds `size` requests.
+/// `Client::call` sends a request and blocks until it is replied to, so that pairs of channels needn't be wired by hand.
+/// Both ends can be cloned and moved to other threads of the same runtime.
+pub fn call_channel<Req, Resp>(size: usize) -> (Client<Req, Resp>, Server<Req, Resp>) {
+    let chan = Rc::new(UnsafeCell::new(Channel::new(size)));
+    (Client { chan: chan.clone() }, Server { chan })
+}
+
+// Shared by both ends.
+type Requests<Req, Resp> = Rc<UnsafeCell<Channel<(Req, Reply<Resp>)>>>;
+
+/// The end of a call channel that requests are sent from.
+pub struct Client<Req, Resp> {
+    chan: Requests<Req, Resp>,
+}
+
+/// The end of a call channel that requests are received and replied to from.
+pub struct Server<Req, Resp> {
+    chan: Requests<Req, Resp>,
+}
+
+/// Where the reply to a request goes, see `Server::recv`.
+/// Dropping it without replying makes the call return None.
+pub struct Reply<Resp> {
+    slot: Rc<Slot<Resp>>,
+}
+
+struct Slot<Resp> {
+    reply: Cell<Option<Resp>>,
+    state: Cell<SlotState>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SlotState {
+    Pending,
+    /// The caller is blocked until the reply.
+    Waiting(Id),
+    /// Replied to, or dropped without a reply.
+    Done,
+}
+
+impl<Req, Resp> Client<Req, Resp> {
+    /// Sends `req`, blocking while the buffer is full, and then until it is replied to.
+    /// Returns the reply, or None if the request was dropped without one.
+    pub fn call(&self, req: Req) -> Option<Resp> {
+        let slot = Rc::new(Slot {
+            reply: Cell::new(None),
+            state: Cell::new(SlotState::Pending),
+        });
+        let reply = Reply { slot: slot.clone() };
+        unsafe { chan_send(self.chan.get(), (req, reply)) };
+
+        // the reply must not come between checking for it and blocking.
+        let _no_preempt = NoPreempt::new();
+        if slot.state.get() == SlotState::Pending {
+            let curr_id = get_current_thread();
+            event!(Debug, Channel, curr_id, "waiting for a reply");
+            slot.state.set(SlotState::Waiting(curr_id));
+            change_thread_state(curr_id, State::SyncBlock);
+            yield_thread();
+        }
+        slot.reply.take()
+    }
+}
+
+impl<Req, Resp> Server<Req, Resp> {
+    /// Receives a request, blocking until one is sent, along with where to reply to it.
+    pub fn recv(&self) -> (Req, Reply<Resp>) {
+        unsafe { chan_recv(self.chan.get()) }
+    }
+
+    /// Receives requests, replying to each with what `f` returns, forever.
+    pub fn serve(&self, mut f: impl FnMut(Req) -> Resp) -> ! {
+        loop {
+            let (req, reply) = self.recv();
+            reply.send(f(req));
+        }
+    }
+}
+
+impl<Resp> Reply<Resp> {
+    /// Replies to the request, making the call waiting on it return `resp`.
+    pub fn send(self, resp: Resp) {
+        self.slot.reply.set(Some(resp));
+    }
+}
+
+impl<Resp> Drop for Reply<Resp> {
+    fn drop(&mut self) {
+        let _no_preempt = NoPreempt::new();
+        if let SlotState::Waiting(id) = self.slot.state.replace(SlotState::Done) {
+            change_thread_state(id, State::Ready);
+        }
+    }
+}
+
+impl<Req, Resp> Clone for Client<Req, Resp> {
+    fn clone(&self) -> Self {
+        Client {
+            chan: self.chan.clone(),
+        }
+    }
+}
+
+impl<Req, Resp> Clone for Server<Req, Resp> {
+    fn clone(&self) -> Self {
+        Server {
+            chan: self.chan.clone(),
+        }
+    }
+}
+
+// needed to send it on a channel.
+impl<Resp> Debug for Reply<Resp> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Reply")
+            .field("state", &self.slot.state.get())
+            .finish()
+    }
+}
@@ -0,0 +1,155 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use crate::preempt::NoPreempt;
+use crate::reactor::Notifier;
+use crate::runtime::{get_current_thread, notifier, wait_notified};
+use crate::thread::Id;
+use crate::trace::event;
+
+/// Creates a channel carrying values from any OS thread to a thread of a runtime,
+/// e.g. between the runtimes of the workers of an `Executor`, which can't share a `Channel`.
+/// Both ends can be sent to other OS threads, and the sender cloned.
+/// The queue grows as needed, so that sending never blocks: a plain OS thread can send as well.
+/// A thread receiving while the queue is empty blocks until a value is sent,
+/// which wakes up its runtime even if it is waiting for IO.
+pub fn remote_channel<T: Send>() -> (RemoteSender<T>, RemoteReceiver<T>) {
+    let inner = Arc::new(Mutex::new(Inner {
+        queue: VecDeque::new(),
+        receiver: None,
+        senders: 1,
+        closed: false,
+    }));
+    (
+        RemoteSender {
+            inner: inner.clone(),
+        },
+        RemoteReceiver { inner },
+    )
+}
+
+/// The end of a remote channel that values are sent from, see `remote_channel`.
+pub struct RemoteSender<T> {
+    inner: Arc<Mutex<Inner<T>>>,
+}
+
+/// The end of a remote channel that values are received from, by the threads of the runtime it is used on.
+pub struct RemoteReceiver<T> {
+    inner: Arc<Mutex<Inner<T>>>,
+}
+
+struct Inner<T> {
+    queue: VecDeque<T>,
+    // the thread blocked receiving, and how to wake it up from another OS thread.
+    receiver: Option<(Id, Notifier)>,
+    senders: usize,
+    // whether the receiver has been dropped.
+    closed: bool,
+}
+
+impl<T> Inner<T> {
+    fn wake_receiver(&mut self) {
+        if let Some((id, notifier)) = self.receiver.take() {
+            notifier.notify(id);
+        }
+    }
+}
+
+impl<T> RemoteSender<T> {
+    /// Sends `val` without blocking, from any OS thread.
+    /// Gives it back if the receiver has been dropped, as it would never be received.
+    pub fn send(&self, val: T) -> Result<(), T> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.closed {
+            return Err(val);
+        }
+        inner.queue.push_back(val);
+        inner.wake_receiver();
+        Ok(())
+    }
+}
+
+impl<T> Clone for RemoteSender<T> {
+    fn clone(&self) -> Self {
+        self.inner.lock().unwrap().senders += 1;
+        RemoteSender {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T> Drop for RemoteSender<T> {
+    fn drop(&mut self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.senders -= 1;
+        // the receiver stops waiting once no value can come anymore.
+        if inner.senders == 0 {
+            inner.wake_receiver();
+        }
+    }
+}
+
+impl<T> RemoteReceiver<T> {
+    /// Receives a value, blocking the current thread while there is none.
+    /// Returns None once there is none left and every sender has been dropped.
+    pub fn recv(&mut self) -> Option<T> {
+        loop {
+            // the thread mustn't be switched away from in between registering and blocking,
+            // or the runtime could pick the notification up before it blocks, and it would never wake up.
+            let _no_preempt = NoPreempt::new();
+            {
+                let mut inner = self.inner.lock().unwrap();
+                if let Some(val) = inner.queue.pop_front() {
+                    return Some(val);
+                }
+                if inner.senders == 0 {
+                    return None;
+                }
+                let id = get_current_thread();
+                event!(
+                    Debug,
+                    Channel,
+                    id,
+                    "blocked on receive from a remote channel"
+                );
+                inner.receiver = Some((id, notifier()));
+            }
+            wait_notified();
+        }
+    }
+
+    /// Receives a value if one is there, without blocking.
+    pub fn try_recv(&mut self) -> Option<T> {
+        self.inner.lock().unwrap().queue.pop_front()
+    }
+
+    /// Returns how many values are waiting to be received.
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T> Drop for RemoteReceiver<T> {
+    fn drop(&mut self) {
+        self.inner.lock().unwrap().closed = true;
+    }
+}
+
+impl<T> fmt::Debug for RemoteSender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RemoteSender").finish_non_exhaustive()
+    }
+}
+
+impl<T> fmt::Debug for RemoteReceiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RemoteReceiver")
+            .field("len", &self.len())
+            .finish()
+    }
+}
@@ -0,0 +1,316 @@
+use std::cell::UnsafeCell;
+use std::fmt;
+use std::mem::MaybeUninit;
+use std::ops::Deref;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering::*};
+use std::sync::Arc;
+
+use crate::preempt::NoPreempt;
+use crate::reactor::Notifier;
+use crate::runtime::{get_current_thread, notifier, wait_notified};
+use crate::thread::Id;
+use crate::trace::event;
+
+/// Creates a channel carrying values from one OS thread to another through a ring of `cap` values,
+/// e.g. between two stages of a pipeline running on the runtimes of different workers.
+/// Unlike `remote_channel`, there is a single sender, which can't be cloned, so values go through without any lock:
+/// each end only moves its own index of the ring. Locks are only taken to wake up a thread blocked on the other end.
+/// Both ends can be sent to other OS threads. Panics if `cap` is zero.
+pub fn spsc<T: Send>(cap: usize) -> (SpscSender<T>, SpscReceiver<T>) {
+    assert!(cap > 0, "the ring of a channel must hold a value at least");
+    let ring = Arc::new(Ring {
+        slots: (0..cap)
+            .map(|_| UnsafeCell::new(MaybeUninit::uninit()))
+            .collect(),
+        producer: CachePadded(Producer {
+            tail: AtomicUsize::new(0),
+            receiver: Waiter::new(),
+        }),
+        consumer: CachePadded(Consumer {
+            head: AtomicUsize::new(0),
+            sender: Waiter::new(),
+        }),
+        receiver_dropped: AtomicBool::new(false),
+        sender_dropped: AtomicBool::new(false),
+    });
+    (
+        SpscSender {
+            ring: ring.clone(),
+            head: 0,
+        },
+        SpscReceiver { ring, tail: 0 },
+    )
+}
+
+/// The end of a single-producer channel that values are sent from, see `spsc`.
+pub struct SpscSender<T> {
+    ring: Arc<Ring<T>>,
+    // the head as last read, which the actual one may only be past: the ring is only read again once it looks full.
+    head: usize,
+}
+
+/// The end of a single-producer channel that values are received from, see `spsc`.
+pub struct SpscReceiver<T> {
+    ring: Arc<Ring<T>>,
+    // the tail as last read, which the actual one may only be past: the ring is only read again once it looks empty.
+    tail: usize,
+}
+
+// The state written by each end is on cache lines of its own, so that an end writing it doesn't take the lines
+// the other end is working on away from its core.
+struct Ring<T> {
+    producer: CachePadded<Producer>,
+    consumer: CachePadded<Consumer>,
+    slots: Box<[UnsafeCell<MaybeUninit<T>>]>,
+    receiver_dropped: AtomicBool,
+    sender_dropped: AtomicBool,
+}
+
+// What the sender writes, or reads at every value it sends.
+struct Producer {
+    // how many values were sent, only moved by the sender.
+    tail: AtomicUsize,
+    // the receiver blocked as the ring is empty.
+    receiver: Waiter,
+}
+
+// What the receiver writes, or reads at every value it receives.
+struct Consumer {
+    // how many values were received, only moved by the receiver.
+    head: AtomicUsize,
+    // the sender blocked as the ring is full.
+    sender: Waiter,
+}
+
+// Aligns a value to the size of two cache lines, the unit that x86_64 prefetches lines in, and the line size of some
+// aarch64 cores, so that nothing else shares a line with it.
+#[repr(align(128))]
+struct CachePadded<T>(T);
+
+impl<T> Deref for CachePadded<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+// The values are only accessed by one end at a time, as told by the indices.
+unsafe impl<T: Send> Send for Ring<T> {}
+unsafe impl<T: Send> Sync for Ring<T> {}
+
+// A thread blocked on one end, and how to wake it up from another OS thread.
+// The thread hands it over to the other end, which takes it back as it wakes the thread up,
+// so that neither end has to lock it.
+struct Waiter(AtomicPtr<(Id, Notifier)>);
+
+impl Waiter {
+    fn new() -> Self {
+        Waiter(AtomicPtr::new(ptr::null_mut()))
+    }
+
+    // Registers the current thread, to be woken up by `wake`.
+    fn register(&self) {
+        self.replace(Box::into_raw(Box::new((get_current_thread(), notifier()))));
+    }
+
+    // Unregisters the thread, if it hasn't been woken up already.
+    fn reclaim(&self) {
+        self.replace(ptr::null_mut());
+    }
+
+    fn replace(&self, waiter: *mut (Id, Notifier)) {
+        let previous = self.0.swap(waiter, SeqCst);
+        if !previous.is_null() {
+            drop(unsafe { Box::from_raw(previous) });
+        }
+    }
+
+    // Wakes up the thread registered, if any.
+    fn wake(&self) {
+        if self.0.load(SeqCst).is_null() {
+            return;
+        }
+        let waiter = self.0.swap(ptr::null_mut(), SeqCst);
+        if !waiter.is_null() {
+            let (id, notifier) = *unsafe { Box::from_raw(waiter) };
+            notifier.notify(id);
+        }
+    }
+}
+
+impl<T> Ring<T> {
+    fn len(&self) -> usize {
+        // the head never passes the tail, as long as it is read first.
+        let head = self.consumer.head.load(SeqCst);
+        self.producer.tail.load(SeqCst).wrapping_sub(head)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn slot(&self, index: usize) -> *mut MaybeUninit<T> {
+        self.slots[index % self.slots.len()].get()
+    }
+}
+
+impl<T> SpscSender<T> {
+    /// Sends `val` if there is room in the ring, without blocking, from any OS thread.
+    /// Gives it back if the ring is full, or if the receiver has been dropped, as it would never be received.
+    pub fn try_send(&mut self, val: T) -> Result<(), T> {
+        let ring = &*self.ring;
+        if ring.receiver_dropped.load(SeqCst) {
+            return Err(val);
+        }
+        let tail = ring.producer.tail.load(Relaxed);
+        if tail.wrapping_sub(self.head) == ring.slots.len() {
+            self.head = ring.consumer.head.load(Acquire);
+            if tail.wrapping_sub(self.head) == ring.slots.len() {
+                return Err(val);
+            }
+        }
+        unsafe { (*ring.slot(tail)).write(val) };
+        ring.producer.tail.store(tail.wrapping_add(1), SeqCst);
+        ring.producer.receiver.wake();
+        Ok(())
+    }
+
+    /// Sends `val`, blocking the current thread while the ring is full, so it must run on a runtime.
+    /// Gives it back if the receiver has been dropped, as it would never be received.
+    pub fn send(&mut self, mut val: T) -> Result<(), T> {
+        loop {
+            // the thread mustn't be switched away from in between registering and blocking, see `RemoteReceiver::recv`.
+            let _no_preempt = NoPreempt::new();
+            val = match self.try_send(val) {
+                Ok(()) => return Ok(()),
+                Err(val) if self.ring.receiver_dropped.load(SeqCst) => return Err(val),
+                Err(val) => val,
+            };
+            self.ring.consumer.sender.register();
+            // the receiver may have made room before seeing the thread registered.
+            if self.ring.len() < self.ring.slots.len() || self.ring.receiver_dropped.load(SeqCst) {
+                self.ring.consumer.sender.reclaim();
+                continue;
+            }
+            event!(
+                Debug,
+                Channel,
+                get_current_thread(),
+                "blocked on send to a full ring"
+            );
+            wait_notified();
+        }
+    }
+
+    /// Returns how many values are waiting to be received.
+    pub fn len(&self) -> usize {
+        self.ring.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T> Drop for SpscSender<T> {
+    fn drop(&mut self) {
+        self.ring.sender_dropped.store(true, SeqCst);
+        // the receiver stops waiting once no value can come anymore.
+        self.ring.producer.receiver.wake();
+    }
+}
+
+impl<T> SpscReceiver<T> {
+    /// Receives a value if one is there, without blocking, from any OS thread.
+    pub fn try_recv(&mut self) -> Option<T> {
+        let ring = &*self.ring;
+        let head = ring.consumer.head.load(Relaxed);
+        if self.tail == head {
+            self.tail = ring.producer.tail.load(Acquire);
+            if self.tail == head {
+                return None;
+            }
+        }
+        let val = unsafe { (*ring.slot(head)).assume_init_read() };
+        ring.consumer.head.store(head.wrapping_add(1), SeqCst);
+        ring.consumer.sender.wake();
+        Some(val)
+    }
+
+    /// Receives a value, blocking the current thread while there is none, so it must run on a runtime.
+    /// Returns None once there is none left and the sender has been dropped.
+    pub fn recv(&mut self) -> Option<T> {
+        loop {
+            let _no_preempt = NoPreempt::new();
+            if let Some(val) = self.try_recv() {
+                return Some(val);
+            }
+            if self.ring.sender_dropped.load(SeqCst) {
+                // the last values may have been sent right before the sender was dropped.
+                return self.try_recv();
+            }
+            self.ring.producer.receiver.register();
+            if !self.ring.is_empty() || self.ring.sender_dropped.load(SeqCst) {
+                self.ring.producer.receiver.reclaim();
+                continue;
+            }
+            event!(
+                Debug,
+                Channel,
+                get_current_thread(),
+                "blocked on receive from an empty ring"
+            );
+            wait_notified();
+        }
+    }
+
+    /// Returns how many values are waiting to be received.
+    pub fn len(&self) -> usize {
+        self.ring.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T> Drop for SpscReceiver<T> {
+    fn drop(&mut self) {
+        self.ring.receiver_dropped.store(true, SeqCst);
+        self.ring.consumer.sender.wake();
+    }
+}
+
+impl<T> Drop for Ring<T> {
+    fn drop(&mut self) {
+        let (head, tail) = (
+            *self.consumer.0.head.get_mut(),
+            *self.producer.0.tail.get_mut(),
+        );
+        let mut index = head;
+        while index != tail {
+            unsafe { (*self.slot(index)).assume_init_drop() };
+            index = index.wrapping_add(1);
+        }
+        self.producer.receiver.reclaim();
+        self.consumer.sender.reclaim();
+    }
+}
+
+impl<T> fmt::Debug for SpscSender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SpscSender")
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+impl<T> fmt::Debug for SpscReceiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SpscReceiver")
+            .field("len", &self.len())
+            .finish()
+    }
+}
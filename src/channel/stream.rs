@@ -0,0 +1,108 @@
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use super::Channel;
+use crate::runtime::{chan_poll_recv, chan_poll_send};
+
+/// Receives from a channel from async code, e.g. a task of a tokio runtime driving the runtime with `Runtime::run_async`,
+/// while green threads send to it as usual. An endless iterator of futures, each resolving to the next value received.
+/// The task is woken up once a value may be there, instead of blocking like `chan_recv`.
+pub struct ChannelStream<T> {
+    chan: *mut Channel<T>,
+}
+
+impl<T> ChannelStream<T> {
+    /// # Safety
+    ///
+    /// `chan` must point to a live Channel that outlives the stream, and which is only used on the OS thread of the runtime.
+    pub unsafe fn new(chan: *mut Channel<T>) -> Self {
+        ChannelStream { chan }
+    }
+
+    /// Receives a value if one is there, or registers the task of `cx` to be woken up once one may be, like `Stream::poll_next`.
+    pub fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<T> {
+        chan_poll_recv(unsafe { &mut *self.chan }, cx)
+    }
+
+    /// Returns a future resolving to the next value received.
+    pub fn recv(&mut self) -> RecvFuture<T> {
+        RecvFuture { chan: self.chan }
+    }
+}
+
+impl<T> Iterator for ChannelStream<T> {
+    type Item = RecvFuture<T>;
+
+    fn next(&mut self) -> Option<RecvFuture<T>> {
+        Some(self.recv())
+    }
+}
+
+/// The future returned by `ChannelStream::recv`.
+pub struct RecvFuture<T> {
+    chan: *mut Channel<T>,
+}
+
+impl<T> Future for RecvFuture<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let chan = self.chan;
+        chan_poll_recv(unsafe { &mut *chan }, cx)
+    }
+}
+
+/// Sends to a channel from async code, while green threads receive from it as usual, see `ChannelStream`.
+/// The task is woken up once there may be room for the value, instead of blocking like `chan_send`.
+pub struct ChannelSink<T> {
+    chan: *mut Channel<T>,
+}
+
+impl<T> ChannelSink<T> {
+    /// # Safety
+    ///
+    /// `chan` must point to a live Channel that outlives the sink, and which is only used on the OS thread of the runtime.
+    pub unsafe fn new(chan: *mut Channel<T>) -> Self {
+        ChannelSink { chan }
+    }
+
+    /// Sends `val` if there is room for it, or returns it and registers the task of `cx` to be woken up once there may be.
+    /// A value can only be sent on a channel without a buffer when a thread is blocked receiving from it.
+    pub fn poll_send(&mut self, val: T, cx: &mut Context<'_>) -> Result<(), T> {
+        chan_poll_send(unsafe { &mut *self.chan }, val, cx)
+    }
+
+    /// Returns a future resolving once `val` has been sent.
+    pub fn send(&mut self, val: T) -> SendFuture<T> {
+        SendFuture {
+            chan: self.chan,
+            val: Some(val),
+        }
+    }
+}
+
+/// The future returned by `ChannelSink::send`.
+pub struct SendFuture<T> {
+    chan: *mut Channel<T>,
+    val: Option<T>,
+}
+
+// the value is never pinned.
+impl<T> Unpin for SendFuture<T> {}
+
+impl<T> Future for SendFuture<T> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let val = self.val.take().expect("polled after completion");
+        let chan = self.chan;
+        match chan_poll_send(unsafe { &mut *chan }, val, cx) {
+            Ok(()) => Poll::Ready(()),
+            Err(val) => {
+                self.val = Some(val);
+                Poll::Pending
+            }
+        }
+    }
+}
@@ -0,0 +1,323 @@
+// Contexts, like those of Go: a deadline, a cancellation and values, handed down to the threads handling a request,
+// so that all of its work can be timed out or cancelled together.
+// The operations of a context wait like the runtime's own, but are interrupted once the context is done:
+// the deadline is a timer of the reactor for the waiting thread, and cancelling interrupts the threads waiting.
+
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::error;
+use std::fmt::{self, Debug, Display};
+use std::io;
+use std::os::fd::RawFd;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crate::channel::Channel;
+use crate::reactor::Interest;
+use crate::runtime::{self, chan_recv_interruptible, chan_send_interruptible, get_current_thread};
+use crate::thread::Id;
+use crate::time;
+
+crate::green_local! {
+    // The context the current thread runs in, see `Context::scope`.
+    static CURRENT: RefCell<Option<Context>> = RefCell::new(None);
+}
+
+/// Why a context is done.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The context, or one of its parents, was cancelled.
+    Cancelled,
+    /// The deadline of the context, or of one of its parents, has passed.
+    DeadlineExceeded,
+}
+
+/// Carries a deadline, a cancellation and values down a tree of threads, e.g. those handling a request.
+/// Derived contexts are done once their parent is, and cloning one shares it: a clone is moved into every thread spawned.
+/// The blocking operations it offers (`sleep`, `recv`, `send`, `wait_io`) fail with `Error` once it is done,
+/// including when they are already blocked.
+#[derive(Clone)]
+pub struct Context {
+    inner: Rc<Inner>,
+}
+
+struct Inner {
+    parent: Option<Context>,
+    deadline: Option<Instant>,
+    // set for the contexts which can be cancelled, i.e. created by `with_cancel`, `with_deadline` or `with_timeout`.
+    cancel: Option<Cancellation>,
+    value: Option<(&'static str, Rc<dyn Any>)>,
+}
+
+struct Cancellation {
+    err: Cell<Option<Error>>,
+    // the threads blocked in an operation of the context or of a context derived from it.
+    waiters: RefCell<Vec<Id>>,
+}
+
+/// Cancels the context it was returned with, and those derived from it.
+pub struct Cancel {
+    ctx: Context,
+}
+
+impl Context {
+    /// Returns a context which is never done, to derive the others from.
+    pub fn background() -> Self {
+        Context::derive(None, None, None, None)
+    }
+
+    /// Returns a context derived from this one, which can be cancelled with the `Cancel` returned.
+    pub fn with_cancel(&self) -> (Context, Cancel) {
+        let ctx = Context::derive(Some(self.clone()), None, Some(Cancellation::new()), None);
+        (ctx.clone(), Cancel { ctx })
+    }
+
+    /// Returns a context derived from this one, done once `deadline` has passed,
+    /// or earlier if cancelled with the `Cancel` returned.
+    pub fn with_deadline(&self, deadline: Instant) -> (Context, Cancel) {
+        let ctx = Context::derive(
+            Some(self.clone()),
+            Some(deadline),
+            Some(Cancellation::new()),
+            None,
+        );
+        (ctx.clone(), Cancel { ctx })
+    }
+
+    /// Returns a context derived from this one, done once `timeout` has elapsed, see `with_deadline`.
+    pub fn with_timeout(&self, timeout: Duration) -> (Context, Cancel) {
+        self.with_deadline(time::now() + timeout)
+    }
+
+    /// Returns a context derived from this one, carrying `value` under `key`.
+    pub fn with_value<V: Any>(&self, key: &'static str, value: V) -> Context {
+        Context::derive(Some(self.clone()), None, None, Some((key, Rc::new(value))))
+    }
+
+    /// Runs `f` in this context, which `is_cancelled` and `check_cancelled` then look at,
+    /// e.g. so that a long computation can stop once it is done without being passed the context down every call.
+    /// The threads of a `Group` or of a `JoinSet` run in the context they are passed.
+    pub fn scope<R>(&self, f: impl FnOnce() -> R) -> R {
+        struct Restore(Option<Context>);
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                let previous = self.0.take();
+                CURRENT.with(|current| *current.borrow_mut() = previous);
+            }
+        }
+        let _restore = Restore(CURRENT.with(|current| current.replace(Some(self.clone()))));
+        f()
+    }
+
+    /// Returns the context the current thread runs in, if any, see `scope`.
+    pub fn current() -> Option<Context> {
+        CURRENT.with(|current| current.borrow().clone())
+    }
+
+    fn derive(
+        parent: Option<Context>,
+        deadline: Option<Instant>,
+        cancel: Option<Cancellation>,
+        value: Option<(&'static str, Rc<dyn Any>)>,
+    ) -> Self {
+        Context {
+            inner: Rc::new(Inner {
+                parent,
+                deadline,
+                cancel,
+                value,
+            }),
+        }
+    }
+
+    /// Returns the value stored under `key` by this context or the closest of its parents, if it is a `V`.
+    pub fn value<V: Any>(&self, key: &str) -> Option<Rc<V>> {
+        self.ancestors()
+            .find_map(|inner| inner.value.as_ref().filter(|(k, _)| *k == key))
+            .and_then(|(_, value)| value.clone().downcast().ok())
+    }
+
+    /// Returns the earliest deadline of this context and its parents.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.ancestors().filter_map(|inner| inner.deadline).min()
+    }
+
+    /// Returns why the context is done, or None if it isn't.
+    pub fn err(&self) -> Option<Error> {
+        if let Some(err) = self
+            .ancestors()
+            .find_map(|inner| inner.cancel.as_ref().and_then(|cancel| cancel.err.get()))
+        {
+            return Some(err);
+        }
+        match self.deadline() {
+            Some(deadline) if time::now() >= deadline => Some(Error::DeadlineExceeded),
+            _ => None,
+        }
+    }
+
+    /// Returns true once the context is done.
+    pub fn is_done(&self) -> bool {
+        self.err().is_some()
+    }
+
+    /// Blocks the current thread for at least `dur`, or until the context is done.
+    pub fn sleep(&self, dur: Duration) -> Result<(), Error> {
+        self.sleep_until(time::now() + dur)
+    }
+
+    /// Blocks the current thread until `wake` has passed, or until the context is done, see `time::sleep_until`.
+    pub fn sleep_until(&self, wake: Instant) -> Result<(), Error> {
+        self.wait(|| {
+            runtime::sleep_until(wake);
+            // woken up early by the deadline or a cancellation.
+            (time::now() >= wake).then_some(())
+        })
+    }
+
+    /// Receives from `chan` like `chan_recv`, unless the context is done first.
+    ///
+    /// # Safety
+    ///
+    /// `chan` must point to a live Channel that is not accessed from outside the runtime.
+    pub unsafe fn recv<T>(&self, chan: *mut Channel<T>) -> Result<T, Error> {
+        self.wait(|| unsafe { chan_recv_interruptible(chan) })
+    }
+
+    /// Sends `val` to `chan` like `chan_send`, unless the context is done first, in which case `val` is returned.
+    ///
+    /// # Safety
+    ///
+    /// `chan` must point to a live Channel that is not accessed from outside the runtime.
+    pub unsafe fn send<T>(&self, chan: *mut Channel<T>, val: T) -> Result<(), (Error, T)> {
+        let mut val = Some(val);
+        let sent = self.wait(|| {
+            let v = val.take().expect("sent twice");
+            match unsafe { chan_send_interruptible(chan, v) } {
+                Ok(()) => Some(()),
+                Err(v) => {
+                    val = Some(v);
+                    None
+                }
+            }
+        });
+        sent.map_err(|err| (err, val.take().expect("the value was sent")))
+    }
+
+    /// Blocks the current thread until `fd` is ready for `interest`, like `runtime::wait_io`, unless the context is done first.
+    /// Fails with an error of kind `TimedOut` past the deadline, and `Other` if cancelled, wrapping the `Error`.
+    pub fn wait_io(&self, fd: RawFd, interest: Interest) -> io::Result<()> {
+        // readiness can be spurious anyway, so the wait counts as interrupted whenever the context is done.
+        let waited = self.wait(|| {
+            let res = runtime::wait_io(fd, interest);
+            (!self.is_done()).then_some(res)
+        });
+        match waited {
+            Ok(res) => res,
+            Err(Error::DeadlineExceeded) => Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                Error::DeadlineExceeded,
+            )),
+            Err(err) => Err(io::Error::other(err)),
+        }
+    }
+
+    // Runs `op`, a wait of the current thread which returns None if interrupted, making it interruptible by the context:
+    // cancelling it interrupts the thread, as does the deadline passing.
+    // An operation which completed anyway succeeds, so that e.g. a value received isn't lost.
+    fn wait<T>(&self, op: impl FnOnce() -> Option<T>) -> Result<T, Error> {
+        if let Some(err) = self.err() {
+            return Err(err);
+        }
+        let id = get_current_thread();
+        for cancel in self.cancellations() {
+            cancel.waiters.borrow_mut().push(id);
+        }
+        if let Some(deadline) = self.deadline() {
+            runtime::set_deadline(deadline);
+        }
+
+        let res = op();
+
+        runtime::clear_wait();
+        for cancel in self.cancellations() {
+            cancel.waiters.borrow_mut().retain(|&waiter| waiter != id);
+        }
+        res.ok_or_else(|| {
+            self.err()
+                .expect("a wait was interrupted while its context isn't done")
+        })
+    }
+
+    fn ancestors(&self) -> impl Iterator<Item = &Inner> {
+        std::iter::successors(Some(&*self.inner), |inner| {
+            inner.parent.as_ref().map(|parent| &*parent.inner)
+        })
+    }
+
+    fn cancellations(&self) -> impl Iterator<Item = &Cancellation> {
+        self.ancestors().filter_map(|inner| inner.cancel.as_ref())
+    }
+}
+
+impl Debug for Context {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Context")
+            .field("deadline", &self.deadline())
+            .field("err", &self.err())
+            .finish()
+    }
+}
+
+impl Cancellation {
+    fn new() -> Self {
+        Cancellation {
+            err: Cell::new(None),
+            waiters: RefCell::new(Vec::new()),
+        }
+    }
+}
+
+impl Cancel {
+    /// Cancels the context, interrupting the threads blocked in its operations, and in those of the contexts derived from it.
+    /// Does nothing if it is already done.
+    pub fn cancel(&self) {
+        let cancel = self.ctx.inner.cancel.as_ref().unwrap();
+        if self.ctx.is_done() {
+            return;
+        }
+        cancel.err.set(Some(Error::Cancelled));
+        let waiters = cancel.waiters.take();
+        for id in waiters {
+            runtime::interrupt(id);
+        }
+    }
+}
+
+/// Returns true once the context the current thread runs in is done (see `Context::scope`), cancelled or past its deadline,
+/// for CPU-bound work to check now and then whether to carry on. False if the thread runs in no context.
+pub fn is_cancelled() -> bool {
+    CURRENT.with(|current| current.borrow().as_ref().is_some_and(Context::is_done))
+}
+
+/// A cancellation point for CPU-bound work, e.g. every iteration of a long loop: lets the other threads run,
+/// like `maybe_yield`, so that those cancelling get to, then fails with why the context the current thread runs in is done,
+/// if it is, for the work to stop with `?`.
+pub fn check_cancelled() -> Result<(), Error> {
+    runtime::maybe_yield();
+    match CURRENT.with(|current| current.borrow().as_ref().and_then(Context::err)) {
+        Some(err) => Err(err),
+        None => Ok(()),
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Cancelled => write!(f, "context cancelled"),
+            Error::DeadlineExceeded => write!(f, "context deadline exceeded"),
+        }
+    }
+}
+
+impl error::Error for Error {}
@@ -0,0 +1,227 @@
+// Symmetric coroutines: closures running on a stack of their own, which switch straight to one another with `transfer`,
+// carrying a payload, without going through the scheduler. Unlike generators, there is no caller to return to:
+// a coroutine keeps running until it transfers to another one, or completes.
+// The coroutine running is tracked per OS thread, and the OS thread itself is the root coroutine,
+// which the coroutines it started return to once they complete.
+
+use std::any::Any;
+use std::cell::{Cell, RefCell, UnsafeCell};
+use std::marker::PhantomData;
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::Rc;
+
+use crate::arch::{Arch, Context};
+use crate::generator::{self, Body};
+use crate::preempt;
+use crate::thread::{MmapAllocator, Stack, StackAllocator};
+use crate::DEFAULT_STACK_SIZE;
+
+thread_local! {
+    // The coroutine running, none until the OS thread has transferred to one.
+    static CURRENT: RefCell<Option<Rc<Inner>>> = const { RefCell::new(None) };
+    // The OS thread, as a coroutine, once it has transferred to another one.
+    static ROOT: RefCell<Option<Rc<Inner>>> = const { RefCell::new(None) };
+}
+
+/// A handle to a coroutine, which `transfer` switches to with a payload of type `P`.
+/// Coroutines transfer control to one another directly: the one transferring is suspended until another one transfers back to it,
+/// and its call to `transfer` then returns the payload it was given.
+/// Once the closure of a coroutine completes, its result is transferred to the coroutine which first started it,
+/// or to whichever one that returns to. A panic of the closure is resumed there too.
+/// Dropping the last handle of a suspended coroutine frees its stack without unwinding it, so what it holds is leaked.
+/// The running coroutine is tracked per OS thread: a green thread must not switch to another one
+/// while running a coroutine, e.g. by blocking, if the other one uses coroutines too.
+pub struct Coroutine<P> {
+    inner: Rc<Inner>,
+    _payload: PhantomData<fn(P) -> P>,
+}
+
+struct Inner {
+    ctx: UnsafeCell<Context>,
+    // none for the OS thread.
+    stack: Option<Stack>,
+    // the closure, until the coroutine is first transferred to.
+    body: UnsafeCell<Body>,
+    // where the coroutine returns to once it completes, none for the OS thread.
+    root: RefCell<Option<Rc<Inner>>>,
+    payload: RefCell<Option<Box<dyn Any>>>,
+    panic: RefCell<Option<Box<dyn Any + Send>>>,
+    // set by whoever transfers to the coroutine, which takes them once running again:
+    // the coroutine itself, so that it doesn't keep itself alive while suspended, and the one it was transferred from,
+    // which can only be dropped once it has switched away.
+    resumed: RefCell<Option<Rc<Inner>>>,
+    from: RefCell<Option<Rc<Inner>>>,
+    done: Cell<bool>,
+}
+
+impl<P: 'static> Coroutine<P> {
+    /// Creates a coroutine running `f`, which doesn't start until it is first transferred to,
+    /// and is given the payload of that transfer.
+    pub fn new(f: impl FnOnce(P) -> P + 'static) -> Self {
+        let stack = MmapAllocator
+            .allocate(DEFAULT_STACK_SIZE, true)
+            .unwrap_or_else(|err| panic!("failed to allocate a coroutine stack: {err}"));
+        let mut inner = Inner::new(Some(stack));
+        inner
+            .ctx
+            .get_mut()
+            .bootstrap(inner.stack.as_mut().unwrap(), generator::entry);
+        let inner = Rc::new(inner);
+
+        // the closure only refers to the coroutine while it runs, so that it doesn't keep itself alive while suspended.
+        let this = Rc::as_ptr(&inner);
+        let body: Box<dyn FnOnce()> = Box::new(move || {
+            let payload = unsafe { resumed(this) }.take_payload();
+            let result = panic::catch_unwind(AssertUnwindSafe(|| f(payload)));
+            let this = current();
+            this.done.set(true);
+            let root = this
+                .root
+                .borrow_mut()
+                .take()
+                .expect("started a coroutine without a root");
+            match result {
+                Ok(payload) => *root.payload.borrow_mut() = Some(Box::new(payload)),
+                Err(payload) => *root.panic.borrow_mut() = Some(payload),
+            }
+            unsafe { switch(this, root) };
+            unreachable!("a completed coroutine was resumed");
+        });
+        unsafe { *inner.body.get() = Some(body) };
+
+        Coroutine {
+            inner,
+            _payload: PhantomData,
+        }
+    }
+
+    /// Returns a handle to the coroutine running, the OS thread itself if none is.
+    pub fn current() -> Self {
+        Coroutine {
+            inner: current(),
+            _payload: PhantomData,
+        }
+    }
+
+    /// Suspends the coroutine running and switches to `other`, handing it `payload`,
+    /// which its own call to `transfer` returns, or its closure is given if it hasn't started yet.
+    /// Returns the payload of the transfer resuming the coroutine running.
+    /// Transferring to the coroutine running returns `payload` right away.
+    /// Panics if `other` has completed, or if the payload resuming the coroutine running isn't a `P`.
+    pub fn transfer(other: &Coroutine<P>, payload: P) -> P {
+        let this = current();
+        if Rc::ptr_eq(&this, &other.inner) {
+            return payload;
+        }
+        assert!(
+            !other.inner.done.get(),
+            "transferred to a completed coroutine"
+        );
+        if unsafe { (*other.inner.body.get()).is_some() } {
+            let root = match this.stack {
+                Some(_) => this.root.borrow().clone(),
+                None => Some(this.clone()),
+            };
+            *other.inner.root.borrow_mut() = root;
+        }
+        *other.inner.payload.borrow_mut() = Some(Box::new(payload));
+
+        let ptr = Rc::as_ptr(&this);
+        unsafe { switch(this, other.inner.clone()) };
+        let this = unsafe { resumed(ptr) };
+        if let Some(payload) = this.panic.borrow_mut().take() {
+            panic::resume_unwind(payload);
+        }
+        this.take_payload()
+    }
+
+    /// Returns true once the closure has completed.
+    pub fn is_done(&self) -> bool {
+        self.inner.done.get()
+    }
+}
+
+impl<P> Clone for Coroutine<P> {
+    fn clone(&self) -> Self {
+        Coroutine {
+            inner: self.inner.clone(),
+            _payload: PhantomData,
+        }
+    }
+}
+
+impl Inner {
+    fn new(stack: Option<Stack>) -> Self {
+        Inner {
+            ctx: UnsafeCell::new(Context::default()),
+            stack,
+            body: UnsafeCell::new(None),
+            root: RefCell::new(None),
+            payload: RefCell::new(None),
+            panic: RefCell::new(None),
+            resumed: RefCell::new(None),
+            from: RefCell::new(None),
+            done: Cell::new(false),
+        }
+    }
+
+    fn take_payload<P: 'static>(&self) -> P {
+        let payload = self
+            .payload
+            .borrow_mut()
+            .take()
+            .expect("resumed a coroutine without a payload");
+        *payload
+            .downcast()
+            .unwrap_or_else(|_| panic!("transferred a payload of another type"))
+    }
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        if let Some(stack) = self.stack.take() {
+            MmapAllocator.deallocate(stack);
+        }
+    }
+}
+
+fn current() -> Rc<Inner> {
+    if let Some(current) = CURRENT.with_borrow(|current| current.clone()) {
+        return current;
+    }
+    ROOT.with_borrow_mut(|root| {
+        root.get_or_insert_with(|| Rc::new(Inner::new(None)))
+            .clone()
+    })
+}
+
+// Switches from the coroutine running, `from`, to `to`, handing `from` over so that it is only dropped once `to` runs.
+// Neither is left on the stack of `from`, which may never run again.
+unsafe fn switch(from: Rc<Inner>, to: Rc<Inner>) {
+    let (old, new) = (from.ctx.get(), to.ctx.get());
+    let body = to.body.get();
+    if (*body).is_some() {
+        generator::set_starting(body);
+    }
+    *to.from.borrow_mut() = Some(from);
+    let resumed = &(*Rc::as_ptr(&to)).resumed;
+    *resumed.borrow_mut() = Some(to);
+    CURRENT.set(None);
+    // the coroutine starts with preemption enabled, see `runtime::start`.
+    let depth = preempt::depth();
+    Context::switch(old, new);
+    preempt::set_depth(depth);
+}
+
+// Takes over from whoever switched to the coroutine `this`, which is running again, and returns it.
+// Only the coroutine that was switched away from is dropped here, now that nothing runs on its stack.
+unsafe fn resumed(this: *const Inner) -> Rc<Inner> {
+    let this = (*this)
+        .resumed
+        .borrow_mut()
+        .take()
+        .expect("resumed a coroutine without a transfer");
+    drop(this.from.borrow_mut().take());
+    CURRENT.set(Some(this.clone()));
+    this
+}
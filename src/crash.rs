@@ -0,0 +1,51 @@
+// Reporting the panic of a thread, which aborts the process as there is nothing to resume it on.
+// The panic hook keeps a backtrace of the thread as it panics, before the unwinding takes its frames away,
+// and once the unwinding reaches the bottom of the thread (see `runtime::run_thread`), the report is written to stderr:
+// the backtrace, followed by the state of every thread of the runtime, as dumped on a signal (see `dump`).
+
+use std::any::Any;
+use std::fmt::Write;
+use std::panic;
+use std::process;
+use std::sync::Once;
+
+use crate::runtime::{capture_backtrace, dump_threads, get_current_thread, take_backtrace};
+
+// Chains the hook keeping backtraces to whatever hook is set, once per process.
+pub(crate) fn install() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            capture_backtrace();
+            previous(info)
+        }));
+    });
+}
+
+// Reports the panic of the current thread, whose message the hook has already printed, and aborts the process.
+// The backtrace is the one of the last panic of the thread, which is the one reported
+// unless the thread resumed a panic caught elsewhere, as `resume_unwind` doesn't call the hook.
+pub(crate) fn report(payload: Box<dyn Any + Send>) -> ! {
+    let id = get_current_thread();
+    let msg = match payload.downcast_ref::<&str>() {
+        Some(msg) => msg,
+        None => match payload.downcast_ref::<String>() {
+            Some(msg) => msg.as_str(),
+            None => "Box<dyn Any>",
+        },
+    };
+    let mut out = String::new();
+    let _ = writeln!(out, "uthreads: thread {id:?} panicked: {msg}, aborting");
+    match take_backtrace() {
+        Some(backtrace) => {
+            let _ = writeln!(out, "backtrace of thread {id:?}:\n{backtrace}");
+        }
+        None => {
+            let _ = writeln!(out, "no backtrace of thread {id:?}");
+        }
+    }
+    let _ = dump_threads(&mut out);
+    eprint!("{out}");
+    process::abort()
+}
@@ -0,0 +1,106 @@
+//! Lets debuggers find the green threads of a stopped process.
+//! The runtime keeps `UTHREADS_REGISTRY`, an unmangled symbol with a fixed layout,
+//! describing where its threads are in memory and where each one keeps the registers it was switched out with.
+//! From those, a debugger can list the threads and unwind the stack of any of them.
+//! `debugger/uthreads-gdb.py` and `debugger/uthreads-lldb.py` do that for GDB and LLDB.
+//! With several runtimes at once (see `executor`), the registry describes the first one created, until it is dropped.
+
+use core::mem::offset_of;
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+use crate::arch::{Arch, Context, Frame};
+use crate::thread::Thread;
+
+/// Offset of the return address when it is on top of the saved stack instead of in the `Context`.
+pub const PC_ON_STACK: usize = usize::MAX;
+
+/// Where the threads of the runtime are, and how to read them.
+/// Offsets are in bytes, and `state` is the discriminant of `State`, in the order its variants are declared.
+#[derive(Debug)]
+#[repr(C)]
+pub struct Registry {
+    /// Changed whenever the layout of the registry does.
+    pub version: u32,
+    /// Whether `sp`, `sp_adjust`, `fp` and `pc` are valid, as some backends don't keep the registers in the `Context`.
+    pub has_frames: u32,
+    /// The threads, `len` of them, `stride` bytes apart.
+    pub threads: *const Thread,
+    pub len: usize,
+    pub stride: usize,
+    /// Offsets of the id, the state and the `Context` in a thread.
+    pub id: usize,
+    pub state: usize,
+    pub ctx: usize,
+    /// Offset of the saved stack pointer in the `Context`, and what to add to it to get the one of the caller of `switch`.
+    pub sp: usize,
+    pub sp_adjust: usize,
+    /// Offset of the saved frame pointer in the `Context`.
+    pub fp: usize,
+    /// Offset of the saved return address in the `Context`, or `PC_ON_STACK`.
+    pub pc: usize,
+}
+
+const FRAME: Option<Frame> = <Context as Arch>::FRAME;
+
+/// The registry of the runtime, kept up to date as threads are spawned and complete.
+/// The running thread's `Context` is stale: its registers are the ones of the OS thread.
+#[no_mangle]
+pub static mut UTHREADS_REGISTRY: Registry = Registry {
+    version: 1,
+    has_frames: FRAME.is_some() as u32,
+    threads: ptr::null(),
+    len: 0,
+    stride: size_of::<Thread>(),
+    id: offset_of!(Thread, id),
+    state: offset_of!(Thread, state),
+    ctx: offset_of!(Thread, ctx),
+    sp: match FRAME {
+        Some(f) => f.sp,
+        None => 0,
+    },
+    sp_adjust: match FRAME {
+        Some(f) => f.sp_adjust,
+        None => 0,
+    },
+    fp: match FRAME {
+        Some(f) => f.fp,
+        None => 0,
+    },
+    pc: match FRAME {
+        Some(Frame { pc: Some(pc), .. }) => pc,
+        _ => PC_ON_STACK,
+    },
+};
+
+// The runtime the registry describes, identified by its address.
+static OWNER: AtomicPtr<()> = AtomicPtr::new(ptr::null_mut());
+
+// Points the registry at `threads`, the threads of runtime `owner`, whenever they may have moved.
+// Does nothing if the registry describes another runtime.
+pub(crate) fn publish(owner: *const (), threads: &[Thread]) {
+    let owner = owner.cast_mut();
+    if let Err(current) =
+        OWNER.compare_exchange(ptr::null_mut(), owner, Ordering::AcqRel, Ordering::Acquire)
+    {
+        if current != owner {
+            return;
+        }
+    }
+    unsafe {
+        UTHREADS_REGISTRY.threads = threads.as_ptr();
+        UTHREADS_REGISTRY.len = threads.len();
+    }
+}
+
+// Empties the registry if it describes runtime `owner`, for the next runtime to take it over.
+pub(crate) fn release(owner: *const ()) {
+    if OWNER.load(Ordering::Acquire) != owner.cast_mut() {
+        return;
+    }
+    unsafe {
+        UTHREADS_REGISTRY.threads = ptr::null();
+        UTHREADS_REGISTRY.len = 0;
+    }
+    OWNER.store(ptr::null_mut(), Ordering::Release);
+}
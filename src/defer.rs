@@ -0,0 +1,38 @@
+// Scoped cleanup, like the `defer` statement of Go.
+
+/// Runs a closure when dropped, which is when the scope holding it exits, whether it returns or unwinds.
+/// Usually created through the `defer!` macro.
+pub struct Defer<F: FnOnce()> {
+    f: Option<F>,
+}
+
+impl<F: FnOnce()> Defer<F> {
+    pub fn new(f: F) -> Self {
+        Defer { f: Some(f) }
+    }
+
+    /// Drops the guard without running the closure.
+    pub fn dismiss(mut self) {
+        self.f = None;
+    }
+}
+
+impl<F: FnOnce()> Drop for Defer<F> {
+    fn drop(&mut self) {
+        if let Some(f) = self.f.take() {
+            f();
+        }
+    }
+}
+
+/// Runs the statements given when the current scope exits, including when the thread unwinds, like `defer` in Go:
+/// `defer!(println!("done"))` or `defer! { a; b }`. Several of them run in reverse order.
+/// The statements borrow the variables they use until then.
+#[macro_export]
+macro_rules! defer {
+    ($($body:tt)*) => {
+        let _defer = $crate::defer::Defer::new(|| {
+            $($body)*;
+        });
+    };
+}
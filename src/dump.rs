@@ -0,0 +1,95 @@
+// Dumping the state of every thread to stderr, to find out why a runtime hangs.
+// The dump can be triggered by a signal (see `runtime::dump_threads_on`), so it neither allocates nor takes locks:
+// it is formatted into a small buffer, written out whenever it fills up.
+// The handler may run on any OS thread, or interrupt the runtime in the middle of an update,
+// so the dump is only a best effort look at the runtime.
+
+use std::fmt;
+use std::io;
+use std::mem;
+
+use crate::errno;
+use crate::runtime::dump_threads;
+
+// Installs the handler dumping the threads on `signum`, in place of whatever handled it before.
+pub(crate) fn install(signum: libc::c_int) -> io::Result<()> {
+    // Miri doesn't support signal handlers.
+    if cfg!(miri) {
+        return Ok(());
+    }
+    unsafe {
+        let mut action: libc::sigaction = mem::zeroed();
+        action.sa_sigaction = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        // threads blocked in a system call, e.g. the runtime polling for IO, carry on once the dump is done.
+        action.sa_flags = libc::SA_RESTART | libc::SA_ONSTACK;
+        libc::sigemptyset(&mut action.sa_mask);
+        if libc::sigaction(signum, &action, std::ptr::null_mut()) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+extern "C" fn on_signal(_: libc::c_int) {
+    // errno may be changed by the writes, and the code interrupted may be about to look at it.
+    let errno = errno::get();
+    let mut out = Stderr::new();
+    let _ = dump_threads(&mut out);
+    drop(out);
+    errno::set(errno);
+}
+
+// Writes to stderr through a fixed size buffer, flushed when full and when dropped.
+struct Stderr {
+    buf: [u8; 256],
+    len: usize,
+}
+
+impl Stderr {
+    fn new() -> Self {
+        Stderr {
+            buf: [0; 256],
+            len: 0,
+        }
+    }
+
+    fn flush(&mut self) {
+        let mut written = 0;
+        while written < self.len {
+            let n = unsafe {
+                libc::write(
+                    libc::STDERR_FILENO,
+                    self.buf[written..].as_ptr().cast(),
+                    self.len - written,
+                )
+            };
+            if n <= 0 {
+                break;
+            }
+            written += n as usize;
+        }
+        self.len = 0;
+    }
+}
+
+impl fmt::Write for Stderr {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut bytes = s.as_bytes();
+        while !bytes.is_empty() {
+            if self.len == self.buf.len() {
+                self.flush();
+            }
+            let n = bytes.len().min(self.buf.len() - self.len);
+            self.buf[self.len..self.len + n].copy_from_slice(&bytes[..n]);
+            self.len += n;
+            bytes = &bytes[n..];
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Stderr {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
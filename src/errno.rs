@@ -0,0 +1,41 @@
+// The error number of the last failed call into the OS: errno, or the last error on Windows.
+// It belongs to the OS thread, so the green threads running on it share it, and switching threads saves and restores it
+// for each to keep its own (see `runtime::switch_away`). Signal handlers preserve it for the code they interrupt.
+// Neither allocates nor takes locks, so that signal handlers can use it.
+
+#[cfg(target_os = "linux")]
+pub(crate) fn get() -> i32 {
+    unsafe { *libc::__errno_location() }
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn set(errno: i32) {
+    unsafe { *libc::__errno_location() = errno };
+}
+
+#[cfg(target_os = "macos")]
+pub(crate) fn get() -> i32 {
+    unsafe { *libc::__error() }
+}
+
+#[cfg(target_os = "macos")]
+pub(crate) fn set(errno: i32) {
+    unsafe { *libc::__error() = errno };
+}
+
+#[cfg(windows)]
+#[link(name = "kernel32")]
+extern "system" {
+    fn GetLastError() -> u32;
+    fn SetLastError(code: u32);
+}
+
+#[cfg(windows)]
+pub(crate) fn get() -> i32 {
+    unsafe { GetLastError() as i32 }
+}
+
+#[cfg(windows)]
+pub(crate) fn set(errno: i32) {
+    unsafe { SetLastError(errno as u32) };
+}
@@ -0,0 +1,346 @@
+//! Runs green threads on several OS threads, the workers, each with a runtime of its own.
+//! Work is submitted as jobs, closures run on a new green thread of whichever worker picks them up.
+//! Every worker has a queue of its own, which the jobs spawned from its threads go to,
+//! and a worker with nothing left to run steals the jobs queued on the others.
+//!
+//! Only jobs move between workers, and only before they start: once a job runs on a green thread,
+//! that thread stays on the worker it was spawned on until it completes, and is never stolen.
+//! The runtimes of the workers are independent, so their scheduler state, channels and wake paths aren't shared:
+//! the threads of different workers can't use the runtime's primitives (`Channel`, `Once`...) together,
+//! and communicate through `Send` types instead, e.g. `channel::remote_channel`.
+//! Jobs can be pinned to a worker, with `Executor::spawn_on` or `spawn_pinned`, for cache locality:
+//! those are never stolen, even before they start.
+//! Only the threads of the worker `preempt` was enabled from can be preempted.
+//! See `Builder` to configure the workers, e.g. to pin them to CPUs.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread::{self, JoinHandle};
+
+use crate::reactor::RuntimeWaker;
+use crate::runtime::{self, get_current_thread, park, yield_thread, Runtime};
+use crate::thread::Id;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+// The jobs queued on a worker.
+#[derive(Default)]
+struct Queue {
+    // Only run by this worker.
+    pinned: VecDeque<Job>,
+    // Run by this worker, unless another one steals them.
+    jobs: VecDeque<Job>,
+}
+
+/// Runs jobs on a fixed number of worker OS threads, see the module documentation.
+/// Dropping it waits for every job to complete, like `join`.
+pub struct Executor {
+    shared: Arc<Shared>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+struct Shared {
+    queues: Vec<Mutex<Queue>>,
+    /// How to wake up the thread of each worker that takes the jobs, once it has started.
+    feeders: Vec<OnceLock<(RuntimeWaker, Id)>>,
+    /// Jobs spawned which haven't completed yet.
+    pending: AtomicUsize,
+    /// Set once no more jobs are spawned from outside the workers, see `Executor::join`.
+    closed: AtomicBool,
+    /// The queue the next job spawned from outside the workers goes to.
+    next: AtomicUsize,
+}
+
+thread_local! {
+    // The executor the current OS thread is a worker of, and its index, if any.
+    static WORKER: RefCell<Option<(Arc<Shared>, usize)>> = const { RefCell::new(None) };
+}
+
+/// Configures the workers of an `Executor` before starting them.
+#[derive(Debug, Clone)]
+pub struct Builder {
+    /// The CPUs each worker may run on, all of them if None.
+    affinity: Vec<Option<Vec<usize>>>,
+}
+
+impl Builder {
+    /// Configures `workers` workers, which must not be 0.
+    pub fn new(workers: usize) -> Builder {
+        assert!(workers > 0, "an executor needs at least one worker");
+        Builder {
+            affinity: vec![None; workers],
+        }
+    }
+
+    /// Restricts worker `worker` to the CPUs numbered `cpus`, e.g. to keep latency-sensitive work
+    /// off the CPUs other processes run on. Only supported on Linux.
+    pub fn affinity<I: IntoIterator<Item = usize>>(
+        &mut self,
+        worker: usize,
+        cpus: I,
+    ) -> &mut Builder {
+        self.affinity[worker] = Some(cpus.into_iter().collect());
+        self
+    }
+
+    /// Pins every worker to a single CPU, taken from `cpus` in turn, going round if there are fewer CPUs than workers.
+    pub fn pin<I: IntoIterator<Item = usize>>(&mut self, cpus: I) -> &mut Builder {
+        let cpus: Vec<usize> = cpus.into_iter().collect();
+        assert!(!cpus.is_empty(), "no CPUs to pin the workers to");
+        for (worker, cpu) in (0..self.affinity.len()).zip(cpus.iter().cycle()) {
+            self.affinity[worker] = Some(vec![*cpu]);
+        }
+        self
+    }
+
+    /// Starts the workers, failing if any of them couldn't be configured as asked.
+    pub fn build(&self) -> io::Result<Executor> {
+        let workers = self.affinity.len();
+        let shared = Arc::new(Shared {
+            queues: (0..workers).map(|_| Mutex::default()).collect(),
+            feeders: (0..workers).map(|_| OnceLock::new()).collect(),
+            pending: AtomicUsize::new(0),
+            closed: AtomicBool::new(false),
+            next: AtomicUsize::new(0),
+        });
+        let (tx, rx) = mpsc::channel();
+        let workers = self
+            .affinity
+            .iter()
+            .enumerate()
+            .map(|(index, cpus)| {
+                let shared = shared.clone();
+                let cpus = cpus.clone();
+                let started = tx.clone();
+                thread::Builder::new()
+                    .name(format!("uthreads-worker-{index}"))
+                    .spawn(move || work(shared, index, cpus, started))
+                    .expect("failed to spawn an executor worker")
+            })
+            .collect::<Vec<_>>();
+
+        let mut executor = Executor { shared, workers };
+        for started in rx.iter().take(executor.workers.len()) {
+            if let Err(err) = started {
+                executor.stop();
+                return Err(err);
+            }
+        }
+        Ok(executor)
+    }
+}
+
+impl Executor {
+    /// Starts `workers` worker OS threads, which must not be 0. See `Builder` to configure them.
+    pub fn new(workers: usize) -> Self {
+        Builder::new(workers)
+            .build()
+            .expect("failed to start the executor workers")
+    }
+
+    /// Starts `workers` worker OS threads, as many as the CPUs available.
+    pub fn with_available_parallelism() -> Self {
+        Executor::new(thread::available_parallelism().map_or(1, |n| n.get()))
+    }
+
+    /// Queues `f` to run on a new green thread of one of the workers, in turn,
+    /// unless another one with nothing to run steals it before it starts.
+    pub fn spawn(&self, f: impl FnOnce() + Send + 'static) {
+        let index = self.shared.next.fetch_add(1, Ordering::Relaxed) % self.shared.queues.len();
+        self.shared.push(index, Box::new(f), false);
+    }
+
+    /// Queues `f` to run on a new green thread of worker `worker`, and no other: the job is never stolen.
+    /// This only decides where the job starts, as every green thread stays on its worker anyway.
+    pub fn spawn_on(&self, worker: usize, f: impl FnOnce() + Send + 'static) {
+        assert!(
+            worker < self.shared.queues.len(),
+            "no worker {worker} in the executor"
+        );
+        self.shared.push(worker, Box::new(f), true);
+    }
+
+    /// Waits for every job to complete, including the ones they spawn, then stops the workers.
+    pub fn join(mut self) {
+        self.stop();
+    }
+
+    fn stop(&mut self) {
+        self.shared.closed.store(true, Ordering::Release);
+        self.shared.notify();
+        for worker in self.workers.drain(..) {
+            if let Err(payload) = worker.join() {
+                std::panic::resume_unwind(payload);
+            }
+        }
+    }
+}
+
+impl Drop for Executor {
+    fn drop(&mut self) {
+        if !thread::panicking() {
+            self.stop();
+        }
+    }
+}
+
+/// Queues `f` to run on a new green thread, on the same worker if called from the thread of an `Executor`,
+/// unless another one with nothing to run steals it first.
+/// Spawns it on the current runtime otherwise, like `runtime::spawn`.
+pub fn spawn(f: impl FnOnce() + Send + 'static) {
+    let worker = WORKER.with(|worker| worker.borrow().clone());
+    match worker {
+        Some((shared, index)) => shared.push(index, Box::new(f), false),
+        None => {
+            runtime::spawn(f);
+        }
+    }
+}
+
+/// Queues `f` to run on a new green thread of the same worker if called from the thread of an `Executor`,
+/// and no other: the job is never stolen, e.g. to keep working on the data in the caches of its CPU.
+/// Like `Executor::spawn_on`, this only decides where the job starts, the thread never moves once it does.
+/// Spawns it on the current runtime otherwise, like `runtime::spawn`.
+/// `runtime::spawn` also spawns threads on the current worker directly, which don't need to be `Send`.
+pub fn spawn_pinned(f: impl FnOnce() + Send + 'static) {
+    let worker = WORKER.with(|worker| worker.borrow().clone());
+    match worker {
+        Some((shared, index)) => shared.push(index, Box::new(f), true),
+        None => {
+            runtime::spawn(f);
+        }
+    }
+}
+
+impl Shared {
+    // Queues `job` on worker `index`, for it only if `pinned`.
+    fn push(&self, index: usize, job: Job, pinned: bool) {
+        self.pending.fetch_add(1, Ordering::Relaxed);
+        let mut queue = self.queues[index].lock().unwrap();
+        if pinned {
+            queue.pinned.push_back(job);
+        } else {
+            queue.jobs.push_back(job);
+        }
+        drop(queue);
+        self.notify();
+    }
+
+    // Takes the oldest job queued on worker `index`, pinned ones first,
+    // or else steals the newest one of another worker which isn't pinned to it.
+    fn take(&self, index: usize) -> Option<Job> {
+        let mut queue = self.queues[index].lock().unwrap();
+        if let Some(job) = queue.pinned.pop_front().or_else(|| queue.jobs.pop_front()) {
+            return Some(job);
+        }
+        drop(queue);
+        let len = self.queues.len();
+        (1..len).find_map(|i| {
+            self.queues[(index + i) % len]
+                .lock()
+                .unwrap()
+                .jobs
+                .pop_back()
+        })
+    }
+
+    fn complete(&self) {
+        if self.pending.fetch_sub(1, Ordering::AcqRel) == 1 && self.closed.load(Ordering::Acquire) {
+            self.notify();
+        }
+    }
+
+    fn done(&self) -> bool {
+        self.closed.load(Ordering::Acquire) && self.pending.load(Ordering::Acquire) == 0
+    }
+
+    // Wakes up every worker waiting for jobs, as any of them may steal a new one.
+    fn notify(&self) {
+        for (waker, id) in self.feeders.iter().filter_map(OnceLock::get) {
+            waker.unpark(*id);
+        }
+    }
+}
+
+// Runs worker `index`, once restricted to `cpus` if any, which `started` is told about.
+fn work(
+    shared: Arc<Shared>,
+    index: usize,
+    cpus: Option<Vec<usize>>,
+    started: Sender<io::Result<()>>,
+) {
+    let pinned = cpus.map_or(Ok(()), |cpus| set_affinity(&cpus));
+    let failed = pinned.is_err();
+    let _ = started.send(pinned);
+    if failed {
+        return;
+    }
+
+    WORKER.with(|worker| *worker.borrow_mut() = Some((shared.clone(), index)));
+    let mut runtime = Runtime::new();
+    unsafe { runtime.init() };
+    let waker = runtime.waker();
+    runtime.spawn(move || feed(shared, index, waker));
+    runtime.run();
+    WORKER.with(|worker| worker.borrow_mut().take());
+}
+
+// Spawns the jobs of worker `index` on its runtime as it runs out of threads to run, until the executor is done.
+fn feed(shared: Arc<Shared>, index: usize, waker: RuntimeWaker) {
+    let _ = shared.feeders[index].set((waker, get_current_thread()));
+    // jobs queued before the feeder could be woken up would be missed otherwise.
+    shared.notify();
+    loop {
+        // the other threads run first, the jobs left in the queue meanwhile can be stolen by idle workers.
+        if runtime::ready_count() > 0 {
+            yield_thread();
+            continue;
+        }
+        if let Some(job) = shared.take(index) {
+            let shared = shared.clone();
+            runtime::spawn(move || {
+                job();
+                shared.complete();
+            });
+            yield_thread();
+            continue;
+        }
+        if shared.done() {
+            return;
+        }
+        park();
+    }
+}
+
+// Restricts the calling OS thread to the CPUs numbered `cpus`.
+#[cfg(target_os = "linux")]
+fn set_affinity(cpus: &[usize]) -> io::Result<()> {
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        for &cpu in cpus {
+            if cpu >= libc::CPU_SETSIZE as usize {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("no CPU {cpu}"),
+                ));
+            }
+            libc::CPU_SET(cpu, &mut set);
+        }
+        if libc::sched_setaffinity(0, size_of::<libc::cpu_set_t>(), &set) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_affinity(_cpus: &[usize]) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "setting the CPU affinity of workers is only supported on Linux",
+    ))
+}
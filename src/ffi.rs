@@ -0,0 +1,146 @@
+// The C API of the runtime, for C and C++ programs to run green threads.
+// Build the crate as a static or dynamic library with the `ffi` feature, e.g.
+// `cargo rustc --release --features ffi --crate-type staticlib`, and include `include/uthreads.h`,
+// which is generated from this module by cbindgen (see `cbindgen.toml`).
+//
+// The functions are those of the Rust API, on the runtime of the calling OS thread.
+// A panic can't unwind into C, so the process aborts where the Rust API would panic.
+
+use std::ffi::c_void;
+use std::ptr;
+
+use crate::channel::Channel;
+use crate::runtime::{self, chan_recv, chan_send, Runtime};
+
+/// A runtime, see `uthreads_runtime_new`.
+pub struct UthreadsRuntime {
+    runtime: Runtime,
+}
+
+/// A channel of byte buffers, see `uthreads_channel_new`.
+pub struct UthreadsChannel {
+    chan: Channel<Box<[u8]>>,
+}
+
+/// Creates a runtime and initialises it on the calling OS thread, which runs its threads from then on.
+/// Returns NULL if it can't be created.
+///
+/// # Safety
+///
+/// No other runtime may be initialised on the calling OS thread while this one is in use.
+#[no_mangle]
+pub unsafe extern "C" fn uthreads_runtime_new() -> *mut UthreadsRuntime {
+    let Ok(runtime) = Runtime::try_new() else {
+        return ptr::null_mut();
+    };
+    let rt = Box::leak(Box::new(UthreadsRuntime { runtime }));
+    if unsafe { rt.runtime.try_init() }.is_err() {
+        drop(unsafe { Box::from_raw(rt) });
+        return ptr::null_mut();
+    }
+    rt
+}
+
+/// Runs the threads of `rt` until none is left to run.
+///
+/// # Safety
+///
+/// `rt` must have been returned by `uthreads_runtime_new` on the calling OS thread, and not freed.
+#[no_mangle]
+pub unsafe extern "C" fn uthreads_runtime_run(rt: *mut UthreadsRuntime) {
+    unsafe { (*rt).runtime.run() };
+}
+
+/// Frees `rt`, along with the threads it didn't run to completion.
+///
+/// # Safety
+///
+/// `rt` must have been returned by `uthreads_runtime_new`, and not freed already. It must not be running.
+#[no_mangle]
+pub unsafe extern "C" fn uthreads_runtime_free(rt: *mut UthreadsRuntime) {
+    drop(unsafe { Box::from_raw(rt) });
+}
+
+/// Spawns a thread calling `f` with `arg`, on the runtime of the calling OS thread.
+/// Returns the id of the thread, or 0 if it can't be spawned as too many threads are alive.
+///
+/// # Safety
+///
+/// A runtime must be initialised on the calling OS thread, and `arg` valid for whatever `f` does with it.
+#[no_mangle]
+pub unsafe extern "C" fn uthreads_spawn(f: extern "C" fn(*mut c_void), arg: *mut c_void) -> usize {
+    runtime::try_spawn(move || f(arg)).map_or(0, |id| id.0)
+}
+
+/// Gives control to the other threads of the runtime, which runs the calling thread again later.
+///
+/// # Safety
+///
+/// Must be called from a thread of a runtime.
+#[no_mangle]
+pub unsafe extern "C" fn uthreads_yield() {
+    runtime::yield_thread();
+}
+
+/// Creates a channel of byte buffers, whose buffer holds `size` of them before senders block.
+#[no_mangle]
+pub extern "C" fn uthreads_channel_new(size: usize) -> *mut UthreadsChannel {
+    Box::into_raw(Box::new(UthreadsChannel {
+        chan: Channel::new(size),
+    }))
+}
+
+/// Frees `chan`, along with the buffers sent to it and not received.
+///
+/// # Safety
+///
+/// `chan` must have been returned by `uthreads_channel_new`, and not freed already. No thread may be blocked on it.
+#[no_mangle]
+pub unsafe extern "C" fn uthreads_channel_free(chan: *mut UthreadsChannel) {
+    drop(unsafe { Box::from_raw(chan) });
+}
+
+/// Sends a copy of the `len` bytes at `data` to `chan`, blocking the calling thread while its buffer is full.
+///
+/// # Safety
+///
+/// `chan` must be a live channel, used from the threads of a single runtime, and `data` valid for reading `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn uthreads_channel_send(
+    chan: *mut UthreadsChannel,
+    data: *const u8,
+    len: usize,
+) {
+    let buf = if len == 0 {
+        Box::default()
+    } else {
+        Box::from(unsafe { std::slice::from_raw_parts(data, len) })
+    };
+    unsafe { chan_send(&mut (*chan).chan, buf) };
+}
+
+/// Receives a buffer from `chan`, blocking the calling thread until one is sent.
+/// Returns the buffer, of `*len` bytes, which is freed with `uthreads_buffer_free`.
+///
+/// # Safety
+///
+/// `chan` must be a live channel, used from the threads of a single runtime, and `len` valid for writing.
+#[no_mangle]
+pub unsafe extern "C" fn uthreads_channel_recv(
+    chan: *mut UthreadsChannel,
+    len: *mut usize,
+) -> *mut u8 {
+    let buf = unsafe { chan_recv(&mut (*chan).chan) };
+    unsafe { *len = buf.len() };
+    Box::into_raw(buf).cast()
+}
+
+/// Frees a buffer returned by `uthreads_channel_recv`, of `len` bytes.
+///
+/// # Safety
+///
+/// `data` and `len` must be what `uthreads_channel_recv` returned, and the buffer not freed already.
+#[no_mangle]
+pub unsafe extern "C" fn uthreads_buffer_free(data: *mut u8, len: usize) {
+    drop(unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(data, len)) });
+}
@@ -0,0 +1,126 @@
+// Filesystem operations for green threads.
+// Regular files are always ready as far as readiness polling is concerned, yet reading or writing them can block,
+// so the operations are run on the blocking pool while the calling green thread is blocked.
+
+use std::fs;
+use std::io::{self as std_io, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::blocking::spawn_blocking;
+use crate::io::{Read, Write};
+
+/// Reads the whole file at `path`.
+pub fn read<P: AsRef<Path>>(path: P) -> std_io::Result<Vec<u8>> {
+    let path = path.as_ref().to_owned();
+    spawn_blocking(move || fs::read(path))
+}
+
+/// Reads the whole file at `path` into a string.
+pub fn read_to_string<P: AsRef<Path>>(path: P) -> std_io::Result<String> {
+    let path = path.as_ref().to_owned();
+    spawn_blocking(move || fs::read_to_string(path))
+}
+
+/// Writes `contents` to the file at `path`, creating or truncating it.
+pub fn write<P: AsRef<Path>, C: AsRef<[u8]>>(path: P, contents: C) -> std_io::Result<()> {
+    let path = path.as_ref().to_owned();
+    let contents = contents.as_ref().to_owned();
+    spawn_blocking(move || fs::write(path, contents))
+}
+
+/// An open file, whose operations only block the calling green thread.
+#[derive(Debug)]
+pub struct File {
+    // shared with the job running the current operation on the blocking pool.
+    inner: Arc<fs::File>,
+}
+
+impl File {
+    /// Opens the file at `path` in read-only mode.
+    pub fn open<P: AsRef<Path>>(path: P) -> std_io::Result<File> {
+        let path = path.as_ref().to_owned();
+        spawn_blocking(move || fs::File::open(path)).map(File::from_std)
+    }
+
+    /// Opens the file at `path` in write-only mode, creating or truncating it.
+    pub fn create<P: AsRef<Path>>(path: P) -> std_io::Result<File> {
+        let path: PathBuf = path.as_ref().to_owned();
+        spawn_blocking(move || fs::File::create(path)).map(File::from_std)
+    }
+
+    pub fn from_std(file: fs::File) -> File {
+        File {
+            inner: Arc::new(file),
+        }
+    }
+
+    /// Moves the cursor, see `std::io::Seek`.
+    pub fn seek(&self, pos: SeekFrom) -> std_io::Result<u64> {
+        self.run(move |mut file| file.seek(pos))
+    }
+
+    /// Flushes the data and metadata of the file to disk.
+    pub fn sync_all(&self) -> std_io::Result<()> {
+        self.run(|file| file.sync_all())
+    }
+
+    pub fn metadata(&self) -> std_io::Result<fs::Metadata> {
+        self.run(|file| file.metadata())
+    }
+
+    pub fn set_len(&self, size: u64) -> std_io::Result<()> {
+        self.run(move |file| file.set_len(size))
+    }
+
+    fn run<F, T>(&self, f: F) -> std_io::Result<T>
+    where
+        F: FnOnce(&fs::File) -> std_io::Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let file = self.inner.clone();
+        spawn_blocking(move || f(&file))
+    }
+}
+
+impl Read for &File {
+    fn read(&mut self, buf: &mut [u8]) -> std_io::Result<usize> {
+        // the job can't borrow `buf`, so the data is read into a buffer of its own and copied over.
+        let len = buf.len();
+        let data = self.run(move |mut file| {
+            let mut data = vec![0; len];
+            let n = std_io::Read::read(&mut file, &mut data)?;
+            data.truncate(n);
+            Ok(data)
+        })?;
+        buf[..data.len()].copy_from_slice(&data);
+        Ok(data.len())
+    }
+}
+
+impl Write for &File {
+    fn write(&mut self, buf: &[u8]) -> std_io::Result<usize> {
+        let data = buf.to_owned();
+        self.run(move |mut file| std_io::Write::write(&mut file, &data))
+    }
+
+    fn flush(&mut self) -> std_io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Read for File {
+    fn read(&mut self, buf: &mut [u8]) -> std_io::Result<usize> {
+        (&*self).read(buf)
+    }
+}
+
+impl Write for File {
+    fn write(&mut self, buf: &[u8]) -> std_io::Result<usize> {
+        (&*self).write(buf)
+    }
+
+    fn flush(&mut self) -> std_io::Result<()> {
+        (&*self).flush()
+    }
+}
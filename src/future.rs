@@ -0,0 +1,73 @@
+// Waiting on std futures from green threads, so that async libraries can be used from straight-line code.
+// The future is polled on the thread waiting on it, which parks in between polls,
+// and is unparked through the reactor's wake pipe when the future's waker fires, from whatever OS thread.
+
+use std::future::{Future, IntoFuture};
+use std::pin::pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+
+use crate::reactor::RuntimeWaker;
+use crate::runtime::{self, get_current_thread, park};
+use crate::thread::Id;
+
+/// Blocks the current thread until `future` completes, and returns its output.
+/// Other threads keep running while the future is pending.
+pub fn wait_on<F: IntoFuture>(future: F) -> F::Output {
+    let mut future = pin!(future.into_future());
+    let waker = ThreadWaker::current().into_waker();
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+        // returns right away if the waker fired while polling.
+        park();
+    }
+}
+
+/// Unparks a green thread when woken up, as a `std::task::Waker` (see `into_waker`), to plug the runtime into poll-based APIs:
+/// the thread polls, parks with `runtime::park` while pending, and is unparked when it can make progress.
+/// Can be woken up from any OS thread, like the `RuntimeWaker` it wraps.
+#[derive(Debug, Clone)]
+pub struct ThreadWaker {
+    waker: RuntimeWaker,
+    id: Id,
+}
+
+impl ThreadWaker {
+    /// Returns a waker unparking thread `id` of the runtime `waker` belongs to.
+    pub fn new(waker: RuntimeWaker, id: Id) -> Self {
+        ThreadWaker { waker, id }
+    }
+
+    /// Returns a waker unparking the current thread.
+    pub fn current() -> Self {
+        ThreadWaker::new(runtime::waker(), get_current_thread())
+    }
+
+    /// Returns the id of the thread unparked.
+    pub fn id(&self) -> Id {
+        self.id
+    }
+
+    /// Unparks the thread, see `RuntimeWaker::unpark`.
+    pub fn unpark(&self) {
+        self.waker.unpark(self.id);
+    }
+
+    /// Returns a `Waker` unparking the thread.
+    pub fn into_waker(self) -> Waker {
+        Waker::from(Arc::new(self))
+    }
+}
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.unpark();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.unpark();
+    }
+}
@@ -0,0 +1,195 @@
+// Generators: closures running on a stack of their own, which hand values to their caller as they go.
+// They use the same context switching as the threads, but switch straight between the generator and whoever resumes it,
+// without going through the scheduler, so they work with or without a runtime, and from any thread.
+
+use std::any::Any;
+use std::cell::Cell;
+use std::marker::PhantomData;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr::{self, NonNull};
+
+use crate::arch::{Arch, Context};
+use crate::preempt;
+use crate::thread::{MmapAllocator, Stack, StackAllocator};
+use crate::DEFAULT_STACK_SIZE;
+
+pub(crate) type Body = Option<Box<dyn FnOnce()>>;
+
+thread_local! {
+    // The body of the generator being started, which `entry` runs.
+    static STARTING: Cell<*mut Body> = const { Cell::new(ptr::null_mut()) };
+}
+
+/// A closure running on a stack of its own, which yields values to its caller through the `Yielder` it is given:
+/// `resume` runs it until it yields the next value, or completes.
+/// A panic of the closure is resumed on the caller of `resume`.
+/// Dropping a generator which hasn't completed unwinds its closure, as if `yield_` had panicked.
+pub struct Generator<Y> {
+    // allocated on the heap, as the closure refers to it, and only ever accessed through this pointer.
+    inner: NonNull<Inner<Y>>,
+    _owns: PhantomData<Inner<Y>>,
+}
+
+/// Passed to the closure of a `Generator`, to yield values to its caller.
+pub struct Yielder<Y> {
+    inner: *mut Inner<Y>,
+}
+
+struct Inner<Y> {
+    ctx: Context,
+    // the context of whoever resumed the generator.
+    caller: Context,
+    stack: Option<Stack>,
+    // the closure, until the generator is first resumed.
+    body: Body,
+    yielded: Option<Y>,
+    panic: Option<Box<dyn Any + Send>>,
+    done: bool,
+    // set to make `yield_` unwind the closure.
+    cancel: bool,
+}
+
+// The payload `yield_` unwinds with when the generator is dropped.
+struct Cancelled;
+
+impl<Y: 'static> Generator<Y> {
+    /// Creates a generator running `f`, which doesn't start until it is first resumed.
+    pub fn new(f: impl FnOnce(&Yielder<Y>) + 'static) -> Self {
+        let stack = MmapAllocator
+            .allocate(DEFAULT_STACK_SIZE, true)
+            .unwrap_or_else(|err| panic!("failed to allocate a generator stack: {err}"));
+        let inner = Box::new(Inner {
+            ctx: Context::default(),
+            caller: Context::default(),
+            stack: Some(stack),
+            body: None,
+            yielded: None,
+            panic: None,
+            done: false,
+            cancel: false,
+        });
+
+        let this = Box::into_raw(inner);
+        let body: Box<dyn FnOnce()> = Box::new(move || {
+            let yielder = Yielder { inner: this };
+            let result = panic::catch_unwind(AssertUnwindSafe(|| f(&yielder)));
+            unsafe {
+                if let Err(payload) = result {
+                    if !payload.is::<Cancelled>() {
+                        (*this).panic = Some(payload);
+                    }
+                }
+                (*this).done = true;
+                Context::switch(&mut (*this).ctx, &(*this).caller);
+            }
+            unreachable!("a completed generator was resumed");
+        });
+        unsafe {
+            (*this).body = Some(body);
+            let Inner { ctx, stack, .. } = &mut *this;
+            ctx.bootstrap(stack.as_mut().unwrap(), entry);
+        }
+
+        Generator {
+            inner: unsafe { NonNull::new_unchecked(this) },
+            _owns: PhantomData,
+        }
+    }
+}
+
+impl<Y> Generator<Y> {
+    /// Runs the closure until it yields a value, which is returned, or completes, in which case None is returned,
+    /// now and on every later call.
+    pub fn resume(&mut self) -> Option<Y> {
+        let inner = self.inner.as_ptr();
+        unsafe {
+            if (*inner).done {
+                return None;
+            }
+            self.switch();
+            if let Some(payload) = (*inner).panic.take() {
+                panic::resume_unwind(payload);
+            }
+            (*inner).yielded.take()
+        }
+    }
+
+    /// Returns true once the closure has completed.
+    pub fn is_done(&self) -> bool {
+        unsafe { (*self.inner.as_ptr()).done }
+    }
+
+    // Switches to the generator, until it yields or completes.
+    fn switch(&mut self) {
+        let inner = self.inner.as_ptr();
+        unsafe {
+            if (*inner).body.is_some() {
+                set_starting(&mut (*inner).body);
+            }
+            // the generator starts with preemption enabled, see `runtime::start`.
+            let depth = preempt::depth();
+            Context::switch(&mut (*inner).caller, &(*inner).ctx);
+            preempt::set_depth(depth);
+        }
+    }
+}
+
+impl<Y> Iterator for Generator<Y> {
+    type Item = Y;
+
+    fn next(&mut self) -> Option<Y> {
+        self.resume()
+    }
+}
+
+impl<Y> Drop for Generator<Y> {
+    fn drop(&mut self) {
+        let inner = self.inner.as_ptr();
+        unsafe {
+            // a generator which hasn't started has nothing to unwind, and unwinding one while panicking would abort.
+            let started = (*inner).body.is_none();
+            if started && !(*inner).done && !std::thread::panicking() {
+                (*inner).cancel = true;
+                self.switch();
+            }
+            // a generator which caught the cancellation and yielded again is never resumed, its stack can go.
+            let inner = Box::from_raw(inner);
+            if let Some(stack) = inner.stack {
+                MmapAllocator.deallocate(stack);
+            }
+        }
+    }
+}
+
+impl<Y> Yielder<Y> {
+    /// Hands `val` to the caller of `resume`, and returns once the generator is resumed again.
+    pub fn yield_(&self, val: Y) {
+        let inner = self.inner;
+        unsafe {
+            (*inner).yielded = Some(val);
+            let depth = preempt::depth();
+            Context::switch(&mut (*inner).ctx, &(*inner).caller);
+            preempt::set_depth(depth);
+            if (*inner).cancel {
+                panic::resume_unwind(Box::new(Cancelled));
+            }
+        }
+    }
+}
+
+// Runs the body of the generator being started, which switches back to its caller once done instead of returning.
+// Coroutines start the same way.
+pub(crate) fn entry() {
+    let body = unsafe { (*STARTING.get()).take() };
+    body.expect("started a generator without a body")();
+}
+
+// The body of the generator being started, also for the backends which start it on another OS thread.
+#[cfg(miri)]
+pub(crate) fn starting() -> *mut Body {
+    STARTING.get()
+}
+
+pub(crate) fn set_starting(body: *mut Body) {
+    STARTING.set(body);
+}
@@ -0,0 +1,159 @@
+// Groups of threads, to cancel and wait for together, e.g. the handlers of the connections accepted by one listener.
+// Cancellation is cooperative, through a context shared by the members of the group (see `context`):
+// cancelling the group interrupts the members blocked in its operations, and the others see it done the next time they look.
+
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::rc::Rc;
+use std::time::Duration;
+
+use crate::context::{Cancel, Context};
+use crate::runtime::{
+    self, add_budget, change_thread_state, get_current_thread, remove_budget, set_budget,
+    yield_thread,
+};
+use crate::thread::{Id, State};
+
+/// A named set of threads, spawned with `spawn`, which can be cancelled and waited for together.
+/// Cloning a group shares it. Dropping it neither cancels nor waits for the members, which keep it alive until they
+/// complete: they are detached. To stop them, `cancel` the group and `join_all` before dropping it.
+#[derive(Clone)]
+pub struct Group {
+    inner: Rc<Inner>,
+}
+
+struct Inner {
+    name: String,
+    ctx: Context,
+    cancel: Cancel,
+    // the members which haven't completed yet.
+    members: RefCell<Vec<Id>>,
+    // the threads blocked in `join_all`.
+    joiners: RefCell<Vec<Id>>,
+    // the key of the CPU budget of the members in the runtime, if limited.
+    budget: Cell<Option<usize>>,
+}
+
+impl Group {
+    /// Creates an empty group, whose members are cancelled when it is, see `cancel`.
+    pub fn new(name: impl Into<String>) -> Self {
+        Group::with_context(name, &Context::background())
+    }
+
+    /// Creates an empty group, whose members are also cancelled along with `parent`, e.g. the context of a whole server.
+    pub fn with_context(name: impl Into<String>, parent: &Context) -> Self {
+        let (ctx, cancel) = parent.with_cancel();
+        Group {
+            inner: Rc::new(Inner {
+                name: name.into(),
+                ctx,
+                cancel,
+                members: RefCell::new(Vec::new()),
+                joiners: RefCell::new(Vec::new()),
+                budget: Cell::new(None),
+            }),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.inner.name
+    }
+
+    /// Returns the context of the group, which its members are passed, done once the group is cancelled.
+    pub fn context(&self) -> &Context {
+        &self.inner.ctx
+    }
+
+    /// Spawns a thread into the group, running `f` with the context of the group, and returns its id.
+    /// The thread should stop once the context is done, which its blocking operations tell.
+    /// Panics if too many threads are alive, see `Runtime::set_max_threads`.
+    pub fn spawn(&self, f: impl FnOnce(Context) + 'static) -> Id {
+        let group = self.clone();
+        let id = runtime::spawn(move || {
+            let ctx = group.inner.ctx.clone();
+            ctx.scope(|| f(ctx.clone()));
+            group.leave(get_current_thread());
+        });
+        self.inner.members.borrow_mut().push(id);
+        if let Some(budget) = self.inner.budget.get() {
+            set_budget(id, Some(budget));
+        }
+        id
+    }
+
+    /// Limits the CPU time the members may use together to `quota` every `period`, or lifts the limit with None.
+    /// Once over the quota, they only run when no thread outside of the group is ready, until the next period starts,
+    /// e.g. so that a group doing batch work doesn't hold up one serving requests.
+    /// Time is only counted as the members yield or block, so a member running without doing so still holds up the others,
+    /// see `Runtime::set_time_slice`.
+    pub fn set_cpu_quota(&self, quota: Option<Duration>, period: Duration) {
+        if let Some(budget) = self.inner.budget.take() {
+            remove_budget(budget);
+        }
+        let budget = quota.map(|quota| add_budget(quota, period));
+        self.inner.budget.set(budget);
+        for &id in self.inner.members.borrow().iter() {
+            set_budget(id, budget);
+        }
+    }
+
+    /// Returns how many members haven't completed yet.
+    pub fn len(&self) -> usize {
+        self.inner.members.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Cancels the context of the group, interrupting the members blocked in its operations.
+    /// Threads spawned into the group afterwards start with the context done.
+    pub fn cancel(&self) {
+        self.inner.cancel.cancel();
+    }
+
+    /// Blocks the current thread until every member has completed, including those spawned while it waits.
+    /// Panics if called from a member, which would wait for itself.
+    pub fn join_all(&self) {
+        let id = get_current_thread();
+        assert!(
+            !self.inner.members.borrow().contains(&id),
+            "a member of group {:?} waiting for the group to complete",
+            self.inner.name
+        );
+        while !self.is_empty() {
+            self.inner.joiners.borrow_mut().push(id);
+            change_thread_state(id, State::SyncBlock);
+            yield_thread();
+        }
+    }
+
+    fn leave(&self, id: Id) {
+        let mut members = self.inner.members.borrow_mut();
+        members.retain(|&member| member != id);
+        if members.is_empty() {
+            drop(members);
+            for joiner in self.inner.joiners.take() {
+                change_thread_state(joiner, State::Ready);
+            }
+        }
+    }
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        if let Some(budget) = self.budget.get() {
+            remove_budget(budget);
+        }
+    }
+}
+
+impl fmt::Debug for Group {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Group")
+            .field("name", &self.inner.name)
+            .field("len", &self.len())
+            .field("cancelled", &self.inner.ctx.is_done())
+            .finish()
+    }
+}
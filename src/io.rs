@@ -0,0 +1,197 @@
+// Read and Write traits for uthreads IO types.
+// They mirror the ones in std::io, so generic protocol code can be written the same way,
+// with the difference that implementations block the calling green thread rather than the OS thread.
+
+mod buffered;
+mod pipe;
+mod print;
+mod stdio;
+
+use std::io::{Error, ErrorKind, Result};
+
+pub use buffered::{BufReader, BufWriter};
+pub use pipe::{pipe, PipeReader, PipeWriter};
+#[doc(hidden)]
+pub use print::_print;
+pub use stdio::{stderr, stdin, stdout, Stderr, Stdin, Stdout};
+
+const DEFAULT_BUF_SIZE: usize = 8 * 1024;
+
+pub trait Read {
+    /// Reads some bytes into `buf`, returning how many were read.
+    /// Returns 0 once the end of the stream has been reached.
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize>;
+
+    /// Reads exactly enough bytes to fill `buf`.
+    fn read_exact(&mut self, mut buf: &mut [u8]) -> Result<()> {
+        while !buf.is_empty() {
+            match self.read(buf) {
+                Ok(0) => {
+                    return Err(Error::new(
+                        ErrorKind::UnexpectedEof,
+                        "failed to fill buffer",
+                    ))
+                }
+                Ok(n) => buf = &mut buf[n..],
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads all bytes until the end of the stream, appending them to `buf`.
+    fn read_to_end(&mut self, buf: &mut Vec<u8>) -> Result<usize> {
+        let start = buf.len();
+        let mut chunk = [0; DEFAULT_BUF_SIZE];
+        loop {
+            match self.read(&mut chunk) {
+                Ok(0) => return Ok(buf.len() - start),
+                Ok(n) => buf.extend_from_slice(&chunk[..n]),
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Reads all bytes until the end of the stream, appending them to `buf` if they are valid UTF-8.
+    fn read_to_string(&mut self, buf: &mut String) -> Result<usize> {
+        let mut bytes = Vec::new();
+        let n = self.read_to_end(&mut bytes)?;
+        let s = String::from_utf8(bytes).map_err(|_| {
+            Error::new(ErrorKind::InvalidData, "stream did not contain valid UTF-8")
+        })?;
+        buf.push_str(&s);
+        Ok(n)
+    }
+}
+
+pub trait Write {
+    /// Writes some bytes from `buf`, returning how many were written.
+    fn write(&mut self, buf: &[u8]) -> Result<usize>;
+
+    /// Flushes any data buffered along the way to the destination.
+    fn flush(&mut self) -> Result<()>;
+
+    /// Writes the whole of `buf`.
+    fn write_all(&mut self, mut buf: &[u8]) -> Result<()> {
+        while !buf.is_empty() {
+            match self.write(buf) {
+                Ok(0) => {
+                    return Err(Error::new(
+                        ErrorKind::WriteZero,
+                        "failed to write whole buffer",
+                    ))
+                }
+                Ok(n) => buf = &buf[n..],
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A reader with an internal buffer, which allows reading up to a delimiter.
+pub trait BufRead: Read {
+    /// Returns the buffered data, reading more from the inner reader if the buffer is empty.
+    fn fill_buf(&mut self) -> Result<&[u8]>;
+
+    /// Marks `amt` bytes of the buffer as read.
+    fn consume(&mut self, amt: usize);
+
+    /// Reads until `delim` (included) or the end of the stream, appending the bytes to `buf`.
+    fn read_until(&mut self, delim: u8, buf: &mut Vec<u8>) -> Result<usize> {
+        let mut read = 0;
+        loop {
+            let (done, used) = {
+                let available = match self.fill_buf() {
+                    Ok(available) => available,
+                    Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                    Err(err) => return Err(err),
+                };
+                match available.iter().position(|&b| b == delim) {
+                    Some(i) => {
+                        buf.extend_from_slice(&available[..=i]);
+                        (true, i + 1)
+                    }
+                    None => {
+                        buf.extend_from_slice(available);
+                        (available.is_empty(), available.len())
+                    }
+                }
+            };
+            self.consume(used);
+            read += used;
+            if done {
+                return Ok(read);
+            }
+        }
+    }
+
+    /// Reads until a newline (included) or the end of the stream, appending the line to `buf`.
+    fn read_line(&mut self, buf: &mut String) -> Result<usize> {
+        let mut bytes = Vec::new();
+        let n = self.read_until(b'\n', &mut bytes)?;
+        let s = String::from_utf8(bytes).map_err(|_| {
+            Error::new(ErrorKind::InvalidData, "stream did not contain valid UTF-8")
+        })?;
+        buf.push_str(&s);
+        Ok(n)
+    }
+}
+
+impl<R: Read + ?Sized> Read for &mut R {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        (**self).read(buf)
+    }
+}
+
+impl<R: Read + ?Sized> Read for Box<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        (**self).read(buf)
+    }
+}
+
+impl<W: Write + ?Sized> Write for &mut W {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        (**self).write(buf)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        (**self).flush()
+    }
+}
+
+impl<W: Write + ?Sized> Write for Box<W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        (**self).write(buf)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        (**self).flush()
+    }
+}
+
+// In-memory implementations, which never block.
+
+impl Read for &[u8] {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let n = buf.len().min(self.len());
+        let (head, tail) = self.split_at(n);
+        buf[..n].copy_from_slice(head);
+        *self = tail;
+        Ok(n)
+    }
+}
+
+impl Write for Vec<u8> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,161 @@
+use std::io::Result;
+
+use super::{BufRead, Read, Write, DEFAULT_BUF_SIZE};
+
+/// Adds buffering to a reader, so that small reads don't each cost a syscall (and possibly a thread switch).
+pub struct BufReader<R> {
+    inner: R,
+    buf: Box<[u8]>,
+    /// Position of the next byte to be read from `buf`.
+    pos: usize,
+    /// Number of valid bytes in `buf`.
+    filled: usize,
+}
+
+impl<R: Read> BufReader<R> {
+    pub fn new(inner: R) -> Self {
+        BufReader::with_capacity(DEFAULT_BUF_SIZE, inner)
+    }
+
+    pub fn with_capacity(capacity: usize, inner: R) -> Self {
+        BufReader {
+            inner,
+            buf: vec![0; capacity].into_boxed_slice(),
+            pos: 0,
+            filled: 0,
+        }
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Returns the inner reader. Any buffered data is lost.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Returns the data that has been buffered but not read yet.
+    pub fn buffer(&self) -> &[u8] {
+        &self.buf[self.pos..self.filled]
+    }
+}
+
+impl<R: Read> Read for BufReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        // bypass the buffer for large reads when it is empty, as there's no point in copying twice.
+        if self.pos == self.filled && buf.len() >= self.buf.len() {
+            return self.inner.read(buf);
+        }
+
+        let available = self.fill_buf()?;
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: Read> BufRead for BufReader<R> {
+    fn fill_buf(&mut self) -> Result<&[u8]> {
+        if self.pos == self.filled {
+            self.filled = self.inner.read(&mut self.buf)?;
+            self.pos = 0;
+        }
+        Ok(&self.buf[self.pos..self.filled])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos = (self.pos + amt).min(self.filled);
+    }
+}
+
+/// Adds buffering to a writer, so that small writes are combined into fewer syscalls.
+/// The buffered data is written out when the buffer is full, on `flush`, and when the writer is dropped.
+pub struct BufWriter<W: Write> {
+    inner: W,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> BufWriter<W> {
+    pub fn new(inner: W) -> Self {
+        BufWriter::with_capacity(DEFAULT_BUF_SIZE, inner)
+    }
+
+    pub fn with_capacity(capacity: usize, inner: W) -> Self {
+        BufWriter {
+            inner,
+            buf: Vec::with_capacity(capacity),
+        }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Writes out the buffered data and returns the inner writer.
+    pub fn into_inner(mut self) -> Result<W> {
+        self.flush_buf()?;
+        // the buffer is empty, so there's nothing left for drop to do.
+        let mut this = std::mem::ManuallyDrop::new(self);
+        let inner = unsafe { std::ptr::read(&this.inner) };
+        unsafe { std::ptr::drop_in_place(&mut this.buf) };
+        Ok(inner)
+    }
+
+    /// Returns the data that has been buffered but not written out yet.
+    pub fn buffer(&self) -> &[u8] {
+        &self.buf
+    }
+
+    fn flush_buf(&mut self) -> Result<()> {
+        let mut written = 0;
+        let res = loop {
+            if written == self.buf.len() {
+                break Ok(());
+            }
+            match self.inner.write(&self.buf[written..]) {
+                Ok(0) => break Err(std::io::ErrorKind::WriteZero.into()),
+                Ok(n) => written += n,
+                Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(err) => break Err(err),
+            }
+        };
+        self.buf.drain(..written);
+        res
+    }
+}
+
+impl<W: Write> Write for BufWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        if self.buf.len() + buf.len() > self.buf.capacity() {
+            self.flush_buf()?;
+        }
+        // writes that don't fit in the buffer go straight through.
+        if buf.len() >= self.buf.capacity() {
+            return self.inner.write(buf);
+        }
+        self.buf.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.flush_buf()?;
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Drop for BufWriter<W> {
+    fn drop(&mut self) {
+        // errors can't be reported from drop, call flush to handle them.
+        let _ = self.flush_buf();
+    }
+}
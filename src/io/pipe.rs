@@ -0,0 +1,90 @@
+use std::fs::File;
+use std::io::{self, Result};
+use std::os::fd::{AsRawFd, FromRawFd, RawFd};
+
+use super::{Read, Write};
+use crate::reactor::{until_ready, Interest};
+use crate::runtime::deregister_io;
+
+/// The reading end of a pipe, see `pipe`.
+#[derive(Debug)]
+pub struct PipeReader {
+    inner: File,
+}
+
+/// The writing end of a pipe, see `pipe`.
+#[derive(Debug)]
+pub struct PipeWriter {
+    inner: File,
+}
+
+/// Creates a pipe, whose reads and writes block the current thread rather than the whole runtime,
+/// e.g. to pass bytes between threads of different runtimes, or to wake up a runtime from a signal handler.
+/// Both ends are closed when dropped, and not inherited by child processes.
+pub fn pipe() -> Result<(PipeReader, PipeWriter)> {
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // the files own the ends from here on, so that they are closed on every error path.
+    let (reader, writer) = unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+    for fd in fds {
+        unsafe {
+            let flags = libc::fcntl(fd, libc::F_GETFL);
+            if flags < 0
+                || libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) < 0
+                || libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) < 0
+            {
+                return Err(io::Error::last_os_error());
+            }
+        }
+    }
+    Ok((PipeReader { inner: reader }, PipeWriter { inner: writer }))
+}
+
+impl Read for PipeReader {
+    /// Reads into `buf`, blocking the current thread until some data is available.
+    /// Returns 0 once the writing end is closed and everything written has been read.
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        until_ready(self.inner.as_raw_fd(), Interest::Readable, || {
+            io::Read::read(&mut self.inner, buf)
+        })
+    }
+}
+
+impl Write for PipeWriter {
+    /// Writes some of `buf`, blocking the current thread until the pipe can accept data.
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        until_ready(self.inner.as_raw_fd(), Interest::Writable, || {
+            io::Write::write(&mut self.inner, buf)
+        })
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl AsRawFd for PipeReader {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+impl AsRawFd for PipeWriter {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+impl Drop for PipeReader {
+    fn drop(&mut self) {
+        deregister_io(self.as_raw_fd());
+    }
+}
+
+impl Drop for PipeWriter {
+    fn drop(&mut self) {
+        deregister_io(self.as_raw_fd());
+    }
+}
@@ -0,0 +1,123 @@
+// Printing from green threads, see `println!`.
+// Every thread buffers what it prints until it ends a line, and hands the whole lines over to the runtime's queue
+// for the standard output. The first thread to find the queue idle writes it out, through `stdout`,
+// along with what the others queue meanwhile: lines never interleave, and the other threads don't wait for the output.
+
+use std::cell::RefCell;
+use std::fmt;
+use std::io::Write as _;
+use std::mem;
+
+use super::{stdout, Write};
+use crate::preempt::NoPreempt;
+use crate::runtime::get_current_thread;
+use crate::BASE_THREAD_ID;
+
+thread_local! {
+    // The output of the runtime of this OS thread.
+    static OUTPUT: RefCell<Output> = const {
+        RefCell::new(Output {
+            queue: Vec::new(),
+            writing: false,
+        })
+    };
+}
+
+struct Output {
+    // the lines queued, not written out yet.
+    queue: Vec<u8>,
+    // whether a thread is writing the queue out.
+    writing: bool,
+}
+
+crate::green_local! {
+    // What the current thread printed since it last ended a line.
+    static LINE: Line = Line(RefCell::new(Vec::new()));
+}
+
+struct Line(RefCell<Vec<u8>>);
+
+/// Prints to the standard output like `std::print!`, without blocking the runtime, see `println!`.
+#[macro_export]
+macro_rules! print {
+    ($($arg:tt)*) => {
+        $crate::io::_print(format_args!($($arg)*))
+    };
+}
+
+/// Prints a line to the standard output like `std::println!`, for threads printing at once not to mix up their lines:
+/// every thread buffers what it prints until it ends a line, and the line is written out whole.
+/// The thread doesn't wait for the output, unless it is the one writing it out, and that one only blocks itself.
+/// What a thread prints without ending the line comes out once it completes.
+/// Outside of a green thread, e.g. on the base thread, it is the same as `std::println!`.
+#[macro_export]
+macro_rules! println {
+    () => {
+        $crate::print!("\n")
+    };
+    ($($arg:tt)*) => {
+        $crate::io::_print(format_args!("{}\n", format_args!($($arg)*)))
+    };
+}
+
+#[doc(hidden)]
+pub fn _print(args: fmt::Arguments<'_>) {
+    if crate::runtime().is_null() || get_current_thread() == BASE_THREAD_ID {
+        std::print!("{args}");
+        return;
+    }
+    let lines = LINE.with(|line| {
+        let mut line = line.0.borrow_mut();
+        line.write_fmt(args).expect("formatting failed");
+        match line.iter().rposition(|&b| b == b'\n') {
+            Some(end) => line.drain(..=end).collect(),
+            None => Vec::new(),
+        }
+    });
+    if !lines.is_empty() {
+        queue(lines);
+    }
+}
+
+impl Drop for Line {
+    fn drop(&mut self) {
+        let rest = self.0.take();
+        if !rest.is_empty() {
+            queue(rest);
+        }
+    }
+}
+
+// Queues `bytes` for the standard output, and writes the queue out unless another thread is at it.
+fn queue(bytes: Vec<u8>) {
+    {
+        let _no_preempt = NoPreempt::new();
+        let writing = OUTPUT.with(|output| {
+            let mut output = output.borrow_mut();
+            output.queue.extend_from_slice(&bytes);
+            mem::replace(&mut output.writing, true)
+        });
+        if writing {
+            return;
+        }
+    }
+    loop {
+        let chunk = {
+            let _no_preempt = NoPreempt::new();
+            OUTPUT.with(|output| {
+                let mut output = output.borrow_mut();
+                let chunk = mem::take(&mut output.queue);
+                output.writing = !chunk.is_empty();
+                chunk
+            })
+        };
+        if chunk.is_empty() {
+            return;
+        }
+        if let Err(err) = stdout().write_all(&chunk) {
+            let _no_preempt = NoPreempt::new();
+            OUTPUT.with(|output| output.borrow_mut().writing = false);
+            panic!("failed printing to stdout: {err}");
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use std::io::{self, Result};
+use std::os::fd::RawFd;
+
+use super::{Read, Write};
+use crate::preempt::NoPreempt;
+use crate::reactor::Interest;
+use crate::runtime::wait_io;
+
+/// The standard input of the process, see `stdin`.
+#[derive(Debug, Clone, Copy)]
+pub struct Stdin(());
+
+/// The standard output of the process, see `stdout`.
+#[derive(Debug, Clone, Copy)]
+pub struct Stdout(());
+
+/// The standard error of the process, see `stderr`.
+#[derive(Debug, Clone, Copy)]
+pub struct Stderr(());
+
+/// Returns a handle to the standard input, whose reads block the current thread rather than the whole runtime,
+/// e.g. while waiting for the user to type a line.
+/// Unbuffered, wrap it in a `BufReader` to read lines, and keep that one around, as it holds what it read ahead.
+/// Reads directly from the file descriptor, bypassing the buffer of `std::io::stdin`.
+pub fn stdin() -> Stdin {
+    Stdin(())
+}
+
+/// Returns a handle to the standard output, whose writes block the current thread rather than the whole runtime,
+/// e.g. while a pipe to a slow reader is full.
+/// Unbuffered, and written to directly, so it doesn't interleave with what `print!` buffered line by line.
+pub fn stdout() -> Stdout {
+    Stdout(())
+}
+
+/// Returns a handle to the standard error, see `stdout`.
+pub fn stderr() -> Stderr {
+    Stderr(())
+}
+
+impl Read for Stdin {
+    /// Reads into `buf`, blocking the current thread until some input is available.
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        until_ready(libc::STDIN_FILENO, Interest::Readable, || unsafe {
+            libc::read(libc::STDIN_FILENO, buf.as_mut_ptr().cast(), buf.len())
+        })
+    }
+}
+
+impl Write for Stdout {
+    /// Writes some of `buf`, blocking the current thread until the output can accept data.
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        write(libc::STDOUT_FILENO, buf)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl Write for Stderr {
+    /// Writes some of `buf`, blocking the current thread until the output can accept data.
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        write(libc::STDERR_FILENO, buf)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+fn write(fd: RawFd, buf: &[u8]) -> Result<usize> {
+    // a pipe ready for writing has room for that much at least, so that writing it doesn't block.
+    let len = buf.len().min(libc::PIPE_BUF);
+    until_ready(fd, Interest::Writable, || unsafe {
+        libc::write(fd, buf.as_ptr().cast(), len)
+    })
+}
+
+// Runs the blocking operation `f` on `fd`, once `fd` is ready for `interest`, blocking the current thread until then.
+// The standard streams are shared with other processes, so they can't be made non-blocking as sockets and pipes are:
+// that would change them for the others too. Their readiness is checked right before running `f` instead.
+fn until_ready(fd: RawFd, interest: Interest, mut f: impl FnMut() -> isize) -> Result<usize> {
+    loop {
+        {
+            // another thread mustn't get to run in between, and use up what is ready.
+            let _no_preempt = NoPreempt::new();
+            if ready(fd, interest)? {
+                match f() {
+                    n if n >= 0 => return Ok(n as usize),
+                    _ => {
+                        let err = io::Error::last_os_error();
+                        if err.kind() != io::ErrorKind::Interrupted {
+                            return Err(err);
+                        }
+                        continue;
+                    }
+                }
+            }
+        }
+        wait_io(fd, interest)?;
+    }
+}
+
+// Returns whether `fd` is ready for `interest`, or has hung up or failed, in which case `f` says so without blocking.
+// Regular files are always ready.
+fn ready(fd: RawFd, interest: Interest) -> Result<bool> {
+    let mut pollfd = libc::pollfd {
+        fd,
+        events: match interest {
+            Interest::Readable => libc::POLLIN,
+            Interest::Writable => libc::POLLOUT,
+        },
+        revents: 0,
+    };
+    loop {
+        match unsafe { libc::poll(&mut pollfd, 1, 0) } {
+            n if n >= 0 => return Ok(pollfd.revents != 0),
+            _ => {
+                let err = io::Error::last_os_error();
+                if err.kind() != io::ErrorKind::Interrupted {
+                    return Err(err);
+                }
+            }
+        }
+    }
+}
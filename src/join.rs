@@ -0,0 +1,298 @@
+// Joining threads: waiting for them to complete, and getting what they returned.
+// A single thread is joined through the handle `spawn` returns, which the thread hands its result over to as it completes.
+// Sets of threads have their results collected as they complete, e.g. the handlers spawned by a listener.
+// The threads push their results to the set as they complete, and wake up the thread waiting for one, if any.
+// Like groups (see `group`), the threads are cancelled through a context they share, which the set cancels once dropped.
+// `join_all` joins handles for the common case of waiting for all the threads,
+// and `race` is built on a set for the case of waiting for the first one.
+
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::Rc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::context::{Cancel, Context};
+use crate::preempt::NoPreempt;
+use crate::runtime::{
+    self, change_thread_state, get_current_thread, wait_woken, wake_selector, yield_thread,
+};
+use crate::thread::{Id, State, ThreadHandle};
+use crate::time;
+
+/// Spawns a thread running `f`, and returns a handle to join it with, i.e. to wait for what `f` returns.
+/// A panic of `f` is resumed on the thread joining it,
+/// or aborts the process if `f` panics after the handle has been dropped, as anywhere in the runtime.
+/// Panics if too many threads are alive, see `Runtime::set_max_threads`.
+pub fn spawn<T: 'static>(f: impl FnOnce() -> T + 'static) -> JoinHandle<T> {
+    let packet = Rc::new(Packet {
+        result: RefCell::new(None),
+        joiner: Cell::new(None),
+        detached: Cell::new(false),
+    });
+    let theirs = packet.clone();
+    let id = runtime::spawn(move || {
+        let result = panic::catch_unwind(AssertUnwindSafe(f));
+        theirs.complete(result);
+    });
+    JoinHandle { id, packet }
+}
+
+/// Joins a thread spawned with `spawn`. Dropping it detaches the thread, which runs on, and whose result is dropped.
+pub struct JoinHandle<T> {
+    id: Id,
+    packet: Rc<Packet<T>>,
+}
+
+// Where the thread hands its result over to its handle.
+struct Packet<T> {
+    result: RefCell<Option<thread::Result<T>>>,
+    // the thread blocked joining it.
+    joiner: Cell<Option<Id>>,
+    // whether the handle has been dropped, so that no one is left to join the thread.
+    detached: Cell<bool>,
+}
+
+impl<T> JoinHandle<T> {
+    pub fn id(&self) -> Id {
+        self.id
+    }
+
+    /// Returns a handle to the thread, e.g. to change its priority while it runs.
+    pub fn thread(&self) -> ThreadHandle {
+        ThreadHandle::new(self.id)
+    }
+
+    /// Returns whether the thread has completed, in which case joining it doesn't block.
+    pub fn is_finished(&self) -> bool {
+        self.packet.result.borrow().is_some()
+    }
+
+    /// Blocks the current thread until the thread has completed, and returns what it returned.
+    /// Resumes the panic of the thread if it panicked.
+    pub fn join(self) -> T {
+        match self.wait(None) {
+            Ok(val) => val,
+            Err(_) => unreachable!("a join without a deadline timed out"),
+        }
+    }
+
+    /// Like `join`, but stops waiting once `timeout` has passed, on the timers of the runtime,
+    /// and gives the handle back, e.g. to try again later, or to cancel the thread through its context.
+    pub fn join_timeout(self, timeout: Duration) -> Result<T, JoinHandle<T>> {
+        self.wait(Some(time::now() + timeout))
+    }
+
+    fn wait(self, deadline: Option<Instant>) -> Result<T, JoinHandle<T>> {
+        {
+            // the thread mustn't complete in between checking and blocking, or it would never wake this one up.
+            let _no_preempt = NoPreempt::new();
+            if !self.is_finished() {
+                self.packet.joiner.set(Some(get_current_thread()));
+                // blocks as `Select` does without file descriptors, until woken up through `wake_selector` or the deadline.
+                wait_woken(deadline);
+                self.packet.joiner.set(None);
+            }
+        }
+        let result = self.packet.result.borrow_mut().take();
+        match result {
+            Some(Ok(val)) => Ok(val),
+            Some(Err(payload)) => panic::resume_unwind(payload),
+            None => Err(self),
+        }
+    }
+}
+
+impl<T> Packet<T> {
+    fn complete(&self, result: thread::Result<T>) {
+        match result {
+            Err(payload) if self.detached.get() => panic::resume_unwind(payload),
+            Ok(_) if self.detached.get() => {}
+            result => *self.result.borrow_mut() = Some(result),
+        }
+        if let Some(joiner) = self.joiner.take() {
+            wake_selector(joiner);
+        }
+    }
+}
+
+impl<T> Drop for JoinHandle<T> {
+    fn drop(&mut self) {
+        self.packet.detached.set(true);
+        self.packet.result.borrow_mut().take();
+    }
+}
+
+impl<T> fmt::Debug for JoinHandle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JoinHandle")
+            .field("id", &self.id)
+            .field("finished", &self.is_finished())
+            .finish()
+    }
+}
+
+/// Threads spawned with `spawn`, whose results `join_next` returns in the order they complete.
+/// Dropping the set cancels the context of the threads still running, see `abort_all`, but doesn't wait for them:
+/// they are detached, and stop at their own pace. What they return is dropped, and a panic of theirs aborts the process,
+/// as no one is left to resume it on. To wait for them, cancel them and join them all before dropping the set.
+pub struct JoinSet<T> {
+    inner: Rc<Inner<T>>,
+}
+
+struct Inner<T> {
+    ctx: Context,
+    cancel: Cancel,
+    // how many threads haven't completed yet.
+    running: Cell<usize>,
+    // the results of the threads which completed, and which haven't been joined yet.
+    done: RefCell<VecDeque<thread::Result<T>>>,
+    // the thread blocked in `join_next`.
+    joiner: Cell<Option<Id>>,
+    // whether the set has been dropped, so that no one is left to join the threads.
+    dropped: Cell<bool>,
+}
+
+impl<T: 'static> JoinSet<T> {
+    /// Creates an empty set, whose threads are cancelled when it is dropped.
+    pub fn new() -> Self {
+        JoinSet::with_context(&Context::background())
+    }
+
+    /// Creates an empty set, whose threads are also cancelled along with `parent`, e.g. the context of a whole server.
+    pub fn with_context(parent: &Context) -> Self {
+        let (ctx, cancel) = parent.with_cancel();
+        JoinSet {
+            inner: Rc::new(Inner {
+                ctx,
+                cancel,
+                running: Cell::new(0),
+                done: RefCell::new(VecDeque::new()),
+                joiner: Cell::new(None),
+                dropped: Cell::new(false),
+            }),
+        }
+    }
+
+    /// Spawns a thread into the set, running `f` with the context of the set, and returns its id.
+    /// A panic of `f` is resumed on the thread joining its result,
+    /// or aborts the process if `f` completes after the set has been dropped, as anywhere in the runtime.
+    /// Panics if too many threads are alive, see `Runtime::set_max_threads`.
+    pub fn spawn(&mut self, f: impl FnOnce(Context) -> T + 'static) -> Id {
+        let inner = self.inner.clone();
+        let id = runtime::spawn(move || {
+            let ctx = inner.ctx.clone();
+            let result = panic::catch_unwind(AssertUnwindSafe(|| ctx.scope(|| f(ctx.clone()))));
+            inner.complete(result);
+        });
+        self.inner.running.set(self.inner.running.get() + 1);
+        id
+    }
+
+    /// Blocks the current thread until one of the threads has completed, and returns what it returned,
+    /// or right away if one has already. Returns None once every thread has completed and been joined.
+    /// Resumes the panic of the thread if it panicked.
+    pub fn join_next(&mut self) -> Option<T> {
+        loop {
+            // a thread mustn't complete in between checking and blocking, or it would never wake this one up.
+            let _no_preempt = NoPreempt::new();
+            if let Some(val) = self.try_join_next() {
+                return Some(val);
+            }
+            if self.inner.running.get() == 0 {
+                return None;
+            }
+            let id = get_current_thread();
+            self.inner.joiner.set(Some(id));
+            change_thread_state(id, State::SyncBlock);
+            yield_thread();
+        }
+    }
+
+    /// Returns what one of the threads which completed returned, if any, without blocking.
+    /// Resumes the panic of the thread if it panicked.
+    pub fn try_join_next(&mut self) -> Option<T> {
+        match self.inner.done.borrow_mut().pop_front()? {
+            Ok(val) => Some(val),
+            Err(payload) => panic::resume_unwind(payload),
+        }
+    }
+
+    /// Returns how many threads haven't been joined yet, whether they have completed or not.
+    pub fn len(&self) -> usize {
+        self.inner.running.get() + self.inner.done.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Cancels the context of the threads, interrupting those blocked in its operations.
+    /// Their results are still joined, as they stop at their own pace.
+    /// Threads spawned into the set afterwards start with the context done.
+    pub fn abort_all(&self) {
+        self.inner.cancel.cancel();
+    }
+}
+
+/// Blocks the current thread until all the threads of `handles` have completed, e.g. those spawned to fan work out,
+/// and returns what they returned, in the order of `handles`.
+/// Resumes the panic of the first thread in that order which panicked, if any, detaching those after it.
+pub fn join_all<T>(handles: impl IntoIterator<Item = JoinHandle<T>>) -> Vec<T> {
+    handles.into_iter().map(JoinHandle::join).collect()
+}
+
+/// Runs `a` and `b` on a thread each, and blocks the current thread until one of them has completed.
+/// Returns what it returned, and cancels the context of the other, which stops at its own pace.
+/// Resumes the panic of the first to complete, if it panicked.
+pub fn race<T: 'static>(
+    a: impl FnOnce(Context) -> T + 'static,
+    b: impl FnOnce(Context) -> T + 'static,
+) -> T {
+    let mut set = JoinSet::new();
+    set.spawn(a);
+    set.spawn(b);
+    set.join_next()
+        .expect("the threads of the race completed without a result")
+}
+
+impl<T> Inner<T> {
+    fn complete(&self, result: thread::Result<T>) {
+        self.running.set(self.running.get() - 1);
+        match result {
+            Err(payload) if self.dropped.get() => panic::resume_unwind(payload),
+            Ok(_) if self.dropped.get() => {}
+            result => self.done.borrow_mut().push_back(result),
+        }
+        if let Some(joiner) = self.joiner.take() {
+            change_thread_state(joiner, State::Ready);
+        }
+    }
+}
+
+impl<T: 'static> Default for JoinSet<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for JoinSet<T> {
+    // detaches the threads rather than waiting for them, as the set may be dropped where the current thread
+    // mustn't block, e.g. while unwinding, or on the base thread.
+    fn drop(&mut self) {
+        self.inner.dropped.set(true);
+        self.inner.done.borrow_mut().clear();
+        self.inner.cancel.cancel();
+    }
+}
+
+impl<T> fmt::Debug for JoinSet<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JoinSet")
+            .field("running", &self.inner.running.get())
+            .field("done", &self.inner.done.borrow().len())
+            .finish()
+    }
+}
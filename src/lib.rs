@@ -0,0 +1,143 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "std")]
+pub mod actor;
+mod arch;
+#[cfg(feature = "std")]
+pub mod blocking;
+pub mod channel;
+#[cfg(feature = "std")]
+pub mod context;
+#[cfg(feature = "std")]
+pub mod coroutine;
+#[cfg(feature = "std")]
+mod crash;
+pub mod debugger;
+#[cfg(feature = "std")]
+pub mod defer;
+#[cfg(feature = "std")]
+mod dump;
+#[cfg(feature = "std")]
+mod errno;
+#[cfg(feature = "std")]
+pub mod executor;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "std")]
+pub mod fs;
+#[cfg(feature = "std")]
+pub mod future;
+#[cfg(feature = "std")]
+pub mod generator;
+#[cfg(feature = "std")]
+pub mod group;
+#[cfg(feature = "std")]
+pub mod io;
+#[cfg(feature = "std")]
+pub mod join;
+#[cfg(feature = "net")]
+pub mod net;
+#[cfg(feature = "std")]
+mod overflow;
+#[cfg(feature = "std")]
+pub mod pipeline;
+pub mod preempt;
+#[cfg(feature = "std")]
+pub mod process;
+#[cfg(feature = "profiler")]
+pub mod profiler;
+pub mod reactor;
+pub mod runtime;
+#[cfg(feature = "std")]
+pub mod select;
+#[cfg(feature = "std")]
+pub mod supervisor;
+#[cfg(feature = "sync")]
+pub mod sync;
+#[cfg(feature = "std")]
+pub mod testing;
+pub mod thread;
+pub mod time;
+pub mod trace;
+#[cfg(feature = "valgrind")]
+mod valgrind;
+
+#[cfg(feature = "std")]
+use core::cell::Cell;
+#[cfg(not(feature = "std"))]
+use core::sync::atomic::{AtomicPtr, Ordering};
+use core::time::Duration;
+
+#[cfg(feature = "std")]
+pub use context::{check_cancelled, is_cancelled};
+pub use runtime::maybe_yield;
+#[cfg(feature = "macros")]
+pub use uthreads_macros::test;
+
+use runtime::Core;
+use thread::Id;
+
+const DEFAULT_STACK_SIZE: usize = 1024 * 64;
+// How many stacks of completed threads the runtime keeps for reuse.
+const STACK_POOL_SIZE: usize = 64;
+// How much of the stack below the current frame `shrink_stack` leaves alone, for the calls it makes itself.
+#[cfg(feature = "std")]
+const STACK_SHRINK_MARGIN: usize = 1024 * 4;
+const DEFAULT_TIME_SLICE: Duration = Duration::from_millis(10);
+// How many times in a row the thread just woken up may run ahead of the others, see `Core::lifo`.
+const LIFO_LIMIT: u32 = 3;
+// How many switches made on preemption are reported to the hooks at the next preemption point at most, see `Core::preempt`.
+#[cfg(feature = "std")]
+const PREEMPTED_MAX: usize = 16;
+const BASE_THREAD_ID: Id = Id(0);
+
+#[cfg(feature = "std")]
+thread_local! {
+    // We make use of a thread local variable in order to avoid having to pass the Runtime to every function called.
+    // This is not a problem, as there is always supposed to have a maximum of one Runtime per OS thread at any point in time.
+    // Several OS threads can run one each, see `executor`.
+    static RUNTIME: Cell<*mut Core> = const { Cell::new(core::ptr::null_mut()) };
+}
+
+// Without std there are no OS threads to tell apart, so there is one runtime for the whole program,
+// e.g. on the single core a freestanding target runs it on.
+#[cfg(not(feature = "std"))]
+static RUNTIME: AtomicPtr<Core> = AtomicPtr::new(core::ptr::null_mut());
+
+// Returns the core of the runtime of the current OS thread, null if none is initialised.
+// Doesn't allocate, so that signal handlers can use it.
+#[inline]
+fn runtime() -> *mut Core {
+    #[cfg(feature = "std")]
+    return RUNTIME.with(Cell::get);
+    #[cfg(not(feature = "std"))]
+    RUNTIME.load(Ordering::Relaxed)
+}
+
+// Makes `core` the runtime of the current OS thread, null to clear it.
+#[inline]
+fn set_runtime(core: *mut Core) {
+    #[cfg(feature = "std")]
+    RUNTIME.set(core);
+    #[cfg(not(feature = "std"))]
+    RUNTIME.store(core, Ordering::Relaxed);
+}
+
+// Returns the core of the runtime of the current OS thread, for the entry points of the runtime.
+// Panics if none is initialised, e.g. as they are called from another OS thread, rather than dereferencing null.
+#[inline]
+fn expect_runtime() -> *mut Core {
+    let core = runtime();
+    if core.is_null() {
+        no_runtime();
+    }
+    core
+}
+
+#[cold]
+#[inline(never)]
+fn no_runtime() -> ! {
+    panic!("called into the runtime from an OS thread without one: `Runtime::init` must be called on it first")
+}
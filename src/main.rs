@@ -1,25 +1,17 @@
-#![feature(naked_functions)]
+use uthreads::channel::Channel;
+use uthreads::runtime::{chan_recv, chan_send, create_thread, Runtime};
+use uthreads::trace::{self, Level};
 
-mod channel;
-mod runtime;
-mod thread;
-
-use channel::Channel;
-use runtime::{chan_recv, chan_send, create_thread, Runtime};
-use thread::Id;
-
-const DEFAULT_STACK_SIZE: usize = 1024 * 5;
-const BASE_THREAD_ID: Id = Id(0);
-const DEBUG: bool = true;
-
-// We make use of global variables in order to avoid having to pass the Runtime / Channel to every function called.
-// This is not a problem with Runtime, as there is always supposed to have a maximum of one Runtime at any point in time.
-// But, there are legit reason for an application to make use of more than one channel at a time, which is not ergonomic at the moment.
+// We make use of a global variable in order to avoid having to pass the Channel to every function called.
+// There are legit reason for an application to make use of more than one channel at a time, which is not ergonomic at the moment.
 // But this works just fine as a toy runtime and does what it's designed to do.
-static mut RUNTIME: *mut Runtime = std::ptr::null_mut();
 static mut CHAN: *mut Channel<usize> = std::ptr::null_mut();
 
 fn main() {
+    // Show what the runtime does along the way.
+    trace::set_sink(Some(Box::new(trace::print)));
+    trace::set_max_level(Some(Level::Debug));
+
     // Initialise global variables: Runtime and Channel before using them.
     let mut runtime = Runtime::new();
     let chan = Box::from(Channel::new(1));
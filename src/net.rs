@@ -0,0 +1,78 @@
+// Networking types whose operations block the calling green thread instead of the whole runtime.
+// The sockets are put in non-blocking mode, and whenever an operation would block,
+// the thread waits on the reactor until the socket is ready and then retries.
+// Timeouts are timers of the reactor for the waiting thread, which interrupt the wait once they go off.
+
+mod addr;
+mod tcp;
+mod udp;
+
+pub use addr::lookup_host;
+pub use tcp::{TcpListener, TcpStream};
+pub use udp::UdpSocket;
+
+use std::io;
+use std::os::fd::RawFd;
+use std::time::{Duration, Instant};
+
+use crate::reactor::{until_ready, Interest};
+use crate::runtime::{clear_wait, preemption_point, set_deadline, wait_io};
+use crate::time;
+
+// Checks a timeout given to a socket, which can't be zero, as with the sockets of the standard library.
+fn check_timeout(timeout: Option<Duration>) -> io::Result<Option<Duration>> {
+    if timeout == Some(Duration::ZERO) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "cannot set a 0 duration timeout",
+        ));
+    }
+    Ok(timeout)
+}
+
+// Like `until_ready`, but fails with an error of kind `TimedOut` once `timeout` has elapsed, if any:
+// a timer of the reactor then interrupts the wait for `fd`.
+fn until_ready_timeout<T, F>(
+    fd: RawFd,
+    interest: Interest,
+    timeout: Option<Duration>,
+    mut f: F,
+) -> io::Result<T>
+where
+    F: FnMut() -> io::Result<T>,
+{
+    let Some(timeout) = timeout else {
+        return until_ready(fd, interest, f);
+    };
+    let deadline = time::now() + timeout;
+    loop {
+        match f() {
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                wait_io_until(fd, interest, deadline)?
+            }
+            res => {
+                preemption_point();
+                return res;
+            }
+        }
+    }
+}
+
+// Blocks the current thread until `fd` is ready for `interest`, like `wait_io`,
+// but fails with an error of kind `TimedOut` once `deadline` has passed, whether `fd` is ready by then or not.
+fn wait_io_until(fd: RawFd, interest: Interest, deadline: Instant) -> io::Result<()> {
+    if time::now() < deadline {
+        set_deadline(deadline);
+        let res = wait_io(fd, interest);
+        clear_wait();
+        res?;
+    }
+    // woken up by the timer rather than by `fd`, or past the deadline already.
+    if time::now() >= deadline {
+        return Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            "the operation timed out",
+        ));
+    }
+    Ok(())
+}
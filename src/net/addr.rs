@@ -0,0 +1,22 @@
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::vec;
+
+use crate::blocking::spawn_blocking;
+
+/// Resolves `host`, given as "host:port", to the socket addresses it refers to.
+/// Name resolution goes through the system resolver, which blocks,
+/// so it is run on the blocking pool while the current green thread is blocked.
+pub fn lookup_host(host: &str) -> io::Result<vec::IntoIter<SocketAddr>> {
+    // literal addresses don't need the resolver.
+    if let Ok(addr) = host.parse::<SocketAddr>() {
+        return Ok(vec![addr].into_iter());
+    }
+
+    let host = host.to_owned();
+    spawn_blocking(move || {
+        host.to_socket_addrs()
+            .map(|addrs| addrs.collect::<Vec<_>>())
+    })
+    .map(Vec::into_iter)
+}
@@ -0,0 +1,271 @@
+use std::cell::Cell;
+use std::io;
+use std::mem;
+use std::net::{self, Shutdown, SocketAddr, ToSocketAddrs};
+use std::os::fd::{AsRawFd, FromRawFd, RawFd};
+use std::time::{Duration, Instant};
+
+use super::{check_timeout, until_ready_timeout, wait_io_until};
+use crate::io::{Read, Write};
+use crate::reactor::{until_ready, Interest};
+use crate::runtime::{deregister_io, wait_io};
+use crate::time;
+
+/// A TCP socket server, listening for connections.
+#[derive(Debug)]
+pub struct TcpListener {
+    inner: net::TcpListener,
+}
+
+impl TcpListener {
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<TcpListener> {
+        TcpListener::from_std(net::TcpListener::bind(addr)?)
+    }
+
+    /// Wraps a listener from the standard library, putting it in non-blocking mode.
+    pub fn from_std(listener: net::TcpListener) -> io::Result<TcpListener> {
+        listener.set_nonblocking(true)?;
+        Ok(TcpListener { inner: listener })
+    }
+
+    /// Accepts a new connection, blocking the current thread until one arrives.
+    pub fn accept(&self) -> io::Result<(TcpStream, SocketAddr)> {
+        let (stream, addr) =
+            until_ready(self.as_raw_fd(), Interest::Readable, || self.inner.accept())?;
+        Ok((TcpStream::from_std(stream)?, addr))
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+}
+
+impl AsRawFd for TcpListener {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+impl Drop for TcpListener {
+    fn drop(&mut self) {
+        deregister_io(self.as_raw_fd());
+    }
+}
+
+/// A TCP stream between a local and a remote socket.
+#[derive(Debug)]
+pub struct TcpStream {
+    inner: net::TcpStream,
+    // set through `&self` like `set_nodelay`, as a stream is read and written through shared references too.
+    read_timeout: Cell<Option<Duration>>,
+    write_timeout: Cell<Option<Duration>>,
+}
+
+impl TcpStream {
+    /// Opens a connection to `addr`, blocking the current thread until it is established.
+    /// If `addr` yields multiple addresses, each one is tried in order until one succeeds.
+    /// Host names are resolved by the blocking system resolver, use `lookup_host` to resolve them beforehand.
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> io::Result<TcpStream> {
+        let mut last_err = None;
+        for addr in addr.to_socket_addrs()? {
+            match TcpStream::connect_addr(&addr, None) {
+                Ok(stream) => return Ok(stream),
+                Err(err) => last_err = Some(err),
+            }
+        }
+
+        Err(last_err.unwrap_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "could not resolve to any addresses",
+            )
+        }))
+    }
+
+    /// Opens a connection to `addr`, blocking the current thread until it is established,
+    /// or failing with an error of kind `TimedOut` once `timeout` has elapsed. Fails if `timeout` is zero.
+    pub fn connect_timeout(addr: &SocketAddr, timeout: Duration) -> io::Result<TcpStream> {
+        check_timeout(Some(timeout))?;
+        TcpStream::connect_addr(addr, Some(time::now() + timeout))
+    }
+
+    fn connect_addr(addr: &SocketAddr, deadline: Option<Instant>) -> io::Result<TcpStream> {
+        let family = match addr {
+            SocketAddr::V4(_) => libc::AF_INET,
+            SocketAddr::V6(_) => libc::AF_INET6,
+        };
+        let fd = unsafe { libc::socket(family, libc::SOCK_STREAM, 0) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // the std stream owns the socket from here on, so that it is closed on every error path.
+        let stream = unsafe { net::TcpStream::from_raw_fd(fd) };
+        if unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        stream.set_nonblocking(true)?;
+
+        let (storage, len) = sockaddr(addr);
+        let res =
+            unsafe { libc::connect(fd, (&storage as *const libc::sockaddr_storage).cast(), len) };
+        if res < 0 {
+            let err = io::Error::last_os_error();
+            if err.raw_os_error() != Some(libc::EINPROGRESS) {
+                return Err(err);
+            }
+            // the socket becomes writable once the connection attempt has finished, successfully or not.
+            let waited = match deadline {
+                Some(deadline) => wait_io_until(fd, Interest::Writable, deadline),
+                None => wait_io(fd, Interest::Writable),
+            };
+            if let Some(err) = waited.err().or(stream.take_error()?) {
+                deregister_io(fd);
+                return Err(err);
+            }
+        }
+
+        Ok(TcpStream::new(stream))
+    }
+
+    /// Wraps a stream from the standard library, putting it in non-blocking mode.
+    pub fn from_std(stream: net::TcpStream) -> io::Result<TcpStream> {
+        stream.set_nonblocking(true)?;
+        Ok(TcpStream::new(stream))
+    }
+
+    fn new(stream: net::TcpStream) -> TcpStream {
+        TcpStream {
+            inner: stream,
+            read_timeout: Cell::new(None),
+            write_timeout: Cell::new(None),
+        }
+    }
+
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.peer_addr()
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    pub fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        self.inner.shutdown(how)
+    }
+
+    pub fn set_nodelay(&self, nodelay: bool) -> io::Result<()> {
+        self.inner.set_nodelay(nodelay)
+    }
+
+    /// Makes the reads fail with an error of kind `TimedOut` once they have blocked the current thread for `timeout`,
+    /// e.g. so that a dead peer doesn't hold a thread forever, or lets them block for as long as it takes with None.
+    /// Fails if `timeout` is zero.
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.read_timeout.set(check_timeout(timeout)?);
+        Ok(())
+    }
+
+    /// Makes the writes fail with an error of kind `TimedOut` once they have blocked the current thread for `timeout`,
+    /// see `set_read_timeout`. Part of the data may have been written by then.
+    pub fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.write_timeout.set(check_timeout(timeout)?);
+        Ok(())
+    }
+
+    pub fn read_timeout(&self) -> Option<Duration> {
+        self.read_timeout.get()
+    }
+
+    pub fn write_timeout(&self) -> Option<Duration> {
+        self.write_timeout.get()
+    }
+}
+
+impl Read for &TcpStream {
+    /// Reads into `buf`, blocking the current thread until some data is available, see `TcpStream::set_read_timeout`.
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        until_ready_timeout(
+            self.as_raw_fd(),
+            Interest::Readable,
+            self.read_timeout.get(),
+            || io::Read::read(&mut &self.inner, buf),
+        )
+    }
+}
+
+impl Write for &TcpStream {
+    /// Writes some of `buf`, blocking the current thread until the socket can accept data,
+    /// see `TcpStream::set_write_timeout`.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        until_ready_timeout(
+            self.as_raw_fd(),
+            Interest::Writable,
+            self.write_timeout.get(),
+            || io::Write::write(&mut &self.inner, buf),
+        )
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Read for TcpStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        (&*self).read(buf)
+    }
+}
+
+impl Write for TcpStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        (&*self).write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        (&*self).flush()
+    }
+}
+
+impl AsRawFd for TcpStream {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+impl Drop for TcpStream {
+    fn drop(&mut self) {
+        deregister_io(self.as_raw_fd());
+    }
+}
+
+// Converts `addr` to its C representation, as expected by `connect`.
+fn sockaddr(addr: &SocketAddr) -> (libc::sockaddr_storage, libc::socklen_t) {
+    let mut storage: libc::sockaddr_storage = unsafe { mem::zeroed() };
+    let len = match addr {
+        SocketAddr::V4(addr) => {
+            let sin = unsafe {
+                &mut *(&mut storage as *mut libc::sockaddr_storage).cast::<libc::sockaddr_in>()
+            };
+            sin.sin_family = libc::AF_INET as libc::sa_family_t;
+            sin.sin_port = addr.port().to_be();
+            sin.sin_addr = libc::in_addr {
+                s_addr: u32::from_ne_bytes(addr.ip().octets()),
+            };
+            mem::size_of::<libc::sockaddr_in>()
+        }
+        SocketAddr::V6(addr) => {
+            let sin6 = unsafe {
+                &mut *(&mut storage as *mut libc::sockaddr_storage).cast::<libc::sockaddr_in6>()
+            };
+            sin6.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+            sin6.sin6_port = addr.port().to_be();
+            sin6.sin6_flowinfo = addr.flowinfo();
+            sin6.sin6_addr = libc::in6_addr {
+                s6_addr: addr.ip().octets(),
+            };
+            sin6.sin6_scope_id = addr.scope_id();
+            mem::size_of::<libc::sockaddr_in6>()
+        }
+    };
+    (storage, len as libc::socklen_t)
+}
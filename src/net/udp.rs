@@ -0,0 +1,149 @@
+use std::cell::Cell;
+use std::io;
+use std::net::{self, SocketAddr, ToSocketAddrs};
+use std::os::fd::{AsRawFd, RawFd};
+use std::time::Duration;
+
+use super::{check_timeout, until_ready_timeout};
+use crate::reactor::Interest;
+use crate::runtime::deregister_io;
+
+/// A UDP socket.
+/// Sending and receiving block the calling thread until the socket is ready.
+#[derive(Debug)]
+pub struct UdpSocket {
+    inner: net::UdpSocket,
+    // set through `&self` like the other options, as the socket is shared by the threads sending and receiving on it.
+    read_timeout: Cell<Option<Duration>>,
+    write_timeout: Cell<Option<Duration>>,
+}
+
+impl UdpSocket {
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<UdpSocket> {
+        UdpSocket::from_std(net::UdpSocket::bind(addr)?)
+    }
+
+    /// Wraps a socket from the standard library, putting it in non-blocking mode.
+    pub fn from_std(socket: net::UdpSocket) -> io::Result<UdpSocket> {
+        socket.set_nonblocking(true)?;
+        Ok(UdpSocket {
+            inner: socket,
+            read_timeout: Cell::new(None),
+            write_timeout: Cell::new(None),
+        })
+    }
+
+    /// Sets the default destination of `send` and the only source `recv` accepts datagrams from.
+    /// This doesn't involve the network, so it never blocks.
+    pub fn connect<A: ToSocketAddrs>(&self, addr: A) -> io::Result<()> {
+        self.inner.connect(addr)
+    }
+
+    pub fn send_to<A: ToSocketAddrs>(&self, buf: &[u8], addr: A) -> io::Result<usize> {
+        // resolve the address once, instead of every time the socket isn't ready.
+        let addr = addr.to_socket_addrs()?.next().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "no addresses to send data to")
+        })?;
+        until_ready_timeout(
+            self.as_raw_fd(),
+            Interest::Writable,
+            self.write_timeout.get(),
+            || self.inner.send_to(buf, addr),
+        )
+    }
+
+    pub fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        until_ready_timeout(
+            self.as_raw_fd(),
+            Interest::Readable,
+            self.read_timeout.get(),
+            || self.inner.recv_from(buf),
+        )
+    }
+
+    /// Receives a datagram without removing it from the queue.
+    pub fn peek_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        until_ready_timeout(
+            self.as_raw_fd(),
+            Interest::Readable,
+            self.read_timeout.get(),
+            || self.inner.peek_from(buf),
+        )
+    }
+
+    /// Sends to the address the socket is connected to.
+    pub fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        until_ready_timeout(
+            self.as_raw_fd(),
+            Interest::Writable,
+            self.write_timeout.get(),
+            || self.inner.send(buf),
+        )
+    }
+
+    /// Receives from the address the socket is connected to.
+    pub fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        until_ready_timeout(
+            self.as_raw_fd(),
+            Interest::Readable,
+            self.read_timeout.get(),
+            || self.inner.recv(buf),
+        )
+    }
+
+    /// Receives from the address the socket is connected to, without removing the datagram from the queue.
+    pub fn peek(&self, buf: &mut [u8]) -> io::Result<usize> {
+        until_ready_timeout(
+            self.as_raw_fd(),
+            Interest::Readable,
+            self.read_timeout.get(),
+            || self.inner.peek(buf),
+        )
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.peer_addr()
+    }
+
+    pub fn set_broadcast(&self, broadcast: bool) -> io::Result<()> {
+        self.inner.set_broadcast(broadcast)
+    }
+
+    /// Makes receiving and peeking fail with an error of kind `TimedOut` once they have blocked the current thread
+    /// for `timeout`, or lets them block for as long as it takes with None. Fails if `timeout` is zero.
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.read_timeout.set(check_timeout(timeout)?);
+        Ok(())
+    }
+
+    /// Makes sending fail with an error of kind `TimedOut` once it has blocked the current thread for `timeout`,
+    /// see `set_read_timeout`.
+    pub fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.write_timeout.set(check_timeout(timeout)?);
+        Ok(())
+    }
+
+    pub fn read_timeout(&self) -> Option<Duration> {
+        self.read_timeout.get()
+    }
+
+    pub fn write_timeout(&self) -> Option<Duration> {
+        self.write_timeout.get()
+    }
+}
+
+impl AsRawFd for UdpSocket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+impl Drop for UdpSocket {
+    fn drop(&mut self) {
+        deregister_io(self.as_raw_fd());
+    }
+}
@@ -0,0 +1,174 @@
+// Reporting of stack overflows.
+// Every thread stack has an inaccessible guard page below it, so a thread overflowing its stack gets SIGSEGV
+// (SIGBUS on some systems) when it reaches it.
+// The handler runs on an alternate signal stack, as the stack of the faulting thread is exhausted,
+// and checks whether the faulting address is in the guard area of one of the threads of the runtime.
+// If so, it grows the stack if it's growable and has room left, or else reports which thread overflowed and aborts.
+// The kernel also raises SIGSEGV when it can't push the frame of another signal onto a stack, e.g. a tick of preemption
+// landing on a thread close to its guard area. The fault then has no address, and the stack grows below the one
+// of the thread instead, by as much as a frame may take. The other signal is lost.
+// Otherwise the previous handler is restored to deal with the fault.
+
+use std::fmt::{self, Write};
+use std::io;
+use std::mem::{self, MaybeUninit};
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::arch::stack_pointer;
+use crate::runtime::stack_fault;
+use crate::thread::Id;
+use crate::thread::{MmapAllocator, StackAllocator};
+
+const ALT_STACK_SIZE: usize = 1024 * 64;
+// How much of the stack the frame of a signal may take, with the registers saved in it.
+const SIGNAL_FRAME_SIZE: usize = 1024 * 16;
+
+// What a fault in the guard area of a stack turned out to be.
+pub(crate) enum StackFault {
+    /// The fault is not in the guard area of any stack.
+    Elsewhere,
+    /// The stack has grown over the faulting address.
+    Grown,
+    /// The thread with this id has overflowed its stack, which has this size.
+    Overflow(Id, usize),
+}
+
+// The handlers in place before ours, one per signal, restored when a fault is not a stack overflow.
+static mut PREVIOUS: [MaybeUninit<libc::sigaction>; 2] = [MaybeUninit::zeroed(); 2];
+const SIGNALS: [libc::c_int; 2] = [libc::SIGSEGV, libc::SIGBUS];
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+
+// Installs the handler, and an alternate signal stack for the calling OS thread if it doesn't have one yet.
+pub(crate) fn install() -> io::Result<()> {
+    // Miri supports neither alternate signal stacks nor guard pages.
+    if cfg!(miri) {
+        return Ok(());
+    }
+    unsafe {
+        let mut current: libc::stack_t = mem::zeroed();
+        if libc::sigaltstack(ptr::null(), &mut current) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // the main thread already has one, set up by the standard library for the same purpose.
+        if current.ss_flags & libc::SS_DISABLE != 0 {
+            let mut stack = MmapAllocator.allocate(ALT_STACK_SIZE, true)?;
+            let alt = libc::stack_t {
+                ss_sp: stack.as_mut_ptr().cast(),
+                ss_flags: 0,
+                ss_size: stack.len(),
+            };
+            if libc::sigaltstack(&alt, ptr::null_mut()) < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // used for as long as the OS thread lives, so never deallocated.
+        }
+
+        // the handler is process wide, and installing it again would lose the previous ones.
+        if INSTALLED.swap(true, Ordering::Relaxed) {
+            return Ok(());
+        }
+        let mut action: libc::sigaction = mem::zeroed();
+        action.sa_sigaction = on_fault
+            as extern "C" fn(libc::c_int, *mut libc::siginfo_t, *mut libc::c_void)
+            as libc::sighandler_t;
+        action.sa_flags = libc::SA_SIGINFO | libc::SA_ONSTACK;
+        libc::sigemptyset(&mut action.sa_mask);
+        // a tick of preemption would switch threads while the handler runs on the alternate stack,
+        // which the next thread to fault would then run on as well, see `preempt`.
+        libc::sigaddset(&mut action.sa_mask, libc::SIGALRM);
+        for (i, &signum) in SIGNALS.iter().enumerate() {
+            let previous = &raw mut PREVIOUS[i];
+            if libc::sigaction(signum, &action, (*previous).as_mut_ptr()) < 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+    }
+    Ok(())
+}
+
+extern "C" fn on_fault(
+    signum: libc::c_int,
+    info: *mut libc::siginfo_t,
+    context: *mut libc::c_void,
+) {
+    let addr = match unsafe { fault_address(info) } {
+        0 if unpushed_frame(info) => {
+            unsafe { stack_pointer(context) }.saturating_sub(SIGNAL_FRAME_SIZE)
+        }
+        addr => addr,
+    };
+
+    match stack_fault(addr) {
+        // the faulting instruction now succeeds when retried.
+        StackFault::Grown => return,
+        StackFault::Overflow(id, size) => report(id, size),
+        StackFault::Elsewhere => {}
+    }
+
+    // not a stack overflow of ours: let the previous handler deal with the fault,
+    // which happens again as soon as this handler returns.
+    if let Some(i) = SIGNALS.iter().position(|&s| s == signum) {
+        unsafe {
+            let previous = &raw const PREVIOUS[i];
+            libc::sigaction(signum, (*previous).as_ptr(), ptr::null_mut());
+        }
+    }
+}
+
+fn report(id: Id, size: usize) -> ! {
+    let mut msg = Message::new();
+    let _ = writeln!(
+        msg,
+        "thread {id:?} has overflowed its stack ({size} bytes), aborting"
+    );
+    unsafe {
+        libc::write(libc::STDERR_FILENO, msg.buf.as_ptr().cast(), msg.len);
+        libc::abort();
+    }
+}
+
+// Whether the fault was raised as the frame of another signal couldn't be pushed, see the top of the module.
+#[cfg(target_os = "linux")]
+fn unpushed_frame(info: *mut libc::siginfo_t) -> bool {
+    unsafe { (*info).si_code == libc::SI_KERNEL }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn unpushed_frame(_: *mut libc::siginfo_t) -> bool {
+    false
+}
+
+#[cfg(target_os = "linux")]
+unsafe fn fault_address(info: *mut libc::siginfo_t) -> usize {
+    unsafe { (*info).si_addr() as usize }
+}
+
+#[cfg(not(target_os = "linux"))]
+unsafe fn fault_address(info: *mut libc::siginfo_t) -> usize {
+    unsafe { (*info).si_addr as usize }
+}
+
+// Formats the report without allocating, as the allocator may not be used from a signal handler.
+struct Message {
+    buf: [u8; 128],
+    len: usize,
+}
+
+impl Message {
+    fn new() -> Self {
+        Message {
+            buf: [0; 128],
+            len: 0,
+        }
+    }
+}
+
+impl Write for Message {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = (self.len + s.len()).min(self.buf.len());
+        self.buf[self.len..end].copy_from_slice(&s.as_bytes()[..end - self.len]);
+        self.len = end;
+        Ok(())
+    }
+}
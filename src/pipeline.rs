@@ -0,0 +1,135 @@
+// Pipelines: threads connected by channels which are closed once their senders are gone,
+// so that every stage knows when it is done, and helpers wiring the usual topologies.
+
+use std::cell::{Cell, UnsafeCell};
+use std::rc::Rc;
+
+use crate::channel::Channel;
+use crate::runtime::{chan_recv, chan_send, spawn};
+
+struct Shared<T> {
+    // None is only ever handed to the receivers blocked when the channel is closed.
+    chan: UnsafeCell<Channel<Option<T>>>,
+    senders: Cell<usize>,
+    closed: Cell<bool>,
+}
+
+/// The sending end of a pipeline channel. The channel is closed once every clone is dropped.
+pub struct Sender<T> {
+    shared: Rc<Shared<T>>,
+}
+
+/// The receiving end of a pipeline channel, which can be cloned for several threads to share the values received.
+/// An iterator over the values received, until the channel is closed.
+pub struct Receiver<T> {
+    shared: Rc<Shared<T>>,
+}
+
+/// Creates a channel whose buffer holds `size` values, which is closed once all of its senders are dropped.
+/// Both ends can be cloned and moved to other threads of the same runtime.
+pub fn channel<T>(size: usize) -> (Sender<T>, Receiver<T>) {
+    let shared = Rc::new(Shared {
+        chan: UnsafeCell::new(Channel::new(size)),
+        senders: Cell::new(1),
+        closed: Cell::new(false),
+    });
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver { shared },
+    )
+}
+
+/// Spawns `n` threads applying `f` to the values received from `rx`, each taking the next value as it is done with one,
+/// and returns the receiver of the results, in the order they are produced.
+/// It is closed once `rx` is, and the threads have handled every value.
+pub fn fan_out<T, U>(rx: Receiver<T>, n: usize, f: impl Fn(T) -> U + Clone + 'static) -> Receiver<U>
+where
+    T: 'static,
+    U: 'static,
+{
+    let (tx, out) = channel(n);
+    for _ in 0..n {
+        let (rx, tx, f) = (rx.clone(), tx.clone(), f.clone());
+        spawn(move || {
+            for val in rx {
+                tx.send(f(val));
+            }
+        });
+    }
+    out
+}
+
+/// Spawns a thread for each receiver of `rxs`, forwarding what it receives to the receiver returned,
+/// which is closed once all of them are.
+pub fn fan_in<T: 'static>(rxs: Vec<Receiver<T>>) -> Receiver<T> {
+    let (tx, out) = channel(rxs.len());
+    for rx in rxs {
+        let tx = tx.clone();
+        spawn(move || {
+            for val in rx {
+                tx.send(val);
+            }
+        });
+    }
+    out
+}
+
+impl<T> Sender<T> {
+    /// Sends `val`, blocking while the buffer is full.
+    pub fn send(&self, val: T) {
+        unsafe { chan_send(self.shared.chan.get(), Some(val)) };
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.senders.set(self.shared.senders.get() + 1);
+        Sender {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let senders = self.shared.senders.get() - 1;
+        self.shared.senders.set(senders);
+        if senders == 0 {
+            self.shared.closed.set(true);
+            // the threads blocked receiving get None right away, as the buffer is empty if any is blocked.
+            let chan = self.shared.chan.get();
+            for _ in 0..unsafe { (*chan).receivers_blocked() } {
+                unsafe { chan_send(chan, None) };
+            }
+        }
+    }
+}
+
+impl<T> Receiver<T> {
+    /// Receives a value, blocking until one is sent, or None once the channel is closed and its buffer empty.
+    pub fn recv(&self) -> Option<T> {
+        let chan = self.shared.chan.get();
+        if self.shared.closed.get() && unsafe { (*chan).is_empty() } {
+            return None;
+        }
+        unsafe { chan_recv(chan) }
+    }
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        Receiver {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Iterator for Receiver<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.recv()
+    }
+}
@@ -0,0 +1,289 @@
+// Opt-in preemptive scheduling.
+// Once enabled, a timer periodically sends SIGALRM to the runtime's OS thread.
+// The signal handler runs on the stack of the interrupted green thread, where the kernel has saved its full context
+// (including the registers that `switch` doesn't preserve), and switches to the next thread from there.
+// When the preempted thread is scheduled again, the handler returns and the kernel restores the saved context.
+//
+// The runtime itself must never be interrupted midway, e.g. while it is moving threads around,
+// so its entry points disable preemption while they run (see `NoPreempt`), and so must the allocator (see `Allocator`).
+// A tick arriving in the meantime is remembered and acted upon as soon as preemption is enabled again.
+// The handler itself can't allocate, nor call hooks or emit trace events which may, so it doesn't go through
+// the whole scheduler: it only switches to the next thread in turn, see `Core::preempt`.
+// Without std there are no signals to tick with, but the sections of the runtime are still kept track of.
+
+#[cfg(feature = "std")]
+use core::alloc::{GlobalAlloc, Layout};
+#[cfg(feature = "std")]
+use core::ffi::c_void;
+#[cfg(feature = "std")]
+use core::mem;
+#[cfg(feature = "std")]
+use core::ptr;
+use core::sync::atomic::{compiler_fence, AtomicBool, AtomicUsize, Ordering};
+#[cfg(feature = "std")]
+use core::time::Duration;
+#[cfg(feature = "std")]
+use std::alloc::System;
+#[cfg(feature = "std")]
+use std::io;
+
+use crate::runtime::yield_thread;
+#[cfg(feature = "std")]
+use crate::{
+    runtime::{self, get_current_thread},
+    BASE_THREAD_ID,
+};
+
+#[cfg(feature = "std")]
+thread_local! {
+    /// Number of nested sections of the running thread in which preemption is disabled.
+    /// Kept per OS thread, as each may run a runtime of its own.
+    /// Only ever accessed from that OS thread, atomics are used to keep the signal handler sound.
+    static DEPTH: AtomicUsize = const { AtomicUsize::new(0) };
+    /// Set when a tick arrived while preemption was disabled.
+    static PENDING: AtomicBool = const { AtomicBool::new(false) };
+}
+// Without std, there is a single runtime, see `RUNTIME`.
+#[cfg(not(feature = "std"))]
+static DEPTH: AtomicUsize = AtomicUsize::new(0);
+#[cfg(not(feature = "std"))]
+static PENDING: AtomicBool = AtomicBool::new(false);
+/// The OS thread the runtime is running on, which the ticks are directed to.
+#[cfg(feature = "std")]
+static RUNTIME_THREAD: AtomicUsize = AtomicUsize::new(0);
+/// Whether preemption is enabled, in which case threads get their stacks as they are spawned, see `Core::preempt`.
+#[cfg(feature = "std")]
+static ENABLED: AtomicBool = AtomicBool::new(false);
+/// Set once `Allocator` has allocated, which tells it is the global allocator.
+#[cfg(feature = "std")]
+static ALLOCATOR: AtomicBool = AtomicBool::new(false);
+
+// Gives access to one of the counters above, whether it is a thread local or, without std, a static.
+#[cfg(feature = "std")]
+fn with<T: 'static, R>(key: &'static std::thread::LocalKey<T>, f: impl FnOnce(&T) -> R) -> R {
+    key.with(f)
+}
+
+#[cfg(not(feature = "std"))]
+fn with<T, R>(value: &'static T, f: impl FnOnce(&T) -> R) -> R {
+    f(value)
+}
+
+/// Disables preemption of the current thread until dropped.
+pub(crate) struct NoPreempt(());
+
+impl NoPreempt {
+    pub fn new() -> Self {
+        with(&DEPTH, |v| v.fetch_add(1, Ordering::Relaxed));
+        compiler_fence(Ordering::SeqCst);
+        NoPreempt(())
+    }
+}
+
+impl Drop for NoPreempt {
+    fn drop(&mut self) {
+        compiler_fence(Ordering::SeqCst);
+        if with(&DEPTH, |v| v.fetch_sub(1, Ordering::Relaxed)) == 1
+            && with(&PENDING, |v| v.swap(false, Ordering::Relaxed))
+        {
+            // the thread was due to be preempted while it couldn't be, so yield now.
+            yield_thread();
+        }
+    }
+}
+
+// Makes the current thread yield as soon as preemption is enabled, i.e, right away if it already is.
+pub(crate) fn request() {
+    if with(&DEPTH, |v| v.load(Ordering::Relaxed)) == 0 {
+        yield_thread();
+    } else {
+        with(&PENDING, |v| v.store(true, Ordering::Relaxed));
+    }
+}
+
+// Returns how deeply preemption is disabled for the running thread.
+// Saved by threads before switching away, and restored once they are switched back to.
+pub(crate) fn depth() -> usize {
+    with(&DEPTH, |v| v.load(Ordering::Relaxed))
+}
+
+pub(crate) fn set_depth(depth: usize) {
+    compiler_fence(Ordering::SeqCst);
+    with(&DEPTH, |v| v.store(depth, Ordering::Relaxed));
+}
+
+/// Starts preempting threads that have been running for longer than `interval` without yielding.
+/// Must be called from the OS thread running the runtime, once the runtime has been initialised.
+/// The global allocator must be an `Allocator`, so that threads aren't preempted while they allocate:
+/// fails otherwise.
+///
+/// # Safety
+///
+/// Threads can then be switched away from at any point of their execution outside of the runtime and of the allocator.
+/// They must not hold anything that another thread may need to make progress,
+/// such as a lock of the OS thread (e.g. `std::sync::Mutex`, including the one guarding stdout)
+/// or a reference into memory other threads mutate, nor be in C code which isn't async-signal-safe,
+/// e.g. as it allocates with `malloc` directly.
+/// Code that can't guarantee this for a section can run it with `without`.
+/// The handler for SIGALRM is replaced, and the process must not use `setitimer(ITIMER_REAL)` itself.
+#[cfg(feature = "std")]
+pub unsafe fn enable(interval: Duration) -> io::Result<()> {
+    if interval.is_zero() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "preemption interval must not be zero",
+        ));
+    }
+    if !ALLOCATOR.load(Ordering::Relaxed) {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "preemption needs `preempt::Allocator` as the global allocator",
+        ));
+    }
+
+    RUNTIME_THREAD.store(unsafe { libc::pthread_self() } as usize, Ordering::Relaxed);
+
+    unsafe {
+        let mut action: libc::sigaction = mem::zeroed();
+        action.sa_sigaction = on_tick
+            as extern "C" fn(libc::c_int, *mut libc::siginfo_t, *mut c_void)
+            as libc::sighandler_t;
+        // the signal is blocked while the handler runs, so that ticks don't pile up frames on the stack,
+        // until it switches threads, see `unblock`.
+        action.sa_flags = libc::SA_SIGINFO | libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+        if libc::sigaction(libc::SIGALRM, &action, ptr::null_mut()) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    ENABLED.store(true, Ordering::Relaxed);
+    runtime::prepare_threads();
+    set_timer(interval)
+}
+
+/// Stops preempting threads.
+#[cfg(feature = "std")]
+pub fn disable() -> io::Result<()> {
+    ENABLED.store(false, Ordering::Relaxed);
+    set_timer(Duration::ZERO)
+}
+
+#[cfg(feature = "std")]
+pub(crate) fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+// Lets ticks through again, from the handler of one about to switch threads: the handler may not return for a while,
+// and the next threads would never be preempted meanwhile otherwise.
+#[cfg(feature = "std")]
+pub(crate) fn unblock() {
+    unsafe {
+        let mut set: libc::sigset_t = mem::zeroed();
+        libc::sigemptyset(&mut set);
+        libc::sigaddset(&mut set, libc::SIGALRM);
+        libc::pthread_sigmask(libc::SIG_UNBLOCK, &set, ptr::null_mut());
+    }
+}
+
+/// Runs `f` without the current thread being preempted, e.g. to call into C code which isn't async-signal-safe.
+/// A tick arriving meanwhile makes the thread yield once `f` returns.
+pub fn without<R>(f: impl FnOnce() -> R) -> R {
+    let _no_preempt = NoPreempt::new();
+    f()
+}
+
+/// A global allocator which can't be preempted while it allocates, as `enable` requires,
+/// wrapping another one, e.g. `System`:
+///
+/// ```
+/// use std::alloc::System;
+///
+/// #[global_allocator]
+/// static ALLOCATOR: uthreads::preempt::Allocator = uthreads::preempt::Allocator::new(System);
+/// ```
+#[cfg(feature = "std")]
+#[derive(Debug, Default)]
+pub struct Allocator<A = System>(A);
+
+#[cfg(feature = "std")]
+impl<A> Allocator<A> {
+    pub const fn new(allocator: A) -> Self {
+        Allocator(allocator)
+    }
+}
+
+#[cfg(feature = "std")]
+unsafe impl<A: GlobalAlloc> GlobalAlloc for Allocator<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if !ALLOCATOR.load(Ordering::Relaxed) {
+            ALLOCATOR.store(true, Ordering::Relaxed);
+        }
+        without(|| unsafe { self.0.alloc(layout) })
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        if !ALLOCATOR.load(Ordering::Relaxed) {
+            ALLOCATOR.store(true, Ordering::Relaxed);
+        }
+        without(|| unsafe { self.0.alloc_zeroed(layout) })
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        without(|| unsafe { self.0.dealloc(ptr, layout) })
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        without(|| unsafe { self.0.realloc(ptr, layout, new_size) })
+    }
+}
+
+#[cfg(feature = "std")]
+fn set_timer(interval: Duration) -> io::Result<()> {
+    let interval = libc::timeval {
+        tv_sec: interval.as_secs() as libc::time_t,
+        tv_usec: interval.subsec_micros() as libc::suseconds_t,
+    };
+    let timer = libc::itimerval {
+        it_interval: interval,
+        it_value: interval,
+    };
+    if unsafe { libc::setitimer(libc::ITIMER_REAL, &timer, ptr::null_mut()) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+extern "C" fn on_tick(signum: libc::c_int, _: *mut libc::siginfo_t, context: *mut c_void) {
+    let runtime_thread = RUNTIME_THREAD.load(Ordering::Relaxed);
+    if unsafe { libc::pthread_self() } as usize != runtime_thread {
+        // the timer signals the whole process, so the tick may have landed on any OS thread.
+        unsafe { libc::pthread_kill(runtime_thread as libc::pthread_t, signum) };
+        return;
+    }
+    // the thread is sampled wherever it is, the runtime included, see `profiler`.
+    #[cfg(feature = "profiler")]
+    crate::profiler::sample(context);
+    #[cfg(not(feature = "profiler"))]
+    let _ = context;
+
+    // the runtime may be gone while the timer still ticks, in which case nothing is to yield, e.g. as it allocates.
+    if crate::runtime().is_null() {
+        return;
+    }
+    if with(&DEPTH, |v| v.load(Ordering::Relaxed)) > 0 {
+        with(&PENDING, |v| v.store(true, Ordering::Relaxed));
+        return;
+    }
+    // the base thread only runs the scheduler, which is never preempted once it runs.
+    if get_current_thread() == BASE_THREAD_ID {
+        return;
+    }
+
+    // the interrupted code may be about to read errno, which the switch preserves.
+    with(&PENDING, |v| v.store(false, Ordering::Relaxed));
+    if !runtime::switch_preempted() {
+        with(&PENDING, |v| v.store(true, Ordering::Relaxed));
+    }
+}
@@ -0,0 +1,287 @@
+// Child processes for green threads.
+// Spawning a process doesn't block, but waiting for it and talking to it through pipes does.
+// The pipes to the child are made non-blocking and watched by the reactor like sockets,
+// and waiting for the child to exit is done by watching a pidfd on Linux (which becomes readable once the child exits),
+// falling back to checking on the child periodically when pidfds are not available.
+
+use std::ffi::OsStr;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::path::Path;
+use std::process::{self, ExitStatus, Stdio};
+use std::time::Duration;
+
+use crate::io::{Read, Write};
+use crate::reactor::{until_ready, Interest};
+use crate::runtime::{deregister_io, wait_io};
+use crate::time::sleep;
+
+/// How often a child is checked on when it can't be waited for through the reactor.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// A process builder, mirroring `std::process::Command`.
+#[derive(Debug)]
+pub struct Command {
+    inner: process::Command,
+}
+
+impl Command {
+    pub fn new<S: AsRef<OsStr>>(program: S) -> Command {
+        Command {
+            inner: process::Command::new(program),
+        }
+    }
+
+    pub fn arg<S: AsRef<OsStr>>(&mut self, arg: S) -> &mut Command {
+        self.inner.arg(arg);
+        self
+    }
+
+    pub fn args<I, S>(&mut self, args: I) -> &mut Command
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        self.inner.args(args);
+        self
+    }
+
+    pub fn env<K: AsRef<OsStr>, V: AsRef<OsStr>>(&mut self, key: K, val: V) -> &mut Command {
+        self.inner.env(key, val);
+        self
+    }
+
+    pub fn env_remove<K: AsRef<OsStr>>(&mut self, key: K) -> &mut Command {
+        self.inner.env_remove(key);
+        self
+    }
+
+    pub fn current_dir<P: AsRef<Path>>(&mut self, dir: P) -> &mut Command {
+        self.inner.current_dir(dir);
+        self
+    }
+
+    pub fn stdin<T: Into<Stdio>>(&mut self, cfg: T) -> &mut Command {
+        self.inner.stdin(cfg);
+        self
+    }
+
+    pub fn stdout<T: Into<Stdio>>(&mut self, cfg: T) -> &mut Command {
+        self.inner.stdout(cfg);
+        self
+    }
+
+    pub fn stderr<T: Into<Stdio>>(&mut self, cfg: T) -> &mut Command {
+        self.inner.stderr(cfg);
+        self
+    }
+
+    /// Starts the process. The standard streams are inherited unless configured otherwise.
+    pub fn spawn(&mut self) -> io::Result<Child> {
+        Child::from_std(self.inner.spawn()?)
+    }
+
+    /// Runs the process to completion, blocking the current thread until it exits.
+    pub fn status(&mut self) -> io::Result<ExitStatus> {
+        self.spawn()?.wait()
+    }
+}
+
+impl From<process::Command> for Command {
+    fn from(inner: process::Command) -> Command {
+        Command { inner }
+    }
+}
+
+/// A spawned child process.
+#[derive(Debug)]
+pub struct Child {
+    inner: process::Child,
+    /// Becomes readable once the child exits, if pidfds are supported.
+    pidfd: Option<OwnedFd>,
+    pub stdin: Option<ChildStdin>,
+    pub stdout: Option<ChildStdout>,
+    pub stderr: Option<ChildStderr>,
+}
+
+impl Child {
+    /// Wraps a child spawned through the standard library, putting its pipes in non-blocking mode.
+    pub fn from_std(mut child: process::Child) -> io::Result<Child> {
+        Ok(Child {
+            pidfd: pidfd_open(child.id()),
+            stdin: child.stdin.take().map(ChildStdin::new).transpose()?,
+            stdout: child.stdout.take().map(ChildStdout::new).transpose()?,
+            stderr: child.stderr.take().map(ChildStderr::new).transpose()?,
+            inner: child,
+        })
+    }
+
+    pub fn id(&self) -> u32 {
+        self.inner.id()
+    }
+
+    /// Sends SIGKILL to the child, if it is still running.
+    pub fn kill(&mut self) -> io::Result<()> {
+        self.inner.kill()
+    }
+
+    /// Returns the exit status of the child if it has exited, without blocking.
+    pub fn try_wait(&mut self) -> io::Result<Option<ExitStatus>> {
+        self.inner.try_wait()
+    }
+
+    /// Blocks the current thread until the child exits.
+    /// The child's stdin is closed beforehand, so that a child reading it doesn't wait forever.
+    pub fn wait(&mut self) -> io::Result<ExitStatus> {
+        drop(self.stdin.take());
+
+        loop {
+            if let Some(status) = self.inner.try_wait()? {
+                return Ok(status);
+            }
+            match &self.pidfd {
+                Some(pidfd) => wait_io(pidfd.as_raw_fd(), Interest::Readable)?,
+                None => sleep(POLL_INTERVAL),
+            }
+        }
+    }
+}
+
+impl Drop for Child {
+    fn drop(&mut self) {
+        if let Some(pidfd) = &self.pidfd {
+            deregister_io(pidfd.as_raw_fd());
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn pidfd_open(pid: u32) -> Option<OwnedFd> {
+    // pidfds are only available since Linux 5.3.
+    let fd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid as libc::pid_t, 0) };
+    if fd < 0 {
+        return None;
+    }
+    Some(unsafe { OwnedFd::from_raw_fd(fd as RawFd) })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn pidfd_open(_pid: u32) -> Option<OwnedFd> {
+    None
+}
+
+fn set_nonblocking(fd: RawFd) -> io::Result<()> {
+    unsafe {
+        let flags = libc::fcntl(fd, libc::F_GETFL);
+        if flags < 0 || libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Writing end of the child's stdin.
+#[derive(Debug)]
+pub struct ChildStdin {
+    inner: process::ChildStdin,
+}
+
+impl ChildStdin {
+    fn new(inner: process::ChildStdin) -> io::Result<ChildStdin> {
+        set_nonblocking(inner.as_raw_fd())?;
+        Ok(ChildStdin { inner })
+    }
+}
+
+impl Write for ChildStdin {
+    /// Writes some of `buf`, blocking the current thread until the pipe can accept data.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        until_ready(self.inner.as_raw_fd(), Interest::Writable, || {
+            io::Write::write(&mut self.inner, buf)
+        })
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl AsRawFd for ChildStdin {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+impl Drop for ChildStdin {
+    fn drop(&mut self) {
+        deregister_io(self.as_raw_fd());
+    }
+}
+
+/// Reading end of the child's stdout.
+#[derive(Debug)]
+pub struct ChildStdout {
+    inner: process::ChildStdout,
+}
+
+impl ChildStdout {
+    fn new(inner: process::ChildStdout) -> io::Result<ChildStdout> {
+        set_nonblocking(inner.as_raw_fd())?;
+        Ok(ChildStdout { inner })
+    }
+}
+
+impl Read for ChildStdout {
+    /// Reads into `buf`, blocking the current thread until some output is available.
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        until_ready(self.inner.as_raw_fd(), Interest::Readable, || {
+            io::Read::read(&mut self.inner, buf)
+        })
+    }
+}
+
+impl AsRawFd for ChildStdout {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+impl Drop for ChildStdout {
+    fn drop(&mut self) {
+        deregister_io(self.as_raw_fd());
+    }
+}
+
+/// Reading end of the child's stderr.
+#[derive(Debug)]
+pub struct ChildStderr {
+    inner: process::ChildStderr,
+}
+
+impl ChildStderr {
+    fn new(inner: process::ChildStderr) -> io::Result<ChildStderr> {
+        set_nonblocking(inner.as_raw_fd())?;
+        Ok(ChildStderr { inner })
+    }
+}
+
+impl Read for ChildStderr {
+    /// Reads into `buf`, blocking the current thread until some output is available.
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        until_ready(self.inner.as_raw_fd(), Interest::Readable, || {
+            io::Read::read(&mut self.inner, buf)
+        })
+    }
+}
+
+impl AsRawFd for ChildStderr {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+impl Drop for ChildStderr {
+    fn drop(&mut self) {
+        deregister_io(self.as_raw_fd());
+    }
+}
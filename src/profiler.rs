@@ -0,0 +1,188 @@
+// A sampling profiler of the threads, enabled by the `profiler` feature.
+// It piggybacks on preemption (see `preempt`): at every tick, the signal handler records the stack of the interrupted thread,
+// by unwinding from the handler through the signal frame, before it possibly switches threads.
+// The handler can't allocate, so the samples go into a buffer allocated when profiling starts,
+// and are only symbolized once it stops, as the frames are written out in the collapsed format of flamegraph tools.
+
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::ffi::c_void;
+use std::io::{self, Write};
+use std::ptr;
+
+use crate::arch::program_counter;
+use crate::runtime::get_current_thread;
+use crate::thread::Id;
+
+// Deepest stack recorded, the frames further from the interrupted one are left out.
+const MAX_FRAMES: usize = 128;
+
+thread_local! {
+    // The samples being recorded on this OS thread, null if it isn't profiled.
+    static PROFILE: Cell<*mut Samples> = const { Cell::new(ptr::null_mut()) };
+}
+
+// The samples one after the other, as the id of the thread, the number of frames, and the frames, innermost first.
+struct Samples {
+    words: Box<[usize]>,
+    len: usize,
+    count: usize,
+    max: usize,
+    lost: usize,
+}
+
+/// Starts sampling the threads of the runtime of the current OS thread, at every tick of preemption,
+/// which must be enabled, see `preempt::enable`. Up to `max_samples` samples are recorded, those past it are lost.
+/// The stacks are unwound from within the signal handler, like other profilers do, which may deadlock
+/// if the interrupted code holds a lock the unwinder needs, e.g. as it loads a shared library.
+/// Only supported on Linux, on x86_64 and aarch64: no sample is recorded elsewhere.
+pub fn start(max_samples: usize) {
+    let samples = Box::new(Samples {
+        words: vec![0; max_samples * (2 + MAX_FRAMES)].into_boxed_slice(),
+        len: 0,
+        count: 0,
+        max: max_samples,
+        lost: 0,
+    });
+    let previous = PROFILE.with(|profile| profile.replace(Box::into_raw(samples)));
+    if !previous.is_null() {
+        drop(unsafe { Box::from_raw(previous) });
+    }
+}
+
+/// Stops sampling, and returns what was recorded since `start`, empty if it wasn't called.
+pub fn stop() -> Profile {
+    let samples = PROFILE.with(|profile| profile.replace(ptr::null_mut()));
+    if samples.is_null() {
+        return Profile {
+            samples: Vec::new(),
+            lost: 0,
+        };
+    }
+    let samples = unsafe { Box::from_raw(samples) };
+    let mut words = samples.words[..samples.len].iter().copied();
+    let mut profile = Profile {
+        samples: Vec::new(),
+        lost: samples.lost,
+    };
+    while let (Some(id), Some(len)) = (words.next(), words.next()) {
+        profile
+            .samples
+            .push((Id(id), words.by_ref().take(len).collect()));
+    }
+    profile
+}
+
+// Records the stack of the thread interrupted by a tick, from the handler of the tick, given the context of the signal.
+pub(crate) fn sample(context: *mut c_void) {
+    let samples = PROFILE.with(Cell::get);
+    if samples.is_null() {
+        return;
+    }
+    let samples = unsafe { &mut *samples };
+    if samples.count == samples.max {
+        samples.lost += 1;
+        return;
+    }
+    let pc = unsafe { program_counter(context) };
+    if pc == 0 {
+        return;
+    }
+    let mut frames = [0; MAX_FRAMES];
+    let mut len = 0;
+    // the frames of the handler come first, up to the one of the instruction interrupted.
+    unsafe {
+        backtrace::trace_unsynchronized(|frame| {
+            let ip = frame.ip() as usize;
+            if len == 0 && ip != pc {
+                return true;
+            }
+            // past the outermost frame, see `uthreads_thread_entry`.
+            if ip == 0 {
+                return false;
+            }
+            frames[len] = ip;
+            len += 1;
+            len < MAX_FRAMES
+        })
+    };
+    if len == 0 {
+        samples.lost += 1;
+        return;
+    }
+    let start = samples.len;
+    samples.words[start] = get_current_thread().0;
+    samples.words[start + 1] = len;
+    samples.words[start + 2..start + 2 + len].copy_from_slice(&frames[..len]);
+    samples.len = start + 2 + len;
+    samples.count += 1;
+}
+
+/// The stacks sampled by the profiler, see `stop`.
+#[derive(Debug, Clone)]
+pub struct Profile {
+    // the thread and the return addresses of every sample, innermost first.
+    samples: Vec<(Id, Vec<usize>)>,
+    lost: usize,
+}
+
+impl Profile {
+    /// Returns how many samples were recorded.
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns how many samples were lost, as `max_samples` had been recorded already, or as the stack couldn't be unwound.
+    pub fn lost(&self) -> usize {
+        self.lost
+    }
+
+    /// Writes the samples in the collapsed stack format which flamegraph tools take, e.g. `inferno-flamegraph`:
+    /// one line per distinct stack, its frames from the outermost, separated by semicolons, then how many samples had it.
+    /// Every stack starts with the thread it was sampled on, so that the time of each thread can be told apart.
+    /// The frames are symbolized as they are written, from the debug info of the program, and inlined functions show up
+    /// as frames of their own.
+    pub fn write_collapsed(&self, mut out: impl Write) -> io::Result<()> {
+        let mut names: HashMap<usize, Vec<String>> = HashMap::new();
+        let mut stacks: HashMap<String, usize> = HashMap::new();
+        for (id, frames) in &self.samples {
+            let mut stack = format!("{id:?}");
+            for (i, &addr) in frames.iter().enumerate().rev() {
+                // return addresses point after the call, which may be the first instruction of another line or function.
+                let addr = if i == 0 { addr } else { addr - 1 };
+                for name in names.entry(addr).or_insert_with(|| symbolize(addr)) {
+                    stack.push(';');
+                    stack.push_str(name);
+                }
+            }
+            *stacks.entry(stack).or_default() += 1;
+        }
+        let mut stacks: Vec<_> = stacks.into_iter().collect();
+        stacks.sort();
+        for (stack, count) in stacks {
+            writeln!(out, "{stack} {count}")?;
+        }
+        Ok(())
+    }
+}
+
+// Names the functions at `addr`, the outermost first, as several are there if some were inlined.
+fn symbolize(addr: usize) -> Vec<String> {
+    let mut names = Vec::new();
+    backtrace::resolve(addr as *mut c_void, |symbol| {
+        names.push(match symbol.name() {
+            // without the hash of the crate, and without the separator of the frames.
+            Some(name) => format!("{name:#}").replace(';', ":"),
+            None => format!("{addr:#x}"),
+        })
+    });
+    if names.is_empty() {
+        names.push(format!("{addr:#x}"));
+    }
+    names.reverse();
+    names
+}
@@ -0,0 +1,426 @@
+// The reactor lets green threads wait for IO readiness (or timers) without blocking the whole runtime.
+// A thread registers the file descriptor it wants to use and is marked as IoBlocked.
+// The runtime polls the OS for readiness events in between running threads (and blocks on it
+// when no thread is ready to run) and marks the threads waiting on ready descriptors as Ready again.
+// Without std there is no OS to wait on, so the reactor only keeps the timers.
+
+#[cfg(feature = "std")]
+mod doorbell;
+#[cfg(all(feature = "std", target_os = "linux"))]
+mod epoll;
+#[cfg(all(feature = "std", target_os = "macos"))]
+mod kqueue;
+mod timer;
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+mod uring;
+#[cfg(feature = "std")]
+mod waker;
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::time::Duration;
+#[cfg(feature = "std")]
+use std::collections::{HashMap, HashSet};
+#[cfg(feature = "std")]
+use std::io;
+#[cfg(feature = "std")]
+use std::os::fd::RawFd;
+
+#[cfg(feature = "std")]
+use doorbell::Doorbell;
+#[cfg(all(feature = "std", target_os = "linux"))]
+use epoll::Poller;
+#[cfg(all(feature = "std", target_os = "macos"))]
+use kqueue::Poller;
+use timer::Timers;
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+pub(crate) use uring::Ring;
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+pub use uring::{uring_read, uring_write};
+#[cfg(feature = "std")]
+use waker::WakePipe;
+
+#[cfg(feature = "std")]
+pub(crate) use doorbell::Notifier;
+#[cfg(feature = "std")]
+pub use waker::RuntimeWaker;
+
+#[cfg(feature = "std")]
+use crate::runtime::{preemption_point, wait_io};
+use crate::thread::Id;
+use crate::time::{Clock, Instant};
+
+/// The kind of readiness a thread is waiting for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interest {
+    Readable,
+    Writable,
+}
+
+/// Runs the non-blocking IO operation `f` on `fd`,
+/// blocking the current thread until `fd` is ready for `interest` whenever the operation would block.
+#[cfg(feature = "std")]
+pub(crate) fn until_ready<T, F>(fd: RawFd, interest: Interest, mut f: F) -> io::Result<T>
+where
+    F: FnMut() -> io::Result<T>,
+{
+    loop {
+        match f() {
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => wait_io(fd, interest)?,
+            res => {
+                // a thread whose IO never blocks would otherwise never give up control.
+                preemption_point();
+                return res;
+            }
+        }
+    }
+}
+
+/// A readiness event reported by the OS poller.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub(crate) struct Event {
+    pub fd: RawFd,
+    pub readable: bool,
+    pub writable: bool,
+}
+
+/// Threads waiting on a file descriptor, grouped by interest.
+#[cfg(feature = "std")]
+#[derive(Debug, Default)]
+struct Waiters {
+    readers: Vec<Id>,
+    writers: Vec<Id>,
+}
+
+pub(crate) struct Reactor {
+    #[cfg(feature = "std")]
+    poller: Poller,
+    #[cfg(feature = "std")]
+    waiters: HashMap<RawFd, Waiters>,
+    /// Buffer reused between polls to receive the events.
+    #[cfg(feature = "std")]
+    events: Vec<Event>,
+    /// Wakes up threads on behalf of other OS threads.
+    #[cfg(feature = "std")]
+    doorbell: Doorbell,
+    /// Threads waiting to be notified through the doorbell.
+    #[cfg(feature = "std")]
+    parked: HashSet<Id>,
+    /// Threads sleeping until a deadline.
+    timers: Timers,
+    /// Wakes up the runtime on behalf of `RuntimeWaker`s.
+    #[cfg(feature = "std")]
+    wake_pipe: WakePipe,
+    /// Whether to wait for `RuntimeWaker`s even when no thread waits on anything, see `set_held`.
+    #[cfg(feature = "std")]
+    held: bool,
+    /// Completion-based IO, if io_uring is supported by the kernel.
+    #[cfg(all(target_os = "linux", feature = "io-uring"))]
+    ring: Option<Ring>,
+}
+
+impl Reactor {
+    #[cfg(not(feature = "std"))]
+    pub fn new() -> Self {
+        Reactor {
+            timers: Timers::new(),
+        }
+    }
+
+    #[cfg(feature = "std")]
+    pub fn new() -> io::Result<Self> {
+        Ok(Reactor {
+            poller: Poller::new()?,
+            waiters: HashMap::new(),
+            events: Vec::new(),
+            doorbell: Doorbell::new()?,
+            parked: HashSet::new(),
+            timers: Timers::new()?,
+            wake_pipe: WakePipe::new()?,
+            held: false,
+            #[cfg(all(target_os = "linux", feature = "io-uring"))]
+            ring: Ring::new().ok(),
+        })
+    }
+
+    /// Returns true if any thread is waiting for IO readiness or completion, a notification, or a timer.
+    pub fn has_waiters(&self) -> bool {
+        #[cfg(all(target_os = "linux", feature = "io-uring"))]
+        if self.ring.as_ref().is_some_and(Ring::is_busy) {
+            return true;
+        }
+
+        #[cfg(feature = "std")]
+        if !self.waiters.is_empty() || !self.parked.is_empty() || self.wake_pipe.has_parked() {
+            return true;
+        }
+        !self.timers.is_empty()
+    }
+
+    /// Sets whether something outside the runtime may still give it work and wake it up (e.g. a `runtime::Handle`),
+    /// in which case `poll` waits for `RuntimeWaker`s even when no thread waits on anything.
+    #[cfg(feature = "std")]
+    pub fn set_held(&mut self, held: bool) {
+        self.held = held;
+    }
+
+    /// Returns a descriptor which becomes readable when `poll` has events to report, once it has been called,
+    /// so that another event loop can wait for them, along with the timeout returned by `timeout`.
+    #[cfg(feature = "tokio")]
+    pub fn fd(&self) -> RawFd {
+        self.poller.fd()
+    }
+
+    /// Returns how long another event loop may wait on `fd` without missing the next timer, forever if None.
+    #[cfg(feature = "tokio")]
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timers.timeout(None)
+    }
+
+    /// Registers thread `id` to be woken up once `deadline` has passed.
+    pub fn add_timer(&mut self, deadline: Instant, id: Id) {
+        self.timers.add(deadline, id);
+    }
+
+    /// Returns the current time on the clock of the timers.
+    pub fn now(&self) -> Instant {
+        self.timers.now()
+    }
+
+    /// Makes the timers go by `clock`, or by the system's clock if None.
+    pub fn set_clock(&mut self, clock: Option<Box<dyn Clock>>) {
+        self.timers.set_clock(clock);
+    }
+
+    /// Returns a handle that other OS threads can use to wake up threads waiting to be notified.
+    #[cfg(feature = "std")]
+    pub fn notifier(&self) -> Notifier {
+        self.doorbell.notifier()
+    }
+
+    /// Returns a handle that other OS threads can use to unpark threads or interrupt the poller.
+    #[cfg(feature = "std")]
+    pub fn waker(&self) -> RuntimeWaker {
+        self.wake_pipe.waker()
+    }
+
+    /// Registers thread `id` to be woken up once it is unparked through a `RuntimeWaker`.
+    /// Returns false if it has already been unparked, in which case it must not block.
+    #[cfg(feature = "std")]
+    pub fn park_until_unparked(&mut self, id: Id) -> bool {
+        self.wake_pipe.park(id)
+    }
+
+    /// Registers thread `id` to be woken up once it is notified through the doorbell.
+    #[cfg(feature = "std")]
+    pub fn park(&mut self, id: Id) {
+        self.parked.insert(id);
+    }
+
+    /// Returns the file descriptor thread `id` is waiting on, and for what, if any.
+    /// Neither allocates nor takes locks, so that it can be used from a signal handler.
+    #[cfg(feature = "std")]
+    pub fn waiting_fd(&self, id: Id) -> Option<(RawFd, Interest)> {
+        self.waiters.iter().find_map(|(&fd, w)| {
+            if w.readers.contains(&id) {
+                Some((fd, Interest::Readable))
+            } else if w.writers.contains(&id) {
+                Some((fd, Interest::Writable))
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Stops waking up thread `id` at its deadlines.
+    pub fn cancel_timers(&mut self, id: Id) {
+        self.timers.remove(id);
+    }
+
+    /// Stops waking up thread `id` when the file descriptors it waits on are ready.
+    #[cfg(feature = "std")]
+    pub fn cancel_io(&mut self, id: Id) {
+        while let Some((fd, _)) = self.waiting_fd(id) {
+            self.remove_waiter(fd, id);
+        }
+    }
+
+    /// Returns when thread `id` is to be woken up, if it is sleeping.
+    /// Neither allocates nor takes locks, so that it can be used from a signal handler.
+    pub fn deadline(&self, id: Id) -> Option<Instant> {
+        self.timers.deadline(id)
+    }
+
+    /// Registers thread `id` to be woken up when `fd` is ready for `interest`.
+    #[cfg(feature = "std")]
+    pub fn register(&mut self, fd: RawFd, interest: Interest, id: Id) -> io::Result<()> {
+        let waiters = self.waiters.entry(fd).or_default();
+        match interest {
+            Interest::Readable => waiters.readers.push(id),
+            Interest::Writable => waiters.writers.push(id),
+        }
+
+        let (readable, writable) = (!waiters.readers.is_empty(), !waiters.writers.is_empty());
+        if let Err(err) = self.poller.arm(fd, readable, writable) {
+            self.remove_waiter(fd, id);
+            return Err(err);
+        }
+
+        Ok(())
+    }
+
+    /// Stops watching `fd`, e.g. before it is closed.
+    /// Returns the threads that were waiting on it, so that they can be woken up.
+    #[cfg(feature = "std")]
+    pub fn deregister(&mut self, fd: RawFd) -> Vec<Id> {
+        let _ = self.poller.disarm(fd);
+        self.waiters
+            .remove(&fd)
+            .map(|w| w.readers.into_iter().chain(w.writers).collect())
+            .unwrap_or_default()
+    }
+
+    /// Waits for readiness events for up to `timeout` (forever if None)
+    /// and returns the threads that can make progress.
+    #[cfg(feature = "std")]
+    pub fn poll(&mut self, timeout: Option<Duration>) -> io::Result<Vec<Id>> {
+        let mut woken = Vec::new();
+
+        self.timers.expire(&mut woken);
+        // completions are read from memory shared with the kernel, so this doesn't cost a syscall.
+        #[cfg(all(target_os = "linux", feature = "io-uring"))]
+        if let Some(ring) = &mut self.ring {
+            ring.reap(&mut woken);
+            // watch the ring, so that waiting for readiness is interrupted by completions.
+            if ring.is_busy() {
+                self.poller.arm(ring.fd(), true, false)?;
+            }
+        }
+
+        if !self.has_waiters() && !self.held {
+            return Ok(woken);
+        }
+        if !self.parked.is_empty() {
+            self.poller.arm(self.doorbell.fd(), true, false)?;
+        }
+        if self.wake_pipe.needs_arming() {
+            self.poller.arm(self.wake_pipe.fd(), true, false)?;
+        }
+        #[cfg(all(target_os = "linux", not(miri)))]
+        if let Some(fd) = self.timers.arm()? {
+            self.poller.arm(fd, true, false)?;
+        }
+
+        // don't wait if some threads can already make progress.
+        let timeout = if woken.is_empty() {
+            self.timers.timeout(timeout)
+        } else {
+            Some(Duration::ZERO)
+        };
+        self.poller.wait(timeout, &mut self.events)?;
+
+        self.timers.expire(&mut woken);
+        #[cfg(all(target_os = "linux", feature = "io-uring"))]
+        if let Some(ring) = &mut self.ring {
+            ring.reap(&mut woken);
+        }
+
+        for event in self.events.drain(..) {
+            #[cfg(all(target_os = "linux", not(miri)))]
+            if event.fd == self.timers.fd() {
+                self.timers.clear();
+                continue;
+            }
+            if event.fd == self.wake_pipe.fd() {
+                self.wake_pipe.drain(&mut woken);
+                continue;
+            }
+            if event.fd == self.doorbell.fd() {
+                let notified = self.doorbell.drain();
+                woken.extend(notified.into_iter().filter(|id| self.parked.remove(id)));
+                continue;
+            }
+
+            let Some(waiters) = self.waiters.get_mut(&event.fd) else {
+                continue;
+            };
+            if event.readable {
+                woken.append(&mut waiters.readers);
+            }
+            if event.writable {
+                woken.append(&mut waiters.writers);
+            }
+
+            // registrations are one-shot, so re-arm the descriptor for the threads still waiting on it.
+            let (readable, writable) = (!waiters.readers.is_empty(), !waiters.writers.is_empty());
+            if (readable || writable) && self.poller.arm(event.fd, readable, writable).is_err() {
+                // wake up the remaining threads so that they can observe the error themselves.
+                woken.append(&mut waiters.readers);
+                woken.append(&mut waiters.writers);
+            }
+            if waiters.readers.is_empty() && waiters.writers.is_empty() {
+                self.waiters.remove(&event.fd);
+            }
+        }
+
+        Ok(woken)
+    }
+
+    /// Returns the threads whose deadlines have passed.
+    /// There is no IO to wait for, so waiting is up to the clock: if no deadline has passed, it is told the runtime idles
+    /// until the first one (see `Clock::idle`), and read again. It may move there on its own, or wait for it to pass,
+    /// otherwise the runtime calls `poll` again, until it has.
+    #[cfg(not(feature = "std"))]
+    pub fn poll(&mut self, timeout: Option<Duration>) -> Vec<Id> {
+        let mut woken = Vec::new();
+        self.timers.expire(&mut woken);
+        if woken.is_empty() && timeout != Some(Duration::ZERO) && !self.timers.is_empty() {
+            self.timers.timeout(timeout);
+            self.timers.expire(&mut woken);
+        }
+        woken
+    }
+
+    #[cfg(all(target_os = "linux", feature = "io-uring"))]
+    pub fn uring_available(&self) -> bool {
+        self.ring.is_some()
+    }
+
+    /// Submits an io_uring operation on behalf of thread `id`.
+    ///
+    /// # Safety
+    ///
+    /// The buffers referenced by `entry` must stay valid until the operation completes.
+    #[cfg(all(target_os = "linux", feature = "io-uring"))]
+    pub unsafe fn submit(&mut self, id: Id, entry: io_uring::squeue::Entry) -> io::Result<()> {
+        let ring = self.ring.as_mut().expect("io_uring is not available");
+        unsafe { ring.submit(id, entry) }
+    }
+
+    /// Cancels the io_uring operations in flight and waits until the kernel is done with their buffers.
+    /// Returns false if it couldn't, in which case the buffers must never be freed.
+    #[cfg(all(target_os = "linux", feature = "io-uring"))]
+    pub fn cancel_uring(&mut self) -> bool {
+        self.ring
+            .as_mut()
+            .is_none_or(|ring| ring.cancel_all().is_ok())
+    }
+
+    /// Returns the result of the io_uring operation submitted by thread `id`, once it has completed.
+    #[cfg(all(target_os = "linux", feature = "io-uring"))]
+    pub fn take_result(&mut self, id: Id) -> Option<i32> {
+        self.ring.as_mut()?.take_result(id)
+    }
+
+    #[cfg(feature = "std")]
+    fn remove_waiter(&mut self, fd: RawFd, id: Id) {
+        if let Some(waiters) = self.waiters.get_mut(&fd) {
+            waiters.readers.retain(|&t| t != id);
+            waiters.writers.retain(|&t| t != id);
+            if waiters.readers.is_empty() && waiters.writers.is_empty() {
+                self.waiters.remove(&fd);
+            }
+        }
+    }
+}
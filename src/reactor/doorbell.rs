@@ -0,0 +1,124 @@
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::sync::{Arc, Mutex};
+
+use crate::thread::Id;
+
+// State shared between the runtime and the notifiers handed out to other OS threads.
+struct Shared {
+    /// Threads that have been notified since the doorbell was last drained.
+    pending: Mutex<Vec<Id>>,
+    /// Descriptor written to in order to make the doorbell readable for the poller:
+    /// an eventfd on Linux, the write end of a self-pipe elsewhere.
+    write: OwnedFd,
+}
+
+/// Lets other OS threads wake up green threads.
+/// A notification is recorded in a queue and a descriptor watched by the reactor is made readable,
+/// so that the runtime picks it up even if it is blocked waiting for IO.
+pub(crate) struct Doorbell {
+    /// Read end of the self-pipe. An eventfd is both read from and written to, so there is none on Linux.
+    #[cfg(not(target_os = "linux"))]
+    read: OwnedFd,
+    shared: Arc<Shared>,
+}
+
+/// A handle to the doorbell that can be sent to other OS threads.
+#[derive(Clone)]
+pub(crate) struct Notifier {
+    shared: Arc<Shared>,
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(super) fn set_flags(fd: RawFd) -> io::Result<()> {
+    unsafe {
+        let flags = libc::fcntl(fd, libc::F_GETFL);
+        if flags < 0 || libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+impl Doorbell {
+    #[cfg(target_os = "linux")]
+    pub fn new() -> io::Result<Self> {
+        let fd = unsafe { libc::eventfd(0, libc::EFD_NONBLOCK | libc::EFD_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Doorbell {
+            shared: Arc::new(Shared {
+                pending: Mutex::new(Vec::new()),
+                write: unsafe { OwnedFd::from_raw_fd(fd) },
+            }),
+        })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn new() -> io::Result<Self> {
+        let mut fds = [0; 2];
+        if unsafe { libc::pipe(fds.as_mut_ptr()) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let (read, write) = unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+        set_flags(read.as_raw_fd())?;
+        set_flags(write.as_raw_fd())?;
+
+        Ok(Doorbell {
+            read,
+            shared: Arc::new(Shared {
+                pending: Mutex::new(Vec::new()),
+                write,
+            }),
+        })
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn fd(&self) -> RawFd {
+        self.shared.write.as_raw_fd()
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn fd(&self) -> RawFd {
+        self.read.as_raw_fd()
+    }
+
+    pub fn notifier(&self) -> Notifier {
+        Notifier {
+            shared: self.shared.clone(),
+        }
+    }
+
+    /// Resets the descriptor and returns the threads notified since the last call.
+    pub fn drain(&self) -> Vec<Id> {
+        // a single read resets the counter of an eventfd, while a pipe may need several.
+        let mut buf = [0_u8; 64];
+        while unsafe { libc::read(self.fd(), buf.as_mut_ptr().cast(), buf.len()) } > 0 {}
+        std::mem::take(&mut *self.shared.pending.lock().unwrap())
+    }
+}
+
+impl Notifier {
+    /// Asks the runtime to wake up thread `id`. Can be called from any OS thread.
+    pub fn notify(&self, id: Id) {
+        self.shared.pending.lock().unwrap().push(id);
+        // a full pipe (or saturated eventfd counter) is already readable,
+        // so a failed write doesn't lose the notification.
+        #[cfg(target_os = "linux")]
+        let buf = 1_u64.to_ne_bytes();
+        #[cfg(not(target_os = "linux"))]
+        let buf = [1_u8];
+        unsafe {
+            libc::write(
+                self.shared.write.as_raw_fd(),
+                buf.as_ptr().cast(),
+                buf.len(),
+            )
+        };
+    }
+}
@@ -0,0 +1,119 @@
+use std::io;
+use std::os::fd::RawFd;
+use std::time::Duration;
+
+use super::Event;
+
+const MAX_EVENTS: usize = 64;
+
+/// Readiness poller backed by Linux epoll.
+/// Descriptors are registered in one-shot mode, so that a readiness event is only reported
+/// once per `arm`, which matches threads waiting for a single event before retrying their IO.
+pub(crate) struct Poller {
+    epfd: RawFd,
+    events: Vec<libc::epoll_event>,
+}
+
+impl Poller {
+    pub fn new() -> io::Result<Self> {
+        let epfd = unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) };
+        if epfd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Poller {
+            epfd,
+            events: vec![libc::epoll_event { events: 0, u64: 0 }; MAX_EVENTS],
+        })
+    }
+
+    /// Returns the descriptor of the poller itself, readable whenever it has events to report.
+    #[cfg(feature = "tokio")]
+    pub fn fd(&self) -> RawFd {
+        self.epfd
+    }
+
+    /// Watches `fd` for the next readable and/or writable event.
+    pub fn arm(&self, fd: RawFd, readable: bool, writable: bool) -> io::Result<()> {
+        let mut flags = libc::EPOLLONESHOT;
+        if readable {
+            flags |= libc::EPOLLIN | libc::EPOLLRDHUP;
+        }
+        if writable {
+            flags |= libc::EPOLLOUT;
+        }
+        let mut event = libc::epoll_event {
+            events: flags as u32,
+            u64: fd as u64,
+        };
+
+        // the descriptor stays in the interest list after a one-shot event fires, so try modifying it first.
+        let res = unsafe { libc::epoll_ctl(self.epfd, libc::EPOLL_CTL_MOD, fd, &mut event) };
+        if res == 0 {
+            return Ok(());
+        }
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() != Some(libc::ENOENT) {
+            return Err(err);
+        }
+
+        let res = unsafe { libc::epoll_ctl(self.epfd, libc::EPOLL_CTL_ADD, fd, &mut event) };
+        if res < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Removes `fd` from the interest list.
+    pub fn disarm(&self, fd: RawFd) -> io::Result<()> {
+        let res =
+            unsafe { libc::epoll_ctl(self.epfd, libc::EPOLL_CTL_DEL, fd, std::ptr::null_mut()) };
+        if res < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Waits for up to `timeout` (forever if None) and appends the events received to `events`.
+    pub fn wait(&mut self, timeout: Option<Duration>, events: &mut Vec<Event>) -> io::Result<()> {
+        let timeout = match timeout {
+            // round up, so that a short non-zero timeout doesn't turn into a busy loop.
+            Some(t) => t.as_nanos().div_ceil(1_000_000).min(i32::MAX as u128) as i32,
+            None => -1,
+        };
+
+        let n = unsafe {
+            libc::epoll_wait(
+                self.epfd,
+                self.events.as_mut_ptr(),
+                self.events.len() as i32,
+                timeout,
+            )
+        };
+        if n < 0 {
+            let err = io::Error::last_os_error();
+            // being interrupted by a signal is not an error, the caller will simply poll again.
+            if err.kind() == io::ErrorKind::Interrupted {
+                return Ok(());
+            }
+            return Err(err);
+        }
+
+        for event in &self.events[..n as usize] {
+            let flags = event.events as i32;
+            let closed = flags & (libc::EPOLLHUP | libc::EPOLLERR) != 0;
+            events.push(Event {
+                fd: event.u64 as RawFd,
+                readable: closed || flags & (libc::EPOLLIN | libc::EPOLLRDHUP) != 0,
+                writable: closed || flags & libc::EPOLLOUT != 0,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Poller {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.epfd) };
+    }
+}
@@ -0,0 +1,136 @@
+use std::io;
+use std::os::fd::RawFd;
+use std::time::Duration;
+
+use super::Event;
+
+const MAX_EVENTS: usize = 64;
+
+/// Readiness poller backed by BSD/macOS kqueue.
+/// Read and write interests are separate filters, both registered in one-shot mode
+/// to match the behaviour of the epoll backend.
+pub(crate) struct Poller {
+    kq: RawFd,
+    events: Vec<libc::kevent>,
+}
+
+fn kevent(fd: RawFd, filter: i16, flags: u16) -> libc::kevent {
+    libc::kevent {
+        ident: fd as libc::uintptr_t,
+        filter,
+        flags,
+        fflags: 0,
+        data: 0,
+        udata: std::ptr::null_mut(),
+    }
+}
+
+impl Poller {
+    pub fn new() -> io::Result<Self> {
+        let kq = unsafe { libc::kqueue() };
+        if kq < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        unsafe { libc::fcntl(kq, libc::F_SETFD, libc::FD_CLOEXEC) };
+
+        Ok(Poller {
+            kq,
+            events: vec![kevent(0, 0, 0); MAX_EVENTS],
+        })
+    }
+
+    fn apply(&self, changes: &[libc::kevent]) -> io::Result<()> {
+        let res = unsafe {
+            libc::kevent(
+                self.kq,
+                changes.as_ptr(),
+                changes.len() as i32,
+                std::ptr::null_mut(),
+                0,
+                std::ptr::null(),
+            )
+        };
+        if res < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Returns the descriptor of the poller itself, readable whenever it has events to report.
+    #[cfg(feature = "tokio")]
+    pub fn fd(&self) -> RawFd {
+        self.kq
+    }
+
+    /// Watches `fd` for the next readable and/or writable event.
+    pub fn arm(&self, fd: RawFd, readable: bool, writable: bool) -> io::Result<()> {
+        let flags = libc::EV_ADD | libc::EV_ONESHOT;
+        let mut changes = Vec::with_capacity(2);
+        if readable {
+            changes.push(kevent(fd, libc::EVFILT_READ, flags));
+        }
+        if writable {
+            changes.push(kevent(fd, libc::EVFILT_WRITE, flags));
+        }
+        self.apply(&changes)
+    }
+
+    /// Removes both filters of `fd`.
+    pub fn disarm(&self, fd: RawFd) -> io::Result<()> {
+        // a filter that is not registered (or has already fired) can't be deleted, which is fine.
+        for filter in [libc::EVFILT_READ, libc::EVFILT_WRITE] {
+            if let Err(err) = self.apply(&[kevent(fd, filter, libc::EV_DELETE)]) {
+                if err.raw_os_error() != Some(libc::ENOENT) {
+                    return Err(err);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Waits for up to `timeout` (forever if None) and appends the events received to `events`.
+    pub fn wait(&mut self, timeout: Option<Duration>, events: &mut Vec<Event>) -> io::Result<()> {
+        let timeout = timeout.map(|t| libc::timespec {
+            tv_sec: t.as_secs().min(libc::time_t::MAX as u64) as libc::time_t,
+            tv_nsec: t.subsec_nanos() as libc::c_long,
+        });
+        let timeout_ptr = timeout
+            .as_ref()
+            .map_or(std::ptr::null(), |t| t as *const libc::timespec);
+
+        let n = unsafe {
+            libc::kevent(
+                self.kq,
+                std::ptr::null(),
+                0,
+                self.events.as_mut_ptr(),
+                self.events.len() as i32,
+                timeout_ptr,
+            )
+        };
+        if n < 0 {
+            let err = io::Error::last_os_error();
+            // being interrupted by a signal is not an error, the caller will simply poll again.
+            if err.kind() == io::ErrorKind::Interrupted {
+                return Ok(());
+            }
+            return Err(err);
+        }
+
+        for event in &self.events[..n as usize] {
+            let error = event.flags & libc::EV_ERROR != 0;
+            events.push(Event {
+                fd: event.ident as RawFd,
+                readable: error || event.filter == libc::EVFILT_READ,
+                writable: error || event.filter == libc::EVFILT_WRITE,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Poller {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.kq) };
+    }
+}
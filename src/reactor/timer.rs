@@ -0,0 +1,200 @@
+use alloc::boxed::Box;
+use alloc::collections::BinaryHeap;
+use alloc::vec::Vec;
+use core::cmp::Reverse;
+use core::time::Duration;
+#[cfg(feature = "std")]
+use std::io;
+#[cfg(all(feature = "std", target_os = "linux", not(miri)))]
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+
+use crate::thread::Id;
+#[cfg(not(feature = "std"))]
+use crate::time::MockClock;
+use crate::time::{Clock, Instant};
+
+/// Threads sleeping until a deadline, earliest first.
+/// On Linux, a timerfd armed for the earliest deadline is watched by the poller,
+/// so the run loop is woken up precisely rather than at the millisecond granularity of `epoll_wait`.
+/// Miri doesn't support timerfds, so the deadline only bounds the time the poller waits there, as on other systems,
+/// and so it does with a clock other than the system's, which the timerfd knows nothing about.
+/// Without std, there is neither a system's clock nor a timerfd: the deadlines are on the clock of the runtime,
+/// a `MockClock` unless the program sets another one, and waiting for them is up to the clock, see `Clock::idle`.
+pub(crate) struct Timers {
+    queue: BinaryHeap<Reverse<(Instant, Id)>>,
+    /// The clock the deadlines are on, the system's if None.
+    clock: Option<Box<dyn Clock>>,
+    #[cfg(all(feature = "std", target_os = "linux", not(miri)))]
+    timerfd: OwnedFd,
+    /// Deadline the timerfd is currently armed for.
+    #[cfg(all(feature = "std", target_os = "linux", not(miri)))]
+    armed: Option<Instant>,
+}
+
+impl Timers {
+    #[cfg(not(feature = "std"))]
+    pub fn new() -> Self {
+        Timers {
+            queue: BinaryHeap::new(),
+            clock: Some(Box::new(MockClock::new())),
+        }
+    }
+
+    #[cfg(feature = "std")]
+    pub fn new() -> io::Result<Self> {
+        Ok(Timers {
+            queue: BinaryHeap::new(),
+            clock: None,
+            #[cfg(all(target_os = "linux", not(miri)))]
+            timerfd: {
+                let fd = unsafe {
+                    libc::timerfd_create(
+                        libc::CLOCK_MONOTONIC,
+                        libc::TFD_NONBLOCK | libc::TFD_CLOEXEC,
+                    )
+                };
+                if fd < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                unsafe { OwnedFd::from_raw_fd(fd) }
+            },
+            #[cfg(all(target_os = "linux", not(miri)))]
+            armed: None,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    pub fn now(&self) -> Instant {
+        self.clock
+            .as_ref()
+            .map_or_else(system_now, |clock| clock.now())
+    }
+
+    pub fn set_clock(&mut self, clock: Option<Box<dyn Clock>>) {
+        // without a system's clock to fall back to, time goes by hand again.
+        #[cfg(not(feature = "std"))]
+        let clock = clock.or_else(|| Some(Box::new(MockClock::new())));
+        self.clock = clock;
+    }
+
+    pub fn add(&mut self, deadline: Instant, id: Id) {
+        self.queue.push(Reverse((deadline, id)));
+    }
+
+    /// Forgets the deadlines of thread `id`.
+    pub fn remove(&mut self, id: Id) {
+        self.queue.retain(|Reverse((_, t))| *t != id);
+    }
+
+    /// Returns the deadline of thread `id`, if it is sleeping.
+    pub fn deadline(&self, id: Id) -> Option<Instant> {
+        self.queue
+            .iter()
+            .find(|Reverse((_, t))| *t == id)
+            .map(|Reverse((deadline, _))| *deadline)
+    }
+
+    /// Appends the threads whose deadline has passed to `woken`.
+    pub fn expire(&mut self, woken: &mut Vec<Id>) {
+        let now = self.now();
+        while let Some(&Reverse((deadline, id))) = self.queue.peek() {
+            if deadline > now {
+                break;
+            }
+            self.queue.pop();
+            woken.push(id);
+        }
+    }
+
+    /// Returns how long the poller may wait without missing the next deadline,
+    /// given that it was asked to wait for `timeout` (forever if None).
+    /// The timerfd wakes the poller up, if armed, so the timeout doesn't need to change then.
+    pub fn timeout(&self, timeout: Option<Duration>) -> Option<Duration> {
+        #[cfg(all(feature = "std", target_os = "linux", not(miri)))]
+        if self.clock.is_none() {
+            return timeout;
+        }
+        let Some(&Reverse((deadline, _))) = self.queue.peek() else {
+            return timeout;
+        };
+        let until = match &self.clock {
+            // the clock may move on its own as the runtime idles, which it doesn't when only polling.
+            Some(clock) if timeout != Some(Duration::ZERO) => clock.idle(deadline),
+            Some(clock) => deadline.saturating_duration_since(clock.now()),
+            None => deadline.saturating_duration_since(system_now()),
+        };
+        Some(timeout.map_or(until, |t| t.min(until)))
+    }
+
+    /// Arms the timerfd for the earliest deadline, if it isn't already.
+    /// Returns the descriptor to watch for readability, if any.
+    #[cfg(all(feature = "std", target_os = "linux", not(miri)))]
+    pub fn arm(&mut self) -> io::Result<Option<RawFd>> {
+        let Some(&Reverse((deadline, _))) = self.queue.peek() else {
+            return Ok(None);
+        };
+        if self.clock.is_some() {
+            return Ok(None);
+        }
+
+        if self.armed != Some(deadline) {
+            // a zero it_value disarms the timer, so make sure an expired deadline still fires.
+            let until = deadline
+                .saturating_duration_since(Instant::now())
+                .max(Duration::from_nanos(1));
+            let spec = libc::itimerspec {
+                it_interval: libc::timespec {
+                    tv_sec: 0,
+                    tv_nsec: 0,
+                },
+                it_value: libc::timespec {
+                    tv_sec: until.as_secs() as libc::time_t,
+                    tv_nsec: until.subsec_nanos() as libc::c_long,
+                },
+            };
+            let res = unsafe {
+                libc::timerfd_settime(self.timerfd.as_raw_fd(), 0, &spec, std::ptr::null_mut())
+            };
+            if res < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            self.armed = Some(deadline);
+        }
+
+        Ok(Some(self.timerfd.as_raw_fd()))
+    }
+
+    #[cfg(all(feature = "std", target_os = "linux", not(miri)))]
+    pub fn fd(&self) -> RawFd {
+        self.timerfd.as_raw_fd()
+    }
+
+    /// Clears the expiration count of the timerfd once it has fired.
+    #[cfg(all(feature = "std", target_os = "linux", not(miri)))]
+    pub fn clear(&mut self) {
+        let mut expirations = 0_u64;
+        unsafe {
+            libc::read(
+                self.timerfd.as_raw_fd(),
+                (&mut expirations as *mut u64).cast(),
+                8,
+            )
+        };
+        self.armed = None;
+    }
+}
+
+// The clock the deadlines are on when the timers haven't been given one.
+#[cfg(feature = "std")]
+fn system_now() -> Instant {
+    Instant::now()
+}
+
+// Without std, the timers always have a clock, a `MockClock` until the program sets another one.
+#[cfg(not(feature = "std"))]
+fn system_now() -> Instant {
+    Instant::from_duration(Duration::ZERO)
+}
@@ -0,0 +1,147 @@
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::os::fd::{AsRawFd, RawFd};
+
+use io_uring::{opcode, squeue, types, IoUring};
+
+use crate::runtime::{submit_io, uring_available, wait_io};
+use crate::thread::Id;
+
+use super::Interest;
+
+const RING_ENTRIES: u32 = 256;
+// User data of the cancellations submitted by `cancel_all`, which no thread waits for.
+const CANCEL: u64 = u64::MAX;
+
+/// Completion-based IO backed by io_uring.
+/// Each thread has at most one operation in flight, as it is blocked until the operation completes,
+/// so the thread id is used as the user data of the submission.
+pub(crate) struct Ring {
+    ring: IoUring,
+    /// The threads whose operation has been submitted, but whose completion hasn't been reaped yet.
+    in_flight: HashSet<Id>,
+    /// Results of the completed operations, waiting to be picked up by the threads that submitted them.
+    results: HashMap<Id, i32>,
+}
+
+impl Ring {
+    pub fn new() -> io::Result<Self> {
+        Ok(Ring {
+            ring: IoUring::new(RING_ENTRIES)?,
+            in_flight: HashSet::new(),
+            results: HashMap::new(),
+        })
+    }
+
+    /// The ring's file descriptor becomes readable when completions are available,
+    /// which lets the poller wait for both readiness and completion events.
+    pub fn fd(&self) -> RawFd {
+        self.ring.as_raw_fd()
+    }
+
+    pub fn is_busy(&self) -> bool {
+        !self.in_flight.is_empty()
+    }
+
+    /// Submits `entry` on behalf of thread `id`.
+    ///
+    /// # Safety
+    ///
+    /// The buffers referenced by `entry` must stay valid until the operation completes.
+    pub unsafe fn submit(&mut self, id: Id, entry: squeue::Entry) -> io::Result<()> {
+        let entry = entry.user_data(id.0 as u64);
+        while unsafe { self.ring.submission().push(&entry) }.is_err() {
+            // the submission queue is full, hand the pending entries to the kernel to make room.
+            self.ring.submit()?;
+        }
+        self.ring.submit()?;
+        self.in_flight.insert(id);
+        Ok(())
+    }
+
+    /// Cancels the operations in flight, and waits for the kernel to be done with all of them,
+    /// so that the buffers they point at, e.g. on the stacks of threads, can be freed.
+    pub fn cancel_all(&mut self) -> io::Result<()> {
+        for &id in &self.in_flight {
+            let entry = opcode::AsyncCancel::new(id.0 as u64)
+                .build()
+                .user_data(CANCEL);
+            while unsafe { self.ring.submission().push(&entry) }.is_err() {
+                self.ring.submit()?;
+            }
+        }
+        // an operation which couldn't be cancelled, as it was already being carried out, completes on its own.
+        while self.is_busy() {
+            self.ring.submit_and_wait(1)?;
+            self.reap(&mut Vec::new());
+        }
+        Ok(())
+    }
+
+    /// Collects the completed operations and appends the threads that submitted them to `woken`.
+    pub fn reap(&mut self, woken: &mut Vec<Id>) {
+        for cqe in self.ring.completion() {
+            if cqe.user_data() == CANCEL {
+                continue;
+            }
+            let id = Id(cqe.user_data() as usize);
+            self.results.insert(id, cqe.result());
+            self.in_flight.remove(&id);
+            woken.push(id);
+        }
+    }
+
+    pub fn take_result(&mut self, id: Id) -> Option<i32> {
+        self.results.remove(&id)
+    }
+}
+
+fn cvt(res: i32) -> io::Result<usize> {
+    if res < 0 {
+        Err(io::Error::from_raw_os_error(-res))
+    } else {
+        Ok(res as usize)
+    }
+}
+
+/// Reads from `fd` into `buf` at the current file position,
+/// blocking the current thread until the read completes.
+/// Falls back to waiting for readiness and reading if io_uring is not available.
+pub fn uring_read(fd: RawFd, buf: &mut [u8]) -> io::Result<usize> {
+    if !uring_available() {
+        wait_io(fd, Interest::Readable)?;
+        let res = unsafe { libc::read(fd, buf.as_mut_ptr().cast(), buf.len()) };
+        return if res < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(res as usize)
+        };
+    }
+
+    let entry = opcode::Read::new(types::Fd(fd), buf.as_mut_ptr(), buf.len() as u32)
+        .offset(u64::MAX)
+        .build();
+    // the buffer is borrowed for as long as the thread is blocked on the operation.
+    cvt(unsafe { submit_io(entry)? })
+}
+
+/// Writes `buf` to `fd` at the current file position,
+/// blocking the current thread until the write completes.
+/// Falls back to waiting for readiness and writing if io_uring is not available.
+pub fn uring_write(fd: RawFd, buf: &[u8]) -> io::Result<usize> {
+    if !uring_available() {
+        wait_io(fd, Interest::Writable)?;
+        let res = unsafe { libc::write(fd, buf.as_ptr().cast(), buf.len()) };
+        return if res < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(res as usize)
+        };
+    }
+
+    let entry = opcode::Write::new(types::Fd(fd), buf.as_ptr(), buf.len() as u32)
+        .offset(u64::MAX)
+        .build();
+    // the buffer is borrowed for as long as the thread is blocked on the operation.
+    cvt(unsafe { submit_io(entry)? })
+}
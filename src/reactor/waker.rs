@@ -0,0 +1,131 @@
+use std::collections::HashSet;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::sync::Arc;
+
+use crate::thread::Id;
+
+/// Written to the pipe to interrupt the poller without unparking any thread.
+const NUDGE: usize = usize::MAX;
+
+/// Receiving end of the `RuntimeWaker`s.
+/// Each wakeup is a thread id written to a pipe, so that waking the runtime doesn't need any lock or allocation.
+pub(crate) struct WakePipe {
+    read: OwnedFd,
+    write: Arc<OwnedFd>,
+    /// Whether the read end is currently registered with the poller.
+    armed: bool,
+    /// Threads waiting to be unparked.
+    parked: HashSet<Id>,
+    /// Threads unparked before they parked, which shouldn't block on their next `park`.
+    tokens: HashSet<Id>,
+}
+
+/// A handle that lets other OS threads unpark a green thread or nudge the scheduler.
+///
+/// Waking is a single `write` to a pipe and doesn't touch the runtime,
+/// so the handle can be used from any OS thread and from signal handlers.
+#[derive(Debug, Clone)]
+pub struct RuntimeWaker {
+    write: Arc<OwnedFd>,
+}
+
+impl WakePipe {
+    pub fn new() -> io::Result<Self> {
+        let mut fds = [0; 2];
+        #[cfg(target_os = "linux")]
+        let res = unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_NONBLOCK | libc::O_CLOEXEC) };
+        #[cfg(not(target_os = "linux"))]
+        let res = unsafe { libc::pipe(fds.as_mut_ptr()) };
+        if res < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let (read, write) = unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+        #[cfg(not(target_os = "linux"))]
+        {
+            super::doorbell::set_flags(read.as_raw_fd())?;
+            super::doorbell::set_flags(write.as_raw_fd())?;
+        }
+
+        Ok(WakePipe {
+            read,
+            write: Arc::new(write),
+            armed: false,
+            parked: HashSet::new(),
+            tokens: HashSet::new(),
+        })
+    }
+
+    pub fn fd(&self) -> RawFd {
+        self.read.as_raw_fd()
+    }
+
+    pub fn waker(&self) -> RuntimeWaker {
+        RuntimeWaker {
+            write: self.write.clone(),
+        }
+    }
+
+    pub fn has_parked(&self) -> bool {
+        !self.parked.is_empty()
+    }
+
+    /// Parks thread `id` until it is unparked.
+    /// Returns false if it was already unparked, in which case it shouldn't block.
+    pub fn park(&mut self, id: Id) -> bool {
+        if self.tokens.remove(&id) {
+            return false;
+        }
+        self.parked.insert(id);
+        true
+    }
+
+    /// Returns true if the read end needs to be (re-)registered with the poller.
+    pub fn needs_arming(&mut self) -> bool {
+        !std::mem::replace(&mut self.armed, true)
+    }
+
+    /// Empties the pipe once the poller reported it readable, appending the unparked threads to `woken`.
+    pub fn drain(&mut self, woken: &mut Vec<Id>) {
+        self.armed = false;
+
+        let mut buf = [0_u8; 512];
+        loop {
+            let n = unsafe { libc::read(self.fd(), buf.as_mut_ptr().cast(), buf.len()) };
+            if n <= 0 {
+                break;
+            }
+            // writes of up to PIPE_BUF bytes are atomic, so ids are never split.
+            for chunk in buf[..n as usize].chunks_exact(size_of::<usize>()) {
+                let id = Id(usize::from_ne_bytes(chunk.try_into().unwrap()));
+                if id.0 == NUDGE {
+                    continue;
+                }
+                if self.parked.remove(&id) {
+                    woken.push(id);
+                } else {
+                    self.tokens.insert(id);
+                }
+            }
+        }
+    }
+}
+
+impl RuntimeWaker {
+    /// Unparks green thread `id` if it is blocked in `runtime::park`,
+    /// or makes its next call to `park` return immediately otherwise.
+    pub fn unpark(&self, id: Id) {
+        self.send(id.0);
+    }
+
+    /// Interrupts the runtime if it is blocked waiting for events, without waking up any thread.
+    pub fn wake(&self) {
+        self.send(NUDGE);
+    }
+
+    fn send(&self, val: usize) {
+        // the wakeup is dropped if the pipe is full, as blocking here could deadlock the caller.
+        let buf = val.to_ne_bytes();
+        unsafe { libc::write(self.write.as_raw_fd(), buf.as_ptr().cast(), buf.len()) };
+    }
+}
@@ -1,56 +1,802 @@
-use core::arch::asm;
-use core::fmt::Debug;
+mod budget;
+mod chaos;
+mod error;
+#[cfg(feature = "std")]
+mod explore;
+#[cfg(feature = "std")]
+mod handle;
+mod hooks;
+#[cfg(feature = "tokio")]
+mod host;
+mod limit;
+mod metrics;
+mod rng;
+#[cfg(feature = "std")]
+mod schedule;
+mod starvation;
+#[cfg(feature = "std")]
+mod watchdog;
 
-use crate::channel::Channel;
-use crate::thread::{Context, Id, State, Thread};
-use crate::{BASE_THREAD_ID, DEBUG, RUNTIME};
+use alloc::boxed::Box;
+#[cfg(not(feature = "std"))]
+use alloc::collections::BTreeMap as HashMap;
+use alloc::rc::{Rc, Weak};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::any::Any;
+use core::cell::RefCell;
+use core::fmt;
+use core::ptr::NonNull;
+use core::task::{Context as TaskContext, Poll};
+use core::time::Duration;
+#[cfg(feature = "std")]
+use std::backtrace::Backtrace;
+#[cfg(feature = "std")]
+use std::collections::HashMap;
+#[cfg(feature = "std")]
+use std::fs::File;
+#[cfg(feature = "std")]
+use std::io;
+#[cfg(feature = "std")]
+use std::os::fd::RawFd;
+#[cfg(feature = "std")]
+use std::panic::{self, AssertUnwindSafe};
+#[cfg(feature = "std")]
+use std::sync::Arc;
+
+use budget::Budget;
+pub use chaos::{Chaos, ParseChaosError};
+pub use error::RuntimeError;
+#[cfg(feature = "std")]
+use explore::Exploration;
+#[cfg(feature = "std")]
+pub use explore::{explore, Bounds, Explored};
+#[cfg(feature = "std")]
+pub use handle::Handle;
+#[cfg(feature = "std")]
+use handle::Injector;
+use hooks::Hooks;
+use limit::Limit;
+pub use limit::WhenFull;
+pub use metrics::{
+    Blocked, BlockedOn, ChannelMetrics, MemoryStats, Metrics, StackUsage, ThreadInfo, ThreadMemory,
+};
+use rng::Rng;
+#[cfg(feature = "std")]
+pub use schedule::{ParseScheduleError, Schedule};
+#[cfg(feature = "std")]
+use schedule::{Recording, Replay};
+use starvation::Starvation;
+#[cfg(feature = "std")]
+use watchdog::Watchdog;
+
+use crate::arch::{Arch, Context};
+use crate::channel::{Channel, ChannelError, Overflow, Permit, Stats};
+use crate::debugger;
+use crate::preempt::{self, NoPreempt};
+use crate::reactor::Reactor;
+#[cfg(feature = "std")]
+use crate::reactor::{Interest, Notifier, RuntimeWaker};
+use crate::thread::{Id, LeaveChan, Priority, Stack, StackAllocator, State, Thread};
+use crate::time::{self, Clock, Instant};
+use crate::trace::event;
+#[cfg(feature = "valgrind")]
+use crate::valgrind;
+#[cfg(feature = "std")]
+use crate::{crash, dump, errno};
+use crate::{
+    expect_runtime, runtime, set_runtime, BASE_THREAD_ID, DEFAULT_STACK_SIZE, DEFAULT_TIME_SLICE,
+    LIFO_LIMIT, STACK_POOL_SIZE,
+};
+#[cfg(feature = "std")]
+use crate::{
+    overflow::{self, StackFault},
+    thread::MmapAllocator,
+    time::SystemClock,
+    PREEMPTED_MAX, STACK_SHRINK_MARGIN,
+};
 
 /// Represents a Runtime.
+/// Only a handle to the state shared by its threads (see `Core`), which lives on the heap.
 pub struct Runtime {
-    /// All active threads, i.e, which haven't completed.
-    /// Can store threads that are not currently running,
-    /// but are waiting to be chosen by the runtime or for some other event to occur.
-    threads: Vec<Thread>,
-    /// Id of thread that is currently running.
-    current: Id,
-    /// Shows the total number of threads created up until a certain point.
-    /// Used to generate unique thread IDs for threads spawned by a runtime.
-    count: usize,
+    core: NonNull<Core>,
 }
 
 impl Runtime {
+    /// Creates a runtime.
+    /// Panics if it can't be, see `try_new`.
+    #[cfg(feature = "std")]
     pub fn new() -> Self {
-        let base_thread = Thread::new(BASE_THREAD_ID, State::Running);
+        Runtime::with_stack_allocator(MmapAllocator)
+    }
 
-        Runtime {
-            threads: vec![base_thread],
-            current: BASE_THREAD_ID,
-            count: 1,
+    /// Creates a runtime, or returns why it can't be.
+    #[cfg(feature = "std")]
+    pub fn try_new() -> Result<Self, RuntimeError> {
+        Runtime::try_with_stack_allocator(MmapAllocator)
+    }
+
+    /// Creates a runtime getting the stacks of its threads from `stack_allocator`.
+    /// Panics if it can't be, see `try_with_stack_allocator`.
+    pub fn with_stack_allocator(stack_allocator: impl StackAllocator + 'static) -> Self {
+        Runtime::try_with_stack_allocator(stack_allocator).unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// Creates a runtime getting the stacks of its threads from `stack_allocator`, or returns why it can't be.
+    pub fn try_with_stack_allocator(
+        stack_allocator: impl StackAllocator + 'static,
+    ) -> Result<Self, RuntimeError> {
+        let core = Box::leak(Box::new(Core::new(Box::new(stack_allocator))?));
+        core.publish();
+        let runtime = Runtime {
+            core: NonNull::from(core),
+        };
+        #[cfg(feature = "std")]
+        let runtime = runtime.configured_from_env();
+        Ok(runtime)
+    }
+
+    // Applies the settings given through the environment, see `set_seed`, `set_chaos`, `record` and `replay`.
+    #[cfg(feature = "std")]
+    fn configured_from_env(mut self) -> Self {
+        // lets a failing run be replayed without changing the code, see `set_seed`.
+        if let Some(seed) = std::env::var("UTHREADS_SEED")
+            .ok()
+            .and_then(|s| s.parse().ok())
+        {
+            self.set_seed(Some(seed));
+        }
+        if let Some(chaos) = std::env::var("UTHREADS_CHAOS")
+            .ok()
+            .and_then(|s| s.parse().ok())
+        {
+            self.set_chaos(Some(chaos));
+        }
+        // likewise for recording and replaying schedules, see `record` and `replay`.
+        if let Some(path) = std::env::var_os("UTHREADS_REPLAY") {
+            let schedule = Schedule::load(&path).unwrap_or_else(|err| {
+                panic!("failed to load the schedule to replay from {path:?}: {err}")
+            });
+            self.replay(schedule);
         }
+        if let Some(path) = std::env::var_os("UTHREADS_RECORD") {
+            let file = File::create(&path).unwrap_or_else(|err| {
+                panic!("failed to create the schedule recording {path:?}: {err}")
+            });
+            unsafe { self.core.as_mut().recording = Some(Recording::new(Some(file))) };
+        }
+        self
     }
 
-    // Set the global RUNTIME to current Runtime.
+    // Set the RUNTIME of the current OS thread to the core of the current Runtime.
     // This is done to avoid having to pass the Runtime struct to every function.
     // Note that the Runtime will have to be initialised before using it.
     // Also, in most cases, we only need to initialise it once and then destroy it when it's no longer needed,
     // i.e, once all the required tasks are completed. TODO
-    pub unsafe fn init(&self) {
+    /// # Safety
+    ///
+    /// No other Runtime may be initialised on the same OS thread while this one is in use,
+    /// and without std, no other Runtime at all.
+    /// Panics if the handler of stack overflows can't be installed, or if threads can't be switched, see `try_init`.
+    pub unsafe fn init(&mut self) {
+        unsafe { self.try_init() }.unwrap_or_else(|err| panic!("{err}"));
+    }
+
+    /// Initialises the runtime like `init`, but returns an error if the handler of stack overflows can't be installed,
+    /// or if threads can't be switched on the current OS thread, see `RuntimeError::Unsupported`.
+    ///
+    /// # Safety
+    ///
+    /// See `init`.
+    pub unsafe fn try_init(&mut self) -> Result<(), RuntimeError> {
+        if let Some(reason) = Context::unsupported() {
+            return Err(RuntimeError::Unsupported(reason));
+        }
+        set_runtime(self.core.as_ptr());
+        #[cfg(feature = "std")]
+        {
+            crash::install();
+            overflow::install().map_err(RuntimeError::OverflowHandler)?;
+        }
+        Ok(())
+    }
+
+    /// Sets how long a thread may run before the runtime makes it yield, None to never do so.
+    /// The runtime only checks this when a thread calls into it (e.g. to use a channel, spawn a thread or do IO),
+    /// so a thread that never does keeps running until it yields, unless preemption is enabled (see `preempt`).
+    pub fn set_time_slice(&mut self, time_slice: Option<Duration>) {
+        unsafe { self.core.as_mut().time_slice = time_slice };
+    }
+
+    /// Makes the runtime pick the next thread to run at random among the ready ones, with a generator seeded by `seed`,
+    /// instead of in turn. None goes back to taking turns.
+    /// The same program then interleaves its threads the same way every time it is run with the same seed,
+    /// so that a seed found to trigger a bug in tests reproduces it, while trying many seeds explores many interleavings.
+    /// That only holds as long as the threads don't depend on time or on the outside world:
+    /// time slices should be disabled (see `set_time_slice` and `preempt`), and IO or timers make the order vary again.
+    /// Also enabled by setting the `UTHREADS_SEED` environment variable when the runtime is created.
+    pub fn set_seed(&mut self, seed: Option<u64>) {
+        unsafe { self.core.as_mut().rng = seed.map(Rng::new) };
+    }
+
+    /// Enables the chaos mode, meant for tests, or disables it with None.
+    /// The runtime then yields on behalf of the running thread at random whenever it calls into it
+    /// (e.g. to use a channel, spawn a thread or do IO), and picks at random which of the threads woken up runs next,
+    /// to shake out code that only works because of the order the threads happen to run in.
+    /// The random choices are made from `chaos.seed`, so that a failure reproduces with the same seed,
+    /// as long as the program meets the conditions given in `set_seed`.
+    /// Also enabled by setting the `UTHREADS_CHAOS` environment variable when the runtime is created,
+    /// to the probability to yield, optionally followed by a comma and the seed (e.g. `0.2,42`).
+    pub fn set_chaos(&mut self, chaos: Option<Chaos>) {
         unsafe {
-            RUNTIME = self as *const _ as *mut _;
+            self.core.as_mut().chaos = chaos.map(|chaos| (chaos.probability, Rng::new(chaos.seed)))
+        };
+    }
+
+    /// Watches the threads from an OS thread of its own, reporting those which run for longer than `threshold`
+    /// without yielding, or stops watching them with None. Such a thread keeps all the others from running,
+    /// e.g. as it loops without calling into the runtime, or makes a blocking call.
+    /// Every such run is reported once, with an event of kind `Stall` at the `Info` level, see `trace`.
+    /// In debug builds on Linux, the event also tells where the thread was running, sampled by interrupting
+    /// the OS thread of the runtime with SIGURG, whose handler is replaced: the function, if its symbol is exported,
+    /// or else the offset in the executable, which `addr2line -f -i -C -e <executable> <offset>` resolves.
+    #[cfg(feature = "std")]
+    pub fn set_watchdog(&mut self, threshold: Option<Duration>) {
+        let core = unsafe { self.core.as_mut() };
+        core.watchdog = threshold.map(Watchdog::start);
+        if let Some(watchdog) = &core.watchdog {
+            watchdog.switched(core.current);
         }
     }
 
+    /// Reports the threads which have been ready to run for longer than `threshold` without getting their turn,
+    /// and has them run next, ahead of the others, or stops doing so with None.
+    /// Threads wait that long as many others are ready ahead of them, each running for up to a time slice,
+    /// or as they are over the CPU budget of their group (see `Group::set_cpu_quota`):
+    /// running them ahead once in a while makes sure that every thread makes progress eventually.
+    /// Every such wait is reported once, with an event of kind `Starve` at the `Info` level, see `trace`.
+    /// Threads picked at random or replayed are left alone, see `set_seed`, `set_chaos` and `replay`.
+    pub fn set_starvation_threshold(&mut self, threshold: Option<Duration>) {
+        unsafe { self.core.as_mut().starvation = threshold.map(Starvation::new) };
+    }
+
+    /// Makes the runtime overwrite the stacks of completed threads with zeroes before they are reused,
+    /// whether by another thread of the runtime or by the stack allocator, so that what a thread left on its stack,
+    /// e.g. key material, can't be read by the next one running there. Only the part the thread used is overwritten,
+    /// but that still costs some time as every thread completes.
+    pub fn set_zero_stacks(&mut self, zero: bool) {
+        unsafe { self.core.as_mut().zero_stacks = zero };
+    }
+
+    /// Makes the timers of the runtime (`time::sleep`, the deadlines of contexts, `Select::timeout`...) go by `clock`
+    /// instead of the system's clock, e.g. a `MockClock` advanced by hand in tests. `SystemClock` goes back to the latter.
+    /// Should be set before any thread sleeps, as the deadlines already set stay as they were.
+    /// Deadlines are computed from `time::now`, which reads the clock of the runtime.
+    /// Without std, the runtime starts with a `MockClock`, which moves to the timers as no thread is left to run:
+    /// the clock of the platform (e.g. a hardware counter) should be set before any thread sleeps.
+    pub fn set_clock(&mut self, clock: impl Clock + 'static) {
+        // the timers wait for the system's clock more precisely, knowing it is that one, see `Timers`.
+        #[cfg(feature = "std")]
+        let clock =
+            (!(&clock as &dyn Any).is::<SystemClock>()).then(|| Box::new(clock) as Box<dyn Clock>);
+        #[cfg(not(feature = "std"))]
+        let clock = Some(Box::new(clock) as Box<dyn Clock>);
+        unsafe { self.core.as_mut().reactor.set_clock(clock) };
+    }
+
+    /// Starts recording the decisions of the scheduler, from scratch, to be returned by `schedule`.
+    /// Also enabled by setting the `UTHREADS_RECORD` environment variable to a path when the runtime is created,
+    /// in which case the decisions are written there as they are made, so that they are kept even if the process crashes.
+    #[cfg(feature = "std")]
+    pub fn record(&mut self) {
+        unsafe { self.core.as_mut().recording = Some(Recording::new(None)) };
+    }
+
+    /// Returns the decisions of the scheduler recorded so far, None if not recording.
+    #[cfg(feature = "std")]
+    pub fn schedule(&self) -> Option<Schedule> {
+        unsafe { self.core.as_ref() }
+            .recording
+            .as_ref()
+            .map(|recording| recording.schedule().clone())
+    }
+
+    /// Makes the runtime switch threads as recorded in `schedule`, instead of picking them itself,
+    /// so that a run recorded once (e.g. when a test failed) takes the same course again.
+    /// As with `set_seed`, the program must not depend on time or on the outside world for that to work.
+    /// Panics if the run diverges, i.e. a thread to switch to isn't ready when its turn comes.
+    /// Once the whole schedule has been replayed, the runtime goes back to picking threads itself.
+    /// Also enabled by setting the `UTHREADS_REPLAY` environment variable to the path of a saved schedule
+    /// when the runtime is created.
+    #[cfg(feature = "std")]
+    pub fn replay(&mut self, schedule: Schedule) {
+        unsafe { self.core.as_mut().replay = Some(Replay::new(schedule)) };
+    }
+
     pub fn run(&mut self) {
-        if DEBUG {
-            println!("started running from thread: {:?}", self.current);
+        self.drive(|| false, None);
+    }
+
+    /// Runs one thread ready to run, until it blocks, yields or completes, and returns whether there was one,
+    /// so that the threads can make progress from a loop of the caller's own, e.g. a frame of a game or a step of a simulation.
+    /// Picks up the IO which is ready, the timers which went off and the closures spawned through handles first,
+    /// but never waits for them: the runtime doesn't block the caller.
+    pub fn step(&mut self) -> bool {
+        let core = self.core.as_ptr();
+        // the scheduler itself is never preempted.
+        let _no_preempt = NoPreempt::new();
+        unsafe {
+            (*core).poll_io(Some(Duration::ZERO));
+            #[cfg(feature = "std")]
+            (*core).inject();
+            (*core).stepping = true;
+            let ran = switch_away(core);
+            (*core).stepping = false;
+            ran
+        }
+    }
+
+    /// Runs the threads, one `step` at a time, until none is ready to run, and returns how many steps it took.
+    /// Unlike `run`, returns rather than waiting as the threads left are blocked, e.g. on timers or IO,
+    /// and doesn't call the idle hooks (see `on_idle`): calling it again later runs those which have been woken up since.
+    pub fn run_until_idle(&mut self) -> usize {
+        let mut steps = 0;
+        while self.step() {
+            steps += 1;
         }
+        steps
+    }
+
+    /// Runs the threads left for at most `timeout`, e.g. once they have been told to stop through their context
+    /// (see `Group::cancel`), and then shuts the runtime down. The threads which haven't completed by then are abandoned:
+    /// none of their code runs again, and their stacks are freed without unwinding them, so what they own is leaked.
+    /// The io_uring operations they were blocked on are cancelled first, so that the kernel is done with their buffers.
+    /// Returns whether every thread completed in time, so that shutting down can't hang on a thread which never stops.
+    /// A thread running without yielding still holds the runtime up until it does, see `set_time_slice`.
+    pub fn shutdown_timeout(mut self, timeout: Duration) -> bool {
+        self.drive(|| false, Some(time::instant() + timeout));
+        let core = unsafe { self.core.as_ref() };
+        for t in core.threads.iter().filter(|t| t.id != BASE_THREAD_ID) {
+            event!(Info, Exit, t.id, "abandoned at shutdown");
+        }
+        core.threads.len() == 1
+    }
+
+    /// Runs `f` on a thread of its own, along with the other threads, until it completes, and returns what it returned.
+    /// Unlike `run`, returns as soon as `f` has completed: the threads left are run by the next call to `run` or `block_on`.
+    /// A panic of `f` is resumed on the caller, while a panic of the other threads aborts the process, as anywhere in the runtime.
+    /// Without std, panics can't be caught, so that of `f` goes to the panic handler right away too.
+    /// Panics if the runtime runs out of threads to run before `f` has completed, e.g. as its thread is blocked on a channel,
+    /// or if it is shut down meanwhile, see `Handle::shutdown`.
+    pub fn block_on<T: 'static>(&mut self, f: impl FnOnce() -> T + 'static) -> T {
+        let result = Rc::new(RefCell::new(None));
+        let slot = result.clone();
+        #[cfg(feature = "std")]
+        let f = move || panic::catch_unwind(AssertUnwindSafe(f));
+        self.spawn(move || *slot.borrow_mut() = Some(f()));
+        self.drive(|| result.borrow().is_some(), None);
+        match result.take() {
+            #[cfg(feature = "std")]
+            Some(Ok(value)) => value,
+            #[cfg(feature = "std")]
+            Some(Err(payload)) => panic::resume_unwind(payload),
+            #[cfg(not(feature = "std"))]
+            Some(value) => value,
+            None => panic!(
+                "the runtime ran out of threads to run, or was shut down, before the thread of `block_on` completed"
+            ),
+        }
+    }
+
+    /// Runs the threads until thread `id` has completed, like the main goroutine of a Go program,
+    /// and returns as soon as it has, leaving the other threads behind, e.g. those serving in the background:
+    /// they are run by the next call to `run` or `block_on`.
+    /// Returns false if the runtime ran out of threads to run before, e.g. as thread `id` is blocked on a channel,
+    /// or if it was shut down meanwhile, see `Handle::shutdown`.
+    pub fn run_until(&mut self, id: Id) -> bool {
+        let core = self.core.as_ptr();
+        let exited = || unsafe { (*core).threads.iter().all(|t| t.id != id) };
+        self.drive(exited, None);
+        exited()
+    }
+
+    // Runs the threads until `done` returns true, checked every time the control comes back to the base thread,
+    // until none is left to run, or until `deadline` has passed, if any.
+    fn drive(&mut self, done: impl Fn() -> bool, deadline: Option<Instant>) {
+        let core = self.core.as_ptr();
+        event!(Info, Run, unsafe { (*core).current }, "started running");
         // This is run on the main thread. It doesn't run any user code.
         // All it does is check if there are any pending threads that can be immediately run
-        // and then pass on the control to such a thread, if present. If not, the runtime is closed.
-        // As such, we stop the runtime when no immediately runnable threads are found.
-        // But we ideally should wait for threads waiting on external events to complete.
-        // Or introduce a timeout. TODO
-        while self.yield_thread() {}
+        // and then pass on the control to such a thread, if present.
+        // Every time the control comes back, threads waiting on IO that is now ready are made runnable again.
+        // If no thread can be run immediately, the OS thread blocks in the reactor until some IO is ready,
+        // a timer goes off or a thread is unparked, so an idle runtime doesn't use any CPU.
+        // As such, we stop the runtime only when there are neither runnable threads nor threads waiting on any of those.
+        // the scheduler itself is never preempted.
+        // Closures spawned through handles are spawned as threads along the way, and the runtime waits for them as long as handles exist.
+        let _no_preempt = NoPreempt::new();
+        loop {
+            unsafe { (*core).poll_io(Some(Duration::ZERO)) };
+            #[cfg(feature = "std")]
+            unsafe {
+                (*core).inject()
+            };
+            if done()
+                || unsafe { (*core).is_shut_down() }
+                || deadline.is_some_and(|deadline| time::instant() >= deadline)
+            {
+                break;
+            }
+            if unsafe { switch_away(core) } {
+                continue;
+            }
+            // the idle hooks may have made threads ready.
+            if unsafe { (*core).hooks.idle() } {
+                unsafe {
+                    (*core).poll_io(Some(Duration::ZERO));
+                    #[cfg(feature = "std")]
+                    (*core).inject();
+                }
+                if unsafe { switch_away(core) } {
+                    continue;
+                }
+            }
+            // handles may still spawn threads, see `handle`.
+            #[cfg(feature = "std")]
+            let held = unsafe { (*core).injector.has_handles() };
+            #[cfg(not(feature = "std"))]
+            let held = false;
+            if !unsafe { (*core).reactor.has_waiters() } && !held {
+                break;
+            }
+            unsafe {
+                #[cfg(feature = "std")]
+                (*core).reactor.set_held(held);
+                (*core).poll_io(
+                    deadline.map(|deadline| deadline.saturating_duration_since(time::instant())),
+                );
+            }
+        }
+    }
+
+    /// Spawns a thread running `f`, and returns its id.
+    /// Panics if too many threads are alive, see `set_max_threads`.
+    pub fn create_thread(&mut self, f: fn()) -> Id {
+        unsafe { reserve_thread(self.core.as_ptr()) }.unwrap_or_else(|err| panic!("{err}"));
+        unsafe { self.core.as_mut().create_thread(f) }
+    }
+
+    /// Spawns a thread running the closure `f`, which may capture values, unlike the function passed to `create_thread`.
+    /// Returns the id of the thread, e.g. to look up its stack usage.
+    /// Panics if too many threads are alive, see `set_max_threads`.
+    pub fn spawn(&mut self, f: impl FnOnce() + 'static) -> Id {
+        self.try_spawn(f).unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// Spawns a thread running `f` with `arg`, and returns its id, e.g. for a worker serving the connection passed.
+    /// Panics if too many threads are alive, see `set_max_threads`.
+    pub fn spawn_with<T: 'static>(&mut self, f: fn(T), arg: T) -> Id {
+        self.spawn(move || f(arg))
+    }
+
+    /// Spawns a thread running the closure `f` and returns its id, unless too many threads are alive, see `set_max_threads`.
+    pub fn try_spawn(&mut self, f: impl FnOnce() + 'static) -> Result<Id, RuntimeError> {
+        unsafe { reserve_thread(self.core.as_ptr())? };
+        Ok(unsafe { self.core.as_mut().spawn(Box::new(f)) })
+    }
+
+    /// Spawns a thread running the closure `f` at `priority` rather than `Priority::NORMAL`, and returns its id.
+    /// The threads of a lower priority only run once every thread of a higher one is blocked or completed,
+    /// e.g. for background work that mustn't hold up the threads serving requests.
+    /// Panics if too many threads are alive, see `set_max_threads`.
+    pub fn spawn_with_priority(&mut self, priority: Priority, f: impl FnOnce() + 'static) -> Id {
+        unsafe { reserve_thread(self.core.as_ptr()) }.unwrap_or_else(|err| panic!("{err}"));
+        unsafe {
+            self.core
+                .as_mut()
+                .spawn_with_priority(priority, Box::new(f))
+        }
+    }
+
+    /// Changes the priority of the thread with the given id, whether it is ready or blocked, see `ThreadHandle::set_priority`.
+    /// Returns false if there is no such thread, as it completed or was never spawned.
+    pub fn set_priority(&mut self, id: Id, priority: Priority) -> bool {
+        unsafe { self.core.as_mut().set_priority(id, priority) }
+    }
+
+    /// Limits how many threads may be alive at once, the base thread aside, or lifts the limit with None.
+    /// Spawning a thread past the limit then fails or blocks until another thread completes, as `when_full` says:
+    /// `try_spawn` returns the error, while `spawn` panics with it.
+    /// Keeps e.g. a server spawning a thread for every connection it accepts from running out of memory under load.
+    /// The threads spawned through handles (see `handle`) count towards the limit, but are never held back by it.
+    pub fn set_max_threads(&mut self, max: Option<usize>, when_full: WhenFull) {
+        let core = unsafe { self.core.as_mut() };
+        // the threads waiting for the previous limit check again against the new one.
+        let waiters = core.limit.take().map(|limit| limit.waiters);
+        for id in waiters.into_iter().flatten() {
+            core.change_thread_state(id, State::Ready);
+        }
+        core.limit = max.map(|max| Limit::new(max, when_full));
+    }
+
+    /// Returns a handle that any OS thread can use to spawn threads on this runtime, look at its activity or stop it.
+    /// `run` then doesn't return until every handle has been dropped, as they may still spawn threads,
+    /// or until the runtime is shut down through one, see `Handle::shutdown`.
+    #[cfg(feature = "std")]
+    pub fn handle(&self) -> Handle {
+        let core = unsafe { self.core.as_ref() };
+        Handle::new(core.injector.clone(), core.reactor.waker())
+    }
+
+    /// Returns how much of its stack the thread with the given id has used at most, as far as can be told.
+    /// Only whole pages are counted, and only since the stack was last shrunk (see `shrink_stack`).
+    /// None if there is no such thread, or if it runs on the stack of the OS thread, like the base thread.
+    #[cfg(feature = "std")]
+    pub fn stack_usage(&self, id: Id) -> Option<usize> {
+        unsafe { self.core.as_ref().stack_usage(id) }
+    }
+
+    /// Returns a handle that other OS threads can use to wake up this runtime.
+    #[cfg(feature = "std")]
+    pub fn waker(&self) -> RuntimeWaker {
+        unsafe { self.core.as_ref().reactor.waker() }
+    }
+
+    /// Returns a snapshot of the activity of the runtime, e.g. to monitor it.
+    pub fn metrics(&self) -> Metrics {
+        unsafe { self.core.as_ref().metrics() }
+    }
+
+    /// Returns how much memory the runtime holds for its threads and channels, in total and per thread,
+    /// e.g. to tell how many threads a server can afford from how much each one takes under load.
+    /// Looks at every stack, so it takes longer the more threads there are.
+    pub fn memory_stats(&self) -> MemoryStats {
+        unsafe { self.core.as_ref().memory_stats() }
+    }
+
+    /// Returns a snapshot of every live thread, the base thread included: its state and what it is blocked on,
+    /// e.g. to list what the threads are doing from a debug endpoint. See `dump_threads` for the same, as text.
+    pub fn threads(&self) -> Vec<ThreadInfo> {
+        unsafe { self.core.as_ref().threads() }
+    }
+
+    /// Registers `hook` to be called with the id of every thread spawned, before it first runs.
+    /// Hooks are called from within the runtime, on the thread that triggered them,
+    /// so they must not call into it (e.g. spawn a thread, use a channel or yield).
+    pub fn on_spawn(&mut self, hook: impl FnMut(Id) + 'static) {
+        unsafe { self.core.as_mut().hooks.add_spawn(Box::new(hook)) };
+    }
+
+    /// Registers `hook` to be called with the ids of the threads switched from and to, right before every switch.
+    /// See `on_spawn` about what hooks may do.
+    pub fn on_switch(&mut self, hook: impl FnMut(Id, Id) + 'static) {
+        unsafe { self.core.as_mut().hooks.add_switch(Box::new(hook)) };
+    }
+
+    /// Registers `hook` to be called with the id of every thread blocking, and the state it blocks in,
+    /// which tells what it waits on (e.g. `State::IoBlocked`).
+    /// See `on_spawn` about what hooks may do.
+    pub fn on_block(&mut self, hook: impl FnMut(Id, &State) + 'static) {
+        unsafe { self.core.as_mut().hooks.add_block(Box::new(hook)) };
+    }
+
+    /// Registers `hook` to be called with the id of every blocked thread made ready to run again,
+    /// as what it waited on happened.
+    /// See `on_spawn` about what hooks may do.
+    pub fn on_wake(&mut self, hook: impl FnMut(Id) + 'static) {
+        unsafe { self.core.as_mut().hooks.add_wake(Box::new(hook)) };
+    }
+
+    /// Registers `hook` to be called with the id of every thread completing, right before it is switched away from.
+    /// See `on_spawn` about what hooks may do.
+    pub fn on_exit(&mut self, hook: impl FnMut(Id) + 'static) {
+        unsafe { self.core.as_mut().hooks.add_exit(Box::new(hook)) };
+    }
+
+    /// Registers `hook` to be called whenever no thread is ready to run, before the runtime waits for IO or timers,
+    /// or returns as nothing is left to wait for, e.g. to pump the event loop of a GUI or a queue fed from C.
+    /// The threads it makes ready, by waking them through a `RuntimeWaker` or spawning through a `Handle`, run right away.
+    /// See `on_spawn` about what else hooks may do.
+    pub fn on_idle(&mut self, hook: impl FnMut() + 'static) {
+        unsafe { self.core.as_mut().hooks.add_idle(Box::new(hook)) };
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for Runtime {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for Runtime {
+    fn drop(&mut self) {
+        if runtime() == self.core.as_ptr() {
+            set_runtime(core::ptr::null_mut());
+        }
+        unsafe { drop(Box::from_raw(self.core.as_ptr())) };
+    }
+}
+
+// The state shared by the threads of a runtime, which every one of them gets to through RUNTIME.
+// As a thread can't tell when the others run, the following has to hold for the accesses to be sound:
+// - a reference to the core, or to any part of it, never lives across a switch.
+//   The other threads take references of their own while this one is switched out, which would alias it.
+//   This is why switching is done by `switch_away` and `done`, which only borrow the core around the switch.
+// - the contexts are handed to `Context::switch` as raw pointers, which it is done with before any other thread runs:
+//   the threads are moved around as they are created and completed.
+// - only the OS thread the runtime runs on touches the core.
+//   The fault handler is the exception, as it can interrupt any thread, and only looks at the stacks to find the faulting one.
+pub(crate) struct Core {
+    /// All active threads, i.e, which haven't completed.
+    /// Can store threads that are not currently running,
+    /// but are waiting to be chosen by the runtime or for some other event to occur.
+    threads: Vec<Thread>,
+    /// Id of thread that is currently running.
+    current: Id,
+    /// Shows the total number of threads created up until a certain point.
+    /// Used to generate unique thread IDs for threads spawned by a runtime.
+    count: usize,
+    /// Watches the file descriptors that threads are waiting on.
+    reactor: Reactor,
+    /// When the current thread was last switched to.
+    scheduled_at: Instant,
+    /// How long a thread may run before the runtime entry points it calls yield on its behalf, if limited.
+    time_slice: Option<Duration>,
+    /// The last thread that completed, kept around until the runtime has switched away from its stack.
+    exited: Option<Thread>,
+    /// Stacks of completed threads, reused for the threads spawned next.
+    stacks: Vec<Stack>,
+    /// Provides the stacks of the threads.
+    stack_allocator: Box<dyn StackAllocator>,
+    /// Called as the runtime makes scheduling decisions.
+    hooks: Hooks,
+    /// How many times the runtime switched from one thread to another.
+    switches: u64,
+    /// How many times a thread blocked on a channel.
+    channel_waits: u64,
+    /// The activity of the named channels, which may have been dropped since, see `Channel::set_name`.
+    channels: Vec<Weak<Stats>>,
+    /// Whether the runtime is running a single thread, and the last thread it ran so, see `Runtime::step`.
+    stepping: bool,
+    stepped: Id,
+    /// Picks the next thread to run when seeded, see `Runtime::set_seed`.
+    rng: Option<Rng>,
+    /// The decisions of the scheduler, if recorded, see `Runtime::record`.
+    #[cfg(feature = "std")]
+    recording: Option<Recording>,
+    /// The schedule being replayed, if any, see `Runtime::replay`.
+    #[cfg(feature = "std")]
+    replay: Option<Replay>,
+    /// How likely the entry points of the runtime are to yield, and what decides, in chaos mode (see `Runtime::set_chaos`).
+    chaos: Option<(f64, Rng)>,
+    /// The schedule being tried, when exploring them (see `explore`).
+    #[cfg(feature = "std")]
+    exploration: Option<Exploration>,
+    /// The closures of the threads spawned with `spawn` which haven't started yet.
+    closures: HashMap<Id, Box<dyn FnOnce()>>,
+    /// The closures spawned through handles, see `Runtime::handle`.
+    #[cfg(feature = "std")]
+    injector: Arc<Injector>,
+    /// Reports the threads running for too long without yielding, if enabled, see `Runtime::set_watchdog`.
+    #[cfg(feature = "std")]
+    watchdog: Option<Watchdog>,
+    /// Whether the stacks of completed threads are zeroed before they are reused, see `Runtime::set_zero_stacks`.
+    zero_stacks: bool,
+    /// Runs the threads waiting for too long ahead of the others, if enabled, see `Runtime::set_starvation_threshold`.
+    starvation: Option<Starvation>,
+    /// How many threads may be alive at once, if limited, see `Runtime::set_max_threads`.
+    limit: Option<Limit>,
+    /// The thread last woken up by another one, e.g. by sending it a value, which runs next
+    /// rather than waiting for its turn, as it likely has the data it needs still in cache.
+    lifo: Option<Id>,
+    /// How many times in a row the thread in `lifo` was run ahead of the others, up to `LIFO_LIMIT`.
+    lifo_streak: u32,
+    /// The CPU budgets of groups of threads, by key, see `Group::set_cpu_quota`.
+    budgets: HashMap<usize, Budget>,
+    /// The key of the next budget added.
+    #[cfg(feature = "std")]
+    next_budget: usize,
+    /// Whether a thread has been given a priority other than the default, until which the scheduler ignores them.
+    priorities: bool,
+    /// The switches made on preemption which the hooks haven't been told about yet, and how many, see `preempt`.
+    #[cfg(feature = "std")]
+    preempted: ([(Id, Id); PREEMPTED_MAX], usize),
+}
+
+// The contexts to save the running thread to, and to restore the next one from.
+type Switch = (*mut Context, *const Context);
+
+impl Core {
+    fn new(stack_allocator: Box<dyn StackAllocator>) -> Result<Self, RuntimeError> {
+        let base_thread = Thread::new(BASE_THREAD_ID, State::Running);
+        let threads = vec![base_thread];
+
+        Ok(Core {
+            threads,
+            current: BASE_THREAD_ID,
+            count: 1,
+            #[cfg(feature = "std")]
+            reactor: Reactor::new().map_err(RuntimeError::Reactor)?,
+            #[cfg(not(feature = "std"))]
+            reactor: Reactor::new(),
+            scheduled_at: time::instant(),
+            time_slice: Some(DEFAULT_TIME_SLICE),
+            exited: None,
+            stacks: Vec::new(),
+            stack_allocator,
+            hooks: Hooks::default(),
+            switches: 0,
+            channel_waits: 0,
+            channels: Vec::new(),
+            stepping: false,
+            stepped: BASE_THREAD_ID,
+            rng: None,
+            #[cfg(feature = "std")]
+            recording: None,
+            #[cfg(feature = "std")]
+            replay: None,
+            chaos: None,
+            #[cfg(feature = "std")]
+            exploration: None,
+            closures: HashMap::new(),
+            #[cfg(feature = "std")]
+            injector: Arc::default(),
+            #[cfg(feature = "std")]
+            watchdog: None,
+            starvation: None,
+            zero_stacks: false,
+            limit: None,
+            lifo: None,
+            lifo_streak: 0,
+            budgets: HashMap::new(),
+            #[cfg(feature = "std")]
+            next_budget: 0,
+            priorities: false,
+            #[cfg(feature = "std")]
+            preempted: ([(BASE_THREAD_ID, BASE_THREAD_ID); PREEMPTED_MAX], 0),
+        })
+    }
+
+    // Checks for IO readiness and expired timers, waiting for up to `timeout` (forever if None),
+    // and marks the threads that were waiting on them as Ready.
+    fn poll_io(&mut self, timeout: Option<Duration>) {
+        #[cfg(feature = "std")]
+        let woken = self
+            .reactor
+            .poll(timeout)
+            .expect("failed to poll for IO events");
+        #[cfg(not(feature = "std"))]
+        let woken = self.reactor.poll(timeout);
+
+        let ready_since = self.ready_since();
+        // a thread unparked on its own goes to the LIFO slot, as when woken up by another thread of the runtime.
+        let mut unparked = (None, 0);
+        for id in woken {
+            if let Some(thread) = self.threads.iter_mut().find(|t| t.id == id) {
+                // a thread blocked on a channel only has a timer for the deadline of its context, see `context`.
+                if matches!(
+                    thread.state,
+                    State::ChannelBlockSend | State::ChannelBlockRecv
+                ) {
+                    self.interrupt(id);
+                } else if matches!(
+                    thread.state,
+                    State::IoBlocked | State::Sleeping | State::Parked
+                ) {
+                    event!(Debug, Wake, id, "woken up from {:?}", thread.state);
+                    if thread.state == State::Parked {
+                        unparked = (Some(id), unparked.1 + 1);
+                    }
+                    thread.state = State::Ready;
+                    thread.ready_since = ready_since;
+                    self.hooks.woken(id);
+                }
+            }
+        }
+        if let (Some(id), 1) = unparked {
+            self.lifo = Some(id);
+        }
     }
 
     // Helper functions to get the position of a given (or current) thread in the vec of threads.
@@ -59,22 +805,67 @@ impl Runtime {
         self.threads
             .iter()
             .position(|t| t.id == self.current)
-            .unwrap()
+            .expect("the current thread isn't among the threads")
     }
 
     #[inline]
     fn get_pos(&self, id: Id) -> usize {
-        self.threads.iter().position(|t| t.id == id).unwrap()
+        self.threads
+            .iter()
+            .position(|t| t.id == id)
+            .unwrap_or_else(|| panic!("no thread {id:?}, as it completed or was never spawned"))
     }
 
     // Choose the next thread to be run.
     // Only the threads that are not waiting for some external event to occur and are ready are chosen.
-    // Curretly, a rudimentary round robin algorithm is used to select the next thread,
-    // but this can be replaced by something that accounts for thread priority, thread wait time etc.
+    // Currently, a rudimentary round robin algorithm is used among the ready threads of the highest priority,
+    // but this can be replaced by something that accounts for thread wait time etc.
+    // Threads over their CPU budget are passed over, unless no other thread is ready.
     #[inline]
     fn round_robin(&self, start_pos: usize) -> Option<usize> {
+        if self.budgets.is_empty() {
+            return self.highest_in_turn(start_pos, |_| true);
+        }
+        let now = time::instant();
+        self.highest_in_turn(start_pos, |thread| !self.over_budget(thread, now))
+            .or_else(|| self.highest_in_turn(start_pos, |_| true))
+    }
+
+    // Chooses in turn from `start_pos` among the `eligible` threads of the highest priority that is ready.
+    // The base thread takes its turn along with them, whatever their priority, so that IO is still polled for.
+    fn highest_in_turn(
+        &self,
+        start_pos: usize,
+        eligible: impl Fn(&Thread) -> bool,
+    ) -> Option<usize> {
+        match self.top_priority(&eligible) {
+            Some(top) => self.round_robin_where(start_pos, |thread| {
+                eligible(thread) && at_priority(thread, top)
+            }),
+            None => self.round_robin_where(start_pos, eligible),
+        }
+    }
+
+    // Returns the highest priority among the ready threads which are `eligible`, the base thread aside,
+    // None if there are none, or if every thread has the default priority.
+    fn top_priority(&self, eligible: impl Fn(&Thread) -> bool) -> Option<Priority> {
+        if !self.priorities {
+            return None;
+        }
+        self.threads
+            .iter()
+            .filter(|t| t.state == State::Ready && t.id != BASE_THREAD_ID && eligible(t))
+            .map(Thread::effective_priority)
+            .max()
+    }
+
+    fn round_robin_where(
+        &self,
+        start_pos: usize,
+        eligible: impl Fn(&Thread) -> bool,
+    ) -> Option<usize> {
         let mut next_pos = start_pos;
-        while self.threads[next_pos].state != State::Ready {
+        while self.threads[next_pos].state != State::Ready || !eligible(&self.threads[next_pos]) {
             next_pos += 1;
             if next_pos == self.threads.len() {
                 next_pos = 0;
@@ -87,339 +878,1946 @@ impl Runtime {
         Some(next_pos)
     }
 
-    // Cleanup activities when a thread completes what it is asked to do.
-    // And also, gives control back to another thread.
-    #[inline(never)]
-    fn done(&mut self) {
-        // cleanup runs only for the non-main threads.
-        if self.current != BASE_THREAD_ID {
-            let cur_pos = self.cur_pos();
-
-            if DEBUG {
-                println!("from return: {:?}", self.current);
-                println!(
-                    "from return - before: {:?}",
-                    self.threads.iter().map(|t| t.id).collect::<Vec<_>>()
-                );
-            }
-
-            let mut cur_thread = self.threads.remove(cur_pos);
-
-            if DEBUG {
-                println!(
-                    "from return - after: {:?}",
-                    self.threads.iter().map(|t| t.id).collect::<Vec<_>>()
-                );
+    // Choose the next thread to be run after thread `from` stopped running, as replayed if replaying,
+    // and record the choice if recording.
+    fn next_thread(&mut self, from: Id, start_pos: usize) -> Option<usize> {
+        // the base thread gets the control back after every thread when stepping, see `Runtime::step`,
+        // and then picks the next one in turn from the one it ran last.
+        // This goes by the thread which stopped rather than by `start_pos`, which is the base thread's
+        // when a thread completes at the end of the threads.
+        let pos = if !self.stepping {
+            self.decide(start_pos)
+        } else if from != BASE_THREAD_ID {
+            Some(self.get_pos(BASE_THREAD_ID))
+        } else {
+            let next = self
+                .threads
+                .iter()
+                .position(|t| t.id == self.stepped)
+                .map_or(start_pos, |last| (last + 1) % self.threads.len());
+            let pos = self.decide(next);
+            if let Some(pos) = pos {
+                self.stepped = self.threads[pos].id;
             }
+            pos
+        };
+        #[cfg(feature = "std")]
+        if let (Some(recording), Some(pos)) = (&mut self.recording, pos) {
+            recording.push(self.threads[pos].id);
+        }
+        pos
+    }
 
-            // get the next thread to run.
-            let start_pos = if cur_pos == self.threads.len() {
-                0
-            } else {
-                cur_pos
-            };
-            let next_pos = self.round_robin(start_pos).unwrap();
+    // Picks the next thread to run: as replayed or explored, if so, or else as `pick_thread` does.
+    #[cfg(feature = "std")]
+    fn decide(&mut self, start_pos: usize) -> Option<usize> {
+        match self.replay.as_mut().and_then(Replay::next) {
+            Some((step, id)) => Some(
+                self.threads
+                    .iter()
+                    .position(|t| t.id == id && t.state == State::Ready)
+                    .unwrap_or_else(|| {
+                        panic!("the run diverged from the schedule replayed: thread {id:?} isn't ready at step {step}")
+                    }),
+            ),
+            None => match &mut self.exploration {
+                Some(exploration) => {
+                    // the base thread only polls for IO, so it only runs when no other thread can.
+                    let mut ready: Vec<usize> = (0..self.threads.len())
+                        .filter(|&pos| self.threads[pos].state == State::Ready)
+                        .collect();
+                    if ready.len() > 1 {
+                        ready.retain(|&pos| self.threads[pos].id != BASE_THREAD_ID);
+                    }
+                    (!ready.is_empty()).then(|| ready[exploration.decide(ready.len())])
+                }
+                None => self.pick_thread(start_pos),
+            },
+        }
+    }
 
-            // bookkeeping to make sure that the thread states are consistent
-            self.threads[next_pos].state = State::Running;
-            self.current = self.threads[next_pos].id;
+    // Without std, schedules are neither replayed nor explored.
+    #[cfg(not(feature = "std"))]
+    fn decide(&mut self, start_pos: usize) -> Option<usize> {
+        self.pick_thread(start_pos)
+    }
 
-            // store and restore the thread contexts and jump to the target thread.
-            unsafe {
-                let old: *mut Context = &mut cur_thread.ctx;
-                let new: *const Context = &self.threads[next_pos].ctx;
-
-                if DEBUG {
-                    println!(
-                        "\told thread: {:?} @ {:#x}",
-                        self.threads[cur_pos].id, old as usize
-                    );
-                    println!(
-                        "\tnew thread: {:?} @ {:#x}",
-                        self.threads[next_pos].id, new as usize
-                    );
-                }
+    // Choose the next thread to be run, in turn from `start_pos`, or at random when seeded or in chaos mode.
+    // Only the threads of the highest priority ready are picked from, as when taking turns.
+    fn pick_thread(&mut self, start_pos: usize) -> Option<usize> {
+        let top = self.top_priority(|_| true);
+        let rng = match (&mut self.rng, &mut self.chaos) {
+            (Some(rng), _) | (None, Some((_, rng))) => rng,
+            (None, None) => return self.lifo_or_round_robin(start_pos),
+        };
+        let candidate =
+            |t: &Thread| t.state == State::Ready && top.is_none_or(|top| at_priority(t, top));
+        let count = self.threads.iter().filter(|t| candidate(t)).count();
+        if count == 0 {
+            return None;
+        }
+        let n = rng.below(count);
+        self.threads
+            .iter()
+            .enumerate()
+            .filter(|(_, t)| candidate(t))
+            .nth(n)
+            .map(|(pos, _)| pos)
+    }
 
-                #[cfg(target_os = "linux")]
-                asm!("call switch", in("rdi") old, in("rsi") new, clobber_abi("C"));
-                // symbols in macos need an underscore at the beginning.
-                #[cfg(target_os = "macos")]
-                asm!("call _switch", in("rdi") old, in("rsi") new, clobber_abi("C"));
+    // Takes the thread in the LIFO slot if it is still ready, unless it has run ahead of the others too many times in a row,
+    // in which case the others go first, in turn from the one after it, so that two threads passing values back and forth
+    // can't starve the rest. Otherwise chooses in turn from `start_pos`. Starving threads go before all that, if looked for.
+    fn lifo_or_round_robin(&mut self, start_pos: usize) -> Option<usize> {
+        if let Some(pos) = self
+            .starvation
+            .as_mut()
+            .and_then(|starvation| starvation.next(&self.threads, time::instant()))
+        {
+            return Some(pos);
+        }
+        let lifo = self
+            .lifo
+            .take()
+            .and_then(|id| self.threads.iter().position(|t| t.id == id))
+            .filter(|&pos| self.threads[pos].state == State::Ready)
+            .filter(|&pos| !self.over_budget(&self.threads[pos], time::instant()))
+            .filter(|&pos| {
+                self.top_priority(|_| true)
+                    .is_none_or(|top| at_priority(&self.threads[pos], top))
+            });
+        let start_pos = match lifo {
+            Some(pos) if self.lifo_streak < LIFO_LIMIT => {
+                self.lifo_streak += 1;
+                return Some(pos);
             }
+            Some(pos) => (pos + 1) % self.threads.len(),
+            None => start_pos,
+        };
+        self.lifo_streak = 0;
+        self.round_robin(start_pos)
+    }
+
+    // Cleanup activities when a thread completes what it is asked to do.
+    // Returns the switch giving control to another thread, None for the base thread, which has no cleanup to do.
+    fn done(&mut self) -> Option<Switch> {
+        // cleanup runs only for the non-main threads.
+        if self.current == BASE_THREAD_ID {
+            return None;
+        }
+        #[cfg(feature = "std")]
+        self.report_preempted();
+        let cur_pos = self.cur_pos();
 
-            // We would like to avoid compiler optimising this out and actually run all the code up until this point
-            std::hint::black_box(())
+        let cur_thread = self.threads.remove(cur_pos);
+        self.publish();
+        self.charge(cur_thread.budget, self.scheduled_at.elapsed());
+        let cur_id = cur_thread.id;
+        // the thread is still running on its stack, so it can't be reused until the next thread is switched to.
+        // The previous thread to complete has been switched away from for good, though.
+        if let Some(prev) = self.exited.replace(cur_thread) {
+            self.recycle(prev);
         }
-    }
 
-    // give control to another thread.
-    #[inline(never)]
-    fn yield_thread(&mut self) -> bool {
-        if DEBUG {
-            println!("called yield from: {:?}", self.current);
+        event!(
+            Info,
+            Exit,
+            cur_id,
+            "completed, {} threads left",
+            self.threads.len()
+        );
+        self.hooks.exited(cur_id);
+        if let Some(waiter) = self
+            .limit
+            .as_mut()
+            .and_then(|limit| limit.waiters.pop_front())
+        {
+            self.change_thread_state(waiter, State::Ready);
         }
 
         // get the next thread to run.
-        let cur_pos = self.cur_pos();
-        let Some(next_pos) = self.round_robin(cur_pos) else {
-            // return false when no other runnable thread is found.
-            return false;
+        let start_pos = if cur_pos == self.threads.len() {
+            0
+        } else {
+            cur_pos
         };
+        // the base thread is always left to run.
+        let next_pos = self
+            .next_thread(cur_id, start_pos)
+            .expect("no thread ready to run, not even the base thread");
+
+        // bookkeeping to make sure that the thread states are consistent
+        self.threads[next_pos].state = State::Running;
+        self.current = self.threads[next_pos].id;
 
-        if DEBUG {
-            println!("\tswitching to {:?}...", self.threads[next_pos].id);
+        self.switches += 1;
+        #[cfg(feature = "std")]
+        if let Some(watchdog) = &self.watchdog {
+            watchdog.switched(self.current);
         }
+        self.hooks.switched(cur_id, self.current);
+
+        self.prepare(next_pos);
+        let old: *mut Context = &mut self.exited.as_mut().unwrap().ctx;
+        let new: *const Context = &self.threads[next_pos].ctx;
+
+        event!(
+            Trace,
+            Switch,
+            cur_id,
+            "switching to {:?}, contexts {:#x} -> {:#x}",
+            self.current,
+            old as usize,
+            new as usize
+        );
+
+        Some((old, new))
+    }
+
+    // Chooses another thread to give control to, and updates the bookkeeping as if it was already running.
+    // Returns None when no other runnable thread is found.
+    fn schedule(&mut self) -> Option<Switch> {
+        #[cfg(feature = "std")]
+        self.report_preempted();
+        // get the next thread to run.
+        let cur_pos = self.cur_pos();
+        let next_pos = self.next_thread(self.current, cur_pos)?;
 
         // bookkeeping to make sure that the thread states are consistent
 
         if self.threads[cur_pos].state == State::Running {
             self.threads[cur_pos].state = State::Ready;
+            self.threads[cur_pos].ready_since = self.ready_since();
         }
 
         self.threads[next_pos].state = State::Running;
         self.current = self.threads[next_pos].id;
+        let ran = self.scheduled_at.elapsed();
+        self.threads[cur_pos].run_time += ran;
+        self.charge(self.threads[cur_pos].budget, ran);
+        self.switches += 1;
+        #[cfg(feature = "std")]
+        if let Some(watchdog) = &self.watchdog {
+            watchdog.switched(self.current);
+        }
+        self.hooks.switched(self.threads[cur_pos].id, self.current);
 
-        // store and restore the thread contexts and jump to the target thread.
-        unsafe {
-            let old: *mut Context = &mut self.threads[cur_pos].ctx;
-            let new: *const Context = &self.threads[next_pos].ctx;
+        self.prepare(next_pos);
+        let old: *mut Context = &mut self.threads[cur_pos].ctx;
+        let new: *const Context = &self.threads[next_pos].ctx;
 
-            if DEBUG {
-                println!(
-                    "\told thread: {:?} @ {:#x}",
-                    self.threads[cur_pos].id, old as usize
-                );
-                println!(
-                    "\tnew thread: {:?} @ {:#x}",
-                    self.threads[next_pos].id, new as usize
-                );
-            }
+        event!(
+            Trace,
+            Switch,
+            self.threads[cur_pos].id,
+            "switching to {:?}, contexts {:#x} -> {:#x}",
+            self.current,
+            old as usize,
+            new as usize
+        );
+
+        Some((old, new))
+    }
+
+    // Switches threads from the handler of a preemption tick, which must neither allocate nor call out of the runtime:
+    // to the next thread ready in turn, only if it has its stack already (see `prepare_all`), and without the hooks
+    // and the trace events of `schedule`, which are left to the next preemption point (see `report_preempted`).
+    // Returns None when this isn't possible, e.g. when replaying a schedule, and the tick then waits for that point.
+    #[cfg(feature = "std")]
+    fn preempt(&mut self) -> Option<Switch> {
+        if self.stepping
+            || self.recording.is_some()
+            || self.replay.is_some()
+            || self.exploration.is_some()
+        {
+            return None;
+        }
+        let cur_pos = self.cur_pos();
+        let next_pos = self.round_robin(cur_pos)?;
+        if self.threads[next_pos].entry.is_some() {
+            return None;
+        }
 
-            #[cfg(target_os = "linux")]
-            asm!("call switch", in("rdi") old, in("rsi") new, clobber_abi("C"));
-            // symbols in macos need an underscore at the beginning.
-            #[cfg(target_os = "macos")]
-            asm!("call _switch", in("rdi") old, in("rsi") new, clobber_abi("C"));
+        self.threads[cur_pos].state = State::Ready;
+        self.threads[cur_pos].ready_since = self.ready_since();
+        self.threads[next_pos].state = State::Running;
+        self.current = self.threads[next_pos].id;
+        let ran = self.scheduled_at.elapsed();
+        self.threads[cur_pos].run_time += ran;
+        self.charge(self.threads[cur_pos].budget, ran);
+        self.switches += 1;
+        if let Some(watchdog) = &self.watchdog {
+            watchdog.switched(self.current);
+        }
+        // past the switches kept, the hooks miss some rather than threads not being preempted.
+        let (switches, count) = &mut self.preempted;
+        if let Some(switch) = switches.get_mut(*count) {
+            *switch = (self.threads[cur_pos].id, self.current);
+            *count += 1;
         }
 
-        // we would like to avoid compiler optimising this out and actually run all the code up until this point
-        std::hint::black_box(true)
+        let old: *mut Context = &mut self.threads[cur_pos].ctx;
+        let new: *const Context = &self.threads[next_pos].ctx;
+        Some((old, new))
     }
 
-    pub fn create_thread(&mut self, f: fn()) {
-        let mut thread = Thread::new(Id(self.count), State::Ready);
+    // Tells the hooks and the trace about the switches made on preemption since the last time, see `preempt`.
+    #[cfg(feature = "std")]
+    fn report_preempted(&mut self) {
+        let count = core::mem::take(&mut self.preempted.1);
+        for &(from, to) in &self.preempted.0[..count] {
+            event!(Trace, Switch, from, "preempted, switched to {to:?}");
+            self.hooks.switched(from, to);
+        }
+    }
 
-        // prepare the thread
-        unsafe {
-            let s_ptr = thread.stack.as_mut_ptr().add(thread.stack.len());
-            let s_ptr = (s_ptr as usize & !15) as *mut u8;
-            // add cleanup functions that are run when the user function returns
-            std::ptr::write(s_ptr.offset(-16) as *mut usize, done as usize);
-            // aligns stack to a 16 byte boundary
-            std::ptr::write(s_ptr.offset(-24) as *mut usize, do_nothing as usize);
-            // user function
-            std::ptr::write(s_ptr.offset(-32) as *mut usize, f as usize);
-            // bookkeeping
-            thread.ctx.rsp = s_ptr.offset(-32) as u64;
+    // Sets up the stacks of the threads which haven't started yet, so that preemption can switch to them.
+    #[cfg(feature = "std")]
+    fn prepare_all(&mut self) {
+        for pos in 0..self.threads.len() {
+            self.prepare(pos);
         }
+    }
 
-        if DEBUG {
-            println!("spawned new thread: {:?}", thread.id);
+    fn create_thread(&mut self, f: fn()) -> Id {
+        // the last thread to complete isn't running anymore, as it's not the one spawning.
+        if let Some(prev) = self.exited.take() {
+            self.recycle(prev);
         }
+        let id = Id(self.count);
+        // the stack is set up once the thread first runs, see `prepare`, so that spawning is only a matter of queueing it.
+        let mut thread = Thread::new(id, State::Ready);
+        thread.entry = Some(f);
+        thread.ready_since = self.ready_since();
+
+        event!(Info, Spawn, thread.id, "spawned by {:?}", self.current);
+        self.hooks.spawned(thread.id);
 
         self.threads.push(thread);
+        // the handler of a preemption tick can't allocate the stack once the thread is switched to, see `preempt`.
+        #[cfg(feature = "std")]
+        if preempt::enabled() {
+            self.prepare(self.threads.len() - 1);
+        }
+        self.publish();
         self.count += 1;
+        id
     }
 
-    fn change_thread_state(&mut self, id: Id, state: State) {
-        let index = self.get_pos(id);
-        let thread = &mut self.threads[index];
+    // Sets up the stack of the thread at `pos` if it is about to run for the first time, to start in its entry function.
+    fn prepare(&mut self, pos: usize) {
+        let Some(f) = self.threads[pos].entry.take() else {
+            return;
+        };
+        let stack = match self.stacks.pop() {
+            Some(stack) => stack,
+            None => self.allocate_stack(),
+        };
+        let thread = &mut self.threads[pos];
+        thread.stack = stack;
+        thread.main = Some(f);
+        thread.ctx.bootstrap(&mut thread.stack, run_thread);
+    }
 
-        if DEBUG {
-            println!(
-                "Changed thread {:?} from {:?} to {:?}",
-                thread.id, thread.state, state
-            );
+    fn over_budget(&self, thread: &Thread, now: Instant) -> bool {
+        thread
+            .budget
+            .and_then(|key| self.budgets.get(&key))
+            .is_some_and(|budget| budget.exhausted(now))
+    }
+
+    // Counts `ran` against `budget`, if the thread which ran has one.
+    fn charge(&mut self, budget: Option<usize>, ran: Duration) {
+        if let Some(budget) = budget.and_then(|key| self.budgets.get_mut(&key)) {
+            budget.charge(ran, time::instant());
         }
+    }
 
-        thread.state = state;
+    // Points the debugger registry at the threads, whenever they may have moved.
+    fn publish(&self) {
+        debugger::publish(self as *const Core as *const (), &self.threads);
     }
 
-    fn add_val_to_chan<T: Debug>(&mut self, id: Id, val: T) {
-        assert_ne!(self.current, id);
+    // Checks whether a thread may be spawned, as far as the limit on the threads alive goes.
+    // Returns false if the current thread has to wait for one to complete, blocking it: it must switch away and check again.
+    fn check_limit(&mut self) -> Result<bool, RuntimeError> {
+        let current = self.current;
+        let Some(limit) = &mut self.limit else {
+            return Ok(true);
+        };
+        // the base thread doesn't count.
+        if self.threads.len() - 1 < limit.max {
+            return Ok(true);
+        }
+        if limit.when_full == WhenFull::Fail || current == BASE_THREAD_ID {
+            return Err(limit.error());
+        }
+        limit.waiters.push_back(current);
+        event!(Debug, Spawn, current, "waiting for a thread to complete");
+        self.change_thread_state(current, State::SyncBlock);
+        Ok(false)
+    }
 
-        let index = self.get_pos(id);
-        let thread = &mut self.threads[index];
+    // Spawns a thread starting in `run_closure`, which takes `f` back out once it runs.
+    fn spawn(&mut self, f: Box<dyn FnOnce()>) -> Id {
+        self.closures.insert(Id(self.count), f);
+        self.create_thread(run_closure)
+    }
 
-        assert!(thread.chan_val.is_none());
+    fn spawn_with_priority(&mut self, priority: Priority, f: Box<dyn FnOnce()>) -> Id {
+        let id = self.spawn(f);
+        self.set_priority(id, priority);
+        id
+    }
 
-        if DEBUG {
-            println!(
-                "Thread {:?} wrote value {:?} to thread {:?}",
-                self.current, val, id
-            );
+    // Spawns the closures spawned through handles since last time, and answers what they asked.
+    #[cfg(feature = "std")]
+    fn inject(&mut self) {
+        for job in self.injector.take() {
+            self.spawn(job);
         }
+        for query in self.injector.take_queries() {
+            query(self);
+        }
+    }
 
-        let boxed_val = Box::new(val);
-        let ptr = Box::into_raw(boxed_val);
+    // Whether the runtime has been shut down through a handle, see `Handle::shutdown`.
+    fn is_shut_down(&self) -> bool {
+        #[cfg(feature = "std")]
+        return self.injector.is_shut_down();
+        #[cfg(not(feature = "std"))]
+        false
+    }
 
-        thread.chan_val = Some(ptr as usize);
+    #[cfg(feature = "std")]
+    fn shrink_stack(&mut self) -> io::Result<()> {
+        // the base thread runs on the stack of the OS thread, which is not ours to manage.
+        if self.current == BASE_THREAD_ID {
+            return Ok(());
+        }
+        // the stack in use ends about here. Leave some room for the calls made from now on, madvise included.
+        let marker = 0_u8;
+        let sp = core::hint::black_box(&marker) as *const u8 as usize;
+        let pos = self.cur_pos();
+        let stack = &mut self.threads[pos].stack;
+        stack.release_below(sp.saturating_sub(STACK_SHRINK_MARGIN))
     }
 
-    fn get_val_from_chan<T>(&mut self) -> Option<T> {
-        let index = self.get_pos(self.current);
-        let thread = &mut self.threads[index];
-        thread
-            .chan_val
-            .take()
-            .map(|ptr| *unsafe { Box::from_raw(ptr as *mut T) })
+    #[cfg(feature = "std")]
+    fn stack_usage(&self, id: Id) -> Option<usize> {
+        let thread = self.threads.iter().find(|t| t.id == id)?;
+        if thread.id == BASE_THREAD_ID {
+            return None;
+        }
+        thread.stack.used().ok()
+    }
+
+    // Counts the threads ready to run, without the cost of a full snapshot from `metrics`.
+    #[cfg(feature = "std")]
+    pub(crate) fn ready_count(&self) -> usize {
+        self.threads
+            .iter()
+            .filter(|t| t.state == State::Ready && t.id != BASE_THREAD_ID)
+            .count()
+    }
+
+    fn metrics(&self) -> Metrics {
+        // the base thread is neither spawned nor ever completes.
+        let spawned = self.count as u64 - 1;
+        let mut metrics = Metrics {
+            switches: self.switches,
+            spawned,
+            exited: spawned - (self.threads.len() as u64 - 1),
+            channel_waits: self.channel_waits,
+            channels: self
+                .channels
+                .iter()
+                .filter_map(Weak::upgrade)
+                .map(|stats| stats.metrics())
+                .collect(),
+            ..Metrics::default()
+        };
+        metrics.count_threads(&self.threads);
+        for thread in self.threads.iter().filter(|t| t.id != BASE_THREAD_ID) {
+            if thread.stack.size() > 0 {
+                let used = stack_used(&thread.stack);
+                metrics.stacks.add(thread.id, thread.stack.size(), used);
+            }
+        }
+        metrics
+    }
+
+    fn memory_stats(&self) -> MemoryStats {
+        let mut stats = MemoryStats {
+            pooled_stacks: self.stacks.len(),
+            channel_buffers: self
+                .channels
+                .iter()
+                .filter_map(Weak::upgrade)
+                .map(|stats| stats.buffer_bytes())
+                .sum(),
+            ..MemoryStats::default()
+        };
+        for stack in &self.stacks {
+            stats.pooled_reserved += stack.size();
+            stats.stack_committed += stack_used(stack);
+        }
+        stats.threads = self
+            .threads
+            .iter()
+            .filter(|t| t.id != BASE_THREAD_ID)
+            .map(|thread| ThreadMemory {
+                id: thread.id,
+                stack_reserved: thread.stack.size(),
+                stack_committed: stack_used(&thread.stack),
+            })
+            .collect();
+        stats.stack_reserved = stats.pooled_reserved;
+        for thread in &stats.threads {
+            stats.stack_reserved += thread.stack_reserved;
+            stats.stack_committed += thread.stack_committed;
+        }
+        stats
+    }
+
+    fn threads(&self) -> Vec<ThreadInfo> {
+        self.threads
+            .iter()
+            .map(|thread| ThreadInfo {
+                id: thread.id,
+                state: thread.state,
+                blocked_on: self.blocked_on(thread),
+                run_time: thread.run_time,
+            })
+            .collect()
+    }
+
+    // Returns what `thread` is blocked on, as far as the runtime knows.
+    fn blocked_on(&self, thread: &Thread) -> Option<BlockedOn> {
+        match thread.state {
+            State::ChannelBlockSend | State::ChannelBlockRecv => thread
+                .blocked_on
+                .map(|chan| BlockedOn::Channel(chan.as_ptr() as usize)),
+            #[cfg(feature = "std")]
+            State::IoBlocked => self
+                .reactor
+                .waiting_fd(thread.id)
+                .map(|(fd, interest)| BlockedOn::Io(fd, interest)),
+            State::Sleeping => self.reactor.deadline(thread.id).map(BlockedOn::Timer),
+            _ => None,
+        }
+    }
+
+    // Writes what every thread is up to, without allocating, see `dump_threads`.
+    fn dump(&self, out: &mut dyn fmt::Write) -> fmt::Result {
+        writeln!(
+            out,
+            "uthreads: {} threads, {:?} running",
+            self.threads.len(),
+            self.current
+        )?;
+        let now = time::instant();
+        for thread in &self.threads {
+            write!(out, "thread {:?}", thread.id)?;
+            if thread.id == BASE_THREAD_ID {
+                write!(out, " (scheduler)")?;
+            }
+            write!(out, ": {:?}, ran for {:?}", thread.state, thread.run_time)?;
+            match self.blocked_on(thread) {
+                Some(BlockedOn::Channel(chan)) => write!(out, ", on channel {chan:#x}")?,
+                #[cfg(feature = "std")]
+                Some(BlockedOn::Io(fd, interest)) => {
+                    write!(out, ", waiting for fd {fd} to be {interest:?}")?
+                }
+                Some(BlockedOn::Timer(deadline)) => {
+                    let left = deadline.saturating_duration_since(now);
+                    write!(out, ", waking up in {left:?}")?;
+                }
+                None => {}
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+
+    // Keeps the stack of a completed thread for reuse, unless enough are kept already.
+    fn recycle(&mut self, mut thread: Thread) {
+        if self.zero_stacks {
+            unsafe { thread.stack.zero() };
+        }
+        if self.stacks.len() < STACK_POOL_SIZE {
+            // the next thread starts afresh, both in memory use and in the usage reported.
+            // The top page is used by every thread, so it's kept.
+            #[cfg(feature = "std")]
+            {
+                let top = thread.stack.as_ptr() as usize + thread.stack.len();
+                let _ = thread.stack.release_below(top - 1);
+            }
+            self.stacks.push(thread.stack);
+        } else {
+            self.deallocate_stack(thread.stack);
+        }
+    }
+
+    fn allocate_stack(&mut self) -> Stack {
+        #[allow(unused_mut)]
+        let mut stack = self
+            .stack_allocator
+            .allocate(DEFAULT_STACK_SIZE, true)
+            .unwrap_or_else(|err| panic!("failed to allocate a thread stack: {err}"));
+        #[cfg(feature = "valgrind")]
+        {
+            stack.valgrind_id = valgrind::stack_register(stack.reserved());
+        }
+        stack
+    }
+
+    fn deallocate_stack(&mut self, stack: Stack) {
+        #[cfg(feature = "valgrind")]
+        valgrind::stack_deregister(stack.valgrind_id);
+        self.stack_allocator.deallocate(stack);
+    }
+
+    fn slice_exhausted(&self) -> bool {
+        self.current != BASE_THREAD_ID
+            && self
+                .time_slice
+                .is_some_and(|slice| self.scheduled_at.elapsed() >= slice)
+    }
+
+    // Whether the chaos mode, or the schedule being explored, makes the current thread yield at this entry point.
+    fn chaos_yield(&mut self) -> bool {
+        if self.current == BASE_THREAD_ID {
+            return false;
+        }
+        #[cfg(feature = "std")]
+        if let Some(exploration) = &mut self.exploration {
+            return exploration.should_yield();
+        }
+        self.chaos
+            .as_mut()
+            .is_some_and(|(probability, rng)| rng.chance(*probability))
+    }
+
+    fn change_thread_state(&mut self, id: Id, state: State) {
+        let index = self.get_pos(id);
+        let thread = &mut self.threads[index];
+
+        event!(
+            Debug,
+            State,
+            thread.id,
+            "changed from {:?} to {:?}",
+            thread.state,
+            state
+        );
+
+        // any other state is waiting on something.
+        let waiting = |state: &State| !matches!(state, State::Ready | State::Running);
+        if waiting(&state) {
+            self.hooks.blocked(id, &state);
+        } else if waiting(&thread.state) {
+            self.hooks.woken(id);
+            if self.current != BASE_THREAD_ID {
+                self.lifo = Some(id);
+            }
+        }
+        if matches!(state, State::ChannelBlockSend | State::ChannelBlockRecv) {
+            self.channel_waits += 1;
+        } else {
+            self.threads[index].blocked_on = None;
+        }
+        if state == State::Ready {
+            self.threads[index].ready_since = self.ready_since();
+        }
+        self.threads[index].state = state;
+    }
+
+    // Sets the priority of the thread with the given id, returning false if there is no such thread.
+    fn set_priority(&mut self, id: Id, priority: Priority) -> bool {
+        let Some(pos) = self.threads.iter().position(|t| t.id == id) else {
+            return false;
+        };
+        event!(
+            Debug,
+            State,
+            id,
+            "priority changed from {:?} to {:?}",
+            self.threads[pos].priority,
+            priority
+        );
+        self.threads[pos].priority = priority;
+        self.priorities |= priority != Priority::NORMAL;
+        // the thread holding the lock it waits for, if any, inherits its new priority.
+        if let Some((key, holder)) = self.threads[pos].waits_for {
+            self.inherit(key, holder);
+        }
+        true
+    }
+
+    fn priority(&self, id: Id) -> Option<Priority> {
+        let thread = self.threads.iter().find(|t| t.id == id)?;
+        Some(thread.priority)
+    }
+
+    // Whether a ready thread is of a higher priority than the current one, which then has to make way for it.
+    fn outranked(&self) -> bool {
+        if self.current == BASE_THREAD_ID {
+            return false;
+        }
+        let current = self.threads[self.cur_pos()].effective_priority();
+        self.top_priority(|_| true).is_some_and(|top| top > current)
+    }
+
+    // Marks the current thread as waiting for the lock at `key`, held by `holder`, which inherits its priority.
+    #[cfg(feature = "sync")]
+    fn wait_for_lock(&mut self, key: usize, holder: Id) {
+        let pos = self.cur_pos();
+        self.threads[pos].waits_for = Some((key, holder));
+        self.inherit(key, holder);
+    }
+
+    // Hands the lock at `key`, held by the current thread, over to `next`, one of the threads waiting for it, if any.
+    // The current thread stops inheriting the priorities of those waiting, which `next` inherits instead.
+    #[cfg(feature = "sync")]
+    fn release_lock(&mut self, key: usize, next: Option<Id>) {
+        let pos = self.cur_pos();
+        self.threads[pos].inherited.retain(|&(lock, _)| lock != key);
+        let Some(next) = next else {
+            return;
+        };
+        for thread in &mut self.threads {
+            if thread.id == next {
+                thread.waits_for = None;
+            } else if let Some((lock, holder)) = &mut thread.waits_for {
+                if *lock == key {
+                    *holder = next;
+                }
+            }
+        }
+        self.inherit(key, next);
+    }
+
+    // Has `holder` inherit the highest priority of the threads waiting for the lock at `key`, which it holds,
+    // and passes it on down the chain of threads holding the lock the previous one waits for.
+    fn inherit(&mut self, mut key: usize, mut holder: Id) {
+        // the chain is a cycle if the threads are deadlocked, so it is followed at most once around.
+        for _ in 0..self.threads.len() {
+            let top = self
+                .threads
+                .iter()
+                .filter(|t| t.waits_for.is_some_and(|(lock, _)| lock == key))
+                .map(Thread::effective_priority)
+                .max();
+            let Some(thread) = self.threads.iter_mut().find(|t| t.id == holder) else {
+                return;
+            };
+            let before = thread.effective_priority();
+            thread.inherited.retain(|&(lock, _)| lock != key);
+            if let Some(top) = top.filter(|&top| top > thread.priority) {
+                thread.inherited.push((key, top));
+            }
+            if thread.effective_priority() == before {
+                return;
+            }
+            event!(
+                Debug,
+                Sync,
+                holder,
+                "scheduled at priority {:?} for the threads waiting on its locks",
+                thread.effective_priority()
+            );
+            match thread.waits_for {
+                Some(next) => (key, holder) = next,
+                None => return,
+            }
+        }
+    }
+
+    // Returns when a thread made ready now became so, if starvation is looked for.
+    fn ready_since(&self) -> Option<Instant> {
+        self.starvation.as_ref().map(|_| time::instant())
+    }
+
+    // Blocks the current thread on `chan`, to send to it or to receive from it as per `state`.
+    fn block_on_chan(&mut self, chan: NonNull<()>, leave: LeaveChan, state: State) {
+        let index = self.cur_pos();
+        self.threads[index].blocked_on = Some(chan);
+        self.threads[index].leave_chan = Some(leave);
+        self.change_thread_state(self.current, state);
+    }
+
+    // Makes thread `id` Ready if it is blocked on a channel, sleeping or waiting for a file descriptor,
+    // as if what it waited on had happened, after taking it off whatever would have woken it up.
+    // A thread interrupted while sending gets its value back, see `chan_send_interruptible`.
+    fn interrupt(&mut self, id: Id) {
+        let Some(index) = self.threads.iter().position(|t| t.id == id) else {
+            return;
+        };
+        let state = self.threads[index].state;
+        match state {
+            State::Sleeping => {}
+            #[cfg(feature = "std")]
+            State::IoBlocked if self.reactor.waiting_fd(id).is_some() => self.reactor.cancel_io(id),
+            State::ChannelBlockSend | State::ChannelBlockRecv => {
+                let thread = &mut self.threads[index];
+                if let (Some(chan), Some(leave)) = (thread.blocked_on, thread.leave_chan) {
+                    unsafe { leave(chan, id) };
+                }
+            }
+            _ => return,
+        }
+        event!(Debug, Wake, id, "interrupted while {:?}", state);
+        self.reactor.cancel_timers(id);
+        self.change_thread_state(id, State::Ready);
+    }
+
+    #[cfg(feature = "std")]
+    fn wait_io(&mut self, fd: RawFd, interest: Interest) -> io::Result<()> {
+        event!(
+            Debug,
+            Io,
+            self.current,
+            "waiting for fd {} to be {:?}",
+            fd,
+            interest
+        );
+
+        self.reactor.register(fd, interest, self.current)?;
+        self.change_thread_state(self.current, State::IoBlocked);
+        Ok(())
+    }
+
+    #[cfg(all(target_os = "linux", feature = "io-uring"))]
+    unsafe fn submit_io(&mut self, entry: io_uring::squeue::Entry) -> io::Result<()> {
+        event!(Debug, Io, self.current, "submitted an io_uring operation");
+
+        unsafe { self.reactor.submit(self.current, entry)? };
+        self.change_thread_state(self.current, State::IoBlocked);
+        Ok(())
+    }
+
+    #[cfg(all(target_os = "linux", feature = "io-uring"))]
+    fn take_io_result(&mut self) -> Option<i32> {
+        self.reactor.take_result(self.current)
+    }
+
+    #[cfg(feature = "std")]
+    fn wait_notified(&mut self) {
+        event!(Debug, Park, self.current, "waiting to be notified");
+
+        self.reactor.park(self.current);
+        self.change_thread_state(self.current, State::IoBlocked);
+    }
+
+    // Returns false if the current thread has already been unparked.
+    #[cfg(feature = "std")]
+    fn park(&mut self) -> bool {
+        if !self.reactor.park_until_unparked(self.current) {
+            return false;
+        }
+        event!(Debug, Park, self.current, "parked");
+
+        self.change_thread_state(self.current, State::Parked);
+        true
+    }
+
+    fn sleep_until(&mut self, deadline: Instant) {
+        event!(Debug, Sleep, self.current, "sleeping until {:?}", deadline);
+
+        self.reactor.add_timer(deadline, self.current);
+        self.change_thread_state(self.current, State::Sleeping);
+    }
+
+    fn wake_selector(&mut self, id: Id) {
+        let pos = self.get_pos(id);
+        if matches!(
+            self.threads[pos].state,
+            State::IoBlocked | State::Sleeping | State::SyncBlock
+        ) {
+            self.reactor.cancel_timers(id);
+            #[cfg(feature = "std")]
+            self.reactor.cancel_io(id);
+            self.change_thread_state(id, State::Ready);
+        }
+    }
+
+    #[cfg(feature = "std")]
+    fn deregister_io(&mut self, fd: RawFd) {
+        for id in self.reactor.deregister(fd) {
+            self.change_thread_state(id, State::Ready);
+        }
+    }
+}
+
+impl Drop for Core {
+    fn drop(&mut self) {
+        debugger::release(self as *const Core as *const ());
+        #[cfg(feature = "std")]
+        self.injector.close();
+        // the io_uring operations of the threads still blocked, e.g. abandoned by `shutdown_timeout`,
+        // point at their stacks, which the kernel must be done with before they are freed.
+        // If it can't be made to, the stacks are leaked instead.
+        #[cfg(all(target_os = "linux", feature = "io-uring"))]
+        if !self.reactor.cancel_uring() {
+            return;
+        }
+        // hand the stacks back to where they came from, the base thread and the threads which never ran have none.
+        let threads = self.threads.drain(..).chain(self.exited.take());
+        let stacks = threads
+            .filter(|t| t.id != BASE_THREAD_ID && t.entry.is_none())
+            .map(|t| t.stack)
+            .chain(self.stacks.drain(..))
+            .collect::<Vec<_>>();
+        for stack in stacks {
+            self.deallocate_stack(stack);
+        }
     }
 }
 
-// function which does nothing but just return
-// takes care of the stack alignment rules for x86
-#[naked]
-unsafe extern "C" fn do_nothing() {
-    asm!("ret", options(noreturn))
+// a new thread starts running with preemption enabled,
+// whereas the thread that switched to it had it disabled.
+// Generators start the same way, with or without a runtime.
+pub(crate) fn start() {
+    preempt::set_depth(0);
+    if let Some(core) = unsafe { runtime().as_mut() } {
+        core.scheduled_at = time::instant();
+    }
 }
 
-fn done() {
+pub(crate) fn done() {
+    // the locals may call into the runtime as they are dropped, so they go while the thread can still do so.
+    // Dropping them may create others.
+    loop {
+        let locals = {
+            let _no_preempt = NoPreempt::new();
+            let core = unsafe { &mut *expect_runtime() };
+            let pos = core.cur_pos();
+            core::mem::take(&mut core.threads[pos].locals)
+        };
+        if locals.is_empty() {
+            break;
+        }
+        drop(locals);
+    }
+    // never returns, the next thread restores its own preemption depth.
+    core::mem::forget(NoPreempt::new());
     unsafe {
-        (*RUNTIME).done();
+        if let Some((old, new)) = (*expect_runtime()).done() {
+            Context::switch(old, new);
+        }
+    }
+}
+
+// Whether `thread` may run while `top` is the highest priority of the threads ready, see `Core::highest_in_turn`.
+fn at_priority(thread: &Thread, top: Priority) -> bool {
+    thread.id == BASE_THREAD_ID || thread.effective_priority() >= top
+}
+
+// Gives control to another thread, returning false if there's none to run.
+// Only borrows the core to choose the thread, and again once control comes back, see `Core`.
+#[inline(never)]
+unsafe fn switch_away(core: *mut Core) -> bool {
+    // preemption is disabled differently in each thread, so remember how it was for this one.
+    let preempt_depth = preempt::depth();
+    let Some((old, new)) = (unsafe { (*core).schedule() }) else {
+        return false;
     };
+
+    // errno belongs to the OS thread, so the threads keep their own across switches, see `errno`.
+    #[cfg(feature = "std")]
+    let errno = errno::get();
+    // store and restore the thread contexts and jump to the target thread.
+    unsafe { Context::switch(old, new) };
+    #[cfg(feature = "std")]
+    errno::set(errno);
+
+    // here the control is given back to this thread.
+    preempt::set_depth(preempt_depth);
+    unsafe { (*core).scheduled_at = time::instant() };
+
+    // we would like to avoid compiler optimising this out and actually run all the code up until this point
+    core::hint::black_box(true)
 }
 
-fn get_current_thread() -> Id {
-    unsafe { (*RUNTIME).current }
+// Deals with a fault at `addr`, if it is in the guard area of the stack of one of the threads:
+// the stack grows over it if it can, otherwise the thread has overflowed its stack.
+// Called from the SIGSEGV handler, so it must neither allocate nor take locks.
+#[cfg(feature = "std")]
+pub(crate) fn stack_fault(addr: usize) -> StackFault {
+    unsafe {
+        if runtime().is_null() {
+            return StackFault::Elsewhere;
+        }
+        let thread = (*runtime())
+            .threads
+            .iter_mut()
+            .find(|t| t.stack.in_guard(addr));
+        match thread {
+            Some(t) => {
+                if t.stack.grow(addr) {
+                    StackFault::Grown
+                } else {
+                    StackFault::Overflow(t.id, t.stack.len())
+                }
+            }
+            None => StackFault::Elsewhere,
+        }
+    }
+}
+
+// Switches away from the current thread from the handler of a preemption tick, see `Core::preempt`.
+// Returns false if it didn't, in which case the thread is to yield at the next preemption point instead.
+#[cfg(feature = "std")]
+pub(crate) fn switch_preempted() -> bool {
+    // rather than `NoPreempt`, which would yield from the handler once dropped if another tick came meanwhile.
+    let preempt_depth = preempt::depth();
+    preempt::set_depth(preempt_depth + 1);
+    let core = expect_runtime();
+    let switch = unsafe { (*core).preempt() };
+    if let Some((old, new)) = switch {
+        preempt::unblock();
+        let errno = errno::get();
+        unsafe { Context::switch(old, new) };
+        errno::set(errno);
+        unsafe { (*core).scheduled_at = time::instant() };
+    }
+    preempt::set_depth(preempt_depth);
+    switch.is_some()
+}
+
+// Sets up the stacks of the threads which haven't started yet, see `Core::prepare_all`.
+#[cfg(feature = "std")]
+pub(crate) fn prepare_threads() {
+    let _no_preempt = NoPreempt::new();
+    unsafe { (*expect_runtime()).prepare_all() };
+}
+
+/// Returns the ID of the thread that is currently running.
+pub fn get_current_thread() -> Id {
+    unsafe { (*expect_runtime()).current }
+}
+
+// Yields on behalf of the current thread if it has used up its time slice, or at random in chaos mode,
+// as soon as it is out of the runtime.
+pub(crate) fn preemption_point() {
+    let _no_preempt = NoPreempt::new();
+    #[cfg(feature = "std")]
+    unsafe {
+        (*expect_runtime()).report_preempted()
+    };
+    if unsafe { (*expect_runtime()).slice_exhausted() || (*expect_runtime()).chaos_yield() } {
+        preempt::request();
+    }
 }
 
 pub fn yield_thread() {
+    let _no_preempt = NoPreempt::new();
     unsafe {
-        (*RUNTIME).yield_thread();
+        switch_away(expect_runtime());
     }
 }
 
-pub fn create_thread(f: fn()) {
+/// Yields if the current thread has used up its time slice (see `Runtime::set_time_slice`), or at random in chaos mode,
+/// like the entry points of the runtime do. Only reads the clock otherwise, so that CPU-bound loops can call it
+/// every iteration to let the other threads run now and then, without switching every time as `yield_thread` does.
+pub fn maybe_yield() {
+    let _no_preempt = NoPreempt::new();
+    preemption_point();
+}
+
+/// Returns how much of its stack the thread with the given id has used at most, see `Runtime::stack_usage`.
+#[cfg(feature = "std")]
+pub fn stack_usage(id: Id) -> Option<usize> {
+    let _no_preempt = NoPreempt::new();
+    unsafe { (*expect_runtime()).stack_usage(id) }
+}
+
+// Returns how many threads of the current runtime are ready to run, see `Core::ready_count`.
+#[cfg(feature = "std")]
+pub(crate) fn ready_count() -> usize {
+    let _no_preempt = NoPreempt::new();
+    unsafe { (*expect_runtime()).ready_count() }
+}
+
+/// Returns a snapshot of the activity of the runtime, see `Runtime::metrics`.
+pub fn metrics() -> Metrics {
+    let _no_preempt = NoPreempt::new();
+    unsafe { (*expect_runtime()).metrics() }
+}
+
+/// Returns how much memory the runtime holds for its threads and channels, see `Runtime::memory_stats`.
+pub fn memory_stats() -> MemoryStats {
+    let _no_preempt = NoPreempt::new();
+    unsafe { (*expect_runtime()).memory_stats() }
+}
+
+/// Returns a snapshot of every live thread, see `Runtime::threads`.
+pub fn threads() -> Vec<ThreadInfo> {
+    let _no_preempt = NoPreempt::new();
+    unsafe { (*expect_runtime()).threads() }
+}
+
+/// Writes the state of every thread to `out`: its id, what it is doing and for how long it has run,
+/// and what it is blocked on if it is (a channel, a file descriptor or a timer).
+/// Neither allocates nor takes locks, so `out` may be written to from a signal handler, see `dump_threads_on`.
+pub fn dump_threads(out: &mut dyn fmt::Write) -> fmt::Result {
     unsafe {
-        (*RUNTIME).create_thread(f);
+        if runtime().is_null() {
+            return writeln!(out, "uthreads: no runtime");
+        }
+        (*runtime()).dump(out)
     }
 }
 
-fn change_thread_state(id: Id, state: State) {
+/// Dumps the state of every thread to stderr whenever the process receives `signum` (e.g. `libc::SIGQUIT`),
+/// to find out why a runtime hangs. The process carries on afterwards.
+/// Replaces whatever handled the signal before.
+#[cfg(feature = "std")]
+pub fn dump_threads_on(signum: libc::c_int) -> io::Result<()> {
+    dump::install(signum)
+}
+
+/// Returns the memory of the unused part of the current thread's stack to the OS.
+/// Useful for long-lived threads that used a lot of stack once (e.g. in a deep recursion) and don't anymore,
+/// as the pages touched stay resident otherwise.
+#[cfg(feature = "std")]
+pub fn shrink_stack() -> io::Result<()> {
+    let _no_preempt = NoPreempt::new();
+    unsafe { (*expect_runtime()).shrink_stack() }
+}
+
+/// Spawns a thread running `f`, and returns its id, see `Runtime::create_thread`.
+pub fn create_thread(f: fn()) -> Id {
+    let _no_preempt = NoPreempt::new();
+    preemption_point();
     unsafe {
-        (*RUNTIME).change_thread_state(id, state);
+        reserve_thread(expect_runtime()).unwrap_or_else(|err| panic!("{err}"));
+        (*expect_runtime()).create_thread(f)
     }
 }
 
-fn add_val_to_chan<T: Debug>(id: Id, val: T) {
+/// Spawns a thread running the closure `f`, and returns its id, see `Runtime::spawn` and the `go!` macro.
+pub fn spawn(f: impl FnOnce() + 'static) -> Id {
+    try_spawn(f).unwrap_or_else(|err| panic!("{err}"))
+}
+
+/// Spawns a thread running `f` with `arg`, and returns its id, see `Runtime::spawn_with`.
+pub fn spawn_with<T: 'static>(f: fn(T), arg: T) -> Id {
+    spawn(move || f(arg))
+}
+
+/// Spawns a thread running the closure `f` and returns its id, unless too many threads are alive, see `Runtime::try_spawn`.
+pub fn try_spawn(f: impl FnOnce() + 'static) -> Result<Id, RuntimeError> {
+    let _no_preempt = NoPreempt::new();
+    preemption_point();
     unsafe {
-        (*RUNTIME).add_val_to_chan(id, val);
+        reserve_thread(expect_runtime())?;
+        Ok((*expect_runtime()).spawn(Box::new(f)))
     }
 }
 
-fn get_val_from_chan<T>() -> Option<T> {
-    unsafe { (*RUNTIME).get_val_from_chan() }
+/// Spawns a thread running the closure `f` at `priority`, and returns its id, see `Runtime::spawn_with_priority`.
+pub fn spawn_with_priority(priority: Priority, f: impl FnOnce() + 'static) -> Id {
+    let _no_preempt = NoPreempt::new();
+    preemption_point();
+    unsafe {
+        reserve_thread(expect_runtime()).unwrap_or_else(|err| panic!("{err}"));
+        (*expect_runtime()).spawn_with_priority(priority, Box::new(f))
+    }
 }
 
-pub fn chan_send<T: Debug>(chan: *mut Channel<T>, val: T) {
-    if DEBUG {
-        println!("Called send on thread {:?}", get_current_thread());
+/// Changes the priority of the thread with the given id, see `ThreadHandle::set_priority`.
+/// Returns false if there is no such thread, as it completed or was never spawned.
+pub fn set_priority(id: Id, priority: Priority) -> bool {
+    let _no_preempt = NoPreempt::new();
+    unsafe {
+        if !(*expect_runtime()).set_priority(id, priority) {
+            return false;
+        }
+        // the current thread makes way right away if it isn't of the highest priority ready anymore.
+        if (*expect_runtime()).outranked() {
+            switch_away(expect_runtime());
+        }
     }
+    true
+}
 
-    let chan: &mut Channel<T> = unsafe { &mut *chan };
+/// Returns the priority of the thread with the given id, not counting what it inherits, None if there is no such thread.
+pub fn priority(id: Id) -> Option<Priority> {
+    let _no_preempt = NoPreempt::new();
+    unsafe { (*expect_runtime()).priority(id) }
+}
 
-    // if there's a thread waiting to receive a value, 
-    // directly give the value to the waiting thread.
-    // And change the state of the receiving thread to Ready
-    if let Ok(receiver) = chan.recvq.read() {
-        add_val_to_chan(receiver, val);
-        change_thread_state(receiver, State::Ready);
+// Makes sure a thread may be spawned, blocking the current thread until one completes if the limit says so,
+// see `Runtime::set_max_threads`.
+unsafe fn reserve_thread(core: *mut Core) -> Result<(), RuntimeError> {
+    while !unsafe { (*core).check_limit()? } {
+        unsafe { switch_away(core) };
+    }
+    Ok(())
+}
+
+// How much of `stack` has been used, as far as can be told: without std, none of it.
+fn stack_used(stack: &Stack) -> usize {
+    #[cfg(feature = "std")]
+    return stack.used().unwrap_or(0);
+    #[cfg(not(feature = "std"))]
+    {
+        let _ = stack;
+        0
     }
-    // try adding the value to the channel buffer
-    else if let Err(val) = chan.buffer.write(val) {
+}
+
+// Runs the function the current thread was set up to run, see `prepare`.
+// A panic unwinding out of it would abort the process on its way through the bottom frame of the thread,
+// so the process is aborted here, with a report of what happened, see `crash`.
+fn run_thread() {
+    let f = {
+        let _no_preempt = NoPreempt::new();
+        let core = unsafe { &mut *expect_runtime() };
+        let pos = core.cur_pos();
+        core.threads[pos].main.take()
+    };
+    let f = f.expect("thread started without a function");
+    #[cfg(feature = "std")]
+    if let Err(payload) = panic::catch_unwind(f) {
+        crash::report(payload);
+    }
+    // without std, panics don't unwind, they end in the panic handler.
+    #[cfg(not(feature = "std"))]
+    f();
+}
+
+// Keeps a backtrace of the current thread as it panics, if it is a thread of a runtime, see `crash`.
+// The runtime may be in the middle of an update if it is the one panicking, so the thread is looked for.
+#[cfg(feature = "std")]
+pub(crate) fn capture_backtrace() {
+    let core = runtime();
+    if core.is_null() {
+        return;
+    }
+    let _no_preempt = NoPreempt::new();
+    let core = unsafe { &mut *core };
+    let current = core.current;
+    if current == BASE_THREAD_ID {
+        return;
+    }
+    if let Some(thread) = core.threads.iter_mut().find(|t| t.id == current) {
+        thread.backtrace = Some(Backtrace::force_capture());
+    }
+}
+
+// Takes the backtrace of the current thread as it last panicked, if any.
+#[cfg(feature = "std")]
+pub(crate) fn take_backtrace() -> Option<Backtrace> {
+    let _no_preempt = NoPreempt::new();
+    let core = unsafe { &mut *expect_runtime() };
+    let pos = core.cur_pos();
+    core.threads[pos].backtrace.take()
+}
+
+// Runs the closure the current thread was spawned with.
+fn run_closure() {
+    let f = {
+        let _no_preempt = NoPreempt::new();
+        unsafe {
+            (*expect_runtime())
+                .closures
+                .remove(&(*expect_runtime()).current)
+        }
+    };
+    f.expect("thread spawned without a closure")();
+}
+
+/// Spawns a green thread, like the `go` statement of Go:
+/// `go!(f(a, b))` runs the call, and `go!(move || ...)` runs the closure, on a new thread.
+/// The call is made on the new thread, and the local variables it uses are moved into it.
+/// Evaluates to the id of the thread.
+#[macro_export]
+macro_rules! go {
+    (move || $body:expr) => {
+        $crate::runtime::spawn(move || $body)
+    };
+    (|| $body:expr) => {
+        $crate::runtime::spawn(|| $body)
+    };
+    ($call:expr) => {
+        $crate::runtime::spawn(move || {
+            $call;
+        })
+    };
+}
+
+pub(crate) fn change_thread_state(id: Id, state: State) {
+    let _no_preempt = NoPreempt::new();
+    unsafe {
+        (*expect_runtime()).change_thread_state(id, state);
+    }
+}
+
+fn block_on_chan<T>(chan: *mut Channel<T>, state: State) {
+    let _no_preempt = NoPreempt::new();
+    let chan = NonNull::new(chan)
+        .expect("blocking on a null channel")
+        .cast();
+    unsafe {
+        (*expect_runtime()).block_on_chan(chan, leave_chan::<T>, state);
+    }
+}
+
+// Has the runtime report the activity of a channel as it is named, if there is one on this OS thread.
+pub(crate) fn register_channel(stats: Weak<Stats>) {
+    let _no_preempt = NoPreempt::new();
+    if let Some(core) = unsafe { runtime().as_mut() } {
+        core.channels.retain(|stats| stats.strong_count() > 0);
+        core.channels.push(stats);
+    }
+}
+
+// Takes thread `id` off the queues of `chan`, a `Channel<T>`, handing the value it was blocked sending back to it, if any.
+unsafe fn leave_chan<T>(chan: NonNull<()>, id: Id) {
+    let chan = unsafe { chan.cast::<Channel<T>>().as_mut() };
+    if let Some(pos) = chan.recvq.iter().position(|&receiver| receiver == id) {
+        chan.recvq.remove(pos);
+    } else if let Some(pos) = chan.sendq.iter().position(|(sender, _)| *sender == id) {
+        let (_, val) = chan.sendq.remove(pos).unwrap();
+        chan.hand_off(id, val);
+    }
+}
+
+// Adds a CPU budget for a group of threads, see `Group::set_cpu_quota`, and returns its key.
+#[cfg(feature = "std")]
+pub(crate) fn add_budget(quota: Duration, period: Duration) -> usize {
+    let _no_preempt = NoPreempt::new();
+    unsafe {
+        let core = expect_runtime();
+        let key = (*core).next_budget;
+        (*core).next_budget += 1;
+        (*core).budgets.insert(key, Budget::new(quota, period));
+        key
+    }
+}
+
+// Makes thread `id` run on the budget at `key`, or on none.
+#[cfg(feature = "std")]
+pub(crate) fn set_budget(id: Id, budget: Option<usize>) {
+    let _no_preempt = NoPreempt::new();
+    unsafe {
+        let core = expect_runtime();
+        let pos = (*core).get_pos(id);
+        (&mut (*core).threads)[pos].budget = budget;
+    }
+}
+
+// Removes the budget at `key`, the threads running on it are no longer limited.
+// Does nothing once the runtime is gone.
+#[cfg(feature = "std")]
+pub(crate) fn remove_budget(key: usize) {
+    let _no_preempt = NoPreempt::new();
+    if let Some(core) = unsafe { runtime().as_mut() } {
+        core.budgets.remove(&key);
+    }
+}
+
+// Returns the priority thread `id` is scheduled with, the one it inherits included, see `Thread::effective_priority`.
+#[cfg(feature = "sync")]
+pub(crate) fn effective_priority(id: Id) -> Priority {
+    let _no_preempt = NoPreempt::new();
+    let core = unsafe { &*expect_runtime() };
+    core.threads[core.get_pos(id)].effective_priority()
+}
+
+// Marks the current thread as waiting for the lock at `key`, held by `holder`, which inherits its priority until it
+// releases the lock, see `sync::Mutex`. The current thread must then block until the lock is handed over to it.
+#[cfg(feature = "sync")]
+pub(crate) fn wait_for_lock(key: usize, holder: Id) {
+    let _no_preempt = NoPreempt::new();
+    unsafe { (*expect_runtime()).wait_for_lock(key, holder) };
+}
+
+// Hands the lock at `key` over from the current thread to `next`, one of the threads waiting for it, or to none,
+// moving the priority inherited from those waiting along with it.
+#[cfg(feature = "sync")]
+pub(crate) fn release_lock(key: usize, next: Option<Id>) {
+    let _no_preempt = NoPreempt::new();
+    unsafe { (*expect_runtime()).release_lock(key, next) };
+}
+
+// Returns the value of the local at `key` of the current thread, if it has one yet, see `Local`.
+pub(crate) fn local(key: usize) -> Option<*const dyn Any> {
+    let _no_preempt = NoPreempt::new();
+    assert!(
+        !runtime().is_null(),
+        "thread locals are only available on a runtime"
+    );
+    let core = unsafe { &*runtime() };
+    core.threads[core.cur_pos()].locals.get(key)
+}
+
+// Sets the value of the local at `key` of the current thread to `val`, unless it has one already, and returns it.
+pub(crate) fn init_local(key: usize, val: Box<dyn Any>) -> *const dyn Any {
+    let _no_preempt = NoPreempt::new();
+    let core = unsafe { &mut *expect_runtime() };
+    let pos = core.cur_pos();
+    core.threads[pos].locals.insert(key, val)
+}
+
+// Wakes up thread `id` if it is blocked on a channel, sleeping or waiting for a file descriptor, see `context`.
+#[cfg(feature = "std")]
+pub(crate) fn interrupt(id: Id) {
+    let _no_preempt = NoPreempt::new();
+    unsafe { (*expect_runtime()).interrupt(id) };
+}
+
+// Interrupts the next wait of the current thread on a channel, a timer or a file descriptor once `deadline` has passed.
+#[cfg(feature = "std")]
+pub(crate) fn set_deadline(deadline: Instant) {
+    let _no_preempt = NoPreempt::new();
+    unsafe {
+        let core = expect_runtime();
+        (*core).reactor.add_timer(deadline, (*core).current);
+    }
+}
+
+// Forgets the deadline of the current thread and the file descriptor it waited on, once done waiting.
+#[cfg(any(feature = "std", feature = "sync"))]
+pub(crate) fn clear_wait() {
+    let _no_preempt = NoPreempt::new();
+    unsafe {
+        let core = expect_runtime();
+        let id = (*core).current;
+        (*core).reactor.cancel_timers(id);
+        #[cfg(feature = "std")]
+        (*core).reactor.cancel_io(id);
+    }
+}
+
+// Blocks the current thread until one of `fds` is ready, `deadline` has passed,
+// or one of the channels it registered with wakes it up through `wake_selector`, see `Select`.
+#[cfg(feature = "std")]
+pub(crate) fn select_wait(fds: &[(RawFd, Interest)], deadline: Option<Instant>) -> io::Result<()> {
+    let _no_preempt = NoPreempt::new();
+    unsafe {
+        let core = expect_runtime();
+        let id = (*core).current;
+        for &(fd, interest) in fds {
+            if let Err(err) = (*core).reactor.register(fd, interest, id) {
+                (*core).reactor.cancel_io(id);
+                return Err(err);
+            }
+        }
+    }
+    block_selecting(fds.len(), deadline);
+    Ok(())
+}
+
+// Blocks the current thread until `deadline` has passed, or it is woken up through `wake_selector`,
+// like `select_wait` without file descriptors.
+#[cfg(any(feature = "std", feature = "sync"))]
+pub(crate) fn wait_woken(deadline: Option<Instant>) {
+    let _no_preempt = NoPreempt::new();
+    block_selecting(0, deadline);
+}
+
+// Blocks the current thread, in `select_wait`, once it has registered with the `fds` file descriptors.
+#[cfg(any(feature = "std", feature = "sync"))]
+fn block_selecting(fds: usize, deadline: Option<Instant>) {
+    unsafe {
+        let core = expect_runtime();
+        let id = (*core).current;
+        if let Some(deadline) = deadline {
+            (*core).reactor.add_timer(deadline, id);
+        }
+        event!(
+            Debug,
+            Sync,
+            id,
+            "selecting over {} file descriptors, until {:?}",
+            fds,
+            deadline
+        );
+        // the reactor wakes up the threads blocked on IO or sleeping, whichever of the two fires.
+        let state = match (fds, deadline) {
+            (1.., _) => State::IoBlocked,
+            (0, Some(_)) => State::Sleeping,
+            (0, None) => State::SyncBlock,
+        };
+        (*core).change_thread_state(id, state);
+    }
+    yield_thread();
+    clear_wait();
+}
+
+// Wakes up thread `id`, blocked in `select_wait`, as a channel it watches may have a value for it.
+pub(crate) fn wake_selector(id: Id) {
+    let _no_preempt = NoPreempt::new();
+    unsafe {
+        (*expect_runtime()).wake_selector(id);
+    }
+}
+
+/// Blocks the current thread until `fd` is ready for `interest`.
+/// The file descriptor is expected to be in non-blocking mode,
+/// and the IO should be retried once this returns, as readiness can be spurious.
+#[cfg(feature = "std")]
+pub fn wait_io(fd: RawFd, interest: Interest) -> io::Result<()> {
+    let _no_preempt = NoPreempt::new();
+    unsafe {
+        (*expect_runtime()).wait_io(fd, interest)?;
+    }
+    yield_thread();
+    Ok(())
+}
+
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+pub(crate) fn uring_available() -> bool {
+    unsafe { (*expect_runtime()).reactor.uring_available() }
+}
+
+// Submits an io_uring operation and blocks the current thread until it completes.
+// Returns the result of the operation, i.e, a negated errno on failure.
+// The buffers referenced by `entry` must stay valid until the operation completes.
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+pub(crate) unsafe fn submit_io(entry: io_uring::squeue::Entry) -> io::Result<i32> {
+    let _no_preempt = NoPreempt::new();
+    unsafe {
+        (*expect_runtime()).submit_io(entry)?;
+    }
+    yield_thread();
+    let res = unsafe { (*expect_runtime()).take_io_result() };
+    Ok(res.expect("thread woken up before its io_uring operation completed"))
+}
+
+// Returns a handle that other OS threads can use to wake up threads blocked in `wait_notified`.
+#[cfg(feature = "std")]
+pub(crate) fn notifier() -> Notifier {
+    unsafe { (*expect_runtime()).reactor.notifier() }
+}
+
+// Blocks the current thread until it is notified through a `Notifier`.
+// Notifications sent before the thread blocks are not lost, as long as the thread doesn't yield in between.
+#[cfg(feature = "std")]
+pub(crate) fn wait_notified() {
+    let _no_preempt = NoPreempt::new();
+    unsafe {
+        (*expect_runtime()).wait_notified();
+    }
+    yield_thread();
+}
+
+/// Returns a handle to the runtime of the current thread, see `Runtime::handle`.
+#[cfg(feature = "std")]
+pub fn handle() -> Handle {
+    let core = unsafe { &*expect_runtime() };
+    Handle::new(core.injector.clone(), core.reactor.waker())
+}
+
+/// Returns a handle that other OS threads can use to unpark green threads or wake up the runtime.
+#[cfg(feature = "std")]
+pub fn waker() -> RuntimeWaker {
+    unsafe { (*expect_runtime()).reactor.waker() }
+}
+
+/// Blocks the current thread until it is unparked through a `RuntimeWaker`.
+/// Returns immediately if the thread has been unparked since it last called `park`.
+#[cfg(feature = "std")]
+pub fn park() {
+    let _no_preempt = NoPreempt::new();
+    if unsafe { (*expect_runtime()).park() } {
+        yield_thread();
+    }
+}
+
+// Returns the current time on the clock of the runtime, see `time::now`.
+pub(crate) fn now() -> Instant {
+    let _no_preempt = NoPreempt::new();
+    match unsafe { runtime().as_ref() } {
+        Some(core) => core.reactor.now(),
+        #[cfg(feature = "std")]
+        None => Instant::now(),
+        // where clocks start counting, as there is no other to go by.
+        #[cfg(not(feature = "std"))]
+        None => Instant::from_duration(Duration::ZERO),
+    }
+}
+
+// Wakes up the threads whose deadlines have passed, e.g. as the clock of the runtime has been advanced by hand.
+// Does nothing outside of a runtime.
+pub(crate) fn expire_timers() {
+    let _no_preempt = NoPreempt::new();
+    if let Some(core) = unsafe { runtime().as_mut() } {
+        core.poll_io(Some(Duration::ZERO));
+    }
+}
+
+// Blocks the current thread until `deadline` has passed.
+pub(crate) fn sleep_until(deadline: Instant) {
+    let _no_preempt = NoPreempt::new();
+    unsafe {
+        (*expect_runtime()).sleep_until(deadline);
+    }
+    yield_thread();
+}
+
+/// Stops watching `fd`, waking up any thread waiting on it.
+/// Must be called before closing a file descriptor that has been passed to `wait_io`.
+#[cfg(feature = "std")]
+pub fn deregister_io(fd: RawFd) {
+    let _no_preempt = NoPreempt::new();
+    unsafe {
+        (*expect_runtime()).deregister_io(fd);
+    }
+}
+
+/// # Safety
+///
+/// `chan` must point to a live Channel that is not accessed from outside the runtime.
+pub unsafe fn chan_send<T>(chan: *mut Channel<T>, val: T) {
+    if unsafe { chan_send_interruptible(chan, val) }.is_err() {
+        panic!("a thread blocked sending was interrupted without a deadline");
+    }
+}
+
+// Sends like `chan_send`, but returns the value if the thread was interrupted while blocked, see `context`.
+pub(crate) unsafe fn chan_send_interruptible<T>(chan: *mut Channel<T>, val: T) -> Result<(), T> {
+    let _no_preempt = NoPreempt::new();
+    preemption_point();
+    event!(Trace, Channel, get_current_thread(), "called send");
+
+    // the other threads use the channel while this one is blocked on it, so it's borrowed again once this thread runs again.
+    let channel: &mut Channel<T> = unsafe { &mut *chan };
+
+    // if there's a thread waiting to receive a value, or room in the buffer, the value goes there.
+    if let Err(val) = try_send(channel, val).or_else(|val| overflow(channel, val)) {
         // In case the buffer is full, add the sender to the waiting list
         let curr_id = get_current_thread();
-        chan.sendq
-            .write((curr_id, val))
-            .expect("No more space in sendq");
+        channel.sendq.push_back((curr_id, val));
+        // the value can be taken from the blocked sender.
+        channel.wake_receivers();
+        let since = channel.block_started();
         // change the state of the sending thread to blocked
-        change_thread_state(curr_id, State::ChannelBlockSend);
+        block_on_chan(chan, State::ChannelBlockSend);
+        event!(
+            Debug,
+            Channel,
+            curr_id,
+            "blocked on send to {}",
+            channel.label()
+        );
         // yield control to another thread
         yield_thread();
+        unsafe { (*chan).count_blocked(since) };
+        // the value was handed back if the thread was interrupted, instead of being taken by a receiver.
+        if let Some(val) = unsafe { (*chan).take_handoff(curr_id) } {
+            return Err(val);
+        }
     }
+    Ok(())
 }
 
-pub fn chan_recv<T: Debug>(chan: *mut Channel<T>) -> T {
-    if DEBUG {
-        println!("Called receive on thread {:?}", get_current_thread());
+/// # Safety
+///
+/// `chan` must point to a live Channel that is not accessed from outside the runtime.
+pub unsafe fn chan_recv<T>(chan: *mut Channel<T>) -> T {
+    unsafe { chan_recv_interruptible(chan) }
+        .expect("a thread blocked receiving was interrupted without a deadline")
+}
+
+// Receives like `chan_recv`, but returns None if the thread was interrupted while blocked, see `context`.
+pub(crate) unsafe fn chan_recv_interruptible<T>(chan: *mut Channel<T>) -> Option<T> {
+    let _no_preempt = NoPreempt::new();
+    preemption_point();
+    event!(Trace, Channel, get_current_thread(), "called receive");
+
+    // the other threads use the channel while this one is blocked on it,
+    // so it's borrowed again once this thread is switched back to, like the core of the runtime.
+    let channel: &mut Channel<T> = unsafe { &mut *chan };
+
+    // take the value of a blocked sender, or from the buffer.
+    if let Some(val) = try_recv(channel) {
+        return Some(val);
     }
+    // if no value is present, block
+    let curr_id = get_current_thread();
+    // add the current thread to waiting list
+    channel.recvq.push_back(curr_id);
+    // a value can be handed to the blocked receiver.
+    channel.wake_senders();
+    let since = channel.block_started();
+    block_on_chan(chan, State::ChannelBlockRecv);
+    event!(
+        Debug,
+        Channel,
+        curr_id,
+        "blocked on receive from {}",
+        channel.label()
+    );
 
-    let chan: &mut Channel<T> = unsafe { &mut *chan };
+    // yield control to another thread
+    yield_thread();
 
-    // if there's a sender blocked on sending, get its value
-    if let Ok((sender, val)) = chan.sendq.read() {
-        if DEBUG {
-            println!(
-                "Found a ready to send thread {:?}, value = {:?}",
-                sender, val
-            );
-        }
-        // change the state of the blocked sender to ready
+    // here the control is given back to this thread
+    // and a value is given from the chan it was blocked on, unless it was interrupted.
+    let channel = unsafe { &mut *chan };
+    channel.count_blocked(since);
+    if let Some(val) = channel.take_handoff(curr_id) {
+        channel.count_received();
+        return Some(val);
+    }
+    // interrupted: senders may have filled the buffer and blocked meanwhile, so one moves into the slot freed.
+    try_recv(channel)
+}
+
+/// Sends `val` to `chan` if it can be without blocking, i.e. to a thread blocked receiving or into the buffer,
+/// and otherwise returns it along with `ChannelError::Full`.
+///
+/// # Safety
+///
+/// `chan` must point to a live Channel that is not accessed from outside the runtime.
+pub unsafe fn chan_try_send<T>(chan: *mut Channel<T>, val: T) -> Result<(), (ChannelError, T)> {
+    let _no_preempt = NoPreempt::new();
+    preemption_point();
+    try_send(unsafe { &mut *chan }, val).map_err(|val| (ChannelError::Full, val))
+}
+
+/// Receives from `chan` if it can without blocking, i.e. from the buffer or a thread blocked sending,
+/// and otherwise fails with `ChannelError::Empty`.
+///
+/// # Safety
+///
+/// `chan` must point to a live Channel that is not accessed from outside the runtime.
+pub unsafe fn chan_try_recv<T>(chan: *mut Channel<T>) -> Result<T, ChannelError> {
+    let _no_preempt = NoPreempt::new();
+    preemption_point();
+    try_recv(unsafe { &mut *chan }).ok_or(ChannelError::Empty)
+}
+
+/// Blocks until there is room in the buffer of `chan`, and keeps it for the value sent with the returned permit,
+/// which then neither blocks nor fails, e.g. so that a producer only does the work of making a value once it can send it.
+/// Panics if the channel has no buffer, as there is no room to keep.
+///
+/// # Safety
+///
+/// `chan` must point to a live Channel that is not accessed from outside the runtime, and stays so as long as the permit.
+pub unsafe fn chan_reserve<T>(chan: *mut Channel<T>) -> Permit<T> {
+    let _no_preempt = NoPreempt::new();
+    preemption_point();
+    let id = get_current_thread();
+    let chan = NonNull::new(chan).expect("reserving on a null channel");
+    // the other threads use the channel while this one is blocked on it, so it's borrowed again every time.
+    while !unsafe { (*chan.as_ptr()).reserve() } {
+        unsafe { (*chan.as_ptr()).add_reserver(id) };
+        event!(Debug, Channel, id, "blocked on reserve in {}", unsafe {
+            (*chan.as_ptr()).label()
+        });
+        change_thread_state(id, State::SyncBlock);
+        yield_thread();
+    }
+    Permit::new(chan)
+}
+
+// Sends `val` into the room kept for it by a permit, see `Permit::send`.
+pub(crate) fn send_permitted<T>(chan: &mut Channel<T>, val: T) {
+    let _no_preempt = NoPreempt::new();
+    chan.unreserve();
+    if try_send(chan, val).is_err() {
+        unreachable!("no room for the value of a permit");
+    }
+}
+
+// Gives back the room kept by a permit dropped unused.
+pub(crate) fn release_permit<T>(chan: &mut Channel<T>) {
+    let _no_preempt = NoPreempt::new();
+    chan.unreserve();
+    // a blocked sender was there first, so it gets the room rather than whoever sends next.
+    if let Some((sender, val)) = chan.sendq.pop_front() {
+        chan.buffer
+            .write(val)
+            .unwrap_or_else(|_| unreachable!("a permit was given back"));
+        chan.count_sent();
         change_thread_state(sender, State::Ready);
-        return val;
     } else {
-        // fetch value from channel buffer
-        match chan.buffer.read() {
-            Ok(val) => {
-                if DEBUG {
-                    println!(
-                        "Thread {:?} found a value in the buffer: {:?}",
-                        get_current_thread(),
-                        val
-                    );
-                }
-                return val;
-            }
-            // if no value present in the buffer, block
-            Err(()) => {
-                let curr_id = get_current_thread();
-                // add the current thread to waiting list
-                chan.recvq.write(curr_id).expect("No more space in recvq");
-                change_thread_state(curr_id, State::ChannelBlockRecv);
-                println!("Added thread {:?} to the recvq", get_current_thread());
-
-                // yield control to another thread
-                yield_thread();
-
-                // here the control is given back to this thread
-                // and a value is given from the chan it was blocked on
-                get_val_from_chan()
-                    .or_else(|| chan.buffer.read().ok())
-                    .unwrap()
-            }
+        chan.wake_senders();
+    }
+}
+
+/// Sends every value of `vals` to `chan` in order, blocking only while the buffer is full,
+/// so that a receiver woken up once can take many of them, e.g. with `chan_recv_many`.
+///
+/// # Safety
+///
+/// `chan` must point to a live Channel that is not accessed from outside the runtime.
+pub unsafe fn chan_send_all<T>(chan: *mut Channel<T>, vals: impl IntoIterator<Item = T>) {
+    let _no_preempt = NoPreempt::new();
+    preemption_point();
+    for val in vals {
+        // the channel is borrowed again for every value, as other threads use it while this one is blocked sending.
+        if let Err(val) = try_send(unsafe { &mut *chan }, val) {
+            unsafe { chan_send(chan, val) };
         }
     }
 }
 
-#[naked]
-#[no_mangle]
-unsafe extern "C" fn switch() {
-    asm!(
-        "mov [rdi + 0x00], rsp",
-        "mov [rdi + 0x08], r15",
-        "mov [rdi + 0x10], r14",
-        "mov [rdi + 0x18], r13",
-        "mov [rdi + 0x20], r12",
-        "mov [rdi + 0x28], rbx",
-        "mov [rdi + 0x30], rbp",
-        "mov rsp, [rsi + 0x00]",
-        "mov r15, [rsi + 0x08]",
-        "mov r14, [rsi + 0x10]",
-        "mov r13, [rsi + 0x18]",
-        "mov r12, [rsi + 0x20]",
-        "mov rbx, [rsi + 0x28]",
-        "mov rbp, [rsi + 0x30]",
-        "ret",
-        options(noreturn)
+/// Receives up to `limit` values from `chan` into `buf`, and returns how many.
+/// Blocks like `chan_recv` until there is at least one, then takes those it can without blocking,
+/// i.e. from the buffer and the threads blocked sending, which saves a switch per value when the channel is busy.
+///
+/// # Safety
+///
+/// `chan` must point to a live Channel that is not accessed from outside the runtime.
+pub unsafe fn chan_recv_many<T>(chan: *mut Channel<T>, buf: &mut Vec<T>, limit: usize) -> usize {
+    if limit == 0 {
+        return 0;
+    }
+    let _no_preempt = NoPreempt::new();
+    buf.push(unsafe { chan_recv(chan) });
+    let channel = unsafe { &mut *chan };
+    let mut received = 1;
+    while received < limit {
+        let Some(val) = try_recv(channel) else {
+            break;
+        };
+        buf.push(val);
+        received += 1;
+    }
+    received
+}
+
+// Gives `val` directly to a thread waiting to receive a value, making it Ready, or else adds it to the buffer.
+// Returns it if neither is possible.
+fn try_send<T>(chan: &mut Channel<T>, val: T) -> Result<(), T> {
+    if let Some(receiver) = chan.recvq.pop_front() {
+        event!(
+            Trace,
+            Channel,
+            get_current_thread(),
+            "handed a {} over to {:?}",
+            core::any::type_name::<T>(),
+            receiver
+        );
+        chan.hand_off(receiver, val);
+        change_thread_state(receiver, State::Ready);
+    } else {
+        if !chan.has_room() {
+            return Err(val);
+        }
+        chan.buffer.write(val)?;
+        chan.wake_receivers();
+    }
+    chan.count_sent();
+    Ok(())
+}
+
+// Handles `val`, sent to `chan` while it is full, according to the overflow policy of the channel.
+// Returns it if the sender is to wait for room.
+fn overflow<T>(chan: &mut Channel<T>, val: T) -> Result<(), T> {
+    let dropped = match chan.overflow() {
+        Overflow::Block => return Err(val),
+        Overflow::DropNewest => val,
+        Overflow::DropOldest => match chan.buffer.read() {
+            Ok(oldest) => {
+                chan.buffer
+                    .write(val)
+                    .unwrap_or_else(|_| unreachable!("a value was just read from the buffer"));
+                chan.count_sent();
+                oldest
+            }
+            Err(()) => val,
+        },
+        Overflow::Error => panic!(
+            "sent a {} to a full channel whose overflow policy is `Error`",
+            core::any::type_name::<T>()
+        ),
+    };
+    event!(
+        Debug,
+        Channel,
+        get_current_thread(),
+        "dropped a {} as the channel is full",
+        core::any::type_name::<T>()
     );
+    chan.count_dropped();
+    drop(dropped);
+    Ok(())
+}
+
+// Takes the oldest value of the buffer, or else the value of a thread blocked on sending, making it Ready.
+fn try_recv<T>(chan: &mut Channel<T>) -> Option<T> {
+    if let Ok(val) = chan.buffer.read() {
+        event!(
+            Trace,
+            Channel,
+            get_current_thread(),
+            "found a {} in the buffer",
+            core::any::type_name::<T>()
+        );
+        // the value of a blocked sender goes to the back of the buffer, so that values are received in the order they were sent.
+        if let Some((sender, next)) = chan.sendq.pop_front() {
+            chan.buffer
+                .write(next)
+                .unwrap_or_else(|_| unreachable!("a value was just read from the buffer"));
+            chan.count_sent();
+            change_thread_state(sender, State::Ready);
+        } else {
+            chan.wake_senders();
+        }
+        chan.count_received();
+        return Some(val);
+    }
+    if let Some((sender, val)) = chan.sendq.pop_front() {
+        event!(
+            Trace,
+            Channel,
+            get_current_thread(),
+            "received a {} from blocked sender {:?}",
+            core::any::type_name::<T>(),
+            sender
+        );
+        // change the state of the blocked sender to ready
+        change_thread_state(sender, State::Ready);
+        chan.count_sent();
+        chan.count_received();
+        return Some(val);
+    }
+    None
+}
+
+// Wakes up the runtime if it waits for events, as a task of an async runtime may have made a thread Ready.
+// Without std, the runtime never waits for events while such a task could run.
+fn nudge() {
+    #[cfg(feature = "std")]
+    unsafe {
+        (*expect_runtime()).reactor.waker().wake()
+    };
+}
+
+// Sends `val` on `chan` if it can be without blocking, for a task of an async runtime,
+// which is woken up once it may be otherwise, see `ChannelSink`.
+pub(crate) fn chan_poll_send<T>(
+    chan: &mut Channel<T>,
+    val: T,
+    cx: &mut TaskContext<'_>,
+) -> Result<(), T> {
+    let _no_preempt = NoPreempt::new();
+    try_send(chan, val)
+        .or_else(|val| overflow(chan, val))
+        .inspect(|()| nudge())
+        .inspect_err(|_| chan.add_sender(cx.waker()))
+}
+
+// Receives a value from `chan` if it can be without blocking, for a task of an async runtime,
+// which is woken up once it may be otherwise, see `ChannelStream`.
+pub(crate) fn chan_poll_recv<T>(chan: &mut Channel<T>, cx: &mut TaskContext<'_>) -> Poll<T> {
+    let _no_preempt = NoPreempt::new();
+    match try_recv(chan) {
+        Some(val) => {
+            nudge();
+            Poll::Ready(val)
+        }
+        None => {
+            chan.add_receiver(cx.waker());
+            Poll::Pending
+        }
+    }
 }
@@ -0,0 +1,38 @@
+use core::time::Duration;
+
+use crate::time::Instant;
+
+// How much CPU time the threads sharing it may use per period, see `Group::set_cpu_quota`.
+// Threads over budget only run when no other thread is ready, until the next period starts.
+pub(crate) struct Budget {
+    quota: Duration,
+    period: Duration,
+    // when the current period started, and how much of it the threads have run.
+    started: Instant,
+    used: Duration,
+}
+
+impl Budget {
+    #[cfg(feature = "std")]
+    pub(crate) fn new(quota: Duration, period: Duration) -> Self {
+        Budget {
+            quota,
+            period,
+            started: crate::time::instant(),
+            used: Duration::ZERO,
+        }
+    }
+
+    // Counts `ran` against the budget, starting a new period first if the current one is over.
+    pub(crate) fn charge(&mut self, ran: Duration, now: Instant) {
+        if now.duration_since(self.started) >= self.period {
+            self.started = now;
+            self.used = Duration::ZERO;
+        }
+        self.used += ran;
+    }
+
+    pub(crate) fn exhausted(&self, now: Instant) -> bool {
+        self.used >= self.quota && now.duration_since(self.started) < self.period
+    }
+}
@@ -0,0 +1,44 @@
+use alloc::string::String;
+use core::fmt;
+use core::str::FromStr;
+
+/// Settings of the chaos mode, see `Runtime::set_chaos`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Chaos {
+    /// How likely the runtime is to yield on behalf of a thread whenever it calls into it, in 0.0..=1.0.
+    pub probability: f64,
+    /// Seeds the random choices.
+    pub seed: u64,
+}
+
+/// The error returned when chaos settings can't be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseChaosError(String);
+
+impl fmt::Display for ParseChaosError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid chaos settings {:?}, expected PROBABILITY[,SEED]",
+            self.0
+        )
+    }
+}
+
+impl core::error::Error for ParseChaosError {}
+
+impl FromStr for Chaos {
+    type Err = ParseChaosError;
+
+    // Parses `PROBABILITY[,SEED]`, the seed being 0 if omitted.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || ParseChaosError(s.into());
+        let (probability, seed) = s.split_once(',').unwrap_or((s, "0"));
+        let probability: f64 = probability.trim().parse().map_err(|_| err())?;
+        if !(0.0..=1.0).contains(&probability) {
+            return Err(err());
+        }
+        let seed = seed.trim().parse().map_err(|_| err())?;
+        Ok(Chaos { probability, seed })
+    }
+}
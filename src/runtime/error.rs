@@ -0,0 +1,49 @@
+use core::error;
+use core::fmt;
+#[cfg(feature = "std")]
+use std::io;
+
+/// The errors of the runtime which a program may want to deal with, rather than abort on.
+#[derive(Debug)]
+pub enum RuntimeError {
+    /// The IO reactor of the runtime couldn't be created.
+    #[cfg(feature = "std")]
+    Reactor(io::Error),
+    /// The handler catching stack overflows couldn't be installed.
+    #[cfg(feature = "std")]
+    OverflowHandler(io::Error),
+    /// A thread couldn't be spawned, as `max` threads were alive already, see `Runtime::set_max_threads`.
+    TooManyThreads { max: usize },
+    /// Threads can't be switched on the OS thread, for the reason given,
+    /// e.g. as the shadow stack of Intel CET is enabled and the context switching backend doesn't support it.
+    Unsupported(&'static str),
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            #[cfg(feature = "std")]
+            RuntimeError::Reactor(err) => write!(f, "failed to create the IO reactor: {err}"),
+            #[cfg(feature = "std")]
+            RuntimeError::OverflowHandler(err) => {
+                write!(f, "failed to install the stack overflow handler: {err}")
+            }
+            RuntimeError::TooManyThreads { max } => {
+                write!(f, "failed to spawn a thread, {max} are alive already")
+            }
+            RuntimeError::Unsupported(reason) => {
+                write!(f, "threads can't be switched on this OS thread: {reason}")
+            }
+        }
+    }
+}
+
+impl error::Error for RuntimeError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            #[cfg(feature = "std")]
+            RuntimeError::Reactor(err) | RuntimeError::OverflowHandler(err) => Some(err),
+            RuntimeError::TooManyThreads { .. } | RuntimeError::Unsupported(_) => None,
+        }
+    }
+}
@@ -0,0 +1,198 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use super::Runtime;
+use crate::runtime;
+
+/// Limits of `explore`, to keep the number of schedules tried manageable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bounds {
+    /// How many schedules to try at most.
+    pub max_schedules: usize,
+    /// How many times at most the runtime yields on behalf of the threads in a schedule.
+    /// Most bugs need few of them to show up, and every one allowed multiplies the number of schedules.
+    pub max_yields: usize,
+}
+
+impl Default for Bounds {
+    fn default() -> Self {
+        Bounds {
+            max_schedules: 10_000,
+            max_yields: 2,
+        }
+    }
+}
+
+/// What `explore` did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Explored {
+    /// How many schedules were tried.
+    pub schedules: usize,
+    /// Whether those were all the schedules within the bounds, i.e. `max_schedules` wasn't reached.
+    pub complete: bool,
+}
+
+/// Runs `test` once for every way of interleaving its threads, within `bounds`, for small tests.
+/// `test` is given a new runtime every time, already initialised, to spawn its threads on and run,
+/// and checks the outcome with assertions, in the threads or once the runtime has run.
+/// Every time a thread calls into the runtime (e.g. to use a channel or spawn a thread), it may yield,
+/// and every time the runtime switches threads, any of the ready ones may run next.
+/// The schedules are tried one after the other, depth first, so the test must not depend on time or on the outside world,
+/// and must run the same way every time it is given the same schedule: statics have to be reset at its start.
+///
+/// When `test` panics, the schedule it panicked in is printed to stderr. Setting the `UTHREADS_EXPLORE` environment variable
+/// to it then makes `explore` run only that schedule, to debug it.
+/// The panic hook this reports from is put back as it was once `explore` returns, or once it has panicked.
+pub fn explore(bounds: Bounds, mut test: impl FnMut(&mut Runtime)) -> Explored {
+    let only: Option<Vec<usize>> = std::env::var("UTHREADS_EXPLORE").ok().map(|path| {
+        path.split_whitespace()
+            .map(|choice| choice.parse().expect("invalid choice in UTHREADS_EXPLORE"))
+            .collect()
+    });
+
+    install_hook();
+    let explored = panic::catch_unwind(AssertUnwindSafe(|| {
+        let mut prefix = only.clone().unwrap_or_default();
+        let mut schedules = 0;
+        let complete = loop {
+            let mut runtime = Runtime::new();
+            runtime.set_time_slice(None);
+            unsafe {
+                runtime.core.as_mut().exploration =
+                    Some(Exploration::new(schedules, prefix, bounds.max_yields))
+            };
+            unsafe { runtime.init() };
+            test(&mut runtime);
+            schedules += 1;
+
+            let exploration = unsafe { runtime.core.as_mut().exploration.take() }.unwrap();
+            drop(runtime);
+            if only.is_some() {
+                break true;
+            }
+            match exploration.next_prefix() {
+                Some(next) if schedules < bounds.max_schedules => prefix = next,
+                Some(_) => break false,
+                None => break true,
+            }
+        };
+        Explored {
+            schedules,
+            complete,
+        }
+    }));
+    // the hook can't be replaced while panicking, so this waits for the unwind to be caught.
+    uninstall_hook();
+    explored.unwrap_or_else(|payload| panic::resume_unwind(payload))
+}
+
+type Hook = Arc<dyn Fn(&panic::PanicHookInfo) + Send + Sync>;
+
+// How many explorations are running, on any OS thread, and the panic hook to put back once there are none left.
+static HOOK: Mutex<(usize, Option<Hook>)> = Mutex::new((0, None));
+
+// Makes panics report the schedule being explored before calling the previous hook.
+// The hook is shared by the explorations running at the same time, e.g. in parallel tests,
+// and only reports for the OS thread which panicked.
+fn install_hook() {
+    let mut hook = HOOK.lock().unwrap_or_else(PoisonError::into_inner);
+    if hook.0 == 0 {
+        let previous: Hook = panic::take_hook().into();
+        hook.1 = Some(previous.clone());
+        panic::set_hook(Box::new(move |info| {
+            report();
+            previous(info)
+        }));
+    }
+    hook.0 += 1;
+}
+
+// Puts the previous panic hook back once the last exploration running is done.
+fn uninstall_hook() {
+    let mut hook = HOOK.lock().unwrap_or_else(PoisonError::into_inner);
+    hook.0 -= 1;
+    if hook.0 == 0 {
+        if let Some(previous) = hook.1.take() {
+            let _ = panic::take_hook();
+            panic::set_hook(Box::new(move |info| previous(info)));
+        }
+    }
+}
+
+// Prints the schedule being explored, from the panic hook.
+fn report() {
+    let Some(exploration) =
+        (unsafe { runtime().as_ref() }).and_then(|core| core.exploration.as_ref())
+    else {
+        return;
+    };
+    let path: Vec<String> = exploration
+        .decisions
+        .iter()
+        .map(|(choice, _)| choice.to_string())
+        .collect();
+    eprintln!(
+        "uthreads: panicked in schedule {} of the exploration, set UTHREADS_EXPLORE=\"{}\" to run only that one",
+        exploration.schedule,
+        path.join(" ")
+    );
+}
+
+// The schedule being explored: the choices to make at the first decisions, then the first option at the next ones.
+pub(crate) struct Exploration {
+    // index of the schedule, for reporting.
+    schedule: usize,
+    prefix: Vec<usize>,
+    // the choices made so far, and how many options there were.
+    decisions: Vec<(usize, usize)>,
+    yields_left: usize,
+}
+
+impl Exploration {
+    fn new(schedule: usize, prefix: Vec<usize>, max_yields: usize) -> Self {
+        Exploration {
+            schedule,
+            prefix,
+            decisions: Vec::new(),
+            yields_left: max_yields,
+        }
+    }
+
+    // Picks one of `count` options, which must not be 0. Decisions with a single option aren't recorded.
+    pub(crate) fn decide(&mut self, count: usize) -> usize {
+        if count == 1 {
+            return 0;
+        }
+        let step = self.decisions.len();
+        let choice = self.prefix.get(step).copied().unwrap_or(0);
+        assert!(
+            choice < count,
+            "the run diverged from the schedule explored at step {step}"
+        );
+        self.decisions.push((choice, count));
+        choice
+    }
+
+    // Whether to yield on behalf of the running thread at this entry point of the runtime.
+    pub(crate) fn should_yield(&mut self) -> bool {
+        if self.yields_left == 0 || self.decide(2) == 0 {
+            return false;
+        }
+        self.yields_left -= 1;
+        true
+    }
+
+    // Returns the choices leading to the schedule to try after this one, depth first, None if this was the last one.
+    fn next_prefix(&self) -> Option<Vec<usize>> {
+        let last = self
+            .decisions
+            .iter()
+            .rposition(|&(choice, count)| choice + 1 < count)?;
+        let mut prefix: Vec<usize> = self.decisions[..last]
+            .iter()
+            .map(|&(choice, _)| choice)
+            .collect();
+        prefix.push(self.decisions[last].0 + 1);
+        Some(prefix)
+    }
+}
@@ -0,0 +1,132 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+
+use super::{Core, Metrics};
+use crate::reactor::RuntimeWaker;
+use crate::runtime;
+
+type Job = Box<dyn FnOnce() + Send>;
+type Query = Box<dyn FnOnce(&Core) + Send>;
+
+/// A handle to a runtime that any OS thread can spawn green threads with, see `Runtime::handle`.
+/// E.g. for the callbacks of a C library, or the tasks of another executor, to hand work over to the runtime,
+/// or for libraries to use the runtime they are given rather than that of the current OS thread.
+/// The runtime keeps running, waiting for work, as long as a handle to it exists, or until shut down through one.
+#[derive(Debug, Clone)]
+pub struct Handle {
+    injector: Arc<Injector>,
+    waker: RuntimeWaker,
+}
+
+// The closures spawned through handles, until the runtime gets to spawn them as threads.
+pub(crate) struct Injector {
+    jobs: Mutex<Vec<Job>>,
+    // what other OS threads asked about the runtime, answered by the scheduler along with spawning the jobs.
+    // None once the runtime is gone, as no answer would ever come.
+    queries: Mutex<Option<Vec<Query>>>,
+    shutdown: AtomicBool,
+}
+
+impl Default for Injector {
+    fn default() -> Self {
+        Injector {
+            jobs: Mutex::default(),
+            queries: Mutex::new(Some(Vec::new())),
+            shutdown: AtomicBool::new(false),
+        }
+    }
+}
+
+impl std::fmt::Debug for Injector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Injector").finish_non_exhaustive()
+    }
+}
+
+impl Handle {
+    pub(crate) fn new(injector: Arc<Injector>, waker: RuntimeWaker) -> Self {
+        Handle { injector, waker }
+    }
+
+    /// Spawns a thread running the closure `f` on the runtime, as soon as its scheduler gets to it.
+    /// Closures spawned once the runtime has been dropped never run.
+    /// Unlike `Runtime::spawn`, doesn't return the id of the thread, which doesn't exist yet.
+    pub fn spawn(&self, f: impl FnOnce() + Send + 'static) {
+        self.injector.jobs.lock().unwrap().push(Box::new(f));
+        self.waker.wake();
+    }
+
+    /// Returns a snapshot of the activity of the runtime, see `Runtime::metrics`, or None if the runtime is gone.
+    /// From another OS thread, blocks it until the scheduler of the runtime answers, which it does while the runtime runs.
+    pub fn metrics(&self) -> Option<Metrics> {
+        self.query(Core::metrics)
+    }
+
+    /// Returns a handle that other OS threads can use to unpark the threads of the runtime or wake it up.
+    pub fn waker(&self) -> RuntimeWaker {
+        self.waker.clone()
+    }
+
+    /// Stops the runtime: `run` returns as soon as the scheduler gets the control back, and so do the later calls,
+    /// without waiting for the threads left, e.g. those serving connections, or for the other handles to be dropped.
+    /// The threads which haven't completed are abandoned once the runtime is dropped, see `Runtime::shutdown_timeout`.
+    pub fn shutdown(&self) {
+        self.injector.shutdown.store(true, Ordering::Release);
+        self.waker.wake();
+    }
+
+    fn query<R: Send + 'static>(&self, f: fn(&Core) -> R) -> Option<R> {
+        // the scheduler doesn't run while the threads of its runtime do, so they look for themselves.
+        let core = unsafe { runtime().as_ref() };
+        if let Some(core) = core.filter(|core| Arc::ptr_eq(&core.injector, &self.injector)) {
+            return Some(f(core));
+        }
+        let (tx, rx) = mpsc::sync_channel(1);
+        self.injector
+            .queries
+            .lock()
+            .unwrap()
+            .as_mut()?
+            .push(Box::new(move |core| {
+                let _ = tx.send(f(core));
+            }));
+        self.waker.wake();
+        rx.recv().ok()
+    }
+}
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        // the runtime may be waiting only for handles, and has to check whether any is left.
+        self.waker.wake();
+    }
+}
+
+impl Injector {
+    // Returns whether any handle to the runtime exists, besides the injector of the runtime itself.
+    pub(crate) fn has_handles(self: &Arc<Self>) -> bool {
+        Arc::strong_count(self) > 1
+    }
+
+    pub(crate) fn take(&self) -> Vec<Job> {
+        std::mem::take(&mut *self.jobs.lock().unwrap())
+    }
+
+    pub(crate) fn take_queries(&self) -> Vec<Query> {
+        self.queries
+            .lock()
+            .unwrap()
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn is_shut_down(&self) -> bool {
+        self.shutdown.load(Ordering::Acquire)
+    }
+
+    // Called as the runtime is dropped: the queries left are dropped unanswered, which their handles see.
+    pub(crate) fn close(&self) {
+        self.queries.lock().unwrap().take();
+    }
+}
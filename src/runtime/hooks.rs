@@ -0,0 +1,71 @@
+// Callbacks registered on the runtime, called as it makes scheduling decisions (see `Runtime::on_spawn` and co).
+// They run in the middle of the runtime, with its core borrowed, so they must not call into it.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use crate::thread::{Id, State};
+
+type BlockHook = Box<dyn FnMut(Id, &State)>;
+
+#[derive(Default)]
+pub(crate) struct Hooks {
+    spawn: Vec<Box<dyn FnMut(Id)>>,
+    switch: Vec<Box<dyn FnMut(Id, Id)>>,
+    block: Vec<BlockHook>,
+    wake: Vec<Box<dyn FnMut(Id)>>,
+    exit: Vec<Box<dyn FnMut(Id)>>,
+    idle: Vec<Box<dyn FnMut()>>,
+}
+
+impl Hooks {
+    pub(crate) fn add_spawn(&mut self, hook: Box<dyn FnMut(Id)>) {
+        self.spawn.push(hook);
+    }
+
+    pub(crate) fn add_switch(&mut self, hook: Box<dyn FnMut(Id, Id)>) {
+        self.switch.push(hook);
+    }
+
+    pub(crate) fn add_block(&mut self, hook: BlockHook) {
+        self.block.push(hook);
+    }
+
+    pub(crate) fn add_wake(&mut self, hook: Box<dyn FnMut(Id)>) {
+        self.wake.push(hook);
+    }
+
+    pub(crate) fn add_exit(&mut self, hook: Box<dyn FnMut(Id)>) {
+        self.exit.push(hook);
+    }
+
+    pub(crate) fn add_idle(&mut self, hook: Box<dyn FnMut()>) {
+        self.idle.push(hook);
+    }
+
+    pub(crate) fn spawned(&mut self, id: Id) {
+        self.spawn.iter_mut().for_each(|hook| hook(id));
+    }
+
+    pub(crate) fn switched(&mut self, from: Id, to: Id) {
+        self.switch.iter_mut().for_each(|hook| hook(from, to));
+    }
+
+    pub(crate) fn blocked(&mut self, id: Id, reason: &State) {
+        self.block.iter_mut().for_each(|hook| hook(id, reason));
+    }
+
+    pub(crate) fn woken(&mut self, id: Id) {
+        self.wake.iter_mut().for_each(|hook| hook(id));
+    }
+
+    pub(crate) fn exited(&mut self, id: Id) {
+        self.exit.iter_mut().for_each(|hook| hook(id));
+    }
+
+    // Returns whether there was any hook to call.
+    pub(crate) fn idle(&mut self) -> bool {
+        self.idle.iter_mut().for_each(|hook| hook());
+        !self.idle.is_empty()
+    }
+}
@@ -0,0 +1,86 @@
+use std::io;
+use std::os::fd::{AsRawFd, RawFd};
+use std::time::Duration;
+
+use tokio::io::unix::AsyncFd;
+use tokio::io::Interest;
+
+use super::{switch_away, Runtime};
+use crate::preempt::NoPreempt;
+use crate::trace::event;
+
+// How many times the runtime switches threads before letting the tasks of the host runtime run.
+const BUDGET: usize = 64;
+
+// The descriptor of the poller of the reactor, which stays owned by the reactor.
+struct PollerFd(RawFd);
+
+impl AsRawFd for PollerFd {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0
+    }
+}
+
+impl Runtime {
+    /// Runs the threads like `run`, as a task of a tokio runtime, so that green threads can be adopted within an async program.
+    /// The threads run in turns of a few switches, in between which the other tasks of the tokio runtime run,
+    /// and when no thread is ready, the task waits on the tokio reactor for the events the threads wait on, and for their timers.
+    /// Must be awaited on the OS thread the runtime was initialised on, e.g. by `block_on` on a current-thread runtime,
+    /// or by a task spawned on a `LocalSet`. Fails if the reactor can't be registered with tokio's.
+    /// Unlike `run`, only returns once every thread has completed, even if those left are all blocked on channels,
+    /// as tasks may use the channels too (see `ChannelStream` and `ChannelSink`).
+    pub async fn run_async(&mut self) -> io::Result<()> {
+        let core = self.core.as_ptr();
+        event!(
+            Info,
+            Run,
+            unsafe { (*core).current },
+            "started running in tokio"
+        );
+        let poller = AsyncFd::with_interest(
+            PollerFd(unsafe { (*core).reactor.fd() }),
+            Interest::READABLE,
+        )?;
+        // threads blocked on channels may be woken up by tasks, see `ChannelStream`, and tasks nudge the runtime then,
+        // so the runtime waits for its threads as long as any is left, and always watches its wake pipe.
+        unsafe { (*core).reactor.set_held(true) };
+        loop {
+            let timeout = {
+                // the scheduler itself is never preempted.
+                let _no_preempt = NoPreempt::new();
+                let mut switches = 0;
+                loop {
+                    unsafe { (*core).poll_io(Some(Duration::ZERO)) };
+                    unsafe { (*core).inject() };
+                    if switches == BUDGET || !unsafe { switch_away(core) } {
+                        break;
+                    }
+                    switches += 1;
+                }
+                if switches == BUDGET {
+                    None
+                } else {
+                    // only the base thread is left.
+                    let done = unsafe { (*core).threads.len() == 1 };
+                    if done && !unsafe { (*core).injector.has_handles() } {
+                        unsafe { (*core).reactor.set_held(false) };
+                        return Ok(());
+                    }
+                    // polling above armed the poller for the events the threads wait on.
+                    Some(unsafe { (*core).reactor.timeout() })
+                }
+            };
+
+            match timeout {
+                // threads are still ready, they run again once the other tasks had their turn.
+                None => tokio::task::yield_now().await,
+                Some(None) => poller.readable().await?.clear_ready(),
+                Some(Some(timeout)) => {
+                    if let Ok(guard) = tokio::time::timeout(timeout, poller.readable()).await {
+                        guard?.clear_ready();
+                    }
+                }
+            }
+        }
+    }
+}
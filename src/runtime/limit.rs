@@ -0,0 +1,35 @@
+use alloc::collections::VecDeque;
+
+use super::RuntimeError;
+use crate::thread::Id;
+
+/// What spawning a thread does when as many are alive as allowed, see `Runtime::set_max_threads`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WhenFull {
+    /// Fails with `RuntimeError::TooManyThreads`.
+    Fail,
+    /// Blocks the spawning thread until another thread completes.
+    /// The base thread, which can't block, fails instead.
+    Block,
+}
+
+// The limit on the threads alive, and the threads blocked until one completes.
+pub(crate) struct Limit {
+    pub(crate) max: usize,
+    pub(crate) when_full: WhenFull,
+    pub(crate) waiters: VecDeque<Id>,
+}
+
+impl Limit {
+    pub(crate) fn new(max: usize, when_full: WhenFull) -> Self {
+        Limit {
+            max,
+            when_full,
+            waiters: VecDeque::new(),
+        }
+    }
+
+    pub(crate) fn error(&self) -> RuntimeError {
+        RuntimeError::TooManyThreads { max: self.max }
+    }
+}
@@ -0,0 +1,173 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::time::Duration;
+#[cfg(feature = "std")]
+use std::os::fd::RawFd;
+
+#[cfg(feature = "std")]
+use crate::reactor::Interest;
+use crate::thread::{Id, State, Thread};
+use crate::time::Instant;
+use crate::BASE_THREAD_ID;
+
+/// Snapshot of the activity of a runtime, see `Runtime::metrics`.
+/// The counts are since the runtime was created, the rest as of when the snapshot was taken.
+/// The base thread, which only runs the scheduler, is left out.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Metrics {
+    /// How many times the runtime switched from one thread to another.
+    pub switches: u64,
+    /// How many threads have been spawned.
+    pub spawned: u64,
+    /// How many threads have completed.
+    pub exited: u64,
+    /// How many times a thread blocked on a channel, to send or to receive.
+    pub channel_waits: u64,
+    /// How many threads are ready to run, waiting for their turn: the depth of the scheduler queue.
+    pub ready: usize,
+    /// How many threads are blocked, by what they wait on.
+    pub blocked: Blocked,
+    /// The activity of the live named channels, see `Channel::set_name`.
+    pub channels: Vec<ChannelMetrics>,
+    /// How much of their stacks the live threads have used.
+    /// Takes looking at every stack, so the snapshot takes longer the more threads there are.
+    /// Without std, the memory of a stack can't be told apart from what was used, so none is counted as used.
+    pub stacks: StackUsage,
+}
+
+/// How much of their stacks the live threads have used at most, see `Runtime::stack_usage`,
+/// e.g. to size stacks from what the threads actually take rather than to guess.
+/// Threads which haven't run yet, and so have no stack yet, are left out.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct StackUsage {
+    /// How many stacks were looked at.
+    pub threads: usize,
+    /// The most a thread has used.
+    pub max: usize,
+    /// The thread which has used the most, if any.
+    pub max_thread: Option<Id>,
+    /// What the threads have used together.
+    pub total: usize,
+    /// Size of the largest stack, guard area included, to compare `max` with.
+    pub largest: usize,
+}
+
+/// Activity of a named channel since it was named, see `Metrics::channels`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChannelMetrics {
+    pub name: String,
+    /// How many values were sent to the channel, not counting those dropped as it was full.
+    pub sent: u64,
+    pub received: u64,
+    /// How many values were in the buffer as of the last one sent or received.
+    pub depth: usize,
+    /// Total time threads have spent blocked on the channel, sending or receiving.
+    pub blocked: Duration,
+}
+
+/// Memory held by a runtime for its threads and channels, see `Runtime::memory_stats`.
+/// Stacks reserve address space for their whole size upfront, but only the pages the threads touch take up memory:
+/// `stack_committed` tells how much does, as far as can be told (see `Stack::used`), which is what to plan capacity on.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MemoryStats {
+    /// Size of the stacks of the live threads and of the pooled ones, guard areas included.
+    pub stack_reserved: usize,
+    /// How much of these stacks is in memory.
+    pub stack_committed: usize,
+    /// How many stacks of completed threads are kept for reuse by the threads spawned next.
+    pub pooled_stacks: usize,
+    /// Size of the pooled stacks, part of `stack_reserved`.
+    pub pooled_reserved: usize,
+    /// Size of the buffers of the live named channels, see `Channel::set_name`. The runtime doesn't know about the others.
+    pub channel_buffers: usize,
+    /// The stack of every live thread, the base thread left out as it runs on the stack of the OS thread.
+    pub threads: Vec<ThreadMemory>,
+}
+
+/// Memory held for a thread, see `MemoryStats::threads`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ThreadMemory {
+    pub id: Id,
+    /// Size of its stack, guard area included, zero if it hasn't run yet and so has none yet.
+    pub stack_reserved: usize,
+    /// How much of its stack is in memory.
+    pub stack_committed: usize,
+}
+
+/// Number of threads blocked, by what they wait on, see `State`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Blocked {
+    pub channel_send: usize,
+    pub channel_recv: usize,
+    pub sync: usize,
+    pub io: usize,
+    pub sleeping: usize,
+    pub parked: usize,
+}
+
+impl Blocked {
+    /// Returns how many threads are blocked in total.
+    pub fn total(&self) -> usize {
+        self.channel_send + self.channel_recv + self.sync + self.io + self.sleeping + self.parked
+    }
+}
+
+impl StackUsage {
+    /// Returns how much of its stack a thread has used on average.
+    pub fn mean(&self) -> usize {
+        self.total.checked_div(self.threads).unwrap_or(0)
+    }
+
+    // Counts the stack of a thread into the summary.
+    pub(crate) fn add(&mut self, id: Id, size: usize, used: usize) {
+        self.threads += 1;
+        self.total += used;
+        self.largest = self.largest.max(size);
+        if self.max_thread.is_none() || used > self.max {
+            self.max = used;
+            self.max_thread = Some(id);
+        }
+    }
+}
+
+impl Metrics {
+    // Counts the threads that are ready or blocked into the snapshot.
+    pub(crate) fn count_threads(&mut self, threads: &[Thread]) {
+        for thread in threads.iter().filter(|t| t.id != BASE_THREAD_ID) {
+            let count = match thread.state {
+                State::Running => continue,
+                State::Ready => &mut self.ready,
+                State::ChannelBlockSend => &mut self.blocked.channel_send,
+                State::ChannelBlockRecv => &mut self.blocked.channel_recv,
+                State::SyncBlock => &mut self.blocked.sync,
+                State::IoBlocked => &mut self.blocked.io,
+                State::Sleeping => &mut self.blocked.sleeping,
+                State::Parked => &mut self.blocked.parked,
+            };
+            *count += 1;
+        }
+    }
+}
+
+/// What a thread was doing when the snapshot was taken, see `Runtime::threads`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ThreadInfo {
+    pub id: Id,
+    pub state: State,
+    /// What the thread is blocked on, if it is blocked on something the runtime knows about.
+    pub blocked_on: Option<BlockedOn>,
+    /// Total time the thread has spent running.
+    pub run_time: Duration,
+}
+
+/// What a blocked thread waits on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockedOn {
+    /// The channel at this address, to send to it or receive from it.
+    Channel(usize),
+    /// The file descriptor, to be ready for the interest.
+    #[cfg(feature = "std")]
+    Io(RawFd, Interest),
+    /// A timer, going off at the instant.
+    Timer(Instant),
+}
@@ -0,0 +1,32 @@
+// Pseudo random numbers for the seeded scheduling mode and the chaos mode (see `Runtime::set_seed` and `Runtime::set_chaos`).
+// Only needs to be fast and reproducible, so a SplitMix64 generator does.
+
+pub(crate) struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub(crate) fn new(seed: u64) -> Self {
+        Rng { state: seed }
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // Returns a number in 0..n, which must not be 0.
+    pub(crate) fn below(&mut self, n: usize) -> usize {
+        // the bias of the modulo doesn't matter here.
+        (self.next_u64() % n as u64) as usize
+    }
+
+    // Returns true with the given probability, in 0.0..=1.0.
+    pub(crate) fn chance(&mut self, probability: f64) -> bool {
+        // 53 bits, as many as an f64 holds exactly.
+        ((self.next_u64() >> 11) as f64 / (1u64 << 53) as f64) < probability
+    }
+}
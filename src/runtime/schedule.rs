@@ -0,0 +1,118 @@
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::thread::Id;
+
+/// The decisions of the scheduler over a run: which thread it switched to every time, in order.
+/// Recorded with `Runtime::record`, and replayed with `Runtime::replay` to make another run take the same course.
+/// Written as text, with the ids of the threads separated by whitespace.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Schedule {
+    choices: Vec<Id>,
+}
+
+impl Schedule {
+    /// Returns the threads switched to, in order.
+    pub fn choices(&self) -> &[Id] {
+        &self.choices
+    }
+
+    /// Reads a schedule written with `save`, or by the runtime when recording to a file.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        fs::read_to_string(path)?
+            .parse()
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    /// Writes the schedule to the file at `path`.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for id in &self.choices {
+            writeln!(f, "{}", id.0)?;
+        }
+        Ok(())
+    }
+}
+
+/// The error returned when a schedule can't be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseScheduleError(String);
+
+impl fmt::Display for ParseScheduleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid thread id in schedule: {:?}", self.0)
+    }
+}
+
+impl std::error::Error for ParseScheduleError {}
+
+impl FromStr for Schedule {
+    type Err = ParseScheduleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let choices = s
+            .split_whitespace()
+            .map(|id| {
+                id.parse()
+                    .map(Id)
+                    .map_err(|_| ParseScheduleError(id.into()))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Schedule { choices })
+    }
+}
+
+// The decisions of the scheduler recorded so far,
+// also written to a file as they are made if any, so that they survive the process crashing.
+pub(crate) struct Recording {
+    schedule: Schedule,
+    file: Option<File>,
+}
+
+impl Recording {
+    pub(crate) fn new(file: Option<File>) -> Self {
+        Recording {
+            schedule: Schedule::default(),
+            file,
+        }
+    }
+
+    pub(crate) fn push(&mut self, id: Id) {
+        self.schedule.choices.push(id);
+        if let Some(file) = &mut self.file {
+            // a recording missing its end is still useful, so failing to write doesn't stop the run.
+            let _ = writeln!(file, "{}", id.0);
+        }
+    }
+
+    pub(crate) fn schedule(&self) -> &Schedule {
+        &self.schedule
+    }
+}
+
+// A schedule being replayed, and how far.
+pub(crate) struct Replay {
+    schedule: Schedule,
+    step: usize,
+}
+
+impl Replay {
+    pub(crate) fn new(schedule: Schedule) -> Self {
+        Replay { schedule, step: 0 }
+    }
+
+    // Returns the thread to switch to next, None once the whole schedule has been replayed.
+    pub(crate) fn next(&mut self) -> Option<(usize, Id)> {
+        let id = *self.schedule.choices.get(self.step)?;
+        self.step += 1;
+        Some((self.step - 1, id))
+    }
+}
@@ -0,0 +1,61 @@
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::time::Duration;
+
+use crate::thread::{Id, State, Thread};
+use crate::time::{self, Instant};
+use crate::trace::event;
+use crate::BASE_THREAD_ID;
+
+// Finds the threads which have been ready to run for longer than `threshold` without getting their turn,
+// and has them run ahead of the others, once, so that every thread makes progress, see `Runtime::set_starvation_threshold`.
+pub(crate) struct Starvation {
+    threshold: Duration,
+    // the threads are only looked at a few times per threshold, rather than at every switch.
+    next_check: Instant,
+    // the starving threads found, which run next, longest waiting first.
+    boosted: VecDeque<Id>,
+}
+
+impl Starvation {
+    pub(crate) fn new(threshold: Duration) -> Self {
+        Starvation {
+            threshold,
+            next_check: time::instant(),
+            boosted: VecDeque::new(),
+        }
+    }
+
+    // Returns the position of the next starving thread to run, if any, looking for them first if it is time to.
+    pub(crate) fn next(&mut self, threads: &[Thread], now: Instant) -> Option<usize> {
+        if now >= self.next_check {
+            self.next_check = now + self.threshold / 4;
+            let mut starving: Vec<(Id, Duration)> = threads
+                .iter()
+                .filter(|t| t.id != BASE_THREAD_ID && t.state == State::Ready)
+                .filter_map(|t| Some((t.id, now.duration_since(t.ready_since?))))
+                .filter(|&(id, waited)| waited >= self.threshold && !self.boosted.contains(&id))
+                .collect();
+            starving.sort_by_key(|&(_, waited)| core::cmp::Reverse(waited));
+            for (id, waited) in starving {
+                event!(
+                    Info,
+                    Starve,
+                    id,
+                    "has been ready for {waited:?} without running"
+                );
+                self.boosted.push_back(id);
+            }
+        }
+        // the threads may have run or blocked since they were found.
+        while let Some(id) = self.boosted.pop_front() {
+            if let Some(pos) = threads
+                .iter()
+                .position(|t| t.id == id && t.state == State::Ready)
+            {
+                return Some(pos);
+            }
+        }
+        None
+    }
+}
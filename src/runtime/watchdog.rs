@@ -0,0 +1,214 @@
+use std::ffi::{c_void, CStr};
+use std::fmt;
+use std::mem;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::arch::program_counter;
+use crate::thread::Id;
+use crate::trace::event;
+use crate::BASE_THREAD_ID;
+
+// Watches the runtime from an OS thread of its own, as a thread hogging the CPU keeps the runtime from doing anything,
+// and reports the threads which have run for longer than `threshold` without switching away, see `Runtime::set_watchdog`.
+pub(crate) struct Watchdog {
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<()>>,
+}
+
+struct Shared {
+    threshold: Duration,
+    // how many times the runtime switched threads, and the thread it switched to last.
+    switches: AtomicU64,
+    current: AtomicUsize,
+    stopped: Mutex<bool>,
+    stop: Condvar,
+    // the OS thread of the runtime, to sample where a stalled thread runs, in debug builds, see `sample`.
+    runtime_thread: Option<usize>,
+}
+
+impl Watchdog {
+    pub(crate) fn start(threshold: Duration) -> Self {
+        let shared = Arc::new(Shared {
+            threshold,
+            switches: AtomicU64::new(0),
+            current: AtomicUsize::new(BASE_THREAD_ID.0),
+            stopped: Mutex::new(false),
+            stop: Condvar::new(),
+            // started from the runtime, so on its OS thread.
+            runtime_thread: (cfg!(debug_assertions) && install_sampler())
+                .then(|| unsafe { libc::pthread_self() } as usize),
+        });
+        let thread = thread::Builder::new()
+            .name("uthreads-watchdog".into())
+            .spawn({
+                let shared = shared.clone();
+                move || shared.watch()
+            })
+            .expect("failed to spawn the watchdog thread");
+        Watchdog {
+            shared,
+            thread: Some(thread),
+        }
+    }
+
+    // Called by the runtime as it switches to thread `id`.
+    pub(crate) fn switched(&self, id: Id) {
+        self.shared.current.store(id.0, Ordering::Relaxed);
+        self.shared.switches.fetch_add(1, Ordering::Release);
+    }
+}
+
+impl Shared {
+    fn watch(&self) {
+        // checking a few times per threshold tells how long a thread has run closely enough.
+        let period = self.threshold / 4;
+        let mut last = (self.switches.load(Ordering::Acquire), Instant::now());
+        let mut reported = false;
+        let mut stopped = self.stopped.lock().unwrap();
+        while !*stopped {
+            stopped = self.stop.wait_timeout(stopped, period).unwrap().0;
+            let switches = self.switches.load(Ordering::Acquire);
+            if switches != last.0 {
+                last = (switches, Instant::now());
+                reported = false;
+                continue;
+            }
+            // the base thread only runs the scheduler, which waits for events when no other thread can run.
+            let id = Id(self.current.load(Ordering::Relaxed));
+            let ran = last.1.elapsed();
+            if !reported && id != BASE_THREAD_ID && ran >= self.threshold {
+                match self.locate() {
+                    Some(pc) => event!(
+                        Info,
+                        Stall,
+                        id,
+                        "has run for {ran:?} without yielding, last seen at {}",
+                        Location(pc)
+                    ),
+                    None => event!(Info, Stall, id, "has run for {ran:?} without yielding"),
+                }
+                reported = true;
+            }
+        }
+    }
+
+    // Finds out where the thread hogging the OS thread of the runtime runs, from a few samples of its program counter.
+    // It may well be in a library, e.g. reading the clock in a loop, so a sample in the program itself is preferred.
+    fn locate(&self) -> Option<usize> {
+        let mut last = None;
+        for _ in 0..SAMPLES {
+            let pc = self.sample()?;
+            if in_program(pc) {
+                return Some(pc);
+            }
+            last = Some(pc);
+            thread::sleep(Duration::from_millis(1));
+        }
+        last
+    }
+
+    // Interrupts the OS thread of the runtime to read its program counter.
+    // The thread hogs the CPU, so the handler runs right away, unless the OS thread is blocked in a system call.
+    fn sample(&self) -> Option<usize> {
+        let thread = self.runtime_thread?;
+        SAMPLE.store(0, Ordering::Relaxed);
+        if unsafe { libc::pthread_kill(thread as libc::pthread_t, SAMPLE_SIGNAL) } != 0 {
+            return None;
+        }
+        for _ in 0..100 {
+            let pc = SAMPLE.load(Ordering::Relaxed);
+            if pc != 0 {
+                return Some(pc);
+            }
+            thread::sleep(Duration::from_micros(100));
+        }
+        None
+    }
+}
+
+// Sampling the program counter of the runtime's OS thread, to tell where a stalled thread runs in debug builds.
+// SIGURG is ignored by default, and hardly ever used otherwise.
+const SAMPLE_SIGNAL: libc::c_int = libc::SIGURG;
+static SAMPLE: AtomicUsize = AtomicUsize::new(0);
+static SAMPLER: AtomicBool = AtomicBool::new(false);
+const SAMPLES: usize = 8;
+
+// Installs the handler sampling the program counter, where it can be read from the context of the signal.
+// Returns whether it is installed.
+fn install_sampler() -> bool {
+    if cfg!(miri)
+        || !cfg!(all(
+            target_os = "linux",
+            any(target_arch = "x86_64", target_arch = "aarch64")
+        ))
+    {
+        return false;
+    }
+    if SAMPLER.swap(true, Ordering::Relaxed) {
+        return true;
+    }
+    unsafe {
+        let mut action: libc::sigaction = mem::zeroed();
+        action.sa_sigaction = on_sample
+            as extern "C" fn(libc::c_int, *mut libc::siginfo_t, *mut c_void)
+            as libc::sighandler_t;
+        // the runtime may be polling for IO, which carries on once the sample is taken.
+        action.sa_flags = libc::SA_SIGINFO | libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+        libc::sigaction(SAMPLE_SIGNAL, &action, ptr::null_mut()) == 0
+    }
+}
+
+extern "C" fn on_sample(_: libc::c_int, _: *mut libc::siginfo_t, context: *mut c_void) {
+    SAMPLE.store(unsafe { program_counter(context) }, Ordering::Relaxed);
+}
+
+// Whether `pc` is in the same object file as the runtime, i.e. the program rather than a shared library.
+fn in_program(pc: usize) -> bool {
+    let base = |addr: usize| {
+        let mut info: libc::Dl_info = unsafe { mem::zeroed() };
+        (unsafe { libc::dladdr(addr as *const c_void, &mut info) } != 0)
+            .then_some(info.dli_fbase as usize)
+    };
+    base(pc).is_some_and(|object| Some(object) == base(in_program as *const () as usize))
+}
+
+// Names the function a program counter is in, if the dynamic linker knows it, and otherwise the offset in the object file,
+// which `addr2line` resolves, as functions aren't exported from executables unless linked to be.
+struct Location(usize);
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let pc = self.0;
+        let mut info: libc::Dl_info = unsafe { mem::zeroed() };
+        if unsafe { libc::dladdr(pc as *const c_void, &mut info) } == 0 || info.dli_fname.is_null()
+        {
+            return write!(f, "{pc:#x}");
+        }
+        let file = unsafe { CStr::from_ptr(info.dli_fname) }.to_string_lossy();
+        if info.dli_sname.is_null() {
+            write!(f, "{file} + {:#x}", pc - info.dli_fbase as usize)
+        } else {
+            let symbol = unsafe { CStr::from_ptr(info.dli_sname) }.to_string_lossy();
+            write!(
+                f,
+                "{symbol} + {:#x} in {file}",
+                pc - info.dli_saddr as usize
+            )
+        }
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        *self.shared.stopped.lock().unwrap() = true;
+        self.shared.stop.notify_one();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,174 @@
+// Waiting on several things at once: values to receive from channels, file descriptors to be ready, and a timeout.
+// The thread registers with every channel and with the reactor for every file descriptor, and blocks until one of them,
+// or the timer of the deadline, wakes it up. It then checks again what is ready, as wakeups can be spurious.
+
+use std::io;
+use std::os::fd::RawFd;
+use std::ptr::NonNull;
+use std::time::{Duration, Instant};
+
+use crate::channel::Channel;
+use crate::preempt::NoPreempt;
+use crate::reactor::Interest;
+use crate::runtime::{get_current_thread, select_wait};
+use crate::thread::Id;
+use crate::time;
+
+/// Blocks the current thread until one of several operations is ready, e.g. in a server waiting for jobs from a channel
+/// and for connections on a listener at once, with a timeout: `recv` and `io` add the operations, and `wait` blocks.
+/// It only tells which operation is ready, which the thread then carries out without blocking,
+/// e.g. with `chan_try_recv`. The operation may still fail as not ready, if another thread got there first,
+/// or as readiness can be spurious for file descriptors, in which case the thread waits again.
+pub struct Select {
+    ops: Vec<Op>,
+    deadline: Option<Instant>,
+}
+
+enum Op {
+    // a channel, with its type erased, and the functions to use it knowing its type.
+    Recv {
+        chan: NonNull<()>,
+        ready: unsafe fn(NonNull<()>) -> bool,
+        watch: unsafe fn(NonNull<()>, Id, bool),
+    },
+    Io(RawFd, Interest),
+}
+
+impl Select {
+    pub fn new() -> Self {
+        Select {
+            ops: Vec::new(),
+            deadline: None,
+        }
+    }
+
+    /// Waits for a value to receive from `chan`, from its buffer or from a thread blocked sending.
+    /// Returns the index of the operation, which `wait` returns once it is ready.
+    ///
+    /// # Safety
+    ///
+    /// `chan` must point to a live Channel, which stays so as long as the `Select` is used,
+    /// and is not accessed from outside the runtime.
+    pub unsafe fn recv<T>(&mut self, chan: *mut Channel<T>) -> usize {
+        let chan = NonNull::new(chan)
+            .expect("selecting on a null channel")
+            .cast();
+        self.push(Op::Recv {
+            chan,
+            ready: ready::<T>,
+            watch: watch::<T>,
+        })
+    }
+
+    /// Waits for `fd` to be ready for `interest`, see `runtime::wait_io`.
+    /// Returns the index of the operation, which `wait` returns once it is ready.
+    pub fn io(&mut self, fd: RawFd, interest: Interest) -> usize {
+        self.push(Op::Io(fd, interest))
+    }
+
+    /// Stops waiting once `timeout` has elapsed from now.
+    pub fn timeout(&mut self, timeout: Duration) {
+        self.deadline(time::now() + timeout);
+    }
+
+    /// Stops waiting once `deadline` has passed.
+    pub fn deadline(&mut self, deadline: Instant) {
+        self.deadline = Some(deadline);
+    }
+
+    fn push(&mut self, op: Op) -> usize {
+        self.ops.push(op);
+        self.ops.len() - 1
+    }
+
+    /// Blocks the current thread until one of the operations is ready, and returns its index.
+    /// The first one is returned if several are. Returns None once the deadline has passed, if any.
+    /// Fails if a file descriptor can't be waited on, e.g. as it is a regular file.
+    pub fn wait(&mut self) -> io::Result<Option<usize>> {
+        let id = get_current_thread();
+        let fds: Vec<(RawFd, Interest)> = self
+            .ops
+            .iter()
+            .filter_map(|op| match *op {
+                Op::Io(fd, interest) => Some((fd, interest)),
+                Op::Recv { .. } => None,
+            })
+            .collect();
+        loop {
+            // what is ready mustn't change in between checking and blocking.
+            let _no_preempt = NoPreempt::new();
+            if let Some(i) = self.ready()? {
+                return Ok(Some(i));
+            }
+            if self
+                .deadline
+                .is_some_and(|deadline| time::now() >= deadline)
+            {
+                return Ok(None);
+            }
+            self.watch(id, true);
+            let waited = select_wait(&fds, self.deadline);
+            self.watch(id, false);
+            waited?;
+        }
+    }
+
+    // Returns the first operation ready, checking the file descriptors without blocking.
+    fn ready(&self) -> io::Result<Option<usize>> {
+        let mut pollfds: Vec<libc::pollfd> = self
+            .ops
+            .iter()
+            .filter_map(|op| match *op {
+                Op::Io(fd, interest) => Some(libc::pollfd {
+                    fd,
+                    events: match interest {
+                        Interest::Readable => libc::POLLIN,
+                        Interest::Writable => libc::POLLOUT,
+                    },
+                    revents: 0,
+                }),
+                Op::Recv { .. } => None,
+            })
+            .collect();
+        if !pollfds.is_empty()
+            && unsafe { libc::poll(pollfds.as_mut_ptr(), pollfds.len() as libc::nfds_t, 0) } < 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+        // the file descriptors were polled in the order of their operations,
+        // which are checked in order so that the first one ready is returned, whatever its kind.
+        let mut pollfds = pollfds.iter();
+        Ok(self.ops.iter().position(|op| match *op {
+            Op::Recv { chan, ready, .. } => unsafe { ready(chan) },
+            Op::Io(..) => pollfds.next().is_some_and(|pollfd| pollfd.revents != 0),
+        }))
+    }
+
+    // Registers the current thread with the channels, to be woken up as a value is sent, or unregisters it.
+    fn watch(&self, id: Id, on: bool) {
+        for op in &self.ops {
+            if let Op::Recv { chan, watch, .. } = *op {
+                unsafe { watch(chan, id, on) };
+            }
+        }
+    }
+}
+
+impl Default for Select {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe fn ready<T>(chan: NonNull<()>) -> bool {
+    !unsafe { chan.cast::<Channel<T>>().as_ref() }.is_empty()
+}
+
+unsafe fn watch<T>(chan: NonNull<()>, id: Id, on: bool) {
+    let chan = unsafe { chan.cast::<Channel<T>>().as_mut() };
+    if on {
+        chan.add_selector(id);
+    } else {
+        chan.remove_selector(id);
+    }
+}
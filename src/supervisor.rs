@@ -0,0 +1,203 @@
+// Supervisors: actors which start other actors, and restart them when they panic, like the supervisors of Erlang.
+// A supervisor is an actor itself, so supervisors can supervise one another, forming a tree:
+// one which restarts its children too often gives up and panics, which its own supervisor deals with in turn.
+
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::actor::{self, Actor, Addr, Context, Exit, Monitor, MAILBOX_SIZE};
+
+thread_local! {
+    // Tells apart the instances of the children, including those of supervisors which were restarted.
+    static INSTANCES: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Which children a supervisor restarts when one of them panics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    /// Only the child which panicked.
+    OneForOne,
+    /// Every child still running, once it has handled the messages sent to it before.
+    AllForOne,
+}
+
+/// An actor starting a set of children, which are actors too, and restarting them when they panic, following its `Strategy`.
+/// A child which stops, e.g. through its address, isn't restarted, and the supervisor stops once all of its children have.
+/// Stopping the supervisor stops its children.
+/// If children panic more than a number of times within a window of time (see `set_max_restarts`),
+/// the supervisor stops them and panics: a supervisor which isn't supervised itself then aborts the process.
+///
+/// A supervisor starts its children once it is spawned with `actor::spawn`, or started by its own supervisor.
+pub struct Supervisor {
+    strategy: Strategy,
+    max_restarts: usize,
+    window: Duration,
+    children: Vec<Child>,
+    // when the children were restarted within the window.
+    restarts: VecDeque<Instant>,
+}
+
+struct Child {
+    spec: Box<dyn Supervised>,
+    // the instance running, if any.
+    instance: Option<u64>,
+}
+
+/// Sent to a supervisor by its children as they exit.
+#[derive(Debug)]
+pub struct ChildExit {
+    child: usize,
+    instance: u64,
+    exit: Exit,
+}
+
+// A child of a supervisor, with the means to start new instances of it.
+trait Supervised {
+    fn start(&self, monitor: Monitor);
+    fn restart(&self);
+    fn stop(&self);
+    fn close(&self);
+}
+
+struct Spec<A: Actor, F> {
+    addr: Addr<A>,
+    factory: F,
+}
+
+impl Supervisor {
+    /// Creates a supervisor without children, which gives up after 3 restarts within 5 seconds.
+    pub fn new(strategy: Strategy) -> Self {
+        Supervisor {
+            strategy,
+            max_restarts: 3,
+            window: Duration::from_secs(5),
+            children: Vec::new(),
+            restarts: VecDeque::new(),
+        }
+    }
+
+    /// Sets how many restarts the supervisor makes within `window` before giving up.
+    pub fn set_max_restarts(&mut self, restarts: usize, window: Duration) {
+        self.max_restarts = restarts;
+        self.window = window;
+    }
+
+    /// Adds a child, started with the actor `factory` returns, and with a new one every time it restarts.
+    /// Returns its address, which stays valid across restarts: the messages sent to it wait for the next instance.
+    pub fn add_child<A: Actor>(&mut self, factory: impl Fn() -> A + 'static) -> Addr<A> {
+        let addr = Addr::with_capacity(MAILBOX_SIZE);
+        self.children.push(Child {
+            spec: Box::new(Spec {
+                addr: addr.clone(),
+                factory,
+            }),
+            instance: None,
+        });
+        addr
+    }
+
+    fn start_child(&mut self, child: usize, supervisor: Addr<Supervisor>) {
+        let instance = INSTANCES.get() + 1;
+        INSTANCES.set(instance);
+        self.children[child].instance = Some(instance);
+        self.children[child].spec.start(Box::new(move |exit| {
+            // a supervisor which has stopped has nothing left to do with its children.
+            let _ = supervisor.send(ChildExit {
+                child,
+                instance,
+                exit,
+            });
+        }));
+    }
+
+    // Records a restart, returning false if there were too many within the window.
+    fn may_restart(&mut self) -> bool {
+        let now = Instant::now();
+        while let Some(&at) = self.restarts.front() {
+            if now.duration_since(at) <= self.window {
+                break;
+            }
+            self.restarts.pop_front();
+        }
+        self.restarts.push_back(now);
+        self.restarts.len() <= self.max_restarts
+    }
+
+    fn stop_children(&mut self) {
+        for child in &mut self.children {
+            match child.instance.take() {
+                Some(_) => child.spec.stop(),
+                None => child.spec.close(),
+            }
+        }
+    }
+}
+
+impl Actor for Supervisor {
+    type Message = ChildExit;
+
+    fn started(&mut self, ctx: &mut Context<Self>) {
+        for child in 0..self.children.len() {
+            self.start_child(child, ctx.address());
+        }
+        if self.children.is_empty() {
+            ctx.stop();
+        }
+    }
+
+    fn handle(&mut self, msg: ChildExit, ctx: &mut Context<Self>) {
+        // an instance which was already replaced, or stopped with the supervisor.
+        if self.children[msg.child].instance != Some(msg.instance) {
+            return;
+        }
+        self.children[msg.child].instance = None;
+        match msg.exit {
+            Exit::Stopped => {
+                if self.children.iter().all(|child| child.instance.is_none()) {
+                    ctx.stop();
+                }
+            }
+            Exit::Restarting => self.start_child(msg.child, ctx.address()),
+            Exit::Panicked => {
+                if !self.may_restart() {
+                    self.stop_children();
+                    panic!(
+                        "the supervisor gave up after {} restarts within {:?}",
+                        self.max_restarts, self.window
+                    );
+                }
+                self.start_child(msg.child, ctx.address());
+                if self.strategy == Strategy::AllForOne {
+                    for (i, child) in self.children.iter().enumerate() {
+                        if i != msg.child && child.instance.is_some() {
+                            child.spec.restart();
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn stopped(&mut self) {
+        self.stop_children();
+    }
+}
+
+impl<A: Actor, F: Fn() -> A> Supervised for Spec<A, F> {
+    fn start(&self, monitor: Monitor) {
+        actor::start(&self.addr, (self.factory)(), Some(monitor));
+    }
+
+    fn restart(&self) {
+        self.addr.restart();
+    }
+
+    fn stop(&self) {
+        self.addr.stop();
+    }
+
+    fn close(&self) {
+        self.addr.close();
+    }
+}
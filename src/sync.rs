@@ -0,0 +1,21 @@
+// Synchronisation primitives for green threads.
+// A thread that has to wait on one of these is marked as blocked and gives control to another thread,
+// instead of spinning and starving the thread it is waiting on.
+
+mod barrier;
+mod condvar;
+mod latch;
+mod mutex;
+mod once;
+mod rate;
+mod rwlock;
+mod semaphore;
+
+pub use barrier::{Barrier, BarrierWaitResult};
+pub use condvar::{Condvar, WaitTimeoutResult};
+pub use latch::CountDownLatch;
+pub use mutex::{Mutex, MutexGuard};
+pub use once::{Once, OnceCell};
+pub use rate::RateLimiter;
+pub use rwlock::{Fairness, RwLock, RwLockReadGuard, RwLockWriteGuard};
+pub use semaphore::{Semaphore, SemaphorePermit};
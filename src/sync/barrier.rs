@@ -0,0 +1,84 @@
+use alloc::vec::Vec;
+use core::cell::{Cell, RefCell};
+use core::fmt;
+
+use crate::preempt::NoPreempt;
+use crate::runtime::{change_thread_state, get_current_thread, yield_thread};
+use crate::thread::{Id, State};
+use crate::trace::event;
+
+/// Lets a number of threads wait for each other, e.g. between the phases of an algorithm split among them.
+/// Every thread calling `wait` blocks until as many threads as the barrier is for have, and they all go on at once.
+/// The barrier can be used again right away for the next phase: each time it opens, a new generation starts.
+pub struct Barrier {
+    // how many threads each generation is for.
+    n: usize,
+    // the threads of the current generation waiting for the others.
+    waiters: RefCell<Vec<Id>>,
+    generation: Cell<u64>,
+}
+
+/// Tells whether the thread was the leader of its generation at a `Barrier`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct BarrierWaitResult(bool);
+
+impl BarrierWaitResult {
+    /// Returns whether the thread is the leader of its generation: exactly one is, the last one to come,
+    /// e.g. for it to do the bookkeeping between two phases.
+    pub fn is_leader(&self) -> bool {
+        self.0
+    }
+}
+
+impl Barrier {
+    /// Creates a barrier for `n` threads. A barrier for none lets every thread through, like one for a single thread.
+    pub const fn new(n: usize) -> Self {
+        Barrier {
+            n,
+            waiters: RefCell::new(Vec::new()),
+            generation: Cell::new(0),
+        }
+    }
+
+    /// Blocks the current thread until all the threads of its generation have come, see `Barrier`.
+    pub fn wait(&self) -> BarrierWaitResult {
+        let id = get_current_thread();
+        // the generation mustn't be completed between counting the thread in and blocking it.
+        let _no_preempt = NoPreempt::new();
+        if self.waiters.borrow().len() + 1 >= self.n {
+            let waiters = core::mem::take(&mut *self.waiters.borrow_mut());
+            self.generation.set(self.generation.get() + 1);
+            for waiter in waiters {
+                change_thread_state(waiter, State::Ready);
+            }
+            return BarrierWaitResult(true);
+        }
+        event!(
+            Debug,
+            Sync,
+            id,
+            "waiting on a barrier at {}/{}",
+            self.waiters.borrow().len() + 1,
+            self.n
+        );
+        self.waiters.borrow_mut().push(id);
+        change_thread_state(id, State::SyncBlock);
+        yield_thread();
+        BarrierWaitResult(false)
+    }
+
+    /// Returns how many times the barrier has opened.
+    pub fn generation(&self) -> u64 {
+        self.generation.get()
+    }
+}
+
+impl fmt::Debug for Barrier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Barrier")
+            .field("n", &self.n)
+            .field("waiting", &self.waiters.borrow().len())
+            .field("generation", &self.generation.get())
+            .finish()
+    }
+}
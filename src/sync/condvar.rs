@@ -0,0 +1,125 @@
+use alloc::collections::VecDeque;
+use core::cell::RefCell;
+use core::fmt;
+use core::time::Duration;
+
+use super::MutexGuard;
+use crate::preempt::NoPreempt;
+use crate::runtime::{get_current_thread, wait_woken, wake_selector};
+use crate::thread::Id;
+use crate::time::{self, Instant};
+use crate::trace::event;
+
+/// Lets threads wait for a condition on the value of a `Mutex` to hold, e.g. for a queue to have an item,
+/// while other threads change the value and notify them.
+/// Waiting releases the lock, and takes it again once the thread is notified, so that the others can change the value.
+/// The threads waiting are notified in the order they came. A thread is only ever woken up by a notification
+/// or by its timeout passing, but the condition may not hold anymore by the time it gets the lock back,
+/// as another thread may have taken it first: it should be checked again in a loop, see `wait_while`.
+#[derive(Default)]
+pub struct Condvar {
+    // the threads waiting to be notified, in the order they came.
+    waiters: RefCell<VecDeque<Id>>,
+}
+
+/// Tells whether `Condvar::wait_timeout` returned as its timeout passed.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct WaitTimeoutResult(bool);
+
+impl WaitTimeoutResult {
+    /// Returns whether the timeout passed before the thread was notified.
+    pub fn timed_out(&self) -> bool {
+        self.0
+    }
+}
+
+impl Condvar {
+    pub const fn new() -> Self {
+        Condvar {
+            waiters: RefCell::new(VecDeque::new()),
+        }
+    }
+
+    /// Releases the lock `guard` holds and blocks the current thread until it is notified, then takes the lock again.
+    pub fn wait<'a, T: ?Sized>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        let (guard, _) = self.wait_until(guard, None);
+        guard
+    }
+
+    /// Like `wait`, but keeps waiting as long as `condition` holds, which is checked with the lock held.
+    pub fn wait_while<'a, T: ?Sized>(
+        &self,
+        mut guard: MutexGuard<'a, T>,
+        mut condition: impl FnMut(&mut T) -> bool,
+    ) -> MutexGuard<'a, T> {
+        while condition(&mut *guard) {
+            guard = self.wait(guard);
+        }
+        guard
+    }
+
+    /// Like `wait`, but stops waiting once `timeout` has passed, on the timers of the runtime.
+    /// The lock is taken again either way, and the result tells whether the thread timed out rather than being notified.
+    pub fn wait_timeout<'a, T: ?Sized>(
+        &self,
+        guard: MutexGuard<'a, T>,
+        timeout: Duration,
+    ) -> (MutexGuard<'a, T>, WaitTimeoutResult) {
+        let (guard, timed_out) = self.wait_until(guard, Some(time::now() + timeout));
+        (guard, WaitTimeoutResult(timed_out))
+    }
+
+    /// Wakes up the thread which has been waiting the longest, if any.
+    pub fn notify_one(&self) {
+        let _no_preempt = NoPreempt::new();
+        if let Some(id) = self.waiters.borrow_mut().pop_front() {
+            wake_selector(id);
+        }
+    }
+
+    /// Wakes up every thread waiting. They take the lock one after the other.
+    pub fn notify_all(&self) {
+        let _no_preempt = NoPreempt::new();
+        let waiters = core::mem::take(&mut *self.waiters.borrow_mut());
+        for id in waiters {
+            wake_selector(id);
+        }
+    }
+
+    // Waits to be notified, or for `deadline` to pass, and returns whether it did.
+    // A thread notified is taken off the queue by the thread notifying it, so one still in it timed out.
+    fn wait_until<'a, T: ?Sized>(
+        &self,
+        guard: MutexGuard<'a, T>,
+        deadline: Option<Instant>,
+    ) -> (MutexGuard<'a, T>, bool) {
+        let mutex = guard.mutex();
+        let id = get_current_thread();
+        let timed_out = {
+            // a notification must not come in between releasing the lock and blocking, or it would be lost.
+            let _no_preempt = NoPreempt::new();
+            event!(Debug, Sync, id, "waiting on a condvar until {:?}", deadline);
+            self.waiters.borrow_mut().push_back(id);
+            drop(guard);
+            // blocks as `Select` does without file descriptors, until woken up through `wake_selector` or the deadline.
+            wait_woken(deadline);
+            let mut waiters = self.waiters.borrow_mut();
+            match waiters.iter().position(|&waiter| waiter == id) {
+                Some(pos) => {
+                    waiters.remove(pos);
+                    true
+                }
+                None => false,
+            }
+        };
+        (mutex.lock(), timed_out)
+    }
+}
+
+impl fmt::Debug for Condvar {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Condvar")
+            .field("waiters", &self.waiters.borrow().len())
+            .finish()
+    }
+}
@@ -0,0 +1,70 @@
+use alloc::vec::Vec;
+use core::cell::{Cell, UnsafeCell};
+
+use crate::preempt::NoPreempt;
+use crate::runtime::{change_thread_state, get_current_thread, yield_thread};
+use crate::thread::{Id, State};
+use crate::trace::event;
+
+/// A single-use gate, open once counted down to zero, e.g. for a thread to wait for the workers it fanned work out to.
+/// The threads counting down never block, and those calling `wait` block until the count hits zero,
+/// so they don't need to know each other, nor how many of them there are.
+pub struct CountDownLatch {
+    count: Cell<usize>,
+    /// Threads waiting for the count to hit zero.
+    waiters: UnsafeCell<Vec<Id>>,
+}
+
+impl CountDownLatch {
+    /// Creates a latch which opens after `count` calls to `count_down`, or right away if `count` is zero.
+    pub const fn new(count: usize) -> Self {
+        CountDownLatch {
+            count: Cell::new(count),
+            waiters: UnsafeCell::new(Vec::new()),
+        }
+    }
+
+    /// Returns how many calls to `count_down` are left before the latch opens.
+    pub fn count(&self) -> usize {
+        self.count.get()
+    }
+
+    /// Counts down by one, waking up the threads waiting if the count hits zero.
+    /// Does nothing once the latch is open.
+    pub fn count_down(&self) {
+        let _no_preempt = NoPreempt::new();
+        match self.count.get() {
+            0 => {}
+            1 => {
+                self.count.set(0);
+                let waiters = core::mem::take(unsafe { &mut *self.waiters.get() });
+                for id in waiters {
+                    change_thread_state(id, State::Ready);
+                }
+            }
+            count => self.count.set(count - 1),
+        }
+    }
+
+    /// Blocks the current thread until the count hits zero, or returns right away if it already has.
+    pub fn wait(&self) {
+        let id = get_current_thread();
+        loop {
+            // the count must not hit zero between checking it and blocking.
+            let _no_preempt = NoPreempt::new();
+            if self.count.get() == 0 {
+                return;
+            }
+            event!(
+                Debug,
+                Sync,
+                id,
+                "waiting on a latch at {}",
+                self.count.get()
+            );
+            unsafe { (*self.waiters.get()).push(id) };
+            change_thread_state(id, State::SyncBlock);
+            yield_thread();
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use alloc::collections::VecDeque;
+use core::cell::{Cell, RefCell, UnsafeCell};
+use core::cmp::Reverse;
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+
+use crate::preempt::NoPreempt;
+use crate::runtime::{
+    change_thread_state, effective_priority, get_current_thread, release_lock, wait_for_lock,
+    yield_thread,
+};
+use crate::thread::{Id, State};
+use crate::trace::event;
+
+/// A lock protecting a value shared by threads, e.g. through an `Rc`, which may be held across blocking operations:
+/// threads waiting for it are blocked rather than spinning, so the thread holding it gets to run and release it.
+/// As it is released, the lock is handed over to the thread of the highest priority waiting, in the order they came
+/// among equals, and the thread holding it runs with the priority of the threads waiting if it is higher than its own
+/// (priority inheritance). Otherwise, a thread of a low priority holding the lock would be kept from releasing it
+/// by threads of priorities in between, which would keep a thread of a high priority waiting for them all (priority
+/// inversion). The priority is passed on to the thread holding the lock the holder waits for in turn, and so on.
+/// Unlike the standard library's, the lock isn't poisoned when a thread panics while holding it: it is just released.
+pub struct Mutex<T: ?Sized> {
+    // the thread holding the lock, if any.
+    holder: Cell<Option<Id>>,
+    // the threads waiting for the lock, in the order they came.
+    waiters: RefCell<VecDeque<Id>>,
+    value: UnsafeCell<T>,
+}
+
+/// Gives access to the value of a `Mutex` while the lock is held, which it releases once dropped.
+pub struct MutexGuard<'a, T: ?Sized> {
+    mutex: &'a Mutex<T>,
+}
+
+impl<T> Mutex<T> {
+    pub const fn new(value: T) -> Self {
+        Mutex {
+            holder: Cell::new(None),
+            waiters: RefCell::new(VecDeque::new()),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+impl<T: ?Sized> Mutex<T> {
+    /// Takes the lock, blocking the current thread until it is handed over to it if another thread holds it.
+    /// Panics if the current thread holds it already, as it would wait for itself.
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        let id = get_current_thread();
+        // the lock must not be released between checking it and blocking.
+        let _no_preempt = NoPreempt::new();
+        match self.holder.get() {
+            None => self.holder.set(Some(id)),
+            Some(holder) => {
+                assert_ne!(holder, id, "thread {id:?} locking a mutex it holds");
+                event!(Debug, Sync, id, "waiting for a mutex held by {:?}", holder);
+                self.waiters.borrow_mut().push_back(id);
+                wait_for_lock(self.key(), holder);
+                change_thread_state(id, State::SyncBlock);
+                yield_thread();
+                // the thread releasing the lock hands it over before waking this one up.
+                debug_assert_eq!(self.holder.get(), Some(id));
+            }
+        }
+        MutexGuard { mutex: self }
+    }
+
+    /// Takes the lock if no thread holds it, without blocking.
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        let _no_preempt = NoPreempt::new();
+        if self.holder.get().is_some() {
+            return None;
+        }
+        self.holder.set(Some(get_current_thread()));
+        Some(MutexGuard { mutex: self })
+    }
+
+    /// Returns whether a thread holds the lock.
+    pub fn is_locked(&self) -> bool {
+        self.holder.get().is_some()
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+
+    // Identifies the lock to the runtime, which tracks the priorities inherited through it.
+    fn key(&self) -> usize {
+        self as *const Self as *const () as usize
+    }
+
+    // Releases the lock, handing it over to the waiting thread of the highest priority, if any.
+    fn unlock(&self) {
+        let _no_preempt = NoPreempt::new();
+        // without waiters, the holder inherits nothing through the lock, and the runtime has nothing to do.
+        if self.waiters.borrow().is_empty() {
+            self.holder.set(None);
+            return;
+        }
+        let next = {
+            let mut waiters = self.waiters.borrow_mut();
+            let pos = waiters
+                .iter()
+                .enumerate()
+                .max_by_key(|&(pos, &id)| (effective_priority(id), Reverse(pos)))
+                .map(|(pos, _)| pos);
+            pos.and_then(|pos| waiters.remove(pos))
+        };
+        self.holder.set(next);
+        release_lock(self.key(), next);
+        if let Some(next) = next {
+            change_thread_state(next, State::Ready);
+        }
+    }
+}
+
+impl<'a, T: ?Sized> MutexGuard<'a, T> {
+    // The lock the guard holds, for `Condvar` to release it and take it again.
+    pub(super) fn mutex(&self) -> &'a Mutex<T> {
+        self.mutex
+    }
+}
+
+impl<T: Default> Default for Mutex<T> {
+    fn default() -> Self {
+        Mutex::new(T::default())
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for Mutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("Mutex");
+        if self.is_locked() {
+            d.field("value", &format_args!("<locked>"));
+        } else {
+            d.field("value", &unsafe { &*self.value.get() });
+        }
+        d.finish()
+    }
+}
+
+impl<T: ?Sized> Deref for MutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.value.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.value.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.unlock();
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for MutexGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
@@ -0,0 +1,160 @@
+use alloc::vec::Vec;
+use core::cell::{Cell, UnsafeCell};
+
+use crate::preempt::NoPreempt;
+use crate::runtime::{change_thread_state, get_current_thread, yield_thread};
+use crate::thread::{Id, State};
+use crate::trace::event;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OnceState {
+    // the initialisation routine hasn't been run yet.
+    Incomplete,
+    // the initialisation routine is being run by the given thread.
+    Running(Id),
+    // the initialisation routine has finished.
+    Complete,
+}
+
+/// Runs a one-time initialisation routine.
+/// Threads that call `call_once` while another thread is running the routine
+/// are blocked until it completes, which allows the routine itself to block (e.g. on a channel).
+pub struct Once {
+    state: Cell<OnceState>,
+    // the threads waiting for the routine to complete.
+    waiters: UnsafeCell<Vec<Id>>,
+}
+
+impl Once {
+    pub const fn new() -> Self {
+        Once {
+            state: Cell::new(OnceState::Incomplete),
+            waiters: UnsafeCell::new(Vec::new()),
+        }
+    }
+
+    pub fn is_completed(&self) -> bool {
+        self.state.get() == OnceState::Complete
+    }
+
+    /// Runs `f` if no other call to `call_once` has run it yet.
+    /// Returns only once the routine has completed, whichever thread ran it.
+    pub fn call_once<F: FnOnce()>(&self, f: F) {
+        loop {
+            // the state must not change between checking and updating it.
+            let no_preempt = NoPreempt::new();
+            match self.state.get() {
+                OnceState::Complete => return,
+                OnceState::Incomplete => {
+                    let guard = Completion {
+                        once: self,
+                        state: OnceState::Incomplete,
+                    };
+                    self.state.set(OnceState::Running(get_current_thread()));
+                    drop(no_preempt);
+                    f();
+                    // if `f` panics, the guard resets the state so that another thread can retry.
+                    let mut guard = guard;
+                    guard.state = OnceState::Complete;
+                    return;
+                }
+                OnceState::Running(id) => {
+                    let curr_id = get_current_thread();
+                    if id == curr_id {
+                        panic!("Once::call_once called recursively by thread {:?}", id);
+                    }
+
+                    event!(Debug, Sync, curr_id, "waiting on Once run by {:?}", id);
+
+                    // add the current thread to waiting list and block until the routine completes
+                    unsafe { (*self.waiters.get()).push(curr_id) };
+                    change_thread_state(curr_id, State::SyncBlock);
+                    yield_thread();
+                }
+            }
+        }
+    }
+}
+
+impl Default for Once {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Publishes the final state of a `Once` and wakes up the threads waiting on it.
+struct Completion<'a> {
+    once: &'a Once,
+    state: OnceState,
+}
+
+impl Drop for Completion<'_> {
+    fn drop(&mut self) {
+        let _no_preempt = NoPreempt::new();
+        self.once.state.set(self.state);
+        let waiters = core::mem::take(unsafe { &mut *self.once.waiters.get() });
+        for id in waiters {
+            change_thread_state(id, State::Ready);
+        }
+    }
+}
+
+/// A cell which can be written to only once.
+/// Concurrent callers of `get_or_init` are blocked while the first one initialises the cell.
+pub struct OnceCell<T> {
+    once: Once,
+    value: UnsafeCell<Option<T>>,
+}
+
+impl<T> OnceCell<T> {
+    pub const fn new() -> Self {
+        OnceCell {
+            once: Once::new(),
+            value: UnsafeCell::new(None),
+        }
+    }
+
+    /// Returns the value, if the cell has been initialised.
+    pub fn get(&self) -> Option<&T> {
+        if self.once.is_completed() {
+            unsafe { (*self.value.get()).as_ref() }
+        } else {
+            None
+        }
+    }
+
+    pub fn get_mut(&mut self) -> Option<&mut T> {
+        self.value.get_mut().as_mut()
+    }
+
+    /// Initialises the cell with `value`.
+    /// Blocks if another thread is initialising the cell, and returns the value back if the cell was already set.
+    pub fn set(&self, value: T) -> Result<(), T> {
+        let mut value = Some(value);
+        self.get_or_init(|| value.take().unwrap());
+        match value {
+            None => Ok(()),
+            Some(value) => Err(value),
+        }
+    }
+
+    /// Returns the value, initialising the cell with `f` if it is empty.
+    /// Only one thread runs `f`; the others are blocked until it returns.
+    pub fn get_or_init<F: FnOnce() -> T>(&self, f: F) -> &T {
+        self.once.call_once(|| {
+            let val = f();
+            unsafe { *self.value.get() = Some(val) };
+        });
+        self.get().unwrap()
+    }
+
+    pub fn into_inner(self) -> Option<T> {
+        self.value.into_inner()
+    }
+}
+
+impl<T> Default for OnceCell<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,65 @@
+use core::cell::Cell;
+use core::time::Duration;
+
+use crate::preempt::NoPreempt;
+use crate::time::{self, Instant};
+
+/// Limits how often threads go on, e.g. to the requests a server may make to another service,
+/// as a bucket of `burst` tokens refilled at a constant rate, one of which every call to `acquire` takes.
+/// Rather than having a timer refill the bucket, every thread waiting is handed the instant its token comes in,
+/// and sleeps until then on a timer of the runtime: the threads go on in the order they came, and none of them polls.
+#[derive(Debug)]
+pub struct RateLimiter {
+    /// Time between two tokens.
+    interval: Duration,
+    /// How long before `next` a token may be taken: the time to refill all but one token.
+    tolerance: Duration,
+    /// When the bucket will be full again, counting the tokens handed out, possibly to threads still waiting for them.
+    next: Cell<Instant>,
+}
+
+impl RateLimiter {
+    /// Creates a limiter letting `tokens` calls to `acquire` go on every `per`, and up to `burst` at once,
+    /// starting with a full bucket.
+    /// Panics if `tokens` or `burst` is zero.
+    pub fn new(tokens: u32, per: Duration, burst: u32) -> Self {
+        assert!(tokens > 0, "a rate limiter must let some tokens through");
+        assert!(
+            burst > 0,
+            "the bucket of a rate limiter must hold a token at least"
+        );
+        let interval = per / tokens;
+        RateLimiter {
+            interval,
+            tolerance: interval * (burst - 1),
+            next: Cell::new(time::now()),
+        }
+    }
+
+    /// Takes a token, blocking the current thread until one comes in if the bucket is empty.
+    pub fn acquire(&self) {
+        let at = {
+            // the token must be handed out along with moving `next` past it.
+            let _no_preempt = NoPreempt::new();
+            let now = time::now();
+            let next = self.next.get().max(now);
+            self.next.set(next + self.interval);
+            next.checked_sub(self.tolerance).unwrap_or(now).max(now)
+        };
+        if at > time::now() {
+            time::sleep_until(at);
+        }
+    }
+
+    /// Takes a token if there is one in the bucket, without blocking.
+    pub fn try_acquire(&self) -> bool {
+        let _no_preempt = NoPreempt::new();
+        let now = time::now();
+        let next = self.next.get().max(now);
+        if next.checked_sub(self.tolerance).unwrap_or(now) > now {
+            return false;
+        }
+        self.next.set(next + self.interval);
+        true
+    }
+}
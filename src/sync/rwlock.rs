@@ -0,0 +1,262 @@
+use alloc::collections::VecDeque;
+use core::cell::{Cell, RefCell, UnsafeCell};
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+
+use crate::preempt::NoPreempt;
+use crate::runtime::{change_thread_state, get_current_thread, yield_thread};
+use crate::thread::{Id, State};
+use crate::trace::event;
+
+/// A lock letting many threads read a value at once, or a single thread write it,
+/// e.g. for a configuration read by every request and updated now and then.
+/// Threads waiting for it are blocked, and handed the lock as it is released, in the order they came
+/// among those of the same kind: which of the readers or the writers waiting go first is up to the `Fairness`.
+/// Unlike `Mutex`, threads waiting don't pass their priority on to those holding the lock,
+/// as there may be many readers to pass it on to. Nor is the lock poisoned when a thread panics while holding it.
+pub struct RwLock<T: ?Sized> {
+    fairness: Fairness,
+    // how many threads hold the lock to read.
+    readers: Cell<usize>,
+    // whether a thread holds the lock to write.
+    writer: Cell<bool>,
+    // the threads waiting for the lock, in the order they came, by kind.
+    waiting_readers: RefCell<VecDeque<Id>>,
+    waiting_writers: RefCell<VecDeque<Id>>,
+    value: UnsafeCell<T>,
+}
+
+/// Which of the readers or the writers waiting for a `RwLock` go first.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum Fairness {
+    /// Readers get the lock whenever no writer holds it, even with writers waiting.
+    /// Gets the most out of read-mostly workloads, but a writer waits for as long as the readers keep overlapping,
+    /// possibly forever under a constant read load.
+    ReaderPreferring,
+    /// Readers wait as long as a writer holds the lock or waits for it, and writers go first as it is released.
+    /// Writes never wait for more than the readers already in, but readers wait for as long as writers keep coming,
+    /// possibly forever under a constant write load.
+    WriterPreferring,
+    /// Reads and writes take turns: readers coming as a writer waits queue up behind it,
+    /// and once the writer is done, all the readers waiting get the lock at once, ahead of the next writer.
+    /// A writer waits for one phase of reads at most, and a reader for one write at most, so neither starves.
+    #[default]
+    PhaseFair,
+}
+
+/// Gives shared access to the value of a `RwLock`, and releases the lock once dropped.
+pub struct RwLockReadGuard<'a, T: ?Sized> {
+    lock: &'a RwLock<T>,
+}
+
+/// Gives exclusive access to the value of a `RwLock`, and releases the lock once dropped.
+pub struct RwLockWriteGuard<'a, T: ?Sized> {
+    lock: &'a RwLock<T>,
+}
+
+impl<T> RwLock<T> {
+    /// Creates a lock whose readers and writers take turns, see `Fairness::PhaseFair`.
+    pub const fn new(value: T) -> Self {
+        RwLock::with_fairness(value, Fairness::PhaseFair)
+    }
+
+    pub const fn with_fairness(value: T, fairness: Fairness) -> Self {
+        RwLock {
+            fairness,
+            readers: Cell::new(0),
+            writer: Cell::new(false),
+            waiting_readers: RefCell::new(VecDeque::new()),
+            waiting_writers: RefCell::new(VecDeque::new()),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+impl<T: ?Sized> RwLock<T> {
+    pub fn fairness(&self) -> Fairness {
+        self.fairness
+    }
+
+    /// Takes the lock to read, blocking the current thread until a writer is done with it, if any,
+    /// or writers waiting, unless readers are preferred.
+    pub fn read(&self) -> RwLockReadGuard<'_, T> {
+        let _no_preempt = NoPreempt::new();
+        if !self.admit_reader() {
+            let id = get_current_thread();
+            event!(Debug, Sync, id, "waiting to read a rwlock");
+            self.waiting_readers.borrow_mut().push_back(id);
+            change_thread_state(id, State::SyncBlock);
+            yield_thread();
+        }
+        RwLockReadGuard { lock: self }
+    }
+
+    /// Takes the lock to read if that doesn't take waiting, see `read`.
+    pub fn try_read(&self) -> Option<RwLockReadGuard<'_, T>> {
+        let _no_preempt = NoPreempt::new();
+        self.admit_reader().then(|| RwLockReadGuard { lock: self })
+    }
+
+    /// Takes the lock to write, blocking the current thread until no other thread holds it,
+    /// and the threads waiting to go first have had it.
+    pub fn write(&self) -> RwLockWriteGuard<'_, T> {
+        let _no_preempt = NoPreempt::new();
+        if !self.admit_writer() {
+            let id = get_current_thread();
+            event!(Debug, Sync, id, "waiting to write a rwlock");
+            self.waiting_writers.borrow_mut().push_back(id);
+            change_thread_state(id, State::SyncBlock);
+            yield_thread();
+        }
+        RwLockWriteGuard { lock: self }
+    }
+
+    /// Takes the lock to write if no thread holds it, without blocking.
+    pub fn try_write(&self) -> Option<RwLockWriteGuard<'_, T>> {
+        let _no_preempt = NoPreempt::new();
+        self.admit_writer().then(|| RwLockWriteGuard { lock: self })
+    }
+
+    /// Returns how many threads hold the lock to read.
+    pub fn readers(&self) -> usize {
+        self.readers.get()
+    }
+
+    /// Returns whether a thread holds the lock to write.
+    pub fn is_write_locked(&self) -> bool {
+        self.writer.get()
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+
+    // Counts the current thread in as a reader, if it may read right away.
+    // The threads waiting are handed the lock as it is released, so a lock which isn't held has none waiting.
+    fn admit_reader(&self) -> bool {
+        let admitted = !self.writer.get()
+            && (self.fairness == Fairness::ReaderPreferring
+                || self.waiting_writers.borrow().is_empty());
+        if admitted {
+            self.readers.set(self.readers.get() + 1);
+        }
+        admitted
+    }
+
+    fn admit_writer(&self) -> bool {
+        let admitted = !self.writer.get() && self.readers.get() == 0;
+        if admitted {
+            self.writer.set(true);
+        }
+        admitted
+    }
+
+    // Hands the lock over to the threads waiting whose turn it is, once it isn't held anymore.
+    // `after_write` tells whether a writer released it, which ends a phase of writes for `Fairness::PhaseFair`.
+    fn hand_over(&self, after_write: bool) {
+        if self.writer.get() || self.readers.get() > 0 {
+            return;
+        }
+        let readers_first = match self.fairness {
+            Fairness::ReaderPreferring => true,
+            Fairness::WriterPreferring => false,
+            Fairness::PhaseFair => after_write,
+        };
+        let mut writers = self.waiting_writers.borrow_mut();
+        if readers_first || writers.is_empty() {
+            let readers = core::mem::take(&mut *self.waiting_readers.borrow_mut());
+            if !readers.is_empty() {
+                self.readers.set(readers.len());
+                for id in readers {
+                    change_thread_state(id, State::Ready);
+                }
+                return;
+            }
+        }
+        if let Some(id) = writers.pop_front() {
+            self.writer.set(true);
+            change_thread_state(id, State::Ready);
+        }
+    }
+
+    fn read_unlock(&self) {
+        let _no_preempt = NoPreempt::new();
+        self.readers.set(self.readers.get() - 1);
+        self.hand_over(false);
+    }
+
+    fn write_unlock(&self) {
+        let _no_preempt = NoPreempt::new();
+        self.writer.set(false);
+        self.hand_over(true);
+    }
+}
+
+impl<T: Default> Default for RwLock<T> {
+    fn default() -> Self {
+        RwLock::new(T::default())
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for RwLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("RwLock");
+        d.field("fairness", &self.fairness);
+        if self.is_write_locked() {
+            d.field("value", &format_args!("<locked>"));
+        } else {
+            d.field("value", &unsafe { &*self.value.get() });
+        }
+        d.finish()
+    }
+}
+
+impl<T: ?Sized> Deref for RwLockReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for RwLockReadGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.read_unlock();
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for RwLockReadGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized> Deref for RwLockWriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for RwLockWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for RwLockWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.write_unlock();
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for RwLockWriteGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
@@ -0,0 +1,147 @@
+use alloc::collections::VecDeque;
+use core::cell::{Cell, RefCell};
+use core::fmt;
+
+use crate::preempt::NoPreempt;
+use crate::runtime::{change_thread_state, get_current_thread, yield_thread};
+use crate::thread::{Id, State};
+use crate::trace::event;
+
+/// Limits how many threads go on at once, e.g. to the connections a server opens to a database,
+/// as a count of permits that threads take and give back.
+/// Threads waiting for permits are handed them in the order they came: a thread asking for many permits
+/// holds up those coming after it, rather than being overtaken by a stream of threads asking for few,
+/// which would never leave enough for it.
+pub struct Semaphore {
+    permits: Cell<usize>,
+    // the threads waiting, in the order they came, with how many permits each wants.
+    waiters: RefCell<VecDeque<(Id, usize)>>,
+}
+
+/// Permits taken from a `Semaphore`, given back once dropped.
+#[must_use = "the permits are given back right away if not held"]
+pub struct SemaphorePermit<'a> {
+    semaphore: &'a Semaphore,
+    permits: usize,
+}
+
+impl Semaphore {
+    pub const fn new(permits: usize) -> Self {
+        Semaphore {
+            permits: Cell::new(permits),
+            waiters: RefCell::new(VecDeque::new()),
+        }
+    }
+
+    /// Returns how many permits are left to take.
+    pub fn available_permits(&self) -> usize {
+        self.permits.get()
+    }
+
+    /// Takes a permit, blocking the current thread until one is given back if there is none left, see `acquire_many`.
+    pub fn acquire(&self) -> SemaphorePermit<'_> {
+        self.acquire_many(1)
+    }
+
+    /// Takes `n` permits at once, blocking the current thread until they are all there for it.
+    /// The permits are handed out in the order threads asked for them, so the thread waits for those before it,
+    /// and is never overtaken by those after it.
+    pub fn acquire_many(&self, n: usize) -> SemaphorePermit<'_> {
+        let _no_preempt = NoPreempt::new();
+        if !self.take(n) {
+            let id = get_current_thread();
+            event!(
+                Debug,
+                Sync,
+                id,
+                "waiting for {} permits of a semaphore at {}",
+                n,
+                self.permits.get()
+            );
+            self.waiters.borrow_mut().push_back((id, n));
+            change_thread_state(id, State::SyncBlock);
+            yield_thread();
+        }
+        SemaphorePermit {
+            semaphore: self,
+            permits: n,
+        }
+    }
+
+    /// Takes a permit if one is left and no thread is waiting, without blocking.
+    pub fn try_acquire(&self) -> Option<SemaphorePermit<'_>> {
+        self.try_acquire_many(1)
+    }
+
+    /// Takes `n` permits if there are enough left and no thread is waiting, without blocking.
+    pub fn try_acquire_many(&self, n: usize) -> Option<SemaphorePermit<'_>> {
+        let _no_preempt = NoPreempt::new();
+        self.take(n).then(|| SemaphorePermit {
+            semaphore: self,
+            permits: n,
+        })
+    }
+
+    /// Adds `n` permits, handing them out to the threads waiting.
+    pub fn add_permits(&self, n: usize) {
+        let _no_preempt = NoPreempt::new();
+        self.permits.set(self.permits.get() + n);
+        let mut waiters = self.waiters.borrow_mut();
+        // the first thread waiting holds up the others until there are enough permits for it.
+        while let Some(&(id, wanted)) = waiters.front() {
+            if wanted > self.permits.get() {
+                break;
+            }
+            self.permits.set(self.permits.get() - wanted);
+            waiters.pop_front();
+            change_thread_state(id, State::Ready);
+        }
+    }
+
+    // Takes `n` permits for the current thread, if it may without waiting:
+    // there are enough, and no thread came before it.
+    fn take(&self, n: usize) -> bool {
+        let taken = self.waiters.borrow().is_empty() && self.permits.get() >= n;
+        if taken {
+            self.permits.set(self.permits.get() - n);
+        }
+        taken
+    }
+}
+
+impl SemaphorePermit<'_> {
+    /// Returns how many permits are held.
+    pub fn permits(&self) -> usize {
+        self.permits
+    }
+
+    /// Keeps the permits from being given back, taking them out of the semaphore for good.
+    pub fn forget(mut self) {
+        self.permits = 0;
+    }
+}
+
+impl Drop for SemaphorePermit<'_> {
+    fn drop(&mut self) {
+        if self.permits > 0 {
+            self.semaphore.add_permits(self.permits);
+        }
+    }
+}
+
+impl fmt::Debug for Semaphore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Semaphore")
+            .field("permits", &self.permits.get())
+            .field("waiters", &self.waiters.borrow().len())
+            .finish()
+    }
+}
+
+impl fmt::Debug for SemaphorePermit<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SemaphorePermit")
+            .field("permits", &self.permits)
+            .finish()
+    }
+}
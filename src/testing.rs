@@ -0,0 +1,42 @@
+//! Running tests on the runtime, see `run`, and `#[uthreads::test]` with the `macros` feature.
+
+use std::cell::RefCell;
+use std::panic;
+use std::rc::Rc;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+use crate::runtime::Runtime;
+
+/// Runs `body` in a fresh runtime, on a green thread of its own, and returns what it returns once the runtime has run.
+/// The runtime runs on an OS thread of its own, so that the test fails if it takes longer than `timeout`, instead of hanging,
+/// and so that tests run in parallel by the test harness each get their own.
+/// A panic of `body` makes the test fail with it, while a panic of the threads it spawns aborts the process,
+/// as anywhere in the runtime.
+pub fn run<T: Send + 'static>(body: fn() -> T, timeout: Duration) -> T {
+    let (tx, rx) = mpsc::channel();
+    let name = thread::current().name().unwrap_or("test").to_string();
+    thread::Builder::new()
+        .name(name)
+        .spawn(move || {
+            let result = Rc::new(RefCell::new(None));
+            let slot = result.clone();
+            let mut runtime = Runtime::new();
+            unsafe { runtime.init() };
+            runtime.spawn(move || *slot.borrow_mut() = Some(panic::catch_unwind(body)));
+            runtime.run();
+            drop(runtime);
+            let _ = tx.send(result.take());
+        })
+        .expect("failed to spawn the thread running the runtime");
+
+    match rx.recv_timeout(timeout) {
+        Ok(Some(Ok(value))) => value,
+        Ok(Some(Err(payload))) => panic::resume_unwind(payload),
+        Ok(None) | Err(RecvTimeoutError::Disconnected) => {
+            panic!("the runtime stopped before the test completed")
+        }
+        Err(RecvTimeoutError::Timeout) => panic!("the test didn't complete within {timeout:?}"),
+    }
+}
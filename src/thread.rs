@@ -1,12 +1,37 @@
-use crate::DEFAULT_STACK_SIZE;
+mod local;
+mod stack;
+
+use alloc::vec::Vec;
+use core::ptr::NonNull;
+use core::time::Duration;
+#[cfg(feature = "std")]
+use std::backtrace::Backtrace;
+
+pub use crate::arch::Context;
+#[cfg(all(
+    target_arch = "x86_64",
+    not(any(feature = "ucontext", feature = "fibers", miri)),
+    feature = "simd-context"
+))]
+pub use crate::arch::FxArea;
+pub use local::Local;
+pub(crate) use local::Locals;
+#[cfg(feature = "std")]
+pub use stack::{GrowableAllocator, LockedAllocator, MmapAllocator};
+pub use stack::{Stack, StackAllocator, StackError, StaticAllocator};
+
+use crate::runtime;
+use crate::time::Instant;
 
 /// Uniquely identifies a thread.
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
 #[repr(transparent)]
 pub struct Id(pub usize);
 
 /// Possible states that a thread can be in during its lifetime.
-#[derive(PartialEq, Eq, Debug)]
+/// Debuggers tell them apart by their discriminant, see `debugger`.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+#[repr(u8)]
 pub enum State {
     /// Thread is making progress.
     Running,
@@ -16,45 +41,142 @@ pub enum State {
     ChannelBlockSend,
     /// Thread is waiting to receive a value from the channel.
     ChannelBlockRecv,
+    /// Thread is waiting on a synchronisation primitive (e.g. a `Once` being run by another thread).
+    SyncBlock,
+    /// Thread is waiting for a file descriptor to become ready for IO.
+    IoBlocked,
+    /// Thread is sleeping until a deadline.
+    Sleeping,
+    /// Thread is parked until it is unparked through a `RuntimeWaker`.
+    Parked,
 }
 
-/// Stores information about a thread that we want preserved between thread switches.
-/// Currently, we only store the callee saved registers.
-#[derive(Debug, Default)]
-#[repr(C)]
-pub struct Context {
-    pub rsp: u64,
-    pub r15: u64,
-    pub r14: u64,
-    pub r13: u64,
-    pub r12: u64,
-    pub rbx: u64,
-    pub rbp: u64,
+/// How urgent a thread is: the scheduler only runs a thread once no thread of a higher priority is ready,
+/// and takes turns among those of the same priority. Threads start at `Priority::NORMAL`,
+/// see `runtime::spawn_with_priority` and `ThreadHandle::set_priority`,
+/// and get the priority of the threads waiting for the locks they hold, see `sync::Mutex`.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Default)]
+pub struct Priority(pub i32);
+
+impl Priority {
+    pub const LOW: Priority = Priority(-1);
+    pub const NORMAL: Priority = Priority(0);
+    pub const HIGH: Priority = Priority(1);
 }
 
+// Takes a thread off the queues of a channel, handing the value it was blocked sending back to it, if any.
+pub(crate) type LeaveChan = unsafe fn(NonNull<()>, Id);
+
 /// Represents a thread in our runtime.
 #[derive(Debug)]
 pub struct Thread {
     /// Uniquely identifies a thread.
     pub id: Id,
     /// Stack used by the thread to run the function passed.
-    pub stack: Box<[u8]>,
+    pub stack: Stack,
     /// Stores the thread context between successive runs.
     pub ctx: Context,
     /// Represents the current state of the thread.
     pub state: State,
-    /// Stores the value sent by the channel, if any.
-    pub chan_val: Option<usize>,
+    /// The channel the thread is blocked on, if any, with its type erased.
+    pub blocked_on: Option<NonNull<()>>,
+    /// Takes the thread off the queues of the channel it is blocked on, knowing its type, see `Core::interrupt`.
+    pub(crate) leave_chan: Option<LeaveChan>,
+    /// Total time the thread has spent running.
+    pub run_time: Duration,
+    /// The values of the thread's locals, see `Local`.
+    pub(crate) locals: Locals,
+    /// The function the thread starts in, until it is first run: its stack is only set up then.
+    pub(crate) entry: Option<fn()>,
+    /// The function the thread runs, from when its stack is set up until it starts running it, see `run_thread`.
+    pub(crate) main: Option<fn()>,
+    /// The backtrace of the thread as it last panicked, for the report made if the panic ends it, see `crash`.
+    #[cfg(feature = "std")]
+    pub(crate) backtrace: Option<Backtrace>,
+    /// The CPU budget the thread runs on, shared with the rest of its group, see `Group::set_cpu_quota`.
+    pub(crate) budget: Option<usize>,
+    /// When the thread was last made ready to run, if starvation is looked for, see `Runtime::set_starvation_threshold`.
+    pub(crate) ready_since: Option<Instant>,
+    /// How urgent the thread is, unless it inherits a higher priority, see `effective_priority`.
+    pub priority: Priority,
+    /// The priorities the thread inherits from the threads waiting for the locks it holds, by lock.
+    pub(crate) inherited: Vec<(usize, Priority)>,
+    /// The lock the thread waits for, and the thread holding it, which inherits its priority.
+    pub(crate) waits_for: Option<(usize, Id)>,
+}
+
+/// Refers to a thread of the runtime of the current OS thread, to adjust it while it runs or is blocked.
+/// Made from the id spawning returns, or for the current thread with `current`.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct ThreadHandle {
+    id: Id,
+}
+
+impl ThreadHandle {
+    pub fn new(id: Id) -> Self {
+        ThreadHandle { id }
+    }
+
+    /// Returns a handle to the thread calling it.
+    pub fn current() -> Self {
+        ThreadHandle::new(runtime::get_current_thread())
+    }
+
+    pub fn id(&self) -> Id {
+        self.id
+    }
+
+    /// Changes the priority of the thread, whether it is running, ready or blocked, for as long as its importance differs,
+    /// e.g. to put a thread done with the urgent part of its work behind the others.
+    /// The scheduler goes by the priorities as they are whenever it picks a thread, so the change applies from its next pick,
+    /// and the current thread makes way right away if a ready thread now has a higher priority than it.
+    /// While the thread waits for a lock, the thread holding it inherits its new priority, see `sync::Mutex`.
+    /// Returns false if the thread has completed.
+    pub fn set_priority(&self, priority: Priority) -> bool {
+        runtime::set_priority(self.id, priority)
+    }
+
+    /// Returns the priority of the thread, not counting what it inherits (see `Thread::effective_priority`),
+    /// None if the thread has completed.
+    pub fn priority(&self) -> Option<Priority> {
+        runtime::priority(self.id)
+    }
 }
 
 impl Thread {
+    /// Creates a thread without a stack of its own, i.e. one running on the stack of the OS thread.
     pub fn new(id: Id, state: State) -> Self {
+        Thread::with_stack(id, state, Stack::empty())
+    }
+
+    /// Creates a thread running on an existing stack.
+    pub fn with_stack(id: Id, state: State, stack: Stack) -> Self {
         Thread {
             id,
-            stack: vec![0_u8; DEFAULT_STACK_SIZE].into_boxed_slice(),
+            stack,
             ctx: Context::default(),
             state,
-            chan_val: None,
+            blocked_on: None,
+            leave_chan: None,
+            run_time: Duration::ZERO,
+            locals: Locals::default(),
+            entry: None,
+            main: None,
+            #[cfg(feature = "std")]
+            backtrace: None,
+            budget: None,
+            ready_since: None,
+            priority: Priority::NORMAL,
+            inherited: Vec::new(),
+            waits_for: None,
         }
     }
+
+    /// Returns the priority the thread is scheduled with: its own, or the highest it inherits if that is higher.
+    pub fn effective_priority(&self) -> Priority {
+        self.inherited
+            .iter()
+            .map(|&(_, priority)| priority)
+            .fold(self.priority, Priority::max)
+    }
 }
@@ -0,0 +1,83 @@
+use alloc::boxed::Box;
+#[cfg(not(feature = "std"))]
+use alloc::collections::BTreeMap as HashMap;
+use core::any::Any;
+use core::fmt;
+#[cfg(feature = "std")]
+use std::collections::HashMap;
+
+use crate::runtime;
+
+/// A variable with a value of its own in every thread of the runtime, declared with `green_local!`.
+/// `thread_local!` gives every OS thread a value of its own, which all the threads running on it share instead.
+/// The value of a thread is created the first time it accesses it, and dropped once it completes.
+///
+/// This emulates thread-local storage in the runtime rather than giving every thread storage of its own:
+/// the thread pointer (fs base on x86_64, `tpidr_el0` on aarch64) isn't switched along with the threads, so
+/// `thread_local!`, whether in the program, the standard library or other crates, still has a value per OS thread.
+/// Switching it would take a TLS block per thread laid out the way the C library does, which it has no public way
+/// to allocate, and would give the runtime's own thread locals, by which it finds itself, a value per thread too.
+/// Code caching state per thread has to declare it with `green_local!` instead.
+pub struct Local<T: 'static> {
+    init: fn() -> T,
+}
+
+// The values of the locals of a thread, by the address of their `Local`.
+#[derive(Default)]
+pub(crate) struct Locals(HashMap<usize, Box<dyn Any>>);
+
+impl<T: 'static> Local<T> {
+    #[doc(hidden)]
+    pub const fn new(init: fn() -> T) -> Self {
+        Local { init }
+    }
+
+    /// Calls `f` with the value of the current thread, creating it first if it doesn't have one yet.
+    /// Panics if called outside of a runtime.
+    pub fn with<R>(&'static self, f: impl FnOnce(&T) -> R) -> R {
+        let key = self as *const Self as usize;
+        let val = match runtime::local(key) {
+            Some(val) => val,
+            // created outside of the runtime, as `init` may call into it.
+            None => runtime::init_local(key, Box::new((self.init)())),
+        };
+        // the value is boxed, so it stays where it is until the thread completes, whatever other locals are created.
+        let val = unsafe { &*val };
+        f(val.downcast_ref().expect("a local of another type"))
+    }
+}
+
+impl Locals {
+    pub(crate) fn get(&self, key: usize) -> Option<*const dyn Any> {
+        self.0.get(&key).map(|val| &**val as *const dyn Any)
+    }
+
+    // Keeps the value already there, if `init` created it along the way by accessing the local itself.
+    pub(crate) fn insert(&mut self, key: usize, val: Box<dyn Any>) -> *const dyn Any {
+        &**self.0.entry(key).or_insert(val)
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl fmt::Debug for Locals {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Locals")
+            .field("len", &self.0.len())
+            .finish()
+    }
+}
+
+/// Declares variables with a value of their own in every thread of the runtime, like `thread_local!` does for OS threads:
+/// `green_local!(static COUNT: Cell<u32> = Cell::new(0));` declares a `thread::Local`, accessed with `COUNT.with(|count| ...)`.
+/// It doesn't change how `thread_local!` behaves, see `thread::Local`.
+#[macro_export]
+macro_rules! green_local {
+    ($($(#[$attr:meta])* $vis:vis static $name:ident: $t:ty = $init:expr);+ $(;)?) => {
+        $(
+            $(#[$attr])* $vis static $name: $crate::thread::Local<$t> = $crate::thread::Local::new(|| $init);
+        )+
+    };
+}
@@ -0,0 +1,522 @@
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+#[cfg(not(feature = "std"))]
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+use core::ptr::NonNull;
+#[cfg(feature = "std")]
+use std::io;
+
+// Stacks are mostly left unused, so there is no point in reserving swap space for all of them.
+// Miri doesn't support that flag.
+#[cfg(all(feature = "std", target_os = "linux", not(miri)))]
+const MAP_FLAGS: libc::c_int = libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_NORESERVE;
+#[cfg(all(feature = "std", any(not(target_os = "linux"), miri)))]
+const MAP_FLAGS: libc::c_int = libc::MAP_PRIVATE | libc::MAP_ANONYMOUS;
+
+/// Why a `StackAllocator` couldn't provide a stack: the error of the OS, with std.
+#[cfg(feature = "std")]
+pub type StackError = io::Error;
+
+/// Why a `StackAllocator` couldn't provide a stack, without std, where there is no `std::io::Error` to tell.
+#[cfg(not(feature = "std"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StackError {
+    /// No memory is left for another stack.
+    OutOfMemory,
+    /// Stacks of `size` bytes were asked for, while the allocator only provides stacks of up to `max` bytes.
+    TooLarge { size: usize, max: usize },
+}
+
+#[cfg(not(feature = "std"))]
+impl fmt::Display for StackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StackError::OutOfMemory => write!(f, "out of memory for stacks"),
+            StackError::TooLarge { size, max } => {
+                write!(
+                    f,
+                    "stacks of {size} bytes don't fit in slots of {max} bytes"
+                )
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl core::error::Error for StackError {}
+
+/// Provides the memory thread stacks run on.
+/// The runtime gets all of its stacks from one, and hands them back once it doesn't need them anymore.
+pub trait StackAllocator {
+    /// Allocates a stack with at least `size` usable bytes.
+    /// If `guard` is set, the memory right below them should be inaccessible (a guard page),
+    /// so that overflowing the stack faults instead of silently overwriting whatever is next to it.
+    /// Allocators that can't do that may ignore it, overflows then go unnoticed though.
+    fn allocate(&mut self, size: usize, guard: bool) -> Result<Stack, StackError>;
+
+    /// Frees a stack returned by `allocate`, once no thread runs on it anymore.
+    fn deallocate(&mut self, stack: Stack);
+}
+
+/// Memory a thread runs on, made of the usable memory and the guard area right below it, if any.
+/// Only describes the memory, which is owned by the `StackAllocator` that provided it.
+#[derive(Debug)]
+pub struct Stack {
+    /// Start of the memory, i.e. of the guard area.
+    base: NonNull<u8>,
+    /// Size of the whole memory, guard area included.
+    len: usize,
+    /// Size of the guard area.
+    guard: usize,
+    /// Size the guard area can't shrink below as the stack grows, the same as `guard` if it can't grow.
+    #[cfg(feature = "std")]
+    limit: usize,
+    /// Id of the stack for Valgrind, which has to be told about every stack.
+    #[cfg(feature = "valgrind")]
+    pub(crate) valgrind_id: usize,
+}
+
+impl Stack {
+    /// Describes a stack spanning `len` bytes from `base`, the first `guard` of which are the guard area.
+    ///
+    /// # Safety
+    ///
+    /// The memory must be valid for reads and writes past the guard area,
+    /// and must stay so, without being used for anything else, until the stack is handed back to its allocator.
+    pub unsafe fn from_raw_parts(base: NonNull<u8>, len: usize, guard: usize) -> Self {
+        #[cfg(feature = "std")]
+        return unsafe { Stack::from_raw_parts_growable(base, len, guard, guard) };
+        #[cfg(not(feature = "std"))]
+        {
+            assert!(guard <= len, "the guard area must be part of the stack");
+            Stack { base, len, guard }
+        }
+    }
+
+    /// Like `from_raw_parts`, but the stack grows down over its guard area when a thread faults in it,
+    /// until only `limit` bytes are left.
+    ///
+    /// # Safety
+    ///
+    /// On top of what `from_raw_parts` requires,
+    /// the memory between `limit` and `guard` must be mapped, so that `mprotect` can make it readable and writable.
+    #[cfg(feature = "std")]
+    pub unsafe fn from_raw_parts_growable(
+        base: NonNull<u8>,
+        len: usize,
+        guard: usize,
+        limit: usize,
+    ) -> Self {
+        assert!(guard <= len, "the guard area must be part of the stack");
+        assert!(limit <= guard, "the limit must be part of the guard area");
+        Stack {
+            base,
+            len,
+            guard,
+            limit,
+            #[cfg(feature = "valgrind")]
+            valgrind_id: 0,
+        }
+    }
+
+    /// Returns the start of the memory, its size and the size of the guard area, as passed to `from_raw_parts`.
+    pub fn into_raw_parts(self) -> (NonNull<u8>, usize, usize) {
+        (self.base, self.len, self.guard)
+    }
+
+    /// Returns the size of the whole memory, guard area included, as reserved by the allocator.
+    pub fn size(&self) -> usize {
+        self.len
+    }
+
+    // Describes no memory at all, for the base thread which runs on the stack of the OS thread.
+    pub(crate) fn empty() -> Self {
+        Stack {
+            base: NonNull::dangling(),
+            len: 0,
+            guard: 0,
+            #[cfg(feature = "std")]
+            limit: 0,
+            #[cfg(feature = "valgrind")]
+            valgrind_id: 0,
+        }
+    }
+
+    /// Gives the memory of the whole pages below `sp` back to the OS.
+    /// They read as zeroes when touched again. Fails if the memory is locked, see `LockedAllocator`.
+    #[cfg(feature = "std")]
+    pub fn release_below(&mut self, sp: usize) -> io::Result<()> {
+        // Miri doesn't support madvise, and there is no memory to save there anyway.
+        if cfg!(miri) {
+            return Ok(());
+        }
+        let page_size = page_size();
+        let start = (self.base.as_ptr() as usize + self.guard).next_multiple_of(page_size);
+        let end = sp.min(self.base.as_ptr() as usize + self.len) & !(page_size - 1);
+        if end > start
+            && unsafe {
+                libc::madvise(
+                    self.base.as_ptr().with_addr(start).cast(),
+                    end - start,
+                    libc::MADV_DONTNEED,
+                )
+            } < 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Returns how much of the stack has been used, i.e. the distance from its top to the lowest page in memory.
+    /// Only counts whole pages, which are in memory once touched and until given back to the OS (see `release_below`).
+    #[cfg(feature = "std")]
+    pub fn used(&self) -> io::Result<usize> {
+        let page_size = page_size();
+        let start = (self.base.as_ptr() as usize + self.guard).next_multiple_of(page_size);
+        let end = (self.base.as_ptr() as usize + self.len) & !(page_size - 1);
+        if end <= start {
+            return Ok(0);
+        }
+
+        let mut resident = vec![0; (end - start) / page_size];
+        if unsafe {
+            libc::mincore(
+                self.base.as_ptr().with_addr(start).cast(),
+                end - start,
+                resident.as_mut_ptr(),
+            )
+        } < 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(resident
+            .iter()
+            .position(|page| page & 1 != 0)
+            .map_or(0, |lowest| end - start - lowest * page_size))
+    }
+
+    /// Overwrites the part of the stack that has been used with zeroes, as far as `used` tells,
+    /// or the whole of it if that can't be told, e.g. so that the next thread running on it can't read what was left there.
+    ///
+    /// # Safety
+    ///
+    /// No thread may be running on the stack.
+    pub unsafe fn zero(&mut self) {
+        let usable = self.base.as_ptr() as usize + self.guard;
+        let top = self.base.as_ptr() as usize + self.len;
+        // Miri doesn't support mincore.
+        #[cfg(feature = "std")]
+        let used = if cfg!(miri) { None } else { self.used().ok() };
+        // the part of the top page past the last whole one isn't counted by `used`.
+        #[cfg(feature = "std")]
+        let bottom = match used {
+            Some(used) => (top & !(page_size() - 1)) - used,
+            None => usable,
+        };
+        // without std, there are no pages to tell the used ones by.
+        #[cfg(not(feature = "std"))]
+        let bottom = usable;
+        let bottom = bottom.max(usable);
+        unsafe {
+            self.base
+                .as_ptr()
+                .with_addr(bottom)
+                .write_bytes(0, top - bottom)
+        };
+    }
+
+    // Grows the stack so that `addr` is part of it, at least doubling its usable size to keep faults rare.
+    // Returns whether it could.
+    // Called from the SIGSEGV handler, so it must neither allocate nor take locks.
+    #[cfg(feature = "std")]
+    pub(crate) fn grow(&mut self, addr: usize) -> bool {
+        let base = self.base.as_ptr() as usize;
+        if addr < base + self.limit {
+            return false;
+        }
+        let page_size = page_size();
+        let used = self.len - self.guard;
+        let wanted = (base + self.guard - used).min(addr & !(page_size - 1));
+        let guard = wanted.saturating_sub(base).max(self.limit);
+        let grown = unsafe {
+            libc::mprotect(
+                self.base.as_ptr().wrapping_add(guard).cast(),
+                self.guard - guard,
+                libc::PROT_READ | libc::PROT_WRITE,
+            )
+        };
+        if grown < 0 {
+            return false;
+        }
+        self.guard = guard;
+        true
+    }
+
+    // Returns the memory the stack may use, including what it may grow over.
+    #[cfg(feature = "valgrind")]
+    pub(crate) fn reserved(&self) -> std::ops::Range<usize> {
+        let base = self.base.as_ptr() as usize;
+        base + self.limit..base + self.len
+    }
+
+    /// Returns whether `addr` is in the guard area.
+    pub fn in_guard(&self, addr: usize) -> bool {
+        let base = self.base.as_ptr() as usize;
+        (base..base + self.guard).contains(&addr)
+    }
+}
+
+impl Deref for Stack {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe {
+            core::slice::from_raw_parts(self.base.as_ptr().add(self.guard), self.len - self.guard)
+        }
+    }
+}
+
+impl DerefMut for Stack {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe {
+            core::slice::from_raw_parts_mut(
+                self.base.as_ptr().add(self.guard),
+                self.len - self.guard,
+            )
+        }
+    }
+}
+
+/// The default `StackAllocator`, mapping every stack directly from the OS.
+/// The guard area is a single page.
+/// The memory is neither touched nor reserved upfront: the OS only provides the pages a thread actually uses,
+/// so large stacks cost little unless they are used.
+#[cfg(feature = "std")]
+#[derive(Debug, Default)]
+pub struct MmapAllocator;
+
+#[cfg(feature = "std")]
+impl StackAllocator for MmapAllocator {
+    fn allocate(&mut self, size: usize, guard: bool) -> io::Result<Stack> {
+        let page_size = page_size();
+        let guard = if guard { page_size } else { 0 };
+        let len = size.next_multiple_of(page_size) + guard;
+
+        unsafe {
+            let base = libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                MAP_FLAGS,
+                -1,
+                0,
+            );
+            if base == libc::MAP_FAILED {
+                return Err(io::Error::last_os_error());
+            }
+            // stacks grow downwards, so the guard page goes at the lowest address.
+            // Miri doesn't support mprotect, the page is still set aside but accessible there.
+            if guard > 0 && !cfg!(miri) && libc::mprotect(base, guard, libc::PROT_NONE) < 0 {
+                let err = io::Error::last_os_error();
+                libc::munmap(base, len);
+                return Err(err);
+            }
+
+            Ok(Stack::from_raw_parts(
+                NonNull::new_unchecked(base.cast()),
+                len,
+                guard,
+            ))
+        }
+    }
+
+    fn deallocate(&mut self, stack: Stack) {
+        let (base, len, _) = stack.into_raw_parts();
+        unsafe {
+            libc::munmap(base.as_ptr().cast(), len);
+        }
+    }
+}
+
+/// A `StackAllocator` for threads handling secrets, e.g. key material, which must not leave memory.
+/// Like `MmapAllocator`, but the stacks are locked into memory with `mlock`, so that they are never swapped out,
+/// and left out of core dumps on Linux. Every stack is then resident in full, and counts against the limit of the OS
+/// on locked memory (`RLIMIT_MEMLOCK`), which bounds the number of threads.
+/// The pages of locked stacks can't be given back to the OS, see `Stack::release_below`,
+/// so they should be zeroed before reuse, see `Runtime::set_zero_stacks`.
+#[cfg(feature = "std")]
+#[derive(Debug, Default)]
+pub struct LockedAllocator;
+
+#[cfg(feature = "std")]
+impl StackAllocator for LockedAllocator {
+    fn allocate(&mut self, size: usize, guard: bool) -> io::Result<Stack> {
+        let stack = MmapAllocator.allocate(size, guard)?;
+        // Miri supports neither.
+        if cfg!(miri) {
+            return Ok(stack);
+        }
+        let usable = stack.as_ptr() as *mut libc::c_void;
+        unsafe {
+            #[cfg(target_os = "linux")]
+            if libc::madvise(usable, stack.len(), libc::MADV_DONTDUMP) < 0 {
+                let err = io::Error::last_os_error();
+                MmapAllocator.deallocate(stack);
+                return Err(err);
+            }
+            if libc::mlock(usable, stack.len()) < 0 {
+                let err = io::Error::last_os_error();
+                MmapAllocator.deallocate(stack);
+                return Err(err);
+            }
+        }
+        Ok(stack)
+    }
+
+    fn deallocate(&mut self, stack: Stack) {
+        // unmapping the memory unlocks it.
+        MmapAllocator.deallocate(stack);
+    }
+}
+
+/// An experimental `StackAllocator` for stacks that start small and grow as needed.
+/// Like `MmapAllocator`, it maps the full size of every stack, but only the top `initial` bytes are accessible at first.
+/// The rest is part of the guard area, and the stack grows over it when a thread reaches it, up to the guard page.
+/// The stack doesn't move when it grows, so pointers into it stay valid.
+/// As inaccessible memory isn't counted against the memory the OS commits to, many mostly idle threads can be kept around.
+/// Relies on the fault handler the runtime installs when initialised.
+/// Each stack takes two mappings, so the number of threads is bounded by the limit of the OS on those
+/// (`vm.max_map_count` on Linux).
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct GrowableAllocator {
+    /// How much of each stack is accessible at first.
+    pub initial: usize,
+}
+
+#[cfg(feature = "std")]
+impl StackAllocator for GrowableAllocator {
+    fn allocate(&mut self, size: usize, guard: bool) -> io::Result<Stack> {
+        let page_size = page_size();
+        let limit = if guard { page_size } else { 0 };
+        let len = size.next_multiple_of(page_size) + limit;
+        let initial = self
+            .initial
+            .next_multiple_of(page_size)
+            .clamp(page_size, len - limit);
+
+        unsafe {
+            let base = libc::mmap(std::ptr::null_mut(), len, libc::PROT_NONE, MAP_FLAGS, -1, 0);
+            if base == libc::MAP_FAILED {
+                return Err(io::Error::last_os_error());
+            }
+            if libc::mprotect(
+                base.byte_add(len - initial),
+                initial,
+                libc::PROT_READ | libc::PROT_WRITE,
+            ) < 0
+            {
+                let err = io::Error::last_os_error();
+                libc::munmap(base, len);
+                return Err(err);
+            }
+
+            Ok(Stack::from_raw_parts_growable(
+                NonNull::new_unchecked(base.cast()),
+                len,
+                len - initial,
+                limit,
+            ))
+        }
+    }
+
+    fn deallocate(&mut self, stack: Stack) {
+        MmapAllocator.deallocate(stack);
+    }
+}
+
+/// A `StackAllocator` carving the stacks out of a region of memory given upfront, e.g. a `static` array,
+/// rather than mapping them from the OS: the memory of the threads is then bounded and set aside from the start,
+/// as on targets without virtual memory.
+/// The region is split into slots of the same size, a stack per slot, and allocating fails once they are all in use,
+/// which makes spawning a thread panic, unless the threads are limited accordingly (see `Runtime::set_max_threads`).
+/// There are no guard areas, so overflowing a stack goes unnoticed.
+#[derive(Debug)]
+pub struct StaticAllocator {
+    /// Start of the first slot.
+    start: NonNull<u8>,
+    /// Size of every slot.
+    slot: usize,
+    /// The slots not in use, by index.
+    free: Vec<usize>,
+}
+
+impl StaticAllocator {
+    /// Creates an allocator providing stacks of `slot` bytes, as many as fit in `memory`.
+    /// Slots are aligned to 16 bytes, as stacks have to be, so `slot` is rounded down to a multiple of that.
+    pub fn new(memory: &'static mut [u8], slot: usize) -> Self {
+        let skip = memory.as_ptr().align_offset(16).min(memory.len());
+        let memory = &mut memory[skip..];
+        let slot = slot & !15;
+        let slots = memory.len().checked_div(slot).unwrap_or(0);
+        StaticAllocator {
+            start: NonNull::from(memory).cast(),
+            slot,
+            // the lowest slots are handed out first.
+            free: (0..slots).rev().collect(),
+        }
+    }
+}
+
+impl StackAllocator for StaticAllocator {
+    #[cfg(feature = "std")]
+    fn allocate(&mut self, size: usize, _guard: bool) -> io::Result<Stack> {
+        if size > self.slot {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "stacks of {size} bytes don't fit in slots of {} bytes",
+                    self.slot
+                ),
+            ));
+        }
+        let index = self.free.pop().ok_or(io::ErrorKind::OutOfMemory)?;
+        unsafe {
+            Ok(Stack::from_raw_parts(
+                self.start.add(index * self.slot),
+                self.slot,
+                0,
+            ))
+        }
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn allocate(&mut self, size: usize, _guard: bool) -> Result<Stack, StackError> {
+        if size > self.slot {
+            return Err(StackError::TooLarge {
+                size,
+                max: self.slot,
+            });
+        }
+        let index = self.free.pop().ok_or(StackError::OutOfMemory)?;
+        unsafe {
+            Ok(Stack::from_raw_parts(
+                self.start.add(index * self.slot),
+                self.slot,
+                0,
+            ))
+        }
+    }
+
+    fn deallocate(&mut self, stack: Stack) {
+        let (base, _, _) = stack.into_raw_parts();
+        let index = (base.as_ptr() as usize - self.start.as_ptr() as usize) / self.slot;
+        self.free.push(index);
+    }
+}
+
+#[cfg(feature = "std")]
+fn page_size() -> usize {
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+}
@@ -0,0 +1,231 @@
+// Timers for green threads.
+// A sleeping thread is handed over to the reactor, which wakes it up once its deadline has passed,
+// so other threads keep running in the meantime.
+// Deadlines are read on the clock of the runtime, the system's unless replaced, e.g. by a `MockClock` in tests.
+// Without std there is no system's clock: the runtime starts with a `MockClock`, which the program replaces with
+// a clock of its platform (e.g. a hardware counter), and times are `Instant`s of this module rather than of std.
+// That clock also puts the CPU to sleep while the threads wait for their timers, see `Clock::idle`.
+
+use alloc::rc::Rc;
+use core::cell::Cell;
+use core::fmt;
+#[cfg(not(feature = "std"))]
+use core::ops::{Add, AddAssign, Sub, SubAssign};
+use core::time::Duration;
+
+#[cfg(feature = "std")]
+pub use std::time::Instant;
+
+use crate::runtime;
+
+/// A point in time, measured from when the clock it was read on started counting, e.g. as the board was reset.
+/// It stands in for `std::time::Instant`, which is only there with std, and is what clocks return then, see `Clock`.
+#[cfg(not(feature = "std"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Instant(Duration);
+
+#[cfg(not(feature = "std"))]
+impl Instant {
+    /// Returns the point in time `since_start` after the clock started counting.
+    pub const fn from_duration(since_start: Duration) -> Self {
+        Instant(since_start)
+    }
+
+    /// Returns how long after the clock started counting this is.
+    pub const fn as_duration(&self) -> Duration {
+        self.0
+    }
+
+    /// Returns the current time on the clock of the runtime, see `now`.
+    pub fn now() -> Self {
+        now()
+    }
+
+    /// Returns how long the clock of the runtime has gone on since this, see `now`.
+    pub fn elapsed(&self) -> Duration {
+        now().saturating_duration_since(*self)
+    }
+
+    /// Returns how much later this is than `earlier`, zero if it is earlier, as `std::time::Instant` does.
+    pub fn duration_since(&self, earlier: Instant) -> Duration {
+        self.saturating_duration_since(earlier)
+    }
+
+    /// Returns how much later this is than `earlier`, None if it is earlier.
+    pub fn checked_duration_since(&self, earlier: Instant) -> Option<Duration> {
+        self.0.checked_sub(earlier.0)
+    }
+
+    /// Returns how much later this is than `earlier`, zero if it is earlier.
+    pub fn saturating_duration_since(&self, earlier: Instant) -> Duration {
+        self.0.saturating_sub(earlier.0)
+    }
+
+    /// Returns the point in time `dur` after this, None if it can't be represented.
+    pub fn checked_add(&self, dur: Duration) -> Option<Instant> {
+        self.0.checked_add(dur).map(Instant)
+    }
+
+    /// Returns the point in time `dur` before this, None if it is before the clock started counting.
+    pub fn checked_sub(&self, dur: Duration) -> Option<Instant> {
+        self.0.checked_sub(dur).map(Instant)
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    fn add(self, dur: Duration) -> Instant {
+        self.checked_add(dur)
+            .expect("overflow when adding duration to instant")
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl AddAssign<Duration> for Instant {
+    fn add_assign(&mut self, dur: Duration) {
+        *self = *self + dur;
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl Sub<Duration> for Instant {
+    type Output = Instant;
+
+    fn sub(self, dur: Duration) -> Instant {
+        self.checked_sub(dur)
+            .expect("overflow when subtracting duration from instant")
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl SubAssign<Duration> for Instant {
+    fn sub_assign(&mut self, dur: Duration) {
+        *self = *self - dur;
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl Sub<Instant> for Instant {
+    type Output = Duration;
+
+    fn sub(self, earlier: Instant) -> Duration {
+        self.duration_since(earlier)
+    }
+}
+
+/// The time the timers of a runtime go by, see `Runtime::set_clock`.
+/// It must be monotonic: deadlines are kept in the order they fall, on the assumption that time never goes back.
+pub trait Clock {
+    /// Returns the current time.
+    fn now(&self) -> Instant;
+
+    /// Called as no thread can run until the first timer, due at `deadline`, goes off.
+    /// Returns how long the runtime may wait for IO meanwhile, in real time, before looking at the timers again.
+    /// By default, how far the deadline is on this clock. A clock the program advances by hand, like `MockClock`,
+    /// moves to the deadline instead, as no thread is left to advance it.
+    /// Without std, there is no IO to wait for, so the runtime waits here instead, calling it again if it returns early:
+    /// the clock of a board can set its timer to go off at the deadline and sleep until then, e.g. with `wfi`,
+    /// as `examples/riscv-virt` does.
+    fn idle(&self, deadline: Instant) -> Duration {
+        deadline.saturating_duration_since(self.now())
+    }
+}
+
+/// The system's clock, which the runtime goes by unless given another one.
+#[cfg(feature = "std")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+#[cfg(feature = "std")]
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A clock only advanced by hand, with `advance`, for tests of code that sleeps or times out to run instantly,
+/// and the same way every time. Once every thread is blocked, it moves to the first timer on its own.
+/// Cloning it shares it, e.g. to pass it to `Runtime::set_clock` and keep a clone in the test.
+#[derive(Clone)]
+pub struct MockClock {
+    now: Rc<Cell<Instant>>,
+}
+
+impl MockClock {
+    /// Creates a clock standing at the current time, which it doesn't move from until advanced.
+    /// Without std, there is no current time to start from, so it stands where clocks start counting.
+    pub fn new() -> Self {
+        #[cfg(feature = "std")]
+        let now = Instant::now();
+        #[cfg(not(feature = "std"))]
+        let now = Instant::from_duration(Duration::ZERO);
+        MockClock {
+            now: Rc::new(Cell::new(now)),
+        }
+    }
+
+    /// Moves the clock forward by `dur`, waking up the threads whose deadlines have passed,
+    /// which run once the current thread yields.
+    pub fn advance(&self, dur: Duration) {
+        self.now.set(self.now.get() + dur);
+        runtime::expire_timers();
+    }
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        self.now.get()
+    }
+
+    fn idle(&self, deadline: Instant) -> Duration {
+        if deadline > self.now.get() {
+            self.now.set(deadline);
+        }
+        Duration::ZERO
+    }
+}
+
+impl fmt::Debug for MockClock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MockClock")
+            .field("now", &self.now.get())
+            .finish()
+    }
+}
+
+/// Returns the current time on the clock of the runtime, the system's outside of one, see `Runtime::set_clock`.
+/// Deadlines for the runtime should be computed from it, rather than from `Instant::now`, to work with any clock.
+/// Without std, it is where clocks start counting outside of a runtime.
+pub fn now() -> Instant {
+    runtime::now()
+}
+
+// Returns the current time for the runtime to measure what threads do by, e.g. how long they have run or waited:
+// the system's, which stays real whatever the clock of the timers. Without std, that clock is all there is.
+pub(crate) fn instant() -> Instant {
+    #[cfg(feature = "std")]
+    return Instant::now();
+    #[cfg(not(feature = "std"))]
+    now()
+}
+
+/// Blocks the current thread for at least `dur`.
+pub fn sleep(dur: Duration) {
+    sleep_until(now() + dur);
+}
+
+/// Blocks the current thread until `deadline` has passed.
+/// Periodic work should compute its wake times from a fixed start, e.g. `start + n * period`, and sleep until each,
+/// so that the period doesn't drift by how late the thread is woken up, or by how long the work takes.
+/// Deadlines for other blocking operations are set through a context, see `Context::with_deadline`.
+pub fn sleep_until(deadline: Instant) {
+    runtime::sleep_until(deadline);
+}
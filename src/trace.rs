@@ -0,0 +1,177 @@
+//! Instrumentation of the runtime.
+//! The runtime reports what it does (threads spawned, switched between, blocked...) as events,
+//! which are passed to a sink if their level is enabled, see `set_max_level` and `set_sink`.
+//! Nothing is reported by default, unless the `log` feature is enabled:
+//! the events are then forwarded to the `log` crate, under the `uthreads` target.
+//! Without std, there is no lock to guard a sink with, so the events only go to the `log` crate.
+
+#[cfg(feature = "std")]
+mod chrome;
+
+#[cfg(feature = "std")]
+use alloc::boxed::Box;
+use core::fmt;
+use core::sync::atomic::{AtomicU8, Ordering};
+#[cfg(feature = "std")]
+use std::sync::RwLock;
+
+use crate::thread::Id;
+#[cfg(feature = "std")]
+pub use chrome::Recorder;
+
+/// How much detail an event gives, from the least to the most detailed.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub enum Level {
+    /// Changes to the lifetime of threads, e.g. a thread being spawned or completing.
+    Info = 1,
+    /// Scheduling decisions, e.g. a thread blocking or being woken up.
+    Debug,
+    /// Details of every switch and channel operation.
+    Trace,
+}
+
+/// What an event is about.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Kind {
+    /// The runtime has started running its threads.
+    Run,
+    /// A thread has been spawned.
+    Spawn,
+    /// A thread has completed.
+    Exit,
+    /// A thread has given control to another one.
+    Switch,
+    /// The state of a thread has changed.
+    State,
+    /// A thread has been woken up, as what it was waiting on happened.
+    Wake,
+    /// A thread has used a channel.
+    Channel,
+    /// A thread is waiting on IO.
+    Io,
+    /// A thread has parked, or is waiting to be notified.
+    Park,
+    /// A thread has gone to sleep.
+    Sleep,
+    /// A thread is waiting on a synchronisation primitive.
+    Sync,
+    /// A thread has run for too long without yielding, see `Runtime::set_watchdog`.
+    Stall,
+    /// A thread has been ready to run for too long without getting its turn, see `Runtime::set_starvation_threshold`.
+    Starve,
+}
+
+/// Something the runtime did.
+#[derive(Debug, Clone, Copy)]
+pub struct Event<'a> {
+    pub level: Level,
+    pub kind: Kind,
+    /// The thread the event is about, usually the current one.
+    pub thread: Id,
+    /// Describes the event.
+    pub message: fmt::Arguments<'a>,
+}
+
+impl fmt::Display for Event<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[{:?} {:?} {:?}] {}",
+            self.level, self.thread, self.kind, self.message
+        )
+    }
+}
+
+/// Receives the events enabled.
+/// Runs on the thread the event is about, in the middle of the runtime,
+/// so it must not call into the runtime (e.g. spawn a thread, use a channel or yield).
+#[cfg(feature = "std")]
+pub type Sink = Box<dyn Fn(&Event) + Send + Sync>;
+
+// The most detailed level enabled, 0 if none is.
+static MAX_LEVEL: AtomicU8 = AtomicU8::new(if cfg!(feature = "log") {
+    Level::Trace as u8
+} else {
+    0
+});
+#[cfg(feature = "std")]
+static SINK: RwLock<Option<Sink>> = RwLock::new(None);
+
+/// Sets the most detailed level of the events passed to the sink, None to disable them all.
+/// Can be changed at any time, e.g. to look into a misbehaving runtime.
+pub fn set_max_level(level: Option<Level>) {
+    MAX_LEVEL.store(level.map_or(0, |l| l as u8), Ordering::Relaxed);
+}
+
+/// Sets where the events go, None for the default: the `log` crate if the `log` feature is enabled, nowhere otherwise.
+#[cfg(feature = "std")]
+pub fn set_sink(sink: Option<Sink>) {
+    *SINK.write().unwrap_or_else(|e| e.into_inner()) = sink;
+}
+
+/// A sink printing the events to stderr, one per line.
+#[cfg(feature = "std")]
+pub fn print(event: &Event) {
+    eprintln!("{event}");
+}
+
+// Whether events of this level are passed to the sink, checked before preparing them.
+#[inline]
+pub(crate) fn enabled(level: Level) -> bool {
+    level as u8 <= MAX_LEVEL.load(Ordering::Relaxed)
+}
+
+#[cfg(feature = "std")]
+pub(crate) fn emit(event: &Event) {
+    let sink = SINK.read().unwrap_or_else(|e| e.into_inner());
+    match &*sink {
+        Some(sink) => sink(event),
+        None => forward(event),
+    }
+}
+
+#[cfg(not(feature = "std"))]
+pub(crate) fn emit(event: &Event) {
+    forward(event);
+}
+
+#[cfg(feature = "log")]
+fn forward(event: &Event) {
+    let level = match event.level {
+        Level::Info => log::Level::Info,
+        Level::Debug => log::Level::Debug,
+        Level::Trace => log::Level::Trace,
+    };
+    if level > log::max_level() {
+        return;
+    }
+    log::logger().log(
+        &log::Record::builder()
+            .level(level)
+            .target("uthreads")
+            .args(format_args!(
+                "[{:?} {:?}] {}",
+                event.thread, event.kind, event.message
+            ))
+            .build(),
+    );
+}
+
+#[cfg(not(feature = "log"))]
+fn forward(_: &Event) {}
+
+// Reports an event about `thread` if its level is enabled:
+// `event!(Level, Kind, thread, "format", args...)`, the arguments are only evaluated if so.
+macro_rules! event {
+    ($level:ident, $kind:ident, $thread:expr, $($arg:tt)+) => {
+        if $crate::trace::enabled($crate::trace::Level::$level) {
+            $crate::trace::emit(&$crate::trace::Event {
+                level: $crate::trace::Level::$level,
+                kind: $crate::trace::Kind::$kind,
+                thread: $thread,
+                message: format_args!($($arg)+),
+            });
+        }
+    };
+}
+pub(crate) use event;
@@ -0,0 +1,120 @@
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::rc::Rc;
+use std::time::Instant;
+
+use crate::runtime::Runtime;
+use crate::thread::{Id, State};
+use crate::BASE_THREAD_ID;
+
+/// Records the schedule of a runtime, to be viewed as a timeline in a trace viewer
+/// (`chrome://tracing`, or https://ui.perfetto.dev), e.g. by saving it once the runtime is done running.
+/// Every thread gets a track showing when it ran, with marks where it blocked and was woken up.
+/// Everything is kept in memory until written, so recording a long running runtime takes a lot of it.
+#[derive(Debug, Clone)]
+pub struct Recorder {
+    start: Instant,
+    records: Rc<RefCell<Vec<Record>>>,
+}
+
+#[derive(Debug)]
+struct Record {
+    /// Microseconds since the recording started.
+    ts: f64,
+    thread: Id,
+    kind: RecordKind,
+}
+
+#[derive(Debug)]
+enum RecordKind {
+    Spawn,
+    /// The thread starts running.
+    Begin,
+    /// The thread stops running.
+    End,
+    Block(State),
+    Wake,
+}
+
+impl Recorder {
+    /// Starts recording the schedule of `runtime`, which must not be running yet.
+    pub fn new(runtime: &mut Runtime) -> Self {
+        let recorder = Recorder {
+            start: Instant::now(),
+            records: Rc::default(),
+        };
+        // the base thread runs until the runtime first switches away from it.
+        recorder.push(BASE_THREAD_ID, RecordKind::Begin);
+
+        let r = recorder.clone();
+        runtime.on_spawn(move |id| r.push(id, RecordKind::Spawn));
+        let r = recorder.clone();
+        runtime.on_switch(move |from, to| {
+            r.push(from, RecordKind::End);
+            r.push(to, RecordKind::Begin);
+        });
+        let r = recorder.clone();
+        runtime.on_block(move |id, state| r.push(id, RecordKind::Block(*state)));
+        let r = recorder.clone();
+        runtime.on_wake(move |id| r.push(id, RecordKind::Wake));
+        recorder
+    }
+
+    fn push(&self, thread: Id, kind: RecordKind) {
+        let ts = self.start.elapsed().as_secs_f64() * 1e6;
+        self.records.borrow_mut().push(Record { ts, thread, kind });
+    }
+
+    /// Writes what has been recorded so far in the Chrome `trace_event` JSON format.
+    pub fn write(&self, out: impl Write) -> io::Result<()> {
+        let mut out = BufWriter::new(out);
+        let records = self.records.borrow();
+        write!(out, "{{\"traceEvents\":[")?;
+        // name the track of every thread, the base thread runs the scheduler.
+        let mut ids = records.iter().map(|r| r.thread).collect::<Vec<_>>();
+        ids.sort();
+        ids.dedup();
+        for (i, id) in ids.iter().enumerate() {
+            if i > 0 {
+                write!(out, ",")?;
+            }
+            let name = if *id == BASE_THREAD_ID {
+                "scheduler".to_string()
+            } else {
+                format!("thread {}", id.0)
+            };
+            write!(
+                out,
+                "{{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":1,\"tid\":{},\"args\":{{\"name\":\"{name}\"}}}}",
+                id.0
+            )?;
+        }
+        for record in records.iter() {
+            let (ph, name) = match &record.kind {
+                RecordKind::Spawn => ("i", "spawned".to_string()),
+                RecordKind::Begin => ("B", "running".to_string()),
+                RecordKind::End => ("E", "running".to_string()),
+                RecordKind::Block(state) => ("i", format!("blocked: {state:?}")),
+                RecordKind::Wake => ("i", "woken".to_string()),
+            };
+            write!(
+                out,
+                ",{{\"name\":\"{name}\",\"ph\":\"{ph}\",\"ts\":{:.3},\"pid\":1,\"tid\":{}",
+                record.ts, record.thread.0
+            )?;
+            if ph == "i" {
+                write!(out, ",\"s\":\"t\"")?;
+            }
+            write!(out, "}}")?;
+        }
+        write!(out, "]}}")?;
+        out.flush()
+    }
+
+    /// Writes what has been recorded so far to the file at `path`, see `write`.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        self.write(File::create(path)?)
+    }
+}
@@ -0,0 +1,60 @@
+// Telling Valgrind about the stacks of the threads.
+// Valgrind tracks which memory is the stack to tell legitimate stack accesses apart from invalid ones,
+// and gets confused when execution jumps to memory it doesn't know is a stack, reporting bogus errors.
+// Programs talk to Valgrind through client requests: a sequence of instructions that does nothing when run natively,
+// but that Valgrind recognises, see `valgrind.h`.
+
+use std::ops::Range;
+
+const STACK_REGISTER: usize = 0x1501;
+const STACK_DEREGISTER: usize = 0x1502;
+
+// Registers the memory in `range` as a stack, returning the id Valgrind knows it by (0 when not running under it).
+pub(crate) fn stack_register(range: Range<usize>) -> usize {
+    client_request(0, [STACK_REGISTER, range.start, range.end, 0, 0, 0])
+}
+
+pub(crate) fn stack_deregister(id: usize) {
+    client_request(0, [STACK_DEREGISTER, id, 0, 0, 0, 0]);
+}
+
+// Returns `default` when not running under Valgrind.
+#[cfg(target_arch = "x86_64")]
+fn client_request(default: usize, args: [usize; 6]) -> usize {
+    let result;
+    unsafe {
+        core::arch::asm!(
+            "rol rdi, 3",
+            "rol rdi, 13",
+            "rol rdi, 61",
+            "rol rdi, 51",
+            "xchg rbx, rbx",
+            in("rax") args.as_ptr(),
+            inout("rdx") default => result,
+        );
+    }
+    result
+}
+
+#[cfg(target_arch = "aarch64")]
+fn client_request(default: usize, args: [usize; 6]) -> usize {
+    let result;
+    unsafe {
+        core::arch::asm!(
+            "ror x12, x12, #3",
+            "ror x12, x12, #13",
+            "ror x12, x12, #51",
+            "ror x12, x12, #61",
+            "orr x10, x10, x10",
+            in("x4") args.as_ptr(),
+            inout("x3") default => result,
+        );
+    }
+    result
+}
+
+// Not supported by Valgrind on the other architectures the runtime runs on.
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+fn client_request(default: usize, _: [usize; 6]) -> usize {
+    default
+}
@@ -0,0 +1,217 @@
+#![cfg(feature = "std")]
+
+use std::hint::black_box;
+use std::time::Duration;
+
+use uthreads::join;
+use uthreads::runtime::yield_thread;
+use uthreads::testing;
+
+extern "C" fn yield_from_asm() {
+    yield_thread();
+}
+
+// Puts `seed` and the values after it in the callee-saved registers, switches to the other threads,
+// and returns what the registers hold after switching back, which the backend must have restored.
+#[cfg(target_arch = "x86_64")]
+fn callee_saved_across_switch(seed: u64) -> Vec<u64> {
+    use std::arch::asm;
+    // rbx and rbp can't be named, but are saved along with these.
+    let [mut r12, mut r13, mut r14, mut r15] = [seed, seed + 1, seed + 2, seed + 3];
+    unsafe {
+        asm!(
+            "call {f}",
+            f = sym yield_from_asm,
+            inout("r12") r12,
+            inout("r13") r13,
+            inout("r14") r14,
+            inout("r15") r15,
+            clobber_abi("C"),
+        );
+    }
+    vec![r12, r13, r14, r15]
+}
+
+#[cfg(target_arch = "aarch64")]
+fn callee_saved_across_switch(seed: u64) -> Vec<u64> {
+    use std::arch::asm;
+    // x19 and fp can't be named, but are saved along with these.
+    let mut x = [0; 9];
+    let mut d = [0; 8];
+    for (i, v) in x.iter_mut().chain(d.iter_mut()).enumerate() {
+        *v = seed + i as u64;
+    }
+    unsafe {
+        asm!(
+            "bl {f}",
+            f = sym yield_from_asm,
+            inout("x20") x[0], inout("x21") x[1], inout("x22") x[2], inout("x23") x[3], inout("x24") x[4],
+            inout("x25") x[5], inout("x26") x[6], inout("x27") x[7], inout("x28") x[8],
+            inout("d8") d[0], inout("d9") d[1], inout("d10") d[2], inout("d11") d[3],
+            inout("d12") d[4], inout("d13") d[5], inout("d14") d[6], inout("d15") d[7],
+            clobber_abi("C"),
+        );
+    }
+    x.into_iter().chain(d).collect()
+}
+
+#[cfg(target_arch = "riscv64")]
+fn callee_saved_across_switch(seed: u64) -> Vec<u64> {
+    use std::arch::asm;
+    // s0 and s1 can't be named, but are saved along with these.
+    let mut s = [0; 10];
+    // fs0-fs11 are callee-saved with the D extension only.
+    // they only hold floats, so the values go through as bit patterns.
+    let mut fs = [0.0; if cfg!(target_feature = "d") { 12 } else { 0 }];
+    for (i, v) in s.iter_mut().enumerate() {
+        *v = seed + i as u64;
+    }
+    for (i, v) in fs.iter_mut().enumerate() {
+        *v = f64::from_bits(seed + (s.len() + i) as u64);
+    }
+    #[cfg(not(target_feature = "d"))]
+    unsafe {
+        asm!(
+            "call {f}",
+            f = sym yield_from_asm,
+            inout("s2") s[0], inout("s3") s[1], inout("s4") s[2], inout("s5") s[3], inout("s6") s[4],
+            inout("s7") s[5], inout("s8") s[6], inout("s9") s[7], inout("s10") s[8], inout("s11") s[9],
+            clobber_abi("C"),
+        );
+    }
+    #[cfg(target_feature = "d")]
+    unsafe {
+        asm!(
+            "call {f}",
+            f = sym yield_from_asm,
+            inout("s2") s[0], inout("s3") s[1], inout("s4") s[2], inout("s5") s[3], inout("s6") s[4],
+            inout("s7") s[5], inout("s8") s[6], inout("s9") s[7], inout("s10") s[8], inout("s11") s[9],
+            inout("fs0") fs[0], inout("fs1") fs[1], inout("fs2") fs[2], inout("fs3") fs[3],
+            inout("fs4") fs[4], inout("fs5") fs[5], inout("fs6") fs[6], inout("fs7") fs[7],
+            inout("fs8") fs[8], inout("fs9") fs[9], inout("fs10") fs[10], inout("fs11") fs[11],
+            clobber_abi("C"),
+        );
+    }
+    s.into_iter().chain(fs.map(f64::to_bits)).collect()
+}
+
+#[test]
+fn callee_saved_registers_are_restored() {
+    testing::run(
+        || {
+            let handles: Vec<_> = (0..4)
+                .map(|t| {
+                    join::spawn(move || {
+                        let seed = t * 100;
+                        let regs = callee_saved_across_switch(seed);
+                        let expected: Vec<_> = (seed..).take(regs.len()).collect();
+                        assert_eq!(regs, expected, "thread {t}");
+                    })
+                })
+                .collect();
+            join::join_all(handles);
+        },
+        Duration::from_secs(10),
+    );
+}
+
+// Keeps more values live across the switches than there are caller-saved registers, integers and floats alike,
+// so that the compiler keeps some of them in the callee-saved ones, at least with optimisations.
+#[inline(never)]
+fn live_across(switches: usize, seed: u64) -> (u64, f64) {
+    let [a, b, c, d, e, f, g, h, i, j, k, l] = black_box([seed; 12]).map(|v| v * 3 + 1);
+    let [m, n, o, p, q, r, s, t] = black_box([seed as f64; 8]).map(|v| v * 0.5 + 0.25);
+    for _ in 0..switches {
+        yield_thread();
+    }
+    let ints = (a ^ b.rotate_left(1) ^ c.rotate_left(2) ^ d.rotate_left(3))
+        .wrapping_add(e ^ f.rotate_left(4) ^ g.rotate_left(5) ^ h.rotate_left(6))
+        .wrapping_add(i ^ j.rotate_left(7) ^ k.rotate_left(8) ^ l.rotate_left(9));
+    let floats = m + n * 2.0 + o * 3.0 + p * 4.0 + q * 5.0 + r * 6.0 + s * 7.0 + t * 8.0;
+    (ints, floats)
+}
+
+#[test]
+fn values_live_across_switches_survive() {
+    testing::run(
+        || {
+            let handles: Vec<_> = (0..8)
+                .map(|seed| join::spawn(move || (seed, live_across(10, seed))))
+                .collect();
+            for (seed, result) in join::join_all(handles) {
+                assert_eq!(result, live_across(0, seed), "thread {seed}");
+            }
+        },
+        Duration::from_secs(10),
+    );
+}
+
+// Returns the control state of the floating point unit: the rounding mode, which exceptions are masked...
+#[cfg(target_arch = "x86_64")]
+fn fp_control() -> (u32, u16) {
+    use std::arch::asm;
+    let mut mxcsr = 0u32;
+    let mut fpcw = 0u16;
+    unsafe {
+        asm!("stmxcsr [{}]", in(reg) &mut mxcsr);
+        asm!("fnstcw [{}]", in(reg) &mut fpcw);
+    }
+    (mxcsr, fpcw)
+}
+
+#[cfg(target_arch = "x86_64")]
+fn set_fp_control((mxcsr, fpcw): (u32, u16)) {
+    use std::arch::asm;
+    unsafe {
+        asm!("ldmxcsr [{}]", in(reg) &mxcsr);
+        asm!("fldcw [{}]", in(reg) &fpcw);
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+fn rounding_towards_zero((mxcsr, fpcw): (u32, u16)) -> (u32, u16) {
+    (mxcsr | 0x6000, fpcw | 0x0c00)
+}
+
+#[cfg(target_arch = "aarch64")]
+fn fp_control() -> u64 {
+    let fpcr: u64;
+    unsafe { std::arch::asm!("mrs {}, fpcr", out(reg) fpcr) };
+    fpcr
+}
+
+#[cfg(target_arch = "aarch64")]
+fn set_fp_control(fpcr: u64) {
+    unsafe { std::arch::asm!("msr fpcr, {}", in(reg) fpcr) };
+}
+
+#[cfg(target_arch = "aarch64")]
+fn rounding_towards_zero(fpcr: u64) -> u64 {
+    fpcr | 0x00c0_0000
+}
+
+// The rounding mode a thread sets stays its own, rather than leaking into the threads it switches to.
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+#[test]
+fn floating_point_control_is_per_thread() {
+    testing::run(
+        || {
+            let default = fp_control();
+            let changed = join::spawn(move || {
+                let control = rounding_towards_zero(default);
+                set_fp_control(control);
+                yield_thread();
+                let after = fp_control();
+                set_fp_control(default);
+                (control, after)
+            });
+            let other = join::spawn(fp_control);
+            yield_thread();
+            assert_eq!(fp_control(), default);
+            let (control, after) = changed.join();
+            assert_eq!(after, control);
+            assert_eq!(other.join(), default);
+        },
+        Duration::from_secs(10),
+    );
+}
@@ -0,0 +1,62 @@
+#![cfg(all(feature = "std", feature = "sync"))]
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+
+use uthreads::runtime::{self, yield_thread};
+use uthreads::sync::{Barrier, CountDownLatch};
+use uthreads::testing;
+
+const THREADS: usize = 5;
+const PHASES: usize = 4;
+
+#[test]
+fn one_leader_per_generation() {
+    testing::run(
+        || {
+            let barrier = Rc::new(Barrier::new(THREADS));
+            let leaders = Rc::new(RefCell::new(vec![0; PHASES]));
+            // what each thread did in each phase, which the leaders check the whole of between phases.
+            let work = Rc::new(RefCell::new(vec![0; PHASES]));
+            let done = Rc::new(CountDownLatch::new(THREADS));
+            for thread in 0..THREADS {
+                let (barrier, leaders, work, done) =
+                    (barrier.clone(), leaders.clone(), work.clone(), done.clone());
+                runtime::spawn(move || {
+                    for phase in 0..PHASES {
+                        for _ in 0..thread {
+                            yield_thread();
+                        }
+                        work.borrow_mut()[phase] += 1;
+                        if barrier.wait().is_leader() {
+                            assert_eq!(work.borrow()[phase], THREADS);
+                            leaders.borrow_mut()[phase] += 1;
+                        }
+                        // no thread is into the next phase before every one is done with this one.
+                        assert_eq!(work.borrow()[phase], THREADS);
+                    }
+                    done.count_down();
+                });
+            }
+            done.wait();
+            assert_eq!(*leaders.borrow(), [1; PHASES]);
+            assert_eq!(barrier.generation(), PHASES as u64);
+        },
+        Duration::from_secs(10),
+    );
+}
+
+#[test]
+fn barrier_for_one_never_blocks() {
+    testing::run(
+        || {
+            for barrier in [Barrier::new(0), Barrier::new(1)] {
+                assert!(barrier.wait().is_leader());
+                assert!(barrier.wait().is_leader());
+                assert_eq!(barrier.generation(), 2);
+            }
+        },
+        Duration::from_secs(10),
+    );
+}
@@ -0,0 +1,84 @@
+#![cfg(feature = "std")]
+
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::Duration;
+
+use uthreads::channel::Bus;
+use uthreads::join;
+use uthreads::runtime::yield_thread;
+use uthreads::testing;
+
+#[test]
+fn every_subscriber_gets_the_messages_of_its_topic() {
+    let received = testing::run(
+        || {
+            let bus = Bus::new();
+            let subscribers: Vec<_> = ["news", "news", "weather"]
+                .into_iter()
+                .map(|topic| {
+                    let subscription = bus.subscribe(topic);
+                    join::spawn(move || vec![subscription.recv(), subscription.recv()])
+                })
+                .collect();
+            assert_eq!(bus.subscribers("news"), 2);
+            assert_eq!(bus.publish("news", "first"), 2);
+            assert_eq!(bus.publish("sports", "lost"), 0);
+            assert_eq!(bus.publish("weather", "sunny"), 1);
+            assert_eq!(bus.clone().publish("news", "second"), 2);
+            assert_eq!(bus.publish("weather", "rainy"), 1);
+            join::join_all(subscribers)
+        },
+        Duration::from_secs(10),
+    );
+    assert_eq!(
+        received,
+        [["first", "second"], ["first", "second"], ["sunny", "rainy"]]
+    );
+}
+
+#[test]
+fn a_full_subscription_blocks_the_publisher() {
+    let (received, published) = testing::run(
+        || {
+            let bus = Bus::with_capacity(1);
+            let subscription = bus.subscribe("topic");
+            let published = Rc::new(Cell::new(0));
+            let counted = published.clone();
+            let publisher = join::spawn(move || {
+                for i in 0..3 {
+                    bus.publish("topic", i);
+                    counted.set(counted.get() + 1);
+                }
+            });
+            yield_thread();
+            // the first message filled the subscription, the second one is blocked on it.
+            let published_before = published.get();
+            let received: Vec<_> = (0..3).map(|_| subscription.recv()).collect();
+            publisher.join();
+            (received, published_before)
+        },
+        Duration::from_secs(10),
+    );
+    assert_eq!(received, [0, 1, 2]);
+    assert_eq!(published, 1);
+}
+
+#[test]
+fn dropping_a_subscription_unsubscribes() {
+    testing::run(
+        || {
+            let bus = Bus::with_capacity(1);
+            let kept = bus.subscribe("topic");
+            let dropped = bus.subscribe("topic");
+            assert_eq!(bus.publish("topic", 1), 2);
+            // the message left in it is dropped along with it.
+            drop(dropped);
+            assert_eq!(bus.subscribers("topic"), 1);
+            assert_eq!(kept.recv(), 1);
+            assert_eq!(bus.publish("topic", 2), 1);
+            assert_eq!(kept.recv(), 2);
+        },
+        Duration::from_secs(10),
+    );
+}
@@ -0,0 +1,51 @@
+#![cfg(feature = "std")]
+
+use std::time::Duration;
+
+use uthreads::channel::call_channel;
+use uthreads::join;
+use uthreads::runtime::yield_thread;
+use uthreads::testing;
+
+#[test]
+fn each_caller_gets_the_reply_to_its_request() {
+    let replies = testing::run(
+        || {
+            let (client, server) = call_channel::<u32, u32>(1);
+            let server = join::spawn(move || {
+                for _ in 0..4 {
+                    let (req, reply) = server.recv();
+                    // the other callers run meanwhile, and block on the full buffer or on their replies.
+                    yield_thread();
+                    reply.send(req * 10);
+                }
+            });
+            let callers: Vec<_> = (0..4)
+                .map(|i| {
+                    let client = client.clone();
+                    join::spawn(move || client.call(i))
+                })
+                .collect();
+            let replies = join::join_all(callers);
+            server.join();
+            replies
+        },
+        Duration::from_secs(10),
+    );
+    assert_eq!(replies, [Some(0), Some(10), Some(20), Some(30)]);
+}
+
+#[test]
+fn a_request_dropped_without_a_reply_returns_none() {
+    let reply = testing::run(
+        || {
+            let (client, server) = call_channel::<u32, u32>(1);
+            let server = join::spawn(move || drop(server.recv()));
+            let reply = client.call(1);
+            server.join();
+            reply
+        },
+        Duration::from_secs(10),
+    );
+    assert_eq!(reply, None);
+}
@@ -0,0 +1,112 @@
+#![cfg(feature = "std")]
+
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::Duration;
+
+use uthreads::channel::Channel;
+use uthreads::context::{Context, Error};
+use uthreads::group::Group;
+use uthreads::join::JoinSet;
+use uthreads::runtime::{chan_recv, chan_send, yield_thread};
+use uthreads::testing;
+use uthreads::{check_cancelled, is_cancelled};
+
+#[test]
+fn outside_of_a_context_nothing_is_cancelled() {
+    testing::run(
+        || {
+            assert!(!is_cancelled());
+            assert_eq!(check_cancelled(), Ok(()));
+        },
+        Duration::from_secs(10),
+    );
+}
+
+#[test]
+fn a_busy_member_stops_at_its_checkpoint() {
+    testing::run(
+        || {
+            let group = Group::new("busy");
+            let iterations = Rc::new(Cell::new(0));
+            let counted = iterations.clone();
+            let result = Rc::new(Cell::new(None));
+            let returned = result.clone();
+            group.spawn(move |_| {
+                let work = || loop {
+                    check_cancelled()?;
+                    counted.set(counted.get() + 1);
+                };
+                let res: Result<(), Error> = work();
+                assert!(is_cancelled());
+                returned.set(Some(res));
+            });
+            // check_cancelled lets the others run, so the member loops while this thread yields.
+            while iterations.get() < 3 {
+                yield_thread();
+            }
+            assert!(result.get().is_none());
+            group.cancel();
+            group.join_all();
+            assert_eq!(result.get(), Some(Err(Error::Cancelled)));
+        },
+        Duration::from_secs(10),
+    );
+}
+
+#[test]
+fn the_context_is_restored_after_the_scope() {
+    testing::run(
+        || {
+            let (ctx, cancel) = Context::background().with_cancel();
+            cancel.cancel();
+            ctx.scope(|| {
+                assert!(is_cancelled());
+                assert_eq!(check_cancelled(), Err(Error::Cancelled));
+            });
+            assert!(!is_cancelled());
+            // the threads of a join set look at the context of the set.
+            let mut set = JoinSet::with_context(&ctx);
+            set.spawn(|_| is_cancelled());
+            assert_eq!(set.join_next(), Some(true));
+        },
+        Duration::from_secs(10),
+    );
+}
+
+#[test]
+fn a_deadline_past_is_reported() {
+    testing::run(
+        || {
+            let (ctx, _cancel) = Context::background().with_timeout(Duration::ZERO);
+            yield_thread();
+            ctx.scope(|| assert_eq!(check_cancelled(), Err(Error::DeadlineExceeded)));
+        },
+        Duration::from_secs(10),
+    );
+}
+
+#[test]
+fn an_interrupted_receiver_wakes_a_blocked_sender() {
+    let received = testing::run(
+        || {
+            let chan = Box::into_raw(Box::new(Channel::<u32>::new(1)));
+            let (ctx, cancel) = Context::background().with_cancel();
+            let receiver = uthreads::join::spawn(move || unsafe { ctx.recv(chan) });
+            yield_thread();
+            // the receiver is interrupted, then before it runs again the buffer fills up and a sender blocks.
+            let sender = uthreads::join::spawn(move || unsafe {
+                cancel.cancel();
+                chan_send(chan, 1);
+                chan_send(chan, 2);
+            });
+            let first = receiver.join();
+            sender.join();
+            let second = unsafe { chan_recv(chan) };
+            drop(unsafe { Box::from_raw(chan) });
+            (first, second)
+        },
+        Duration::from_secs(10),
+    );
+    assert_eq!(received, (Ok(1), 2));
+}
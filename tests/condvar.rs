@@ -0,0 +1,133 @@
+#![cfg(all(feature = "std", feature = "sync"))]
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::time::Duration;
+
+use uthreads::runtime::{self, yield_thread};
+use uthreads::sync::{Condvar, CountDownLatch, Mutex};
+use uthreads::testing;
+use uthreads::time;
+
+#[test]
+fn waits_for_the_condition() {
+    testing::run(
+        || {
+            let queue = Rc::new((Mutex::new(VecDeque::new()), Condvar::new()));
+            let consumer = queue.clone();
+            let done = Rc::new(CountDownLatch::new(1));
+            let consumed = done.clone();
+            runtime::spawn(move || {
+                let (items, available) = &*consumer;
+                for expected in 0..10 {
+                    let mut items = available.wait_while(items.lock(), |items| items.is_empty());
+                    assert_eq!(items.pop_front(), Some(expected));
+                }
+                consumed.count_down();
+            });
+            let (items, available) = &*queue;
+            for item in 0..10 {
+                items.lock().push_back(item);
+                available.notify_one();
+                yield_thread();
+            }
+            done.wait();
+        },
+        Duration::from_secs(10),
+    );
+}
+
+#[test]
+fn wait_timeout_times_out() {
+    testing::run(
+        || {
+            let (mutex, condvar) = (Mutex::new(0), Condvar::new());
+            let start = time::now();
+            let (guard, result) = condvar.wait_timeout(mutex.lock(), Duration::from_millis(20));
+            assert!(result.timed_out());
+            assert!(time::now() - start >= Duration::from_millis(20));
+            // the lock is taken again.
+            assert!(mutex.is_locked());
+            drop(guard);
+            // the thread which timed out is no longer waiting.
+            assert_eq!(format!("{condvar:?}"), "Condvar { waiters: 0 }");
+        },
+        Duration::from_secs(10),
+    );
+}
+
+#[test]
+fn wait_timeout_notified_in_time() {
+    testing::run(
+        || {
+            let shared = Rc::new((Mutex::new(false), Condvar::new()));
+            let notifier = shared.clone();
+            runtime::spawn(move || {
+                time::sleep(Duration::from_millis(10));
+                *notifier.0.lock() = true;
+                notifier.1.notify_one();
+            });
+            let (mutex, condvar) = &*shared;
+            let start = time::now();
+            let (guard, result) = condvar.wait_timeout(mutex.lock(), Duration::from_secs(5));
+            assert!(!result.timed_out());
+            assert!(*guard);
+            assert!(time::now() - start < Duration::from_secs(5));
+        },
+        Duration::from_secs(10),
+    );
+}
+
+#[test]
+fn notifies_in_the_order_threads_came() {
+    testing::run(
+        || {
+            let shared = Rc::new((Mutex::new(()), Condvar::new()));
+            let log = Rc::new(RefCell::new(Vec::new()));
+            let done = Rc::new(CountDownLatch::new(3));
+            for name in ["first", "second", "third"] {
+                let (shared, log, done) = (shared.clone(), log.clone(), done.clone());
+                runtime::spawn(move || {
+                    let (mutex, condvar) = &*shared;
+                    drop(condvar.wait(mutex.lock()));
+                    log.borrow_mut().push(name);
+                    done.count_down();
+                });
+            }
+            // all three wait.
+            yield_thread();
+            for _ in 0..3 {
+                shared.1.notify_one();
+                yield_thread();
+            }
+            done.wait();
+            assert_eq!(*log.borrow(), ["first", "second", "third"]);
+        },
+        Duration::from_secs(10),
+    );
+}
+
+#[test]
+fn notify_all_wakes_every_thread() {
+    testing::run(
+        || {
+            let shared = Rc::new((Mutex::new(false), Condvar::new()));
+            let done = Rc::new(CountDownLatch::new(5));
+            for _ in 0..5 {
+                let (shared, done) = (shared.clone(), done.clone());
+                runtime::spawn(move || {
+                    let (mutex, condvar) = &*shared;
+                    let ready = condvar.wait_while(mutex.lock(), |ready| !*ready);
+                    assert!(*ready);
+                    done.count_down();
+                });
+            }
+            yield_thread();
+            *shared.0.lock() = true;
+            shared.1.notify_all();
+            done.wait();
+        },
+        Duration::from_secs(10),
+    );
+}
@@ -0,0 +1,53 @@
+#![cfg(feature = "std")]
+
+use std::env;
+use std::process::Command;
+
+use uthreads::runtime::{yield_thread, Runtime};
+
+#[inline(never)]
+fn fail_deep_down() {
+    panic!("the thread failed");
+}
+
+// Runs in a process of its own, which the panic aborts.
+fn crash() {
+    let mut runtime = Runtime::new();
+    unsafe { runtime.init() };
+    runtime.spawn(|| loop {
+        yield_thread();
+    });
+    runtime.spawn(|| {
+        yield_thread();
+        fail_deep_down();
+    });
+    runtime.run();
+}
+
+#[test]
+fn panic_reports_backtrace_and_threads() {
+    if env::var_os("UTHREADS_TEST_CRASH").is_some() {
+        crash();
+        return;
+    }
+    let output = Command::new(env::current_exe().unwrap())
+        .args([
+            "--exact",
+            "panic_reports_backtrace_and_threads",
+            "--nocapture",
+        ])
+        .env("UTHREADS_TEST_CRASH", "1")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("uthreads: thread Id(2) panicked: the thread failed, aborting"),
+        "{stderr}"
+    );
+    // the backtrace goes down to where the thread panicked.
+    let backtrace = &stderr[stderr.find("backtrace of thread Id(2):").expect(&stderr)..];
+    assert!(backtrace.contains("fail_deep_down"), "{stderr}");
+    // along with the other threads, as when dumped.
+    assert!(stderr.contains("thread Id(1): Ready"), "{stderr}");
+}
@@ -0,0 +1,113 @@
+#![cfg(feature = "std")]
+
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use uthreads::executor::{self, Executor};
+use uthreads::runtime::yield_thread;
+
+fn worker_name() -> String {
+    thread::current().name().unwrap().to_string()
+}
+
+#[test]
+fn runs_every_job() {
+    let count = Arc::new(AtomicUsize::new(0));
+    let executor = Executor::new(4);
+    for _ in 0..100 {
+        let count = count.clone();
+        executor.spawn(move || {
+            yield_thread();
+            count.fetch_add(1, Ordering::Relaxed);
+        });
+    }
+    executor.join();
+    assert_eq!(count.load(Ordering::Relaxed), 100);
+}
+
+#[test]
+fn join_waits_for_the_jobs_spawned_by_jobs() {
+    let count = Arc::new(AtomicUsize::new(0));
+    let executor = Executor::new(2);
+    for _ in 0..10 {
+        let count = count.clone();
+        executor.spawn(move || {
+            for _ in 0..10 {
+                let count = count.clone();
+                executor::spawn(move || {
+                    count.fetch_add(1, Ordering::Relaxed);
+                });
+            }
+        });
+    }
+    executor.join();
+    assert_eq!(count.load(Ordering::Relaxed), 100);
+}
+
+#[test]
+fn idle_workers_steal_queued_jobs() {
+    let workers = Arc::new(Mutex::new(HashSet::new()));
+    let executor = Executor::new(4);
+    let seen = workers.clone();
+    executor.spawn_on(0, move || {
+        // every job goes to the queue of worker 0, which each of them keeps busy for a while.
+        for _ in 0..8 {
+            let seen = seen.clone();
+            executor::spawn(move || {
+                seen.lock().unwrap().insert(worker_name());
+                thread::sleep(Duration::from_millis(20));
+            });
+        }
+    });
+    executor.join();
+    assert!(workers.lock().unwrap().len() > 1);
+}
+
+#[test]
+fn green_threads_stay_on_their_worker() {
+    let executor = Executor::new(4);
+    for _ in 0..8 {
+        executor.spawn(|| {
+            let worker = worker_name();
+            for _ in 0..10 {
+                uthreads::time::sleep(Duration::from_millis(1));
+                assert_eq!(worker_name(), worker);
+            }
+        });
+    }
+    executor.join();
+}
+
+#[test]
+fn pinned_jobs_run_on_their_worker() {
+    let executor = Executor::new(4);
+    for _ in 0..8 {
+        executor.spawn_on(1, || {
+            assert_eq!(worker_name(), "uthreads-worker-1");
+            // keeps the worker busy while the others idle, so that they would steal its queued jobs if they could.
+            thread::sleep(Duration::from_millis(5));
+        });
+    }
+    executor.join();
+}
+
+#[test]
+fn jobs_pinned_from_a_job_run_on_its_worker() {
+    let executor = Executor::new(4);
+    for _ in 0..4 {
+        executor.spawn(|| {
+            let worker = worker_name();
+            for _ in 0..4 {
+                let worker = worker.clone();
+                executor::spawn_pinned(move || {
+                    assert_eq!(worker_name(), worker);
+                    thread::sleep(Duration::from_millis(5));
+                });
+            }
+        });
+    }
+    executor.join();
+}
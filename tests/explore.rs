@@ -0,0 +1,49 @@
+#![cfg(feature = "std")]
+
+use std::cell::Cell;
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::Rc;
+
+use uthreads::runtime::{explore, maybe_yield, Bounds};
+
+#[test]
+fn finds_a_lost_update() {
+    let found = panic::catch_unwind(AssertUnwindSafe(|| {
+        explore(Bounds::default(), |runtime| {
+            let counter = Rc::new(Cell::new(0));
+            for _ in 0..2 {
+                let counter = counter.clone();
+                runtime.spawn(move || {
+                    let seen = counter.get();
+                    // the other thread may run in between, and its increment is then lost.
+                    maybe_yield();
+                    counter.set(seen + 1);
+                });
+            }
+            runtime.run();
+            assert_eq!(counter.get(), 2, "an increment was lost");
+        })
+    }));
+    let payload = found.unwrap_err();
+    assert!(payload
+        .downcast_ref::<String>()
+        .is_some_and(|msg| msg.contains("an increment was lost")));
+}
+
+#[test]
+fn passes_every_schedule_of_a_correct_program() {
+    let explored = explore(Bounds::default(), |runtime| {
+        let counter = Rc::new(Cell::new(0));
+        for _ in 0..2 {
+            let counter = counter.clone();
+            runtime.spawn(move || {
+                maybe_yield();
+                counter.set(counter.get() + 1);
+            });
+        }
+        runtime.run();
+        assert_eq!(counter.get(), 2);
+    });
+    assert!(explored.complete);
+    assert!(explored.schedules > 1);
+}
@@ -0,0 +1,65 @@
+#![cfg(all(feature = "ffi", unix))]
+
+// Builds the crate as a static library, as a C program would use it, then compiles tests/ffi/program.c against
+// include/uthreads.h, links it with the library and runs it: the header must declare the functions as they are defined.
+
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const MANIFEST_DIR: &str = env!("CARGO_MANIFEST_DIR");
+
+// Builds the static library into a target directory of its own, so as not to wait for the one the tests run from,
+// and returns its path along with the native libraries it must be linked with.
+fn build_staticlib(target_dir: &Path) -> (PathBuf, Vec<String>) {
+    let output = Command::new(env!("CARGO"))
+        .args(["rustc", "--lib", "--offline", "--features", "ffi"])
+        .args(["--crate-type", "staticlib", "--manifest-path"])
+        .arg(Path::new(MANIFEST_DIR).join("Cargo.toml"))
+        .arg("--target-dir")
+        .arg(target_dir)
+        .args(["--", "--print", "native-static-libs"])
+        .output()
+        .expect("cargo runs");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{stderr}");
+    let native_libs = stderr
+        .lines()
+        .find_map(|line| line.split("native-static-libs: ").nth(1))
+        .unwrap_or_default()
+        .split_whitespace()
+        .map(String::from)
+        .collect();
+    (target_dir.join("debug/libuthreads.a"), native_libs)
+}
+
+#[test]
+fn a_c_program_runs_threads() {
+    let target_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("ffi");
+    let (lib, native_libs) = build_staticlib(&target_dir);
+
+    let program = target_dir.join("program");
+    let cc = env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let status = Command::new(cc)
+        .args(["-std=c99", "-Wall", "-Wextra", "-Werror", "-I"])
+        .arg(Path::new(MANIFEST_DIR).join("include"))
+        .arg(Path::new(MANIFEST_DIR).join("tests/ffi/program.c"))
+        .arg(&lib)
+        .args(&native_libs)
+        .arg("-o")
+        .arg(&program)
+        .status()
+        .expect("the C compiler runs");
+    assert!(status.success());
+
+    let output = Command::new(&program).output().expect("the program runs");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "received 12 messages\n"
+    );
+}
@@ -0,0 +1,70 @@
+// A C program using the runtime through include/uthreads.h, see tests/ffi.rs:
+// producers send messages over a channel to a consumer, which checks and prints them.
+
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "uthreads.h"
+
+#define PRODUCERS 3
+#define MESSAGES 4
+
+struct producer {
+    UthreadsChannel *chan;
+    int id;
+};
+
+static int received[PRODUCERS];
+
+static void produce(void *arg) {
+    struct producer *p = arg;
+    char message[32];
+    for (int i = 0; i < MESSAGES; i++) {
+        int len = snprintf(message, sizeof message, "%d:%d", p->id, i);
+        uthreads_channel_send(p->chan, (const uint8_t *)message, (size_t)len);
+        uthreads_yield();
+    }
+}
+
+static void consume(void *arg) {
+    UthreadsChannel *chan = arg;
+    for (int n = 0; n < PRODUCERS * MESSAGES; n++) {
+        size_t len;
+        uint8_t *data = uthreads_channel_recv(chan, &len);
+        int id, i;
+        char message[32];
+        memcpy(message, data, len);
+        message[len] = '\0';
+        uthreads_buffer_free(data, len);
+        // the messages of each producer arrive in the order it sent them.
+        if (sscanf(message, "%d:%d", &id, &i) != 2 || id < 0 || id >= PRODUCERS || i != received[id]) {
+            fprintf(stderr, "unexpected message %s\n", message);
+            exit(1);
+        }
+        received[id]++;
+    }
+    printf("received %d messages\n", PRODUCERS * MESSAGES);
+}
+
+int main(void) {
+    UthreadsRuntime *rt = uthreads_runtime_new();
+    UthreadsChannel *chan = uthreads_channel_new(1);
+    if (rt == NULL || chan == NULL) {
+        return 1;
+    }
+    struct producer producers[PRODUCERS];
+    for (int id = 0; id < PRODUCERS; id++) {
+        producers[id] = (struct producer){chan, id};
+        if (uthreads_spawn(produce, &producers[id]) == 0) {
+            return 1;
+        }
+    }
+    if (uthreads_spawn(consume, chan) == 0) {
+        return 1;
+    }
+    uthreads_runtime_run(rt);
+    uthreads_channel_free(chan);
+    uthreads_runtime_free(rt);
+    return 0;
+}
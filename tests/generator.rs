@@ -0,0 +1,105 @@
+#![cfg(feature = "std")]
+
+use std::cell::{Cell, RefCell};
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::Rc;
+
+use uthreads::generator::Generator;
+
+type Log = Rc<RefCell<Vec<String>>>;
+
+#[test]
+fn runs_until_each_value_is_yielded() {
+    let log: Log = Rc::default();
+    let logged = log.clone();
+    let mut generator = Generator::new(move |yielder| {
+        for i in 1..=2 {
+            logged.borrow_mut().push(format!("yield {i}"));
+            yielder.yield_(i);
+            logged.borrow_mut().push(format!("resumed after {i}"));
+        }
+    });
+    // nothing runs until the generator is first resumed.
+    assert!(log.borrow().is_empty());
+    for _ in 0..3 {
+        let val = generator.resume();
+        log.borrow_mut().push(format!("got {val:?}"));
+    }
+    assert_eq!(
+        *log.borrow(),
+        [
+            "yield 1",
+            "got Some(1)",
+            "resumed after 1",
+            "yield 2",
+            "got Some(2)",
+            "resumed after 2",
+            "got None",
+        ]
+    );
+}
+
+#[test]
+fn returns_none_once_completed() {
+    let mut generator = Generator::new(|yielder| {
+        for i in 0..3 {
+            yielder.yield_(i);
+        }
+    });
+    assert_eq!(generator.by_ref().collect::<Vec<_>>(), [0, 1, 2]);
+    assert!(generator.is_done());
+    assert_eq!(generator.resume(), None);
+    assert_eq!(generator.resume(), None);
+}
+
+// Sets its flag once dropped, which the closure of a generator only gets to if it is unwound.
+struct SetOnDrop(Rc<Cell<bool>>);
+
+impl Drop for SetOnDrop {
+    fn drop(&mut self) {
+        self.0.set(true);
+    }
+}
+
+#[test]
+fn dropping_a_suspended_generator_unwinds_its_closure() {
+    let dropped = Rc::new(Cell::new(false));
+    let resumed = Rc::new(Cell::new(false));
+    let (guard, after) = (SetOnDrop(dropped.clone()), resumed.clone());
+    let mut generator = Generator::new(move |yielder| {
+        let _guard = guard;
+        yielder.yield_(1);
+        after.set(true);
+    });
+    assert_eq!(generator.resume(), Some(1));
+    assert!(!dropped.get());
+    // the cancellation unwinds from the yield, and isn't resumed as a panic on the caller.
+    drop(generator);
+    assert!(dropped.get());
+    assert!(!resumed.get());
+}
+
+#[test]
+fn dropping_a_generator_which_never_ran_runs_nothing() {
+    let ran = Rc::new(Cell::new(false));
+    let set = ran.clone();
+    let generator = Generator::<()>::new(move |_| set.set(true));
+    drop(generator);
+    assert!(!ran.get());
+}
+
+#[test]
+fn a_panic_of_the_closure_is_resumed_on_the_caller() {
+    let mut generator = Generator::new(|yielder| {
+        yielder.yield_(1);
+        panic!("the generator failed");
+    });
+    assert_eq!(generator.resume(), Some(1));
+    let payload = panic::catch_unwind(AssertUnwindSafe(|| generator.resume())).unwrap_err();
+    assert_eq!(
+        payload.downcast_ref::<&str>(),
+        Some(&"the generator failed")
+    );
+    assert!(generator.is_done());
+    assert_eq!(generator.resume(), None);
+}
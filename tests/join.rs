@@ -0,0 +1,159 @@
+#![cfg(feature = "std")]
+
+use std::cell::Cell;
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::Rc;
+use std::time::Duration;
+
+use uthreads::join::{self, JoinSet};
+use uthreads::runtime::yield_thread;
+use uthreads::testing;
+use uthreads::time;
+
+#[test]
+fn join_returns_what_the_thread_returned() {
+    testing::run(
+        || {
+            let handle = join::spawn(|| {
+                yield_thread();
+                42
+            });
+            assert!(!handle.is_finished());
+            assert_eq!(handle.join(), 42);
+            // joining a thread which has completed doesn't block.
+            let handle = join::spawn(|| "done");
+            yield_thread();
+            assert!(handle.is_finished());
+            assert_eq!(handle.join(), "done");
+        },
+        Duration::from_secs(10),
+    );
+}
+
+#[test]
+fn join_timeout_gives_the_handle_back() {
+    testing::run(
+        || {
+            let handle = join::spawn(|| {
+                time::sleep(Duration::from_millis(50));
+                7
+            });
+            let start = time::now();
+            let handle = handle
+                .join_timeout(Duration::from_millis(10))
+                .expect_err("the thread completed before the timeout");
+            assert!(time::now() - start >= Duration::from_millis(10));
+            assert!(!handle.is_finished());
+            // the handle can be joined again.
+            assert_eq!(handle.join_timeout(Duration::from_secs(5)).ok(), Some(7));
+        },
+        Duration::from_secs(10),
+    );
+}
+
+#[test]
+fn join_resumes_the_panic() {
+    testing::run(
+        || {
+            let handle = join::spawn(|| -> () { panic!("the thread failed") });
+            let payload = panic::catch_unwind(AssertUnwindSafe(|| handle.join())).unwrap_err();
+            assert_eq!(payload.downcast_ref::<&str>(), Some(&"the thread failed"));
+        },
+        Duration::from_secs(10),
+    );
+}
+
+#[test]
+fn dropping_the_handle_detaches_the_thread() {
+    testing::run(
+        || {
+            let ran = Rc::new(Cell::new(false));
+            let theirs = ran.clone();
+            let handle = join::spawn(move || {
+                yield_thread();
+                theirs.set(true);
+            });
+            let thread = handle.thread();
+            drop(handle);
+            while thread.priority().is_some() {
+                yield_thread();
+            }
+            assert!(ran.get());
+        },
+        Duration::from_secs(10),
+    );
+}
+
+#[test]
+fn join_all_returns_results_in_order() {
+    testing::run(
+        || {
+            // the threads complete in the reverse order.
+            let handles: Vec<_> = (0..5)
+                .map(|i| {
+                    join::spawn(move || {
+                        for _ in i..5 {
+                            yield_thread();
+                        }
+                        i * 10
+                    })
+                })
+                .collect();
+            assert_eq!(join::join_all(handles), [0, 10, 20, 30, 40]);
+            assert!(join::join_all(Vec::<join::JoinHandle<()>>::new()).is_empty());
+        },
+        Duration::from_secs(10),
+    );
+}
+
+#[test]
+fn join_set_joins_in_the_order_threads_complete() {
+    testing::run(
+        || {
+            let mut set = JoinSet::new();
+            for i in 0..3 {
+                set.spawn(move |_| {
+                    for _ in i..3 {
+                        yield_thread();
+                    }
+                    i
+                });
+            }
+            assert_eq!(set.len(), 3);
+            let mut results = Vec::new();
+            while let Some(i) = set.join_next() {
+                results.push(i);
+            }
+            assert_eq!(results, [2, 1, 0]);
+            assert!(set.is_empty());
+        },
+        Duration::from_secs(10),
+    );
+}
+
+#[test]
+fn dropping_a_join_set_cancels_and_detaches() {
+    testing::run(
+        || {
+            let stopped = Rc::new(Cell::new(0));
+            let mut set = JoinSet::new();
+            for _ in 0..3 {
+                let stopped = stopped.clone();
+                set.spawn(move |ctx| {
+                    while !ctx.is_done() {
+                        yield_thread();
+                    }
+                    stopped.set(stopped.get() + 1);
+                });
+            }
+            yield_thread();
+            drop(set);
+            // the threads weren't waited for, and stop at their own pace.
+            assert_eq!(stopped.get(), 0);
+            while stopped.get() < 3 {
+                yield_thread();
+            }
+        },
+        Duration::from_secs(10),
+    );
+}
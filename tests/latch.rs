@@ -0,0 +1,84 @@
+#![cfg(all(feature = "std", feature = "sync"))]
+
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::Duration;
+
+use uthreads::join;
+use uthreads::runtime::yield_thread;
+use uthreads::sync::CountDownLatch;
+use uthreads::testing;
+
+#[test]
+fn waiters_go_on_once_counted_down_to_zero() {
+    testing::run(
+        || {
+            let latch = Rc::new(CountDownLatch::new(3));
+            let woken = Rc::new(Cell::new(0));
+            let waiters: Vec<_> = (0..2)
+                .map(|_| {
+                    let latch = latch.clone();
+                    let woken = woken.clone();
+                    join::spawn(move || {
+                        latch.wait();
+                        woken.set(woken.get() + 1);
+                    })
+                })
+                .collect();
+            for left in (1..3).rev() {
+                latch.count_down();
+                assert_eq!(latch.count(), left);
+                yield_thread();
+                assert_eq!(woken.get(), 0);
+            }
+            // counting down never blocks, and the last count wakes every waiter up.
+            latch.count_down();
+            assert_eq!(latch.count(), 0);
+            join::join_all(waiters);
+            assert_eq!(woken.get(), 2);
+        },
+        Duration::from_secs(10),
+    );
+}
+
+#[test]
+fn an_open_latch_stays_open() {
+    testing::run(
+        || {
+            let latch = CountDownLatch::new(0);
+            latch.wait();
+            let latch = CountDownLatch::new(1);
+            latch.count_down();
+            latch.count_down();
+            assert_eq!(latch.count(), 0);
+            latch.wait();
+            latch.wait();
+        },
+        Duration::from_secs(10),
+    );
+}
+
+#[test]
+fn workers_count_down_for_a_waiter_they_dont_know() {
+    testing::run(
+        || {
+            const WORKERS: usize = 8;
+            let latch = Rc::new(CountDownLatch::new(WORKERS));
+            let done = Rc::new(Cell::new(0));
+            for i in 0..WORKERS {
+                let latch = latch.clone();
+                let done = done.clone();
+                join::spawn(move || {
+                    for _ in 0..i {
+                        yield_thread();
+                    }
+                    done.set(done.get() + 1);
+                    latch.count_down();
+                });
+            }
+            latch.wait();
+            assert_eq!(done.get(), WORKERS);
+        },
+        Duration::from_secs(10),
+    );
+}
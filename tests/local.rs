@@ -0,0 +1,75 @@
+#![cfg(feature = "std")]
+
+use std::cell::Cell;
+use std::time::Duration;
+
+use uthreads::join;
+use uthreads::runtime::yield_thread;
+use uthreads::{green_local, testing};
+
+green_local! {
+    static COUNT: Cell<u32> = Cell::new(0);
+}
+
+thread_local! {
+    static OS_COUNT: Cell<u32> = const { Cell::new(0) };
+}
+
+// Counts `n` times in both locals, yielding in between for the other threads to count too.
+// Returns what the thread ends up with in each.
+fn count(n: u32) -> (u32, u32) {
+    for _ in 0..n {
+        COUNT.with(|count| count.set(count.get() + 1));
+        OS_COUNT.with(|count| count.set(count.get() + 1));
+        yield_thread();
+    }
+    (COUNT.with(Cell::get), OS_COUNT.with(Cell::get))
+}
+
+#[test]
+fn every_thread_has_a_value_of_its_own() {
+    let counts = testing::run(
+        || {
+            let threads: Vec<_> = (1..=3).map(|n| join::spawn(move || count(n))).collect();
+            join::join_all(threads)
+        },
+        Duration::from_secs(10),
+    );
+    let green: Vec<_> = counts.iter().map(|&(green, _)| green).collect();
+    assert_eq!(green, [1, 2, 3]);
+    // the thread pointer isn't switched, so `thread_local!` is shared by the threads of the OS thread,
+    // which have all counted in it by the time the last one is done.
+    assert!(counts.iter().all(|&(_, os)| os <= 6));
+    assert_eq!(counts[2].1, 6);
+}
+
+struct Dropped;
+
+green_local! {
+    static DROPPED: Dropped = Dropped;
+}
+
+thread_local! {
+    static DROPS: Cell<u32> = const { Cell::new(0) };
+}
+
+impl Drop for Dropped {
+    fn drop(&mut self) {
+        DROPS.with(|drops| drops.set(drops.get() + 1));
+    }
+}
+
+#[test]
+fn values_are_dropped_once_their_thread_completes() {
+    let drops = testing::run(
+        || {
+            let accessed = join::spawn(|| DROPPED.with(|_| ()));
+            let untouched = join::spawn(|| ());
+            join::join_all([accessed, untouched]);
+            DROPS.with(Cell::get)
+        },
+        Duration::from_secs(10),
+    );
+    // the value is only created once accessed.
+    assert_eq!(drops, 1);
+}
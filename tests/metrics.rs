@@ -0,0 +1,109 @@
+#![cfg(feature = "std")]
+
+use std::hint::black_box;
+use std::time::Duration;
+
+use uthreads::channel::Channel;
+use uthreads::join;
+use uthreads::runtime::{self, yield_thread};
+use uthreads::testing;
+
+// Touches `depth` KiB of stack.
+fn recurse(depth: usize) -> u8 {
+    let frame = black_box([depth as u8; 1024]);
+    if depth == 0 {
+        return frame[0];
+    }
+    frame[1023].wrapping_add(recurse(depth - 1))
+}
+
+#[test]
+fn stack_usage_is_summarized() {
+    let (deep, stacks) = testing::run(
+        || {
+            let deep = runtime::spawn(|| {
+                recurse(16);
+                // still alive when the snapshot is taken.
+                yield_thread();
+            });
+            runtime::spawn(|| {});
+            // both run, the second one to completion.
+            yield_thread();
+            (deep, runtime::metrics().stacks)
+        },
+        Duration::from_secs(10),
+    );
+    // the thread running the test, and the one which recursed.
+    assert_eq!(stacks.threads, 2);
+    assert_eq!(stacks.max_thread, Some(deep));
+    assert!(stacks.max >= 16 * 1024, "{stacks:?}");
+    assert!(stacks.max <= stacks.largest);
+    assert!(stacks.total > stacks.max);
+    assert!(stacks.mean() < stacks.max);
+}
+
+#[test]
+fn memory_stats_add_up() {
+    let (alive, stats) = testing::run(
+        || {
+            let mut chan = Channel::<u64>::new(16);
+            chan.set_name("numbers");
+            let alive = runtime::spawn(|| {
+                recurse(8);
+                yield_thread();
+            });
+            // a thread which completes leaves its stack to the pool, once the next thread is spawned.
+            runtime::spawn(|| {});
+            yield_thread();
+            runtime::spawn(|| {});
+            let stats = runtime::memory_stats();
+            drop(chan);
+            (alive, stats)
+        },
+        Duration::from_secs(10),
+    );
+    let thread = stats.threads.iter().find(|t| t.id == alive).unwrap();
+    assert!(thread.stack_committed >= 8 * 1024, "{stats:?}");
+    assert!(thread.stack_committed <= thread.stack_reserved);
+    // the last thread spawned hasn't run, so has no stack yet.
+    assert!(stats.threads.iter().any(|t| t.stack_reserved == 0));
+    assert_eq!(stats.pooled_stacks, 1);
+    let threads: usize = stats.threads.iter().map(|t| t.stack_reserved).sum();
+    assert_eq!(stats.stack_reserved, threads + stats.pooled_reserved);
+    assert_eq!(stats.channel_buffers, 16 * 8);
+}
+
+#[test]
+fn stack_usage_is_a_high_water_mark() {
+    // where the thread runs, and how much of its stack it has used.
+    fn usage() -> (usize, usize) {
+        let local = 0u8;
+        let used = runtime::stack_usage(runtime::get_current_thread()).unwrap();
+        (&raw const local as usize, used)
+    }
+    let (start, deep, returned, reused) = testing::run(
+        || {
+            let (start, deep, returned) = join::spawn(|| {
+                let start = usage();
+                recurse(16);
+                let deep = usage();
+                (start, deep, usage())
+            })
+            .join();
+            // the stack goes back to the pool as the next thread is spawned, and the one after that runs on it.
+            join::spawn(|| {}).join();
+            let reused = join::spawn(usage).join();
+            (start, deep, returned, reused)
+        },
+        Duration::from_secs(10),
+    );
+    assert!(start.1 <= 8 * 1024, "{start:?}");
+    assert!(deep.1 >= 16 * 1024, "{deep:?}");
+    assert_eq!(returned.1, deep.1);
+    assert!(
+        start.0.abs_diff(reused.0) < 64 * 1024,
+        "{start:x?} {reused:x?}"
+    );
+    // the pages the previous thread used were released as the stack went back to the pool.
+    assert!(reused.1 <= 8 * 1024, "{reused:?}");
+}
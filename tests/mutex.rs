@@ -0,0 +1,174 @@
+#![cfg(all(feature = "std", feature = "sync"))]
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+
+use uthreads::runtime::{self, spawn_with_priority, yield_thread};
+use uthreads::sync::{CountDownLatch, Mutex};
+use uthreads::testing;
+use uthreads::thread::Priority;
+
+type Log = Rc<RefCell<Vec<&'static str>>>;
+
+#[test]
+fn excludes_other_threads() {
+    testing::run(
+        || {
+            let counter = Rc::new(Mutex::new(0));
+            let done = Rc::new(CountDownLatch::new(10));
+            for _ in 0..10 {
+                let (counter, done) = (counter.clone(), done.clone());
+                runtime::spawn(move || {
+                    for _ in 0..10 {
+                        let mut count = counter.lock();
+                        let seen = *count;
+                        // the others run meanwhile, but can't take the lock.
+                        yield_thread();
+                        *count = seen + 1;
+                    }
+                    done.count_down();
+                });
+            }
+            done.wait();
+            assert!(!counter.is_locked());
+            assert_eq!(*counter.lock(), 100);
+        },
+        Duration::from_secs(10),
+    );
+}
+
+#[test]
+fn hands_over_to_the_highest_priority() {
+    testing::run(
+        || {
+            let mutex = Rc::new(Mutex::new(()));
+            let log: Log = Rc::default();
+            let done = Rc::new(CountDownLatch::new(3));
+            let guard = mutex.lock();
+            for (priority, name) in [(1, "low"), (3, "high"), (2, "medium")] {
+                let (mutex, log, done) = (mutex.clone(), log.clone(), done.clone());
+                spawn_with_priority(Priority(priority), move || {
+                    drop(mutex.lock());
+                    log.borrow_mut().push(name);
+                    done.count_down();
+                });
+            }
+            // all three block on the lock.
+            yield_thread();
+            drop(guard);
+            done.wait();
+            assert_eq!(*log.borrow(), ["high", "medium", "low"]);
+        },
+        Duration::from_secs(10),
+    );
+}
+
+// A thread of a low priority holds the lock a thread of a high priority waits for,
+// while a thread of a priority in between keeps running.
+fn inversion(log: Log, mutex: Rc<Mutex<()>>, done: Rc<CountDownLatch>) {
+    let guard = mutex.lock();
+    log.borrow_mut().push("low locked");
+    let (high_log, high_done, high_mutex) = (log.clone(), done.clone(), mutex.clone());
+    spawn_with_priority(Priority(3), move || {
+        drop(high_mutex.lock());
+        high_log.borrow_mut().push("high locked");
+        high_done.count_down();
+    });
+    let (medium_log, medium_done) = (log.clone(), done.clone());
+    spawn_with_priority(Priority(2), move || {
+        for _ in 0..3 {
+            medium_log.borrow_mut().push("medium ran");
+            yield_thread();
+        }
+        medium_done.count_down();
+    });
+    // the thread of a high priority runs, and blocks on the lock.
+    yield_thread();
+    log.borrow_mut().push("low unlocked");
+    drop(guard);
+    done.count_down();
+}
+
+#[test]
+fn holder_inherits_the_priority_of_waiters() {
+    testing::run(
+        || {
+            let log: Log = Rc::default();
+            let done = Rc::new(CountDownLatch::new(3));
+            let (low_log, low_done) = (log.clone(), done.clone());
+            let mutex = Rc::new(Mutex::new(()));
+            spawn_with_priority(Priority(1), move || inversion(low_log, mutex, low_done));
+            done.wait();
+            // without inheritance, the thread of a medium priority would run to completion first.
+            assert_eq!(
+                *log.borrow(),
+                [
+                    "low locked",
+                    "low unlocked",
+                    "high locked",
+                    "medium ran",
+                    "medium ran",
+                    "medium ran"
+                ]
+            );
+        },
+        Duration::from_secs(10),
+    );
+}
+
+#[test]
+fn inheritance_follows_chains_of_locks() {
+    testing::run(
+        || {
+            let log: Log = Rc::default();
+            let done = Rc::new(CountDownLatch::new(4));
+            let (first, second) = (Rc::new(Mutex::new(())), Rc::new(Mutex::new(())));
+            let (low_log, low_done, low_first) = (log.clone(), done.clone(), first.clone());
+            spawn_with_priority(Priority(1), move || {
+                let guard = low_first.lock();
+                let (chain_log, chain_done) = (low_log.clone(), low_done.clone());
+                // holds `second` while waiting for `first`, which the thread of the lowest priority holds.
+                spawn_with_priority(Priority(2), move || {
+                    let guard = second.lock();
+                    let (high_log, high_done, high_second) =
+                        (chain_log.clone(), chain_done.clone(), second.clone());
+                    spawn_with_priority(Priority(4), move || {
+                        drop(high_second.lock());
+                        high_log.borrow_mut().push("high locked");
+                        high_done.count_down();
+                    });
+                    let (medium_log, medium_done) = (chain_log.clone(), chain_done.clone());
+                    spawn_with_priority(Priority(3), move || {
+                        for _ in 0..3 {
+                            medium_log.borrow_mut().push("medium ran");
+                            yield_thread();
+                        }
+                        medium_done.count_down();
+                    });
+                    drop(first.lock());
+                    drop(guard);
+                    chain_done.count_down();
+                });
+                // the other threads run, and block on the locks.
+                yield_thread();
+                low_log.borrow_mut().push("low unlocked");
+                drop(guard);
+                low_done.count_down();
+            });
+            done.wait();
+            // the thread of the highest priority waits for `second`, so for `first` in turn.
+            assert_eq!(
+                *log.borrow(),
+                [
+                    "low unlocked",
+                    "high locked",
+                    "medium ran",
+                    "medium ran",
+                    "medium ran"
+                ]
+            );
+        },
+        Duration::from_secs(10),
+    );
+}
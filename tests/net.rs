@@ -0,0 +1,44 @@
+#![cfg(feature = "net")]
+
+use std::io::ErrorKind;
+use std::time::Duration;
+
+use uthreads::io::Read;
+use uthreads::net::{TcpListener, TcpStream, UdpSocket};
+use uthreads::testing;
+
+#[test]
+fn tcp_read_times_out() {
+    testing::run(
+        || {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+            let (_peer, _) = listener.accept().unwrap();
+            // the timeout is set through a shared reference, like the stream is read through.
+            let mut reader = &stream;
+            stream
+                .set_read_timeout(Some(Duration::from_millis(20)))
+                .unwrap();
+            assert_eq!(stream.read_timeout(), Some(Duration::from_millis(20)));
+            let err = reader.read(&mut [0; 8]).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::TimedOut);
+        },
+        Duration::from_secs(10),
+    );
+}
+
+#[test]
+fn udp_recv_times_out() {
+    testing::run(
+        || {
+            let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+            socket
+                .set_read_timeout(Some(Duration::from_millis(20)))
+                .unwrap();
+            let err = socket.recv_from(&mut [0; 8]).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::TimedOut);
+            assert!(socket.set_write_timeout(Some(Duration::ZERO)).is_err());
+        },
+        Duration::from_secs(10),
+    );
+}
@@ -0,0 +1,113 @@
+#![cfg(all(feature = "std", feature = "sync"))]
+
+use std::cell::{Cell, RefCell};
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::Rc;
+use std::time::Duration;
+
+use uthreads::channel::Channel;
+use uthreads::join;
+use uthreads::runtime::{chan_recv, chan_send, yield_thread};
+use uthreads::sync::{Once, OnceCell};
+use uthreads::testing;
+
+type Log = Rc<RefCell<Vec<&'static str>>>;
+
+#[test]
+fn runs_the_routine_once() {
+    testing::run(
+        || {
+            let once = Once::new();
+            let runs = Cell::new(0);
+            for _ in 0..3 {
+                once.call_once(|| runs.set(runs.get() + 1));
+            }
+            assert!(once.is_completed());
+            assert_eq!(runs.get(), 1);
+        },
+        Duration::from_secs(10),
+    );
+}
+
+#[test]
+fn others_run_while_the_initialiser_blocks() {
+    let (values, log) = testing::run(
+        || {
+            let cell = Rc::new(OnceCell::new());
+            let chan = Box::into_raw(Box::new(Channel::<u32>::new(1)));
+            let log: Log = Rc::default();
+            let initialisers = Rc::new(Cell::new(0));
+            let handles: Vec<_> = (0..3)
+                .map(|_| {
+                    let (cell, log, initialisers) =
+                        (cell.clone(), log.clone(), initialisers.clone());
+                    join::spawn(move || {
+                        *cell.get_or_init(|| {
+                            initialisers.set(initialisers.get() + 1);
+                            log.borrow_mut().push("initialising");
+                            // blocks until the value is sent, while the other callers wait.
+                            unsafe { chan_recv(chan) }
+                        })
+                    })
+                })
+                .collect();
+            // the initialiser and the other callers block, which lets this thread run.
+            yield_thread();
+            assert!(cell.get().is_none());
+            log.borrow_mut().push("sending");
+            unsafe { chan_send(chan, 42) };
+            let values = join::join_all(handles);
+            assert_eq!(initialisers.get(), 1);
+            drop(unsafe { Box::from_raw(chan) });
+            (values, log.take())
+        },
+        Duration::from_secs(10),
+    );
+    assert_eq!(values, [42, 42, 42]);
+    assert_eq!(log, ["initialising", "sending"]);
+}
+
+#[test]
+fn a_panicking_routine_lets_another_caller_run_it() {
+    let log = testing::run(
+        || {
+            let once = Rc::new(Once::new());
+            let log: Log = Rc::default();
+            let (waiting, waiting_log) = (once.clone(), log.clone());
+            // blocks on the routine of this thread, then runs its own once that one panicked.
+            let waiter = join::spawn(move || {
+                waiting.call_once(|| waiting_log.borrow_mut().push("waiter ran"));
+            });
+            let res = panic::catch_unwind(AssertUnwindSafe(|| {
+                once.call_once(|| {
+                    yield_thread();
+                    log.borrow_mut().push("panicking");
+                    panic!("the routine failed");
+                })
+            }));
+            assert!(res.is_err());
+            waiter.join();
+            assert!(once.is_completed());
+            // the routine has completed, so it doesn't run again.
+            once.call_once(|| log.borrow_mut().push("ran again"));
+            log.take()
+        },
+        Duration::from_secs(10),
+    );
+    assert_eq!(log, ["panicking", "waiter ran"]);
+}
+
+#[test]
+fn set_fails_once_the_cell_is_initialised() {
+    testing::run(
+        || {
+            let cell = OnceCell::new();
+            assert_eq!(cell.get(), None);
+            assert_eq!(cell.set(1), Ok(()));
+            assert_eq!(cell.set(2), Err(2));
+            assert_eq!(*cell.get_or_init(|| 3), 1);
+            assert_eq!(cell.into_inner(), Some(1));
+        },
+        Duration::from_secs(10),
+    );
+}
@@ -0,0 +1,33 @@
+#![cfg(feature = "std")]
+
+use std::alloc::System;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use uthreads::join;
+use uthreads::preempt;
+use uthreads::testing;
+
+#[global_allocator]
+static ALLOCATOR: preempt::Allocator = preempt::Allocator::new(System);
+
+// Preemption signals the whole process, so this is the only test of the binary.
+#[test]
+fn threads_that_never_yield_are_preempted() {
+    testing::run(
+        || {
+            static STOP: AtomicBool = AtomicBool::new(false);
+            unsafe { preempt::enable(Duration::from_millis(1)).unwrap() };
+            let spinner = join::spawn(|| {
+                while !STOP.load(Ordering::Relaxed) {
+                    std::hint::spin_loop();
+                }
+            });
+            let stopper = join::spawn(|| STOP.store(true, Ordering::Relaxed));
+            stopper.join();
+            spinner.join();
+            preempt::disable().unwrap();
+        },
+        Duration::from_secs(10),
+    );
+}
@@ -0,0 +1,60 @@
+#![cfg(feature = "std")]
+
+use std::alloc::System;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use uthreads::join;
+use uthreads::preempt;
+use uthreads::testing;
+
+#[global_allocator]
+static ALLOCATOR: preempt::Allocator = preempt::Allocator::new(System);
+
+// Preemption signals the whole process, so this is the only test of the binary.
+#[test]
+fn threads_preempted_while_allocating_keep_the_heap_consistent() {
+    testing::run(
+        || {
+            static STOP: AtomicBool = AtomicBool::new(false);
+            unsafe { preempt::enable(Duration::from_micros(50)).unwrap() };
+            // the threads never yield, so they only take turns when preempted, mostly in the middle of allocating.
+            let workers: Vec<_> = (0..4)
+                .map(|worker| {
+                    join::spawn(move || {
+                        let mut rounds = 0_usize;
+                        let mut kept = HashMap::new();
+                        while !STOP.load(Ordering::Relaxed) {
+                            let values: Vec<usize> = (0..rounds % 512).collect();
+                            let text = format!("{worker}-{rounds}-{}", values.len());
+                            kept.insert(rounds % 100, text.into_boxed_str());
+                            rounds += 1;
+                        }
+                        for (key, text) in kept {
+                            assert!(text.starts_with(&format!("{worker}-")));
+                            assert_eq!(
+                                text.split('-').nth(1).unwrap().parse::<usize>().unwrap() % 100,
+                                key
+                            );
+                        }
+                        rounds
+                    })
+                })
+                .collect();
+            let stopper = join::spawn(|| {
+                let start = Instant::now();
+                while start.elapsed() < Duration::from_millis(500) {
+                    std::hint::spin_loop();
+                }
+                STOP.store(true, Ordering::Relaxed);
+            });
+            stopper.join();
+            for worker in workers {
+                assert!(worker.join() > 0);
+            }
+            preempt::disable().unwrap();
+        },
+        Duration::from_secs(20),
+    );
+}
@@ -0,0 +1,71 @@
+#![cfg(feature = "std")]
+
+use std::env;
+use std::process::Command;
+
+use uthreads::runtime::{yield_thread, Runtime};
+use uthreads::{print, println};
+
+const THREADS: usize = 4;
+const LINES: usize = 5;
+
+// Runs in a process of its own, whose standard output the test reads.
+// Every thread prints its lines in pieces, yielding in between, which would interleave them if printed as they come.
+fn print_lines() {
+    let mut runtime = Runtime::new();
+    unsafe { runtime.init() };
+    for t in 0..THREADS {
+        runtime.spawn(move || {
+            for l in 0..LINES {
+                print!("line {t}.{l}:");
+                yield_thread();
+                print!(" in");
+                yield_thread();
+                println!(" pieces");
+            }
+            // left unfinished, it comes out as the thread completes.
+            print!("tail {t}");
+        });
+    }
+    runtime.run();
+    println!();
+}
+
+#[test]
+fn lines_are_printed_whole() {
+    if env::var_os("UTHREADS_TEST_PRINT").is_some() {
+        print_lines();
+        return;
+    }
+    let output = Command::new(env::current_exe().unwrap())
+        .args(["--exact", "lines_are_printed_whole", "--nocapture"])
+        .env("UTHREADS_TEST_PRINT", "1")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let mut stdout = String::from_utf8(output.stdout).unwrap();
+    // what the threads left unfinished comes out as they complete, and may then be followed by other lines.
+    for t in 0..THREADS {
+        let tail = format!("tail {t}");
+        assert_eq!(stdout.matches(&tail).count(), 1, "{stdout}");
+        stdout = stdout.replace(&tail, "");
+    }
+    // the test harness starts the first line with the name of the test.
+    let lines: Vec<_> = stdout
+        .lines()
+        .filter_map(|line| line.find("line ").map(|start| &line[start..]))
+        .collect();
+    for t in 0..THREADS {
+        let mine: Vec<_> = lines
+            .iter()
+            .filter(|line| line.starts_with(&format!("line {t}.")))
+            .collect();
+        let expected: Vec<_> = (0..LINES)
+            .map(|l| format!("line {t}.{l}: in pieces"))
+            .collect();
+        assert_eq!(mine, expected.iter().collect::<Vec<_>>(), "{stdout}");
+    }
+    assert_eq!(lines.len(), THREADS * LINES, "{stdout}");
+    // the threads printed at once, rather than one after the other.
+    assert!(lines[1].starts_with("line 1."), "{stdout}");
+}
@@ -0,0 +1,104 @@
+#![cfg(all(feature = "std", feature = "sync"))]
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+
+use uthreads::runtime::{spawn_with_priority, yield_thread};
+use uthreads::sync::CountDownLatch;
+use uthreads::testing;
+use uthreads::thread::{Priority, ThreadHandle};
+
+// A thread of a lower priority only runs once none of a higher priority is ready, while equals take turns.
+#[test]
+fn runs_the_highest_priority_first() {
+    testing::run(
+        || {
+            let log = Rc::new(RefCell::new(Vec::new()));
+            let done = Rc::new(CountDownLatch::new(4));
+            let threads = [
+                (Priority::LOW, "low"),
+                (Priority::HIGH, "high a"),
+                (Priority::HIGH, "high b"),
+                (Priority::NORMAL, "normal"),
+            ];
+            for (priority, name) in threads {
+                let (log, done) = (log.clone(), done.clone());
+                spawn_with_priority(priority, move || {
+                    for _ in 0..2 {
+                        log.borrow_mut().push(name);
+                        yield_thread();
+                    }
+                    done.count_down();
+                });
+            }
+            done.wait();
+            assert_eq!(
+                *log.borrow(),
+                ["high a", "high b", "high a", "high b", "normal", "normal", "low", "low"]
+            );
+        },
+        Duration::from_secs(10),
+    );
+}
+
+// The thread lowering its own priority makes way for the others right away.
+#[test]
+fn lowering_the_priority_makes_way() {
+    testing::run(
+        || {
+            let log = Rc::new(RefCell::new(Vec::new()));
+            let done = Rc::new(CountDownLatch::new(2));
+            let (high_log, high_done) = (log.clone(), done.clone());
+            spawn_with_priority(Priority::HIGH, move || {
+                high_log.borrow_mut().push("high");
+                assert!(ThreadHandle::current().set_priority(Priority::LOW));
+                high_log.borrow_mut().push("lowered");
+                high_done.count_down();
+            });
+            let (normal_log, normal_done) = (log.clone(), done.clone());
+            spawn_with_priority(Priority::NORMAL, move || {
+                normal_log.borrow_mut().push("normal");
+                normal_done.count_down();
+            });
+            done.wait();
+            assert_eq!(*log.borrow(), ["high", "normal", "lowered"]);
+        },
+        Duration::from_secs(10),
+    );
+}
+
+// A blocked thread raised above the others runs first once woken up.
+#[test]
+fn raising_a_blocked_thread() {
+    testing::run(
+        || {
+            let log = Rc::new(RefCell::new(Vec::new()));
+            let start = Rc::new(CountDownLatch::new(1));
+            let done = Rc::new(CountDownLatch::new(2));
+            let mut threads = Vec::new();
+            for name in ["first", "second"] {
+                let (log, start, done) = (log.clone(), start.clone(), done.clone());
+                threads.push(ThreadHandle::new(spawn_with_priority(
+                    Priority::LOW,
+                    move || {
+                        start.wait();
+                        log.borrow_mut().push(name);
+                        done.count_down();
+                    },
+                )));
+            }
+            // both block on the latch.
+            yield_thread();
+            assert!(threads[1].set_priority(Priority::HIGH));
+            assert_eq!(threads[1].priority(), Some(Priority::HIGH));
+            start.count_down();
+            done.wait();
+            assert_eq!(*log.borrow(), ["second", "first"]);
+            // both have completed.
+            assert!(!threads[0].set_priority(Priority::HIGH));
+            assert_eq!(threads[0].priority(), None);
+        },
+        Duration::from_secs(10),
+    );
+}
@@ -0,0 +1,80 @@
+#![cfg(all(feature = "std", feature = "sync"))]
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+
+use uthreads::join;
+use uthreads::runtime::Runtime;
+use uthreads::sync::RateLimiter;
+use uthreads::time::{self, MockClock};
+
+// On a mock clock, which moves to the first timer once every thread is blocked, the sleeps take no time,
+// and the instants the threads go on at are exact.
+fn run(f: impl FnOnce() + 'static) {
+    let mut runtime = Runtime::new();
+    unsafe { runtime.init() };
+    runtime.set_clock(MockClock::new());
+    runtime.block_on(f);
+}
+
+#[test]
+fn the_burst_goes_through_then_one_token_per_interval() {
+    run(|| {
+        // 10 tokens a second, one every 100ms, and up to 3 at once.
+        let limiter = RateLimiter::new(10, Duration::from_secs(1), 3);
+        let start = time::now();
+        for _ in 0..3 {
+            limiter.acquire();
+        }
+        assert_eq!(time::now(), start);
+        assert!(!limiter.try_acquire());
+        limiter.acquire();
+        assert_eq!(time::now(), start + Duration::from_millis(100));
+        assert!(!limiter.try_acquire());
+    });
+}
+
+#[test]
+fn the_bucket_refills_while_idle() {
+    run(|| {
+        let limiter = RateLimiter::new(10, Duration::from_secs(1), 3);
+        for _ in 0..3 {
+            assert!(limiter.try_acquire());
+        }
+        assert!(!limiter.try_acquire());
+        // refilled after two intervals, up to the burst and no further.
+        time::sleep(Duration::from_millis(200));
+        assert!(limiter.try_acquire());
+        assert!(limiter.try_acquire());
+        assert!(!limiter.try_acquire());
+        time::sleep(Duration::from_secs(10));
+        for _ in 0..3 {
+            assert!(limiter.try_acquire());
+        }
+        assert!(!limiter.try_acquire());
+    });
+}
+
+#[test]
+fn waiting_threads_go_on_in_order_one_interval_apart() {
+    run(|| {
+        let limiter = Rc::new(RateLimiter::new(10, Duration::from_secs(1), 1));
+        let start = time::now();
+        limiter.acquire();
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let handles: Vec<_> = (0..3)
+            .map(|i| {
+                let limiter = limiter.clone();
+                let log = log.clone();
+                join::spawn(move || {
+                    limiter.acquire();
+                    log.borrow_mut().push((i, time::now() - start));
+                })
+            })
+            .collect();
+        join::join_all(handles);
+        let ms = Duration::from_millis;
+        assert_eq!(*log.borrow(), [(0, ms(100)), (1, ms(200)), (2, ms(300))]);
+    });
+}
@@ -0,0 +1,59 @@
+#![cfg(feature = "std")]
+
+use std::thread;
+use std::time::Duration;
+
+use uthreads::channel::remote_channel;
+use uthreads::join;
+use uthreads::runtime::Runtime;
+
+#[test]
+fn a_thread_receives_from_os_threads() {
+    let (tx, mut rx) = remote_channel::<(usize, u32)>();
+    let mut runtime = Runtime::new();
+    unsafe { runtime.init() };
+    let received = runtime.block_on(move || {
+        let receiver = join::spawn(move || {
+            let mut received = vec![Vec::new(); 4];
+            // blocks while the queue is empty, and returns None once every sender is dropped.
+            while let Some((sender, i)) = rx.recv() {
+                received[sender].push(i);
+            }
+            received
+        });
+        let senders: Vec<_> = (0..4)
+            .map(|sender| {
+                let tx = tx.clone();
+                thread::spawn(move || {
+                    for i in 0..100 {
+                        tx.send((sender, i)).unwrap();
+                        if i % 10 == 0 {
+                            thread::sleep(Duration::from_millis(1));
+                        }
+                    }
+                })
+            })
+            .collect();
+        drop(tx);
+        let received = receiver.join();
+        for sender in senders {
+            sender.join().unwrap();
+        }
+        received
+    });
+    // the values of each sender arrive in the order it sent them.
+    for values in received {
+        assert_eq!(values, (0..100).collect::<Vec<_>>());
+    }
+}
+
+#[test]
+fn sending_fails_once_the_receiver_is_dropped() {
+    let (tx, mut rx) = remote_channel();
+    tx.send(1).unwrap();
+    assert_eq!(rx.len(), 1);
+    assert_eq!(rx.try_recv(), Some(1));
+    assert_eq!(rx.try_recv(), None);
+    drop(rx);
+    assert_eq!(tx.send(2), Err(2));
+}
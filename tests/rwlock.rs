@@ -0,0 +1,163 @@
+#![cfg(all(feature = "std", feature = "sync"))]
+
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::Duration;
+
+use uthreads::runtime::{self, yield_thread};
+use uthreads::sync::{CountDownLatch, Fairness, RwLock};
+use uthreads::testing;
+
+const READERS: usize = 4;
+const ROUNDS: usize = 50;
+
+#[test]
+fn readers_share_and_writers_exclude() {
+    testing::run(
+        || {
+            let lock = Rc::new(RwLock::new(0));
+            let most = Rc::new(Cell::new(0));
+            let done = Rc::new(CountDownLatch::new(READERS + 1));
+            for _ in 0..READERS {
+                let (lock, most, done) = (lock.clone(), most.clone(), done.clone());
+                runtime::spawn(move || {
+                    let value = lock.read();
+                    // the other readers come in meanwhile.
+                    yield_thread();
+                    most.set(lock.readers().max(most.get()));
+                    assert!(!lock.is_write_locked());
+                    assert_eq!(*value, 0);
+                    drop(value);
+                    done.count_down();
+                });
+            }
+            let (writer_lock, writer_done) = (lock.clone(), done.clone());
+            runtime::spawn(move || {
+                let mut value = writer_lock.write();
+                assert_eq!(writer_lock.readers(), 0);
+                assert!(writer_lock.try_read().is_none());
+                *value += 1;
+                yield_thread();
+                drop(value);
+                writer_done.count_down();
+            });
+            done.wait();
+            assert_eq!(most.get(), READERS);
+            assert_eq!(*lock.read(), 1);
+            assert!(lock.try_write().is_some());
+        },
+        Duration::from_secs(10),
+    );
+}
+
+// Keeps the lock read by `READERS` threads taking turns, so that some thread always holds it, while a writer comes in.
+// Returns how many reads were done before the writer got the lock, out of how many in total.
+fn reads_before_write(fairness: Fairness) -> (usize, usize) {
+    let lock = Rc::new(RwLock::with_fairness((), fairness));
+    let reads = Rc::new(Cell::new(0));
+    let done = Rc::new(CountDownLatch::new(READERS + 1));
+    for _ in 0..READERS {
+        let (lock, reads, done) = (lock.clone(), reads.clone(), done.clone());
+        runtime::spawn(move || {
+            for _ in 0..ROUNDS {
+                let guard = lock.read();
+                reads.set(reads.get() + 1);
+                // the other readers come in meanwhile.
+                yield_thread();
+                drop(guard);
+            }
+            done.count_down();
+        });
+    }
+    let (writer_lock, writer_reads, writer_done) = (lock.clone(), reads.clone(), done.clone());
+    let written = Rc::new(Cell::new(None));
+    let seen = written.clone();
+    runtime::spawn(move || {
+        drop(writer_lock.write());
+        seen.set(Some(writer_reads.get()));
+        writer_done.count_down();
+    });
+    done.wait();
+    (written.get().unwrap(), reads.get())
+}
+
+#[test]
+fn reader_preferring_starves_writers() {
+    let (before, total) = testing::run(
+        || reads_before_write(Fairness::ReaderPreferring),
+        Duration::from_secs(10),
+    );
+    assert_eq!(total, READERS * ROUNDS);
+    // the writer only gets in once the readers stop coming.
+    assert_eq!(before, total);
+}
+
+#[test]
+fn writer_preferring_lets_writers_in() {
+    let (before, _) = testing::run(
+        || reads_before_write(Fairness::WriterPreferring),
+        Duration::from_secs(10),
+    );
+    // the writer only waits for the readers already in.
+    assert_eq!(before, READERS);
+}
+
+#[test]
+fn phase_fair_lets_writers_in() {
+    let (before, _) = testing::run(
+        || reads_before_write(Fairness::PhaseFair),
+        Duration::from_secs(10),
+    );
+    assert_eq!(before, READERS);
+}
+
+// Keeps the lock written by `READERS` threads taking turns, while a reader comes in.
+// Returns how many writes were done before the reader got the lock, out of how many in total.
+fn writes_before_read(fairness: Fairness) -> (usize, usize) {
+    let lock = Rc::new(RwLock::with_fairness((), fairness));
+    let writes = Rc::new(Cell::new(0));
+    let done = Rc::new(CountDownLatch::new(READERS + 1));
+    for _ in 0..READERS {
+        let (lock, writes, done) = (lock.clone(), writes.clone(), done.clone());
+        runtime::spawn(move || {
+            for _ in 0..ROUNDS {
+                let guard = lock.write();
+                writes.set(writes.get() + 1);
+                // the other writers queue up meanwhile.
+                yield_thread();
+                drop(guard);
+            }
+            done.count_down();
+        });
+    }
+    let (reader_lock, reader_writes, reader_done) = (lock.clone(), writes.clone(), done.clone());
+    let read = Rc::new(Cell::new(None));
+    let seen = read.clone();
+    runtime::spawn(move || {
+        drop(reader_lock.read());
+        seen.set(Some(reader_writes.get()));
+        reader_done.count_down();
+    });
+    done.wait();
+    (read.get().unwrap(), writes.get())
+}
+
+#[test]
+fn writer_preferring_starves_readers() {
+    let (before, total) = testing::run(
+        || writes_before_read(Fairness::WriterPreferring),
+        Duration::from_secs(10),
+    );
+    assert_eq!(total, READERS * ROUNDS);
+    assert_eq!(before, total);
+}
+
+#[test]
+fn phase_fair_lets_readers_in() {
+    let (before, _) = testing::run(
+        || writes_before_read(Fairness::PhaseFair),
+        Duration::from_secs(10),
+    );
+    // the reader only waits for the write under way as it comes.
+    assert_eq!(before, 1);
+}
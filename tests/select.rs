@@ -0,0 +1,117 @@
+#![cfg(feature = "std")]
+
+use std::os::fd::RawFd;
+use std::time::Duration;
+
+use uthreads::channel::Channel;
+use uthreads::reactor::Interest;
+use uthreads::runtime::{chan_send, chan_try_recv, yield_thread};
+use uthreads::select::Select;
+use uthreads::{testing, time};
+
+// Returns the read and write ends of a new pipe.
+fn pipe() -> (RawFd, RawFd) {
+    let mut fds = [0; 2];
+    assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+    (fds[0], fds[1])
+}
+
+fn close(fds: &[RawFd]) {
+    for &fd in fds {
+        unsafe { libc::close(fd) };
+    }
+}
+
+#[test]
+fn returns_the_channel_a_value_is_sent_to() {
+    let received = testing::run(
+        || {
+            let idle = Box::into_raw(Box::new(Channel::<u32>::new(1)));
+            let chan = Box::into_raw(Box::new(Channel::<u32>::new(1)));
+            uthreads::join::spawn(move || {
+                yield_thread();
+                unsafe { chan_send(chan, 7) };
+            });
+            let mut select = Select::new();
+            unsafe { select.recv(idle) };
+            let index = unsafe { select.recv(chan) };
+            assert_eq!(select.wait().unwrap(), Some(index));
+            let received = unsafe { chan_try_recv(chan) }.unwrap();
+            drop(unsafe { (Box::from_raw(idle), Box::from_raw(chan)) });
+            received
+        },
+        Duration::from_secs(10),
+    );
+    assert_eq!(received, 7);
+}
+
+#[test]
+fn returns_the_file_descriptor_ready() {
+    testing::run(
+        || {
+            let (reader, writer) = pipe();
+            let idle = Box::into_raw(Box::new(Channel::<u32>::new(1)));
+            uthreads::join::spawn(move || {
+                time::sleep(Duration::from_millis(10));
+                assert_eq!(unsafe { libc::write(writer, b"x".as_ptr().cast(), 1) }, 1);
+            });
+            let mut select = Select::new();
+            unsafe { select.recv(idle) };
+            let index = select.io(reader, Interest::Readable);
+            assert_eq!(select.wait().unwrap(), Some(index));
+            drop(unsafe { Box::from_raw(idle) });
+            close(&[reader, writer]);
+        },
+        Duration::from_secs(10),
+    );
+}
+
+#[test]
+fn returns_none_past_the_timeout() {
+    let (res, waited) = testing::run(
+        || {
+            let (reader, writer) = pipe();
+            let idle = Box::into_raw(Box::new(Channel::<u32>::new(1)));
+            let mut select = Select::new();
+            unsafe { select.recv(idle) };
+            select.io(reader, Interest::Readable);
+            select.timeout(Duration::from_millis(20));
+            let start = time::now();
+            let res = select.wait().unwrap();
+            let waited = time::now() - start;
+            drop(unsafe { Box::from_raw(idle) });
+            close(&[reader, writer]);
+            (res, waited)
+        },
+        Duration::from_secs(10),
+    );
+    assert_eq!(res, None);
+    assert!(waited >= Duration::from_millis(20));
+}
+
+#[test]
+fn returns_the_first_of_the_operations_ready() {
+    let indices = testing::run(
+        || {
+            let (reader, writer) = pipe();
+            let idle = Box::into_raw(Box::new(Channel::<u32>::new(1)));
+            let full = Box::into_raw(Box::new(Channel::<u32>::new(1)));
+            unsafe { chan_send(full, 1) };
+            // the write end of an empty pipe is ready, as is the channel with a value, whatever their order.
+            let mut io_first = Select::new();
+            io_first.io(reader, Interest::Readable);
+            io_first.io(writer, Interest::Writable);
+            unsafe { io_first.recv(full) };
+            let mut recv_first = Select::new();
+            unsafe { recv_first.recv(idle) };
+            unsafe { recv_first.recv(full) };
+            recv_first.io(writer, Interest::Writable);
+            let indices = (io_first.wait().unwrap(), recv_first.wait().unwrap());
+            drop(unsafe { (Box::from_raw(idle), Box::from_raw(full)) });
+            close(&[reader, writer]);
+            indices
+        },
+        Duration::from_secs(10),
+    );
+    assert_eq!(indices, (Some(1), Some(1)));
+}
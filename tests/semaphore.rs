@@ -0,0 +1,135 @@
+#![cfg(all(feature = "std", feature = "sync"))]
+
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::time::Duration;
+
+use uthreads::runtime::{self, yield_thread};
+use uthreads::sync::{CountDownLatch, Semaphore};
+use uthreads::testing;
+
+#[test]
+fn limits_the_threads_going_on() {
+    testing::run(
+        || {
+            let semaphore = Rc::new(Semaphore::new(3));
+            let (running, most) = (Rc::new(Cell::new(0)), Rc::new(Cell::new(0)));
+            let done = Rc::new(CountDownLatch::new(10));
+            for _ in 0..10 {
+                let (semaphore, running, most, done) = (
+                    semaphore.clone(),
+                    running.clone(),
+                    most.clone(),
+                    done.clone(),
+                );
+                runtime::spawn(move || {
+                    let permit = semaphore.acquire();
+                    running.set(running.get() + 1);
+                    most.set(most.get().max(running.get()));
+                    yield_thread();
+                    running.set(running.get() - 1);
+                    drop(permit);
+                    done.count_down();
+                });
+            }
+            done.wait();
+            assert_eq!(most.get(), 3);
+            assert_eq!(semaphore.available_permits(), 3);
+        },
+        Duration::from_secs(10),
+    );
+}
+
+#[test]
+fn acquire_many_waits_for_all_permits() {
+    testing::run(
+        || {
+            let semaphore = Rc::new(Semaphore::new(2));
+            let held = semaphore.acquire_many(2);
+            assert!(semaphore.try_acquire().is_none());
+            let done = Rc::new(CountDownLatch::new(1));
+            let (waiter, waiter_done) = (semaphore.clone(), done.clone());
+            runtime::spawn(move || {
+                let permit = waiter.acquire_many(3);
+                assert_eq!(permit.permits(), 3);
+                waiter_done.count_down();
+            });
+            yield_thread();
+            drop(held);
+            // two permits aren't enough.
+            yield_thread();
+            assert_eq!(done.count(), 1);
+            semaphore.add_permits(1);
+            done.wait();
+            assert_eq!(semaphore.available_permits(), 3);
+        },
+        Duration::from_secs(10),
+    );
+}
+
+// A stream of threads taking a permit each, some of them always holding one, doesn't starve a thread taking them all.
+#[test]
+fn large_requests_are_not_starved() {
+    testing::run(
+        || {
+            const SMALL: usize = 20;
+            let semaphore = Rc::new(Semaphore::new(4));
+            let log = Rc::new(RefCell::new(Vec::new()));
+            let done = Rc::new(CountDownLatch::new(SMALL + 1));
+            let spawned = Rc::new(Cell::new(0));
+            let asked_after = Rc::new(Cell::new(None));
+            for i in 0..SMALL {
+                if i == 3 {
+                    let (large, log, done) = (semaphore.clone(), log.clone(), done.clone());
+                    let (spawned, asked_after) = (spawned.clone(), asked_after.clone());
+                    runtime::spawn(move || {
+                        asked_after.set(Some(spawned.get()));
+                        let permit = large.acquire_many(4);
+                        log.borrow_mut().push(None);
+                        drop(permit);
+                        done.count_down();
+                    });
+                }
+                let (semaphore, log, done) = (semaphore.clone(), log.clone(), done.clone());
+                runtime::spawn(move || {
+                    let permit = semaphore.acquire();
+                    log.borrow_mut().push(Some(i));
+                    yield_thread();
+                    yield_thread();
+                    drop(permit);
+                    done.count_down();
+                });
+                spawned.set(i + 1);
+                // the threads spawned so far run, one more coming in as others complete.
+                yield_thread();
+            }
+            done.wait();
+            let log = log.borrow();
+            let large = log.iter().position(Option::is_none).unwrap();
+            // only the threads which asked before it got permits first.
+            let asked_after = asked_after.get().unwrap();
+            assert!(
+                log[..large].iter().all(|&i| i < Some(asked_after)),
+                "{log:?}"
+            );
+            assert_eq!(semaphore.available_permits(), 4);
+        },
+        Duration::from_secs(10),
+    );
+}
+
+#[test]
+fn forgotten_permits_are_not_given_back() {
+    testing::run(
+        || {
+            let semaphore = Semaphore::new(2);
+            semaphore.acquire().forget();
+            assert_eq!(semaphore.available_permits(), 1);
+            let permit = semaphore.try_acquire_many(1).unwrap();
+            assert!(semaphore.try_acquire().is_none());
+            drop(permit);
+            assert_eq!(semaphore.available_permits(), 1);
+        },
+        Duration::from_secs(10),
+    );
+}
@@ -0,0 +1,208 @@
+#![cfg(feature = "std")]
+
+use std::cell::Cell;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use uthreads::channel::spsc;
+use uthreads::runtime::{self, yield_thread, Runtime};
+use uthreads::testing;
+
+// Fewer values go through under Miri, which runs the tests far slower.
+// It only runs those without a runtime, with `cargo +nightly miri test --test spsc`.
+const VALUES: u64 = if cfg!(miri) { 200 } else { 100_000 };
+
+#[test]
+fn values_are_received_in_order() {
+    let (mut tx, mut rx) = spsc(4);
+    for i in 0..4 {
+        tx.try_send(i).unwrap();
+    }
+    assert_eq!(tx.try_send(4), Err(4));
+    assert_eq!(rx.len(), 4);
+    for i in 0..4 {
+        assert_eq!(rx.try_recv(), Some(i));
+    }
+    assert_eq!(rx.try_recv(), None);
+    assert!(tx.is_empty());
+}
+
+#[test]
+fn wraps_around_the_ring() {
+    let (mut tx, mut rx) = spsc(3);
+    let mut next = 0;
+    for i in 0..10 {
+        // the ring is filled up, then emptied except for a value, so that both indices go round it several times.
+        while tx.try_send(next).is_ok() {
+            next += 1;
+        }
+        assert_eq!(rx.len(), 3);
+        for j in 0..2 {
+            assert_eq!(rx.try_recv(), Some(2 * i + j));
+        }
+        assert_eq!(rx.len(), 1);
+    }
+    assert_eq!(rx.try_recv(), Some(next - 1));
+    assert_eq!(rx.try_recv(), None);
+}
+
+#[test]
+fn values_left_in_the_ring_are_dropped() {
+    let value = Arc::new(());
+    let (mut tx, rx) = spsc(4);
+    for _ in 0..3 {
+        tx.try_send(value.clone()).unwrap();
+    }
+    drop(rx);
+    assert_eq!(Arc::strong_count(&value), 4);
+    drop(tx);
+    assert_eq!(Arc::strong_count(&value), 1);
+}
+
+#[test]
+#[cfg_attr(miri, ignore = "the reactor uses epoll in a mode Miri doesn't support")]
+fn a_full_ring_blocks_the_sender_until_a_value_is_received() {
+    let received = testing::run(
+        || {
+            let (mut tx, mut rx) = spsc(2);
+            let sent = Rc::new(Cell::new(0));
+            let counted = sent.clone();
+            let sender = uthreads::join::spawn(move || {
+                for i in 0..5 {
+                    tx.send(i).unwrap();
+                    counted.set(counted.get() + 1);
+                }
+            });
+            yield_thread();
+            // the sender filled the ring, and blocked on the third value.
+            assert_eq!(sent.get(), 2);
+            assert_eq!(rx.len(), 2);
+            let mut received = Vec::new();
+            while let Some(i) = rx.recv() {
+                received.push(i);
+            }
+            sender.join();
+            received
+        },
+        Duration::from_secs(10),
+    );
+    assert_eq!(received, [0, 1, 2, 3, 4]);
+}
+
+#[test]
+#[cfg_attr(miri, ignore = "the reactor uses epoll in a mode Miri doesn't support")]
+fn an_empty_ring_blocks_the_receiver_until_a_value_is_sent() {
+    let received = testing::run(
+        || {
+            let (mut tx, mut rx) = spsc(2);
+            let receiver = uthreads::join::spawn(move || {
+                let mut received = Vec::new();
+                while let Some(i) = rx.recv() {
+                    received.push(i);
+                }
+                received
+            });
+            for i in 0..5 {
+                // the receiver takes every value, then blocks on the empty ring until the next one.
+                while !tx.is_empty() {
+                    yield_thread();
+                }
+                tx.send(i).unwrap();
+            }
+            drop(tx);
+            receiver.join()
+        },
+        Duration::from_secs(10),
+    );
+    assert_eq!(received, [0, 1, 2, 3, 4]);
+}
+
+#[test]
+#[cfg_attr(miri, ignore = "the reactor uses epoll in a mode Miri doesn't support")]
+fn dropping_the_receiver_wakes_a_blocked_sender() {
+    let res = testing::run(
+        || {
+            let (mut tx, rx) = spsc(1);
+            let sender = uthreads::join::spawn(move || {
+                tx.send(1).unwrap();
+                tx.send(2)
+            });
+            yield_thread();
+            drop(rx);
+            sender.join()
+        },
+        Duration::from_secs(10),
+    );
+    assert_eq!(res, Err(2));
+}
+
+#[test]
+#[cfg_attr(miri, ignore = "the reactor uses epoll in a mode Miri doesn't support")]
+fn dropping_the_sender_wakes_a_blocked_receiver() {
+    let received = testing::run(
+        || {
+            let (tx, mut rx) = spsc::<u32>(1);
+            let receiver = uthreads::join::spawn(move || rx.recv());
+            yield_thread();
+            drop(tx);
+            receiver.join()
+        },
+        Duration::from_secs(10),
+    );
+    assert_eq!(received, None);
+}
+
+#[test]
+fn carries_values_between_os_threads_without_blocking() {
+    let (mut tx, mut rx) = spsc(4);
+    let sender = thread::spawn(move || {
+        for mut i in 0..VALUES {
+            while let Err(val) = tx.try_send(i) {
+                i = val;
+                thread::yield_now();
+            }
+        }
+    });
+    for i in 0..VALUES {
+        let val = loop {
+            match rx.try_recv() {
+                Some(val) => break val,
+                None => thread::yield_now(),
+            }
+        };
+        assert_eq!(val, i);
+    }
+    sender.join().unwrap();
+    assert_eq!(rx.try_recv(), None);
+}
+
+// Runs `body` on a runtime of its own, on a new OS thread.
+fn on_runtime<F: FnOnce() + Send + 'static>(body: F) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let mut runtime = Runtime::new();
+        unsafe { runtime.init() };
+        runtime::spawn(body);
+        runtime.run();
+    })
+}
+
+#[test]
+#[cfg_attr(miri, ignore = "the reactor uses epoll in a mode Miri doesn't support")]
+fn blocks_and_wakes_between_the_runtimes_of_os_threads() {
+    let (mut tx, mut rx) = spsc(4);
+    let sender = on_runtime(move || {
+        for i in 0..VALUES {
+            tx.send(i).unwrap();
+        }
+    });
+    let receiver = on_runtime(move || {
+        for i in 0..VALUES {
+            assert_eq!(rx.recv(), Some(i));
+        }
+        assert_eq!(rx.recv(), None);
+    });
+    sender.join().unwrap();
+    receiver.join().unwrap();
+}
@@ -0,0 +1,409 @@
+#![cfg(feature = "std")]
+
+use std::alloc::{self, Layout};
+use std::cell::{Cell, RefCell};
+use std::env;
+use std::fs;
+use std::hint::black_box;
+use std::io;
+use std::ops::Range;
+use std::os::unix::process::ExitStatusExt;
+use std::process::{Command, Output};
+use std::ptr::NonNull;
+use std::rc::Rc;
+use std::time::Duration;
+
+use uthreads::join;
+use uthreads::runtime::{self, yield_thread, Runtime};
+use uthreads::testing;
+use uthreads::thread::{GrowableAllocator, Id, MmapAllocator, Stack, StackAllocator};
+
+// Runs the test `name` again in a process of its own, with `UTHREADS_TEST_CHILD` set for it to do what crashes it.
+fn run_child(name: &str) -> Output {
+    Command::new(env::current_exe().unwrap())
+        .args(["--exact", name, "--nocapture"])
+        .env("UTHREADS_TEST_CHILD", "1")
+        .output()
+        .unwrap()
+}
+
+fn in_child() -> bool {
+    env::var_os("UTHREADS_TEST_CHILD").is_some()
+}
+
+// Recurses until the stack overflows.
+fn recurse_forever(depth: usize) -> usize {
+    let frame = black_box([depth; 64]);
+    // never true, but the compiler can't tell.
+    if black_box(depth == usize::MAX) {
+        return 0;
+    }
+    recurse_forever(depth + 1) + frame[depth % 64]
+}
+
+// Returns the permissions of the mapping of this process which `addr` is in, e.g. "rw-p", as listed in /proc/self/maps.
+fn permissions(addr: usize) -> String {
+    let maps = fs::read_to_string("/proc/self/maps").unwrap();
+    for line in maps.lines() {
+        let mut fields = line.split_whitespace();
+        let range = fields.next().unwrap();
+        let (start, end) = range.split_once('-').unwrap();
+        let start = usize::from_str_radix(start, 16).unwrap();
+        let end = usize::from_str_radix(end, 16).unwrap();
+        if (start..end).contains(&addr) {
+            return fields.next().unwrap().to_string();
+        }
+    }
+    panic!("{addr:#x} isn't mapped:\n{maps}");
+}
+
+// Returns the flags of the mapping of this process which `addr` is in, as listed in /proc/self/smaps, e.g. "rd wr mr".
+fn vm_flags(addr: usize) -> String {
+    let smaps = fs::read_to_string("/proc/self/smaps").unwrap();
+    let mut inside = false;
+    for line in smaps.lines() {
+        if let Some(flags) = line.strip_prefix("VmFlags:") {
+            if inside {
+                return flags.trim().to_string();
+            }
+        } else if let Some((range, _)) = line.split_once(' ') {
+            if let Some((start, end)) = range.split_once('-') {
+                if let (Ok(start), Ok(end)) = (
+                    usize::from_str_radix(start, 16),
+                    usize::from_str_radix(end, 16),
+                ) {
+                    inside = (start..end).contains(&addr);
+                }
+            }
+        }
+    }
+    panic!("{addr:#x} isn't mapped:\n{smaps}");
+}
+
+#[test]
+fn stacks_have_a_guard_page_below_them() {
+    let mut stack = MmapAllocator.allocate(64 * 1024, true).unwrap();
+    let usable = stack.as_mut_ptr() as usize;
+    assert!(stack.len() >= 64 * 1024);
+    assert!(stack.in_guard(usable - 1));
+    assert!(!stack.in_guard(usable));
+    assert_eq!(permissions(usable - 1), "---p");
+    assert_eq!(permissions(usable), "rw-p");
+    assert_eq!(permissions(usable + stack.len() - 1), "rw-p");
+    MmapAllocator.deallocate(stack);
+}
+
+#[test]
+fn stacks_reserve_no_swap() {
+    let mut stack = MmapAllocator.allocate(64 * 1024, true).unwrap();
+    let usable = stack.as_mut_ptr() as usize;
+    let flags = vm_flags(usable);
+    assert!(flags.split(' ').any(|flag| flag == "nr"), "{flags}");
+    MmapAllocator.deallocate(stack);
+}
+
+// Stacks are only backed by memory as far as the threads use them, so threads that use little cost little.
+#[test]
+fn idle_threads_commit_little_of_their_stacks() {
+    let stats = testing::run(
+        || {
+            const THREADS: usize = 1000;
+            let handles: Vec<_> = (0..THREADS).map(|_| join::spawn(yield_thread)).collect();
+            yield_thread();
+            let stats = runtime::memory_stats();
+            join::join_all(handles);
+            stats
+        },
+        Duration::from_secs(10),
+    );
+    let threads: Vec<_> = stats
+        .threads
+        .iter()
+        .filter(|t| t.stack_reserved > 0)
+        .collect();
+    assert!(threads.len() >= 1000, "{}", threads.len());
+    let reserved: usize = threads.iter().map(|t| t.stack_reserved).sum();
+    let committed: usize = threads.iter().map(|t| t.stack_committed).sum();
+    assert!(committed * 8 < reserved, "{committed} of {reserved} bytes");
+}
+
+// Uses about `depth` KiB of stack.
+fn recurse(depth: usize) -> u8 {
+    let frame = black_box([depth as u8; 1024]);
+    if depth == 0 {
+        return frame[0];
+    }
+    frame[1023].wrapping_add(recurse(depth - 1))
+}
+
+#[test]
+fn shrink_stack_releases_the_pages_below() {
+    testing::run(
+        || {
+            let handle = join::spawn(move || {
+                let id = runtime::get_current_thread();
+                recurse(24);
+                let deep = runtime::stack_usage(id).unwrap();
+                runtime::shrink_stack().unwrap();
+                let shrunk = runtime::stack_usage(id).unwrap();
+                // the pages come back, zeroed, as the thread uses them again.
+                recurse(24);
+                (deep, shrunk, runtime::stack_usage(id).unwrap())
+            });
+            let (deep, shrunk, again) = handle.join();
+            assert!(deep >= 24 * 1024, "{deep}");
+            assert!(shrunk <= 16 * 1024, "{shrunk}");
+            assert!(again >= 24 * 1024, "{again}");
+        },
+        Duration::from_secs(10),
+    );
+}
+
+// The base thread runs on the stack of the OS thread, which is left alone.
+#[test]
+fn shrink_stack_does_nothing_on_the_base_thread() {
+    let mut runtime = Runtime::new();
+    unsafe { runtime.init() };
+    assert_eq!(runtime::get_current_thread(), Id(0));
+    runtime::shrink_stack().unwrap();
+    assert_eq!(runtime::stack_usage(Id(0)), None);
+    recurse(16);
+}
+
+// Counts the stacks it hands out and gets back, mapping them with `MmapAllocator`.
+#[derive(Clone, Default)]
+struct Counting {
+    allocated: Rc<Cell<usize>>,
+    deallocated: Rc<Cell<usize>>,
+}
+
+impl StackAllocator for Counting {
+    fn allocate(&mut self, size: usize, guard: bool) -> io::Result<Stack> {
+        self.allocated.set(self.allocated.get() + 1);
+        MmapAllocator.allocate(size, guard)
+    }
+
+    fn deallocate(&mut self, stack: Stack) {
+        self.deallocated.set(self.deallocated.get() + 1);
+        MmapAllocator.deallocate(stack);
+    }
+}
+
+#[test]
+fn stacks_of_completed_threads_are_reused() {
+    let counting = Counting::default();
+    let mut runtime = Runtime::with_stack_allocator(counting.clone());
+    unsafe { runtime.init() };
+    runtime.block_on(|| {
+        for i in 0..10_000 {
+            assert_eq!(join::spawn(move || i).join(), i);
+        }
+    });
+    // the thread running the loop, and the few whose stacks were about to go back to the pool as they were spawned.
+    assert!(
+        counting.allocated.get() <= 3,
+        "{}",
+        counting.allocated.get()
+    );
+    drop(runtime);
+    assert_eq!(counting.deallocated.get(), counting.allocated.get());
+}
+
+#[test]
+fn the_pool_of_stacks_is_bounded() {
+    const THREADS: usize = 200;
+    let counting = Counting::default();
+    let mut runtime = Runtime::with_stack_allocator(counting.clone());
+    unsafe { runtime.init() };
+    let total = counting.clone();
+    let (pooled, held) = runtime.block_on(move || {
+        let handles: Vec<_> = (0..THREADS).map(|_| join::spawn(yield_thread)).collect();
+        join::join_all(handles);
+        let held = counting.allocated.get() - counting.deallocated.get();
+        (runtime::memory_stats().pooled_stacks, held)
+    });
+    // the threads all ran at once, and the stacks which didn't fit in the pool went back to the allocator.
+    // Besides the pool, only the stacks of the thread joining them and of the last one to complete are held,
+    // the latter until the next thread is spawned.
+    assert_eq!(pooled, 64);
+    assert_eq!(held, 64 + 2);
+    assert!(total.allocated.get() > THREADS);
+    drop(runtime);
+    assert_eq!(total.deallocated.get(), total.allocated.get());
+}
+
+// Hands out stacks from the heap, without guard pages, remembering where they are.
+#[derive(Clone, Default)]
+struct Heap {
+    stacks: Rc<RefCell<Vec<Range<usize>>>>,
+    deallocated: Rc<Cell<usize>>,
+}
+
+impl StackAllocator for Heap {
+    fn allocate(&mut self, size: usize, _guard: bool) -> io::Result<Stack> {
+        let layout = Layout::from_size_align(size, 16).unwrap();
+        let base =
+            NonNull::new(unsafe { alloc::alloc(layout) }).ok_or(io::ErrorKind::OutOfMemory)?;
+        let start = base.as_ptr() as usize;
+        self.stacks.borrow_mut().push(start..start + size);
+        Ok(unsafe { Stack::from_raw_parts(base, size, 0) })
+    }
+
+    fn deallocate(&mut self, stack: Stack) {
+        let (base, len, _) = stack.into_raw_parts();
+        self.deallocated.set(self.deallocated.get() + 1);
+        unsafe { alloc::dealloc(base.as_ptr(), Layout::from_size_align(len, 16).unwrap()) };
+    }
+}
+
+#[test]
+fn threads_run_on_the_stacks_of_the_allocator() {
+    let heap = Heap::default();
+    let mut runtime = Runtime::with_stack_allocator(heap.clone());
+    unsafe { runtime.init() };
+    let frames = Rc::new(RefCell::new(Vec::new()));
+    for _ in 0..2 {
+        let frames = frames.clone();
+        runtime.spawn(move || {
+            let local = 0u8;
+            frames.borrow_mut().push(&raw const local as usize);
+            yield_thread();
+        });
+    }
+    runtime.run();
+    let stacks = heap.stacks.borrow().clone();
+    assert_eq!(stacks.len(), 2);
+    for (frame, stack) in frames.borrow().iter().zip(&stacks) {
+        assert!(stack.contains(frame), "{frame:#x} outside of {stack:x?}");
+    }
+    // the stacks are handed back once the runtime is done with them, if not before.
+    drop(runtime);
+    assert_eq!(heap.deallocated.get(), 2);
+}
+
+#[test]
+fn growable_stacks_start_small() {
+    let mut allocator = GrowableAllocator { initial: 4096 };
+    let mut stack = allocator.allocate(64 * 1024, true).unwrap();
+    let usable = stack.as_mut_ptr() as usize;
+    let top = usable + stack.len();
+    assert_eq!(permissions(top - 1), "rw-p");
+    assert_eq!(permissions(top - 4096), "rw-p");
+    assert_eq!(permissions(top - 4097), "---p");
+    assert!(stack.in_guard(top - 4097));
+    allocator.deallocate(stack);
+}
+
+#[test]
+fn growable_stacks_grow_as_threads_need() {
+    let mut runtime = Runtime::with_stack_allocator(GrowableAllocator { initial: 4096 });
+    unsafe { runtime.init() };
+    let used = runtime.block_on(|| {
+        join::spawn(|| {
+            recurse(24);
+            runtime::stack_usage(runtime::get_current_thread()).unwrap()
+        })
+        .join()
+    });
+    assert!(used >= 24 * 1024, "{used}");
+}
+
+#[test]
+fn growable_stacks_still_overflow() {
+    if in_child() {
+        let mut runtime = Runtime::with_stack_allocator(GrowableAllocator { initial: 4096 });
+        unsafe { runtime.init() };
+        runtime.spawn(|| {
+            recurse_forever(0);
+        });
+        runtime.run();
+        return;
+    }
+    let output = run_child("growable_stacks_still_overflow");
+    assert_eq!(output.status.signal(), Some(libc::SIGABRT), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("thread Id(1) has overflowed its stack ("),
+        "{stderr}"
+    );
+}
+
+// Runs in a process of its own, which the fault kills.
+fn write_below_the_stack() {
+    let mut stack = MmapAllocator.allocate(64 * 1024, true).unwrap();
+    stack.fill(1);
+    unsafe { stack.as_mut_ptr().sub(1).write_volatile(1) };
+}
+
+#[test]
+fn writing_past_the_stack_faults() {
+    if in_child() {
+        write_below_the_stack();
+        return;
+    }
+    let output = run_child("writing_past_the_stack_faults");
+    assert_eq!(output.status.signal(), Some(libc::SIGSEGV), "{output:?}");
+}
+
+#[test]
+fn overflowing_a_thread_stack_is_reported() {
+    if in_child() {
+        let mut runtime = Runtime::new();
+        unsafe { runtime.init() };
+        runtime.spawn(|| {});
+        runtime.spawn(|| {
+            recurse_forever(0);
+        });
+        runtime.run();
+        return;
+    }
+    let output = run_child("overflowing_a_thread_stack_is_reported");
+    assert_eq!(output.status.signal(), Some(libc::SIGABRT), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("thread Id(2) has overflowed its stack ("),
+        "{stderr}"
+    );
+}
+
+// Faults elsewhere are left to the handler that was there before the runtime's.
+#[test]
+fn other_faults_still_crash() {
+    if in_child() {
+        let mut runtime = Runtime::new();
+        unsafe { runtime.init() };
+        runtime.spawn(|| unsafe {
+            black_box(std::ptr::null_mut::<u64>()).write_volatile(1);
+        });
+        runtime.run();
+        return;
+    }
+    let output = run_child("other_faults_still_crash");
+    assert_eq!(output.status.signal(), Some(libc::SIGSEGV), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!stderr.contains("overflowed"), "{stderr}");
+}
+
+// The base thread runs on the stack of the OS thread, which the standard library reports overflows of.
+#[test]
+fn overflowing_the_os_thread_stack_is_left_to_std() {
+    if in_child() {
+        let mut runtime = Runtime::new();
+        unsafe { runtime.init() };
+        assert_eq!(runtime::get_current_thread(), Id(0));
+        recurse_forever(0);
+        return;
+    }
+    let output = run_child("overflowing_the_os_thread_stack_is_left_to_std");
+    assert_eq!(output.status.signal(), Some(libc::SIGABRT), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("thread 'overflowing_the_os_thread_stack_is_left_to_std'"),
+        "{stderr}"
+    );
+    assert!(
+        stderr.contains("fatal runtime error: stack overflow"),
+        "{stderr}"
+    );
+    assert!(!stderr.contains("uthreads"), "{stderr}");
+}
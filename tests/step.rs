@@ -0,0 +1,55 @@
+#![cfg(feature = "std")]
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use uthreads::channel::Channel;
+use uthreads::runtime::{chan_recv, chan_send, Runtime};
+
+// The thread woken up by the last one to complete goes to the LIFO slot, but still waits for a step of its own.
+#[test]
+fn step_runs_one_thread() {
+    let mut runtime = Runtime::new();
+    unsafe { runtime.init() };
+    let chan = Box::into_raw(Box::new(Channel::new(1)));
+    let log = Rc::new(RefCell::new(Vec::new()));
+
+    let a = log.clone();
+    runtime.spawn(move || {
+        a.borrow_mut().push("a waits");
+        let val = unsafe { chan_recv(chan) };
+        assert_eq!(val, 1);
+        a.borrow_mut().push("a received");
+    });
+    let b = log.clone();
+    runtime.spawn(move || {
+        unsafe { chan_send(chan, 1) };
+        b.borrow_mut().push("b sent");
+    });
+
+    assert!(runtime.step());
+    assert_eq!(*log.borrow(), ["a waits"]);
+    assert!(runtime.step());
+    assert_eq!(*log.borrow(), ["a waits", "b sent"]);
+    assert!(runtime.step());
+    assert_eq!(*log.borrow(), ["a waits", "b sent", "a received"]);
+    assert!(!runtime.step());
+    drop(unsafe { Box::from_raw(chan) });
+}
+
+#[test]
+fn step_returns_to_the_caller_between_threads() {
+    let mut runtime = Runtime::new();
+    unsafe { runtime.init() };
+    let runs = Rc::new(RefCell::new(0));
+    for _ in 0..3 {
+        let runs = runs.clone();
+        runtime.spawn(move || *runs.borrow_mut() += 1);
+    }
+    for step in 1..=3 {
+        assert!(runtime.step());
+        assert_eq!(*runs.borrow(), step);
+    }
+    assert!(!runtime.step());
+    assert_eq!(runtime.run_until_idle(), 0);
+}
@@ -0,0 +1,87 @@
+#![cfg(feature = "std")]
+
+use std::future::Future;
+use std::pin::pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+use std::time::Duration;
+
+use uthreads::channel::{Channel, ChannelSink, ChannelStream};
+use uthreads::join;
+use uthreads::runtime::{chan_recv, chan_send};
+use uthreads::testing;
+
+// Counts how many times the task it is the waker of was woken up.
+#[derive(Default)]
+struct CountingWaker(AtomicUsize);
+
+impl Wake for CountingWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+fn waker() -> (Arc<CountingWaker>, Waker) {
+    let counter = Arc::new(CountingWaker::default());
+    (counter.clone(), Waker::from(counter))
+}
+
+#[test]
+fn a_task_receives_what_threads_send() {
+    let (received, wakes) = testing::run(
+        || {
+            let chan = Box::into_raw(Box::new(Channel::<u32>::new(1)));
+            let mut stream = unsafe { ChannelStream::new(chan) };
+            let (counter, waker) = waker();
+            let mut cx = Context::from_waker(&waker);
+
+            assert_eq!(stream.poll_recv(&mut cx), Poll::Pending);
+            // the task is woken up as a thread sends, then gets the value.
+            join::spawn(move || unsafe { chan_send(chan, 1) }).join();
+            let wakes = counter.0.load(Ordering::SeqCst);
+            let mut received = vec![];
+            if let Poll::Ready(val) = stream.poll_recv(&mut cx) {
+                received.push(val);
+            }
+
+            // the futures of the stream resolve to the values in the order they were sent.
+            join::spawn(move || unsafe { chan_send(chan, 2) }).join();
+            let mut next = pin!(stream.next().unwrap());
+            if let Poll::Ready(val) = next.as_mut().poll(&mut cx) {
+                received.push(val);
+            }
+            drop(unsafe { Box::from_raw(chan) });
+            (received, wakes)
+        },
+        Duration::from_secs(10),
+    );
+    assert_eq!(received, [1, 2]);
+    assert_eq!(wakes, 1);
+}
+
+#[test]
+fn a_task_sends_to_threads() {
+    let (received, wakes) = testing::run(
+        || {
+            let chan = Box::into_raw(Box::new(Channel::<u32>::new(1)));
+            let mut sink = unsafe { ChannelSink::new(chan) };
+            let (counter, waker) = waker();
+            let mut cx = Context::from_waker(&waker);
+
+            assert_eq!(sink.poll_send(1, &mut cx), Ok(()));
+            // the buffer is full, so the task waits for a thread to receive.
+            let mut send = pin!(sink.send(2));
+            assert_eq!(send.as_mut().poll(&mut cx), Poll::Pending);
+            let first = join::spawn(move || unsafe { chan_recv(chan) }).join();
+            let wakes = counter.0.load(Ordering::SeqCst);
+            assert_eq!(send.as_mut().poll(&mut cx), Poll::Ready(()));
+            let second = join::spawn(move || unsafe { chan_recv(chan) }).join();
+            drop(unsafe { Box::from_raw(chan) });
+            ([first, second], wakes)
+        },
+        Duration::from_secs(10),
+    );
+    assert_eq!(received, [1, 2]);
+    assert_eq!(wakes, 1);
+}
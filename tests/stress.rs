@@ -0,0 +1,124 @@
+#![cfg(feature = "std")]
+
+// Many threads ping-ponging over channels, and sharing locks, while sleeping and yielding along the way,
+// run with the threads picked in turn and at random with a few seeds, for the interleavings to vary.
+
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::Duration;
+
+use uthreads::channel::Channel;
+use uthreads::runtime::{chan_recv, chan_send, yield_thread, Runtime};
+use uthreads::time;
+
+const SEEDS: [Option<u64>; 4] = [None, Some(1), Some(2), Some(3)];
+const PAIRS: usize = 32;
+const ROUNDS: usize = 50;
+
+fn runtime(seed: Option<u64>) -> Runtime {
+    let mut runtime = Runtime::new();
+    runtime.set_seed(seed);
+    unsafe { runtime.init() };
+    runtime
+}
+
+#[test]
+fn ping_pong() {
+    for seed in SEEDS {
+        let mut runtime = runtime(seed);
+        let replies = Rc::new(Cell::new(0));
+        let mut chans = Vec::new();
+        for pair in 0..PAIRS {
+            let ping = Box::into_raw(Box::new(Channel::new(1)));
+            let pong = Box::into_raw(Box::new(Channel::new(1)));
+            chans.extend([ping, pong]);
+            runtime.spawn(move || {
+                for round in 0..ROUNDS {
+                    unsafe { chan_send(ping, round) };
+                    assert_eq!(unsafe { chan_recv(pong) }, round + 1);
+                    if round % 8 == pair % 8 {
+                        time::sleep(Duration::from_millis(1));
+                    } else if round % 2 == 0 {
+                        yield_thread();
+                    }
+                }
+            });
+            let replies = replies.clone();
+            runtime.spawn(move || {
+                for _ in 0..ROUNDS {
+                    let val = unsafe { chan_recv(ping) };
+                    if val % 3 == 0 {
+                        yield_thread();
+                    }
+                    unsafe { chan_send(pong, val + 1) };
+                    replies.set(replies.get() + 1);
+                }
+            });
+        }
+        runtime.run();
+        assert_eq!(replies.get(), PAIRS * ROUNDS, "seed {seed:?}");
+        drop(runtime);
+        for chan in chans {
+            drop(unsafe { Box::from_raw(chan) });
+        }
+    }
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn locks_under_load() {
+    use uthreads::sync::{Condvar, Mutex, RwLock};
+
+    const THREADS: usize = 16;
+    for seed in SEEDS {
+        let mut runtime = runtime(seed);
+        let counter = Rc::new(Mutex::new(0));
+        // both fields are always updated together, and readers check they agree.
+        let pair = Rc::new(RwLock::new((0, 0)));
+        let signal = Rc::new((Mutex::new(0), Condvar::new()));
+        for thread in 0..THREADS {
+            let (counter, pair, signal) = (counter.clone(), pair.clone(), signal.clone());
+            runtime.spawn(move || {
+                for round in 0..ROUNDS {
+                    match (thread + round) % 4 {
+                        0 => {
+                            let mut count = counter.lock();
+                            let seen = *count;
+                            yield_thread();
+                            *count = seen + 1;
+                        }
+                        1 => {
+                            let mut pair = pair.write();
+                            pair.0 += 1;
+                            yield_thread();
+                            pair.1 += 1;
+                        }
+                        2 => {
+                            let pair = pair.read();
+                            yield_thread();
+                            assert_eq!(pair.0, pair.1);
+                        }
+                        _ => {
+                            let (mutex, condvar) = &*signal;
+                            *mutex.lock() += 1;
+                            condvar.notify_one();
+                            let (guard, _) =
+                                condvar.wait_timeout(mutex.lock(), Duration::from_millis(1));
+                            drop(guard);
+                        }
+                    }
+                    if round % 10 == thread % 10 {
+                        time::sleep(Duration::from_millis(1));
+                    }
+                }
+            });
+        }
+        runtime.run();
+        drop(runtime);
+        let quarter = THREADS * ROUNDS / 4;
+        let counter = Rc::try_unwrap(counter).expect("the threads completed");
+        assert_eq!(counter.into_inner(), quarter, "seed {seed:?}");
+        let pair = Rc::try_unwrap(pair).expect("the threads completed");
+        assert_eq!(pair.into_inner(), (quarter, quarter), "seed {seed:?}");
+    }
+}
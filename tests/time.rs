@@ -0,0 +1,57 @@
+#![cfg(feature = "std")]
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use uthreads::join;
+use uthreads::runtime::{maybe_yield, Runtime};
+use uthreads::testing;
+use uthreads::time;
+
+#[test]
+fn sleepers_wake_up_in_deadline_order() {
+    testing::run(
+        || {
+            let start = time::now();
+            let woken = Rc::new(RefCell::new(Vec::new()));
+            let handles: Vec<_> = [30, 10, 20]
+                .into_iter()
+                .map(|ms| {
+                    let woken = woken.clone();
+                    join::spawn(move || {
+                        time::sleep(Duration::from_millis(ms));
+                        assert!(time::now() - start >= Duration::from_millis(ms));
+                        woken.borrow_mut().push(ms);
+                    })
+                })
+                .collect();
+            join::join_all(handles);
+            assert_eq!(*woken.borrow(), [10, 20, 30]);
+        },
+        Duration::from_secs(10),
+    );
+}
+
+// A thread spinning without yielding, only checking whether its time slice is used up, lets the others run.
+#[test]
+fn busy_threads_give_way_once_their_slice_is_used_up() {
+    static STOP: AtomicBool = AtomicBool::new(false);
+    let mut runtime = Runtime::new();
+    unsafe { runtime.init() };
+    runtime.set_time_slice(Some(Duration::from_millis(1)));
+    runtime.block_on(|| {
+        let spinner = join::spawn(|| {
+            let mut spins = 0u64;
+            while !STOP.load(Ordering::Relaxed) {
+                spins += 1;
+                maybe_yield();
+            }
+            spins
+        });
+        let stopper = join::spawn(|| STOP.store(true, Ordering::Relaxed));
+        stopper.join();
+        assert!(spinner.join() > 0);
+    });
+}
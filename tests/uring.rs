@@ -0,0 +1,98 @@
+#![cfg(all(target_os = "linux", feature = "io-uring"))]
+
+use std::os::fd::RawFd;
+use std::time::Duration;
+
+use uthreads::reactor::{uring_read, uring_write};
+use uthreads::runtime::{self, Runtime};
+use uthreads::testing;
+use uthreads::thread::{MmapAllocator, Stack, StackAllocator, StackError};
+
+// Returns the read and write ends of a new pipe.
+fn pipe() -> (RawFd, RawFd) {
+    let mut fds = [0; 2];
+    assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+    (fds[0], fds[1])
+}
+
+fn close(fds: &[RawFd]) {
+    for &fd in fds {
+        unsafe { libc::close(fd) };
+    }
+}
+
+#[test]
+fn reads_what_is_written_to_a_pipe() {
+    let read = testing::run(
+        || {
+            let (reader, writer) = pipe();
+            // the reader blocks until the other thread writes.
+            let handle = uthreads::join::spawn(move || {
+                let mut buf = [0; 16];
+                let n = uring_read(reader, &mut buf).unwrap();
+                buf[..n].to_vec()
+            });
+            assert_eq!(uring_write(writer, b"hello").unwrap(), 5);
+            let read = handle.join();
+            close(&[reader, writer]);
+            read
+        },
+        Duration::from_secs(10),
+    );
+    assert_eq!(read, b"hello");
+}
+
+#[test]
+fn writes_are_read_back_in_order() {
+    let read = testing::run(
+        || {
+            let (reader, writer) = pipe();
+            for chunk in [&b"ab"[..], b"cd", b"ef"] {
+                assert_eq!(uring_write(writer, chunk).unwrap(), chunk.len());
+            }
+            let mut buf = [0; 16];
+            let n = unsafe { libc::read(reader, buf.as_mut_ptr().cast(), buf.len()) };
+            close(&[reader, writer]);
+            buf[..n as usize].to_vec()
+        },
+        Duration::from_secs(10),
+    );
+    assert_eq!(read, b"abcdef");
+}
+
+// Writes a byte to a pipe as the stacks are handed back, which a read still in flight would take.
+struct WritingAllocator(RawFd);
+
+impl StackAllocator for WritingAllocator {
+    fn allocate(&mut self, size: usize, guard: bool) -> Result<Stack, StackError> {
+        MmapAllocator.allocate(size, guard)
+    }
+
+    fn deallocate(&mut self, stack: Stack) {
+        assert_eq!(unsafe { libc::write(self.0, b"x".as_ptr().cast(), 1) }, 1);
+        MmapAllocator.deallocate(stack);
+    }
+}
+
+#[test]
+fn shutdown_cancels_a_read_in_flight() {
+    let (reader, writer) = pipe();
+    let mut runtime = Runtime::with_stack_allocator(WritingAllocator(writer));
+    unsafe { runtime.init() };
+    runtime::spawn(move || {
+        // nothing is written until the stacks are handed back, so the read is still in flight at shutdown.
+        let _ = uring_read(reader, &mut [0; 16]);
+        unreachable!("the read completed before anything was written");
+    });
+    assert!(!runtime.shutdown_timeout(Duration::from_millis(50)));
+
+    // the read was cancelled before the stack was freed rather than completed into it: the byte is left in the pipe.
+    assert_eq!(
+        unsafe { libc::fcntl(reader, libc::F_SETFL, libc::O_NONBLOCK) },
+        0
+    );
+    let mut buf = [0; 1];
+    assert_eq!(unsafe { libc::read(reader, buf.as_mut_ptr().cast(), 1) }, 1);
+    assert_eq!(&buf, b"x");
+    close(&[reader, writer]);
+}
@@ -0,0 +1,58 @@
+#![cfg(feature = "valgrind")]
+
+// Run natively, where the client requests telling Valgrind about the stacks do nothing: the runtime must go on as usual.
+// Running these under Valgrind, e.g. `valgrind --error-exitcode=1 target/debug/deps/valgrind-*`,
+// should report no errors as the threads switch stacks.
+
+use std::hint::black_box;
+use std::time::Duration;
+
+use uthreads::join;
+use uthreads::runtime::{yield_thread, Runtime};
+use uthreads::testing;
+use uthreads::thread::GrowableAllocator;
+
+// Uses about `depth` KiB of stack.
+fn recurse(depth: usize) -> u8 {
+    let frame = black_box([depth as u8; 1024]);
+    if depth == 0 {
+        return frame[0];
+    }
+    frame[1023].wrapping_add(recurse(depth - 1))
+}
+
+#[test]
+fn threads_switch_stacks() {
+    testing::run(
+        || {
+            // more threads than the pool holds, so that stacks are both reused and given back.
+            let handles: Vec<_> = (0..100)
+                .map(|i| {
+                    join::spawn(move || {
+                        yield_thread();
+                        recurse(8);
+                        i
+                    })
+                })
+                .collect();
+            let sum: usize = join::join_all(handles).into_iter().sum();
+            assert_eq!(sum, (0..100).sum());
+        },
+        Duration::from_secs(10),
+    );
+}
+
+// The memory a growable stack may grow over is registered upfront.
+#[test]
+fn growable_stacks_grow() {
+    let mut runtime = Runtime::with_stack_allocator(GrowableAllocator { initial: 4096 });
+    unsafe { runtime.init() };
+    let done = runtime.block_on(|| {
+        join::spawn(|| {
+            recurse(24);
+            "done"
+        })
+        .join()
+    });
+    assert_eq!(done, "done");
+}